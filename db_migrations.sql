@@ -6,6 +6,9 @@
 
   So essentially its pointless to continue to update this, but it is a record of most(?) of the
   changes that were made.
+
+  Changes since then are numbered migrations in the migrations/ directory, which are applied and
+  tracked by the migrate program (see src/db/migrations.rs).
 */
 
 -- Add column for unclassified vehicles, which we will now start tracking.
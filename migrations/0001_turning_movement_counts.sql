@@ -0,0 +1,13 @@
+-- Create table for turning movement counts: 15-minute volume by approach, movement, and class.
+create table tc_tmccount_new (
+    recordnum number not null,
+    countdatetime date not null,
+    approach varchar2(10) not null,
+    movement varchar2(10) not null,
+    vehclass varchar2(50) not null,
+    volume number not null,
+    constraint approach_tc_tmccount_new check (approach in ('north', 'east', 'west', 'south')),
+    constraint movement_tc_tmccount_new check (movement in ('left', 'through', 'right', 'uturn')),
+    constraint unique_record_datetime_approach_movement_class_tmccount
+        unique (recordnum, countdatetime, approach, movement, vehclass)
+);
@@ -0,0 +1,37 @@
+-- Allow counts with more than three lanes.
+create table tc_lanedir (
+    recordnum number not null,
+    lane number(2,0) not null,
+    direction varchar2(10) not null,
+    constraint lane_valid_tc_lanedir check (lane >= 1),
+    constraint direction_tc_lanedir check (direction in ('north', 'east', 'west', 'south')),
+    constraint unique_record_lane_tc_lanedir unique (recordnum, lane)
+);
+-- The count tables' constraints limiting countlane to 1-3 were carried over from the old tables
+-- under names that aren't known here, so drop whichever check constraints there are on it
+-- (other than not null).
+begin
+    for c in (
+        select cc.table_name, cc.constraint_name
+        from user_cons_columns cc
+        join user_constraints uc
+            on uc.table_name = cc.table_name and uc.constraint_name = cc.constraint_name
+        where cc.table_name in (
+                'TC_SPESUM_NEW', 'TC_SPECOUNT_NEW', 'TC_VOLCOUNT_NEW', 'TC_15MINVOLCOUNT_NEW',
+                'TC_CLACOUNT_NEW'
+            )
+            and cc.column_name = 'COUNTLANE'
+            and uc.constraint_type = 'C'
+            and uc.search_condition_vc not like '%IS NOT NULL'
+    ) loop
+        execute immediate
+            'alter table ' || c.table_name || ' drop constraint ' || c.constraint_name;
+    end loop;
+end;
+/
+alter table tc_spesum_new add constraint countlane_valid_spesum_new check (countlane >= 1);
+alter table tc_specount_new add constraint countlane_valid_specount_new check (countlane >= 1);
+alter table tc_volcount_new add constraint countlane_valid_volcount_new check (countlane >= 1);
+alter table tc_15minvolcount_new
+    add constraint countlane_valid_15minvolcount_new check (countlane >= 1);
+alter table tc_clacount_new add constraint countlane_valid_clacount_new check (countlane >= 1);
@@ -0,0 +1,16 @@
+-- Create table for speed distributions with alternative speed bins: volume by interval, lane,
+-- and bin of each scheme.
+create table tc_speeddist (
+    recordnum number not null,
+    countdatetime date not null,
+    countlane number(2,0) not null,
+    cntdir varchar2(10) not null,
+    scheme varchar2(20) not null,
+    binnum number(3,0) not null,
+    speedbin varchar2(20) not null,
+    volume number not null,
+    constraint countlane_valid_speeddist check (countlane >= 1),
+    constraint cntdir_tc_speeddist check (cntdir in ('north', 'east', 'west', 'south')),
+    constraint unique_record_datetime_lane_scheme_bin_speeddist
+        unique (recordnum, countdatetime, countlane, scheme, binnum)
+);
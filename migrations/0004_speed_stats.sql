@@ -0,0 +1,17 @@
+-- Create table for hourly speed statistics: 50th/85th percentile speed, 10-mph pace, and
+-- percentage over the speed limit, by hour and lane.
+create table tc_speedstats (
+    recordnum number not null,
+    countdatetime date not null,
+    countlane number(2,0) not null,
+    cntdir varchar2(10) not null,
+    volume number not null,
+    speed50 number,
+    speed85 number,
+    pacestart number,
+    pacepct number,
+    overlimitpct number,
+    constraint countlane_valid_speedstats check (countlane >= 1),
+    constraint cntdir_tc_speedstats check (cntdir in ('north', 'east', 'west', 'south')),
+    constraint unique_record_datetime_lane_speedstats unique (recordnum, countdatetime, countlane)
+);
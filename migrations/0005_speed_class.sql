@@ -0,0 +1,18 @@
+-- Create table for speed by vehicle class: 15-minute speed range counts, average speed, and 85th
+-- percentile speed of each class, by lane.
+create table tc_speedclass (
+    recordnum number not null,
+    countdatetime date not null,
+    countlane number(2,0) not null,
+    cntdir varchar2(10) not null,
+    vehclass number(2,0) not null,
+    total number not null,
+    avgspeed number not null,
+    speed85 number not null,
+    s1 number, s2 number, s3 number, s4 number, s5 number, s6 number, s7 number,
+    s8 number, s9 number, s10 number, s11 number, s12 number, s13 number, s14 number,
+    constraint countlane_valid_speedclass check (countlane >= 1),
+    constraint cntdir_tc_speedclass check (cntdir in ('north', 'east', 'west', 'south')),
+    constraint unique_record_datetime_lane_class_speedclass
+        unique (recordnum, countdatetime, countlane, vehclass)
+);
@@ -0,0 +1,28 @@
+-- Create table for hourly headways (time between consecutive vehicles in a lane): the number in
+-- each bin (0-1 s, 1-2 s, ... 5-6 s, 6-8 s, 8-10 s, 10-15 s, 15-30 s, 30-60 s, 60+ s), their
+-- mean, and the percentage of vehicles in platoons.
+create table tc_headway (
+    recordnum number not null,
+    countdatetime date not null,
+    countlane number(2,0) not null,
+    cntdir varchar2(10) not null,
+    headways number not null,
+    meanheadway number,
+    platoonpct number,
+    h1 number, h2 number, h3 number, h4 number, h5 number, h6 number,
+    h7 number, h8 number, h9 number, h10 number, h11 number, h12 number,
+    constraint countlane_valid_headway check (countlane >= 1),
+    constraint cntdir_tc_headway check (cntdir in ('north', 'east', 'west', 'south')),
+    constraint unique_record_datetime_lane_headway unique (recordnum, countdatetime, countlane)
+);
+
+-- Create table for hourly gap acceptance (gaps between consecutive vehicles in any lane).
+create table tc_gaps (
+    recordnum number not null,
+    countdatetime date not null,
+    criticalgap number not null,
+    gaps number not null,
+    acceptedgaps number not null,
+    maxgap number,
+    constraint unique_record_datetime_gaps unique (recordnum, countdatetime)
+);
@@ -0,0 +1,19 @@
+-- Create table for length-based classification counts: short (< 22 ft), medium (22-40 ft), long
+-- (40-62 ft), and very long (62+ ft) vehicles. Unclassified vehicles are only in unclassified.
+create table tc_lenclacount (
+    recordnum number not null,
+    countdatetime date not null,
+    -- selected (as in the other class count tables) but derived from countdatetime
+    countdate date generated always as (trunc(countdatetime)) virtual,
+    counttime date generated always as (countdatetime) virtual,
+    countlane number(2,0),
+    cntdir varchar2(10),
+    total number not null,
+    lenbin1 number not null,
+    lenbin2 number not null,
+    lenbin3 number not null,
+    lenbin4 number not null,
+    unclassified number not null,
+    constraint countlane_valid_lenclacount check (countlane >= 1),
+    constraint cntdir_tc_lenclacount check (cntdir in ('north', 'east', 'west', 'south'))
+);
@@ -0,0 +1,30 @@
+-- Create tables for the results of the data checks: one row per check per run (all the checks
+-- applied to a count at one time), and the time periods each result concerns.
+create sequence tc_datacheck_run_seq;
+
+create table tc_datacheck (
+    runid number not null,
+    recordnum number not null,
+    checkid varchar2(50) not null,
+    -- whether the check was of the parsed data, before insert, or of the data in the database
+    stage varchar2(10) not null,
+    severity varchar2(10) not null,
+    measured number,
+    threshold number,
+    message varchar2(4000) not null,
+    checkdatetime date default current_date not null,
+    constraint stage_tc_datacheck check (stage in ('parsed', 'stored')),
+    constraint severity_tc_datacheck check (severity in ('INFO', 'WARN', 'ERROR')),
+    constraint unique_run_check_datacheck unique (runid, checkid)
+);
+
+create index recordnum_tc_datacheck on tc_datacheck (recordnum);
+
+create table tc_datacheck_period (
+    runid number not null,
+    checkid varchar2(50) not null,
+    periodstart date not null,
+    periodend date not null,
+    constraint fk_tc_datacheck_period foreign key (runid, checkid)
+        references tc_datacheck (runid, checkid) on delete cascade
+);
@@ -0,0 +1,16 @@
+-- Create table for the peak hours of turning movement counts: of the intersection as a whole
+-- (null approach) and of each approach, with the peak hour factor and volume of each movement.
+create table tc_tmcpeakhour (
+    recordnum number not null,
+    approach varchar2(10),
+    countdatetime date not null,
+    volume number not null,
+    peak15min number not null,
+    phf number not null,
+    left number not null,
+    through number not null,
+    right number not null,
+    uturn number not null,
+    constraint approach_tc_tmcpeakhour check (approach in ('north', 'east', 'west', 'south')),
+    constraint unique_record_approach_tmcpeakhour unique (recordnum, approach)
+);
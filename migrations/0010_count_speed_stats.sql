@@ -0,0 +1,16 @@
+-- Create table for speed statistics of whole counts: average, 50th/85th percentile speed, 10-mph
+-- pace, and percentage over the speed limit, of every vehicle (null vehclass) or of one class.
+create table tc_countspeedstats (
+    recordnum number not null,
+    countdatetime date not null,
+    vehclass number(2,0),
+    volume number not null,
+    avgspeed number not null,
+    speed50 number not null,
+    speed85 number not null,
+    pacestart number not null,
+    pacepct number not null,
+    overlimitpct number,
+    constraint unique_record_datetime_class_countspeedstats
+        unique (recordnum, countdatetime, vehclass)
+);
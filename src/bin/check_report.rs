@@ -0,0 +1,163 @@
+//! Report the stored [results of the data checks][traffic_counts::non_perm::check_results] of
+//! non-permanent counts, as JSON.
+//!
+//! The report, printed to stdout, has a summary of each check's results (how many there are, how
+//! many are warnings or errors, how many counts they're of, and the range and mean of what was
+//! measured) followed by the results themselves. It covers every result, or only those matching
+//! any of the following options:
+//!   - `--recordnum <recordnum>` - of one count
+//!   - `--run <id>` - of one run of the checks
+//!   - `--check <id>` - of one check, e.g. `"unclassed share"`
+//!   - `--stage <parsed|stored>` - of counts' data before it was stored, or after
+//!   - `--severity <info|warn|error>` - with one severity
+//!   - `--min-measured <value>`, `--max-measured <value>` - that measured at least/most a value
+//!   - `--from <YYYY-MM-DD>`, `--to <YYYY-MM-DD>` - checked on or after/before (inclusive) a date
+//!
+//! e.g. every count whose share of unclassed vehicles was found to be above 10% in 2025:
+//! `cargo run --bin check_report -- --check "unclassed share" --min-measured 10 --from 2025-01-01
+//! --to 2025-12-31`.
+//!
+//! It uses the database of the non_perm_import program's [configuration][traffic_counts::config].
+
+use std::env;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, TimeDelta};
+use log::Level;
+
+use traffic_counts::{
+    config, db,
+    db::store::CountStore,
+    non_perm::check_results::{CheckReport, CheckResultFilter, CheckStage},
+};
+
+const USAGE: &str = "usage: check_report [--recordnum <recordnum>] [--run <id>] [--check <id>] \
+    [--stage <parsed|stored>] [--severity <info|warn|error>] [--min-measured <value>] \
+    [--max-measured <value>] [--from <YYYY-MM-DD>] [--to <YYYY-MM-DD>]";
+
+fn main() -> ExitCode {
+    let filter = match parse_filter(env::args().skip(1)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let db_config = match config::non_perm_db() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let pool = match db::create_pool(&db_config, 1) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to create connection pool: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let conn = match pool.get() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to get db connection: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let results = match conn.select_check_results(&filter) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to get data check results: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match CheckReport::new(results).to_json() {
+        Ok(v) => {
+            println!("{v}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Unable to create report: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Create the filter of the results to report from the program's arguments.
+fn parse_filter(mut args: impl Iterator<Item = String>) -> Result<CheckResultFilter, String> {
+    let mut filter = CheckResultFilter::default();
+    while let Some(option) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for '{option}'"))?;
+        let invalid = || format!("Invalid value for '{option}': '{value}'");
+        let date = || NaiveDate::parse_from_str(&value, "%Y-%m-%d").map_err(|_| invalid());
+        match option.as_str() {
+            "--recordnum" => filter.recordnum = Some(value.parse().map_err(|_| invalid())?),
+            "--run" => filter.run_id = Some(value.parse().map_err(|_| invalid())?),
+            "--check" => filter.check_id = Some(value.clone()),
+            "--stage" => filter.stage = Some(CheckStage::from_str(&value).map_err(|_| invalid())?),
+            "--severity" => filter.severity = Some(Level::from_str(&value).map_err(|_| invalid())?),
+            "--min-measured" => filter.min_measured = Some(value.parse().map_err(|_| invalid())?),
+            "--max-measured" => filter.max_measured = Some(value.parse().map_err(|_| invalid())?),
+            "--from" => filter.from = date()?.and_hms_opt(0, 0, 0),
+            // Through the end of the day.
+            "--to" => filter.to = (date()? + TimeDelta::days(1)).and_hms_opt(0, 0, 0),
+            _ => return Err(format!("Unknown option '{option}'")),
+        }
+    }
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn filter_from_args() {
+        assert_eq!(parse_filter(args(&[])), Ok(CheckResultFilter::default()));
+        assert_eq!(
+            parse_filter(args(&[
+                "--check",
+                "unclassed share",
+                "--severity",
+                "warn",
+                "--stage",
+                "stored",
+                "--min-measured",
+                "10",
+                "--from",
+                "2025-01-01",
+                "--to",
+                "2025-12-31",
+            ])),
+            Ok(CheckResultFilter {
+                check_id: Some("unclassed share".to_string()),
+                severity: Some(Level::Warn),
+                stage: Some(CheckStage::Stored),
+                min_measured: Some(10.0),
+                from: NaiveDate::from_ymd_opt(2025, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0),
+                to: NaiveDate::from_ymd_opt(2026, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0),
+                ..Default::default()
+            })
+        );
+        assert!(parse_filter(args(&["--recordnum"])).is_err());
+        assert!(parse_filter(args(&["--recordnum", "abc"])).is_err());
+        assert!(parse_filter(args(&["--stage", "loaded"])).is_err());
+        assert!(parse_filter(args(&["--limit", "5"])).is_err());
+    }
+}
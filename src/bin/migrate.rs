@@ -0,0 +1,132 @@
+//! Apply and track the [schema migrations][traffic_counts::db::migrations] of the non-permanent
+//! counts database.
+//!
+//! Run with one of the following commands, against the database of the non_perm_import program's
+//! [configuration][traffic_counts::config] (so DVRPCTC_TEST or production, depending on which is
+//! configured):
+//!   - `status` - list the migrations that have been applied and those that are pending
+//!   - `apply` - apply the pending migrations, in order
+//!   - `baseline <version>` - record the migrations up to and including `version` as applied,
+//!     without applying them, for a database that already had their changes made by hand
+//!   - `verify` - check that the database has every table and column that the library expects,
+//!     listing any it doesn't
+//!
+//! e.g. `cargo run --bin migrate -- status`. The program exits with a non-zero status if a
+//! command fails, or if `verify` finds the schema doesn't match.
+
+use std::env;
+use std::process::ExitCode;
+
+use traffic_counts::{
+    config,
+    db::{self, migrations},
+    CountError,
+};
+
+const USAGE: &str = "usage: migrate status | apply | baseline <version> | verify";
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    if !matches!(
+        args[..],
+        ["status"] | ["apply"] | ["baseline", _] | ["verify"]
+    ) {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let db_config = match config::non_perm_db() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let pool = match db::create_pool(&db_config, 1) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to create connection pool: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let conn = match pool.get() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to get db connection: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match args[..] {
+        ["status"] => status(&conn),
+        ["apply"] => apply(&conn),
+        ["baseline", version] => match version.parse() {
+            Ok(version) => baseline(&conn, version),
+            Err(_) => {
+                eprintln!("Invalid version '{version}'\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => verify(&conn),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn status(conn: &oracle::Connection) -> Result<bool, CountError> {
+    println!("Applied:");
+    for migration in migrations::applied(conn)? {
+        println!(
+            "  {:04} {} ({})",
+            migration.version, migration.name, migration.applied
+        );
+    }
+    println!("Pending:");
+    for migration in migrations::pending(conn)? {
+        println!("  {:04} {}", migration.version, migration.name);
+    }
+    Ok(true)
+}
+
+fn apply(conn: &oracle::Connection) -> Result<bool, CountError> {
+    let applied = migrations::apply_pending(conn)?;
+    if applied.is_empty() {
+        println!("No pending migrations.");
+    }
+    for migration in applied {
+        println!("Applied {:04} {}", migration.version, migration.name);
+    }
+    Ok(true)
+}
+
+fn baseline(conn: &oracle::Connection, version: u32) -> Result<bool, CountError> {
+    for migration in migrations::baseline(conn, version)? {
+        println!(
+            "Recorded {:04} {} as applied",
+            migration.version, migration.name
+        );
+    }
+    Ok(true)
+}
+
+fn verify(conn: &oracle::Connection) -> Result<bool, CountError> {
+    let mismatches = migrations::verify(conn)?;
+    if mismatches.is_empty() {
+        println!("Schema matches.");
+    }
+    for mismatch in &mismatches {
+        println!("{mismatch}");
+    }
+    let pending = migrations::pending(conn)?;
+    if !pending.is_empty() {
+        println!("{} migration(s) pending.", pending.len());
+    }
+    Ok(mismatches.is_empty())
+}
@@ -1,32 +1,89 @@
 //! Import non-permanent traffic counts to our database from files.
 //! This program watches a directory for files to be uploaded to one of the following
 //! subdirectories:
-//!   - jamar_vehicle/ - for raw, unbinned class and speed counts of [vehicles][IndividualVehicle],
+//!   - jamar_vehicle/ - for raw, unbinned class and speed counts of [vehicles][traffic_counts::non_perm::IndividualVehicle],
 //!     from STARneXt/JAMAR
-//!   - jamar_bicycle/ - for raw, unbinned class and speed counts of [bicycles][IndividualBicycle],
+//!   - jamar_bicycle/ - for raw, unbinned class and speed counts of [bicycles][traffic_counts::non_perm::IndividualBicycle],
 //!     from STARneXt/JAMAR
 //!   - jamar_vehicle_and_bicycle/ - for raw, unbinned class and speed counts of
-//!     [vehicles][IndividualVehicle] *and* [bicycles][IndividualBicycle], from STARneXt/JAMAR
+//!     [vehicles][traffic_counts::non_perm::IndividualVehicle] *and* [bicycles][traffic_counts::non_perm::IndividualBicycle], from STARneXt/JAMAR
 //!   - jamar_15minutevehicle/ - for
-//!     [pre-binned, 15-minute volume counts][FifteenMinuteVehicle] from STARneXt/JAMAR
+//!     [pre-binned, 15-minute volume counts][traffic_counts::non_perm::FifteenMinuteVehicle] from STARneXt/JAMAR
 //!   - ecocounter_15minutebicycle/ - for
-//!     [pre-binned, 15-minute bicycle counts][FifteenMinuteBicycle] from Eco-Counter
+//!     [pre-binned, 15-minute bicycle counts][traffic_counts::non_perm::FifteenMinuteBicycle] from Eco-Counter
 //!   - ecocounter_15minutepedestrian/ - for
-//!     [pre-binned, 15-minute pedestrian counts][FifteenMinutePedestrian] from Eco-Counter
+//!     [pre-binned, 15-minute pedestrian counts][traffic_counts::non_perm::FifteenMinutePedestrian] from Eco-Counter
+//!   - manual_class/ - for manual class counts, tallied by FHWA class, which become
+//!     [15-minute class counts][traffic_counts::non_perm::TimeBinnedVehicleClassCount]
+//!   - turningmovement/ - for
+//!     [turning movement counts][traffic_counts::non_perm::turning_movement::TurningMovementCount]
+//!     from JAMAR or Miovision, and their [peak hours][traffic_counts::non_perm::turning_movement::PeakHour]
 //!
-//! When a file is found, the program verifies that it contains the correct/expected kind of data,
-//! derives the appropriate counts from it, and then inserts these into our database and removes
-//! the file.
+//! The class counts of vehicles, whether raw or manual, are also stored
+//! [by length][traffic_counts::non_perm::class_scheme::TimeBinnedLengthClassCount].
+//!
+//! Pre-binned counts may also be binned by 5, 10, 30, or 60 minutes or by day; their interval is
+//! detected from the file and they are [resampled][traffic_counts::non_perm::resample] to 15
+//! minutes.
+//!
+//! When a file is found, the program verifies that it contains the correct/expected kind of data
+//! (see [`ParserRegistry::parser_for`]) - a file whose contents don't match the subdirectory it was
+//! uploaded to is moved for review rather than misparsed - derives the appropriate counts from it, and then inserts these into our database and removes
+//! the file. Each count is [imported in one transaction][traffic_counts::db::unit_of_work]: if
+//! storing any of its data or updating its metadata fails, none of it is kept, and the file is
+//! moved for review. Records are sent to the database 1000 at a time; set `batch_size` to send more
+//! or fewer at once.
 //!
 //! **NOTE**:
 //!   - The direction(s) of the count ("cldir1" at a minimum, and possibly "cldir2" and "cldir3")
-//!     need to be set in the database prior to the import.
+//!     need to be set in the database prior to the import. For counts with more than three lanes
+//!     (e.g. multi-lane arterials or ramps with 4-8 channels), set the direction of every lane in
+//!     the TC_LANEDIR table instead, which takes precedence over the "cldir" fields.
 //!   - EcoCounter bicycle counts can be one-way. However, bicycles sometimes go the wrong
 //!     direction and we want to include those that do in the count. **Make sure the field
 //!     "onewaybike" field in the TC_HEADER table is set to true (checked) in this case.** The total
 //!     column from the CSV will be used instead of "in" or "out".
 //!
-//! A [log][`LOG`] of the program's work is kept in the main directory.
+//! ## Configuration
+//!
+//! The program is [configured][traffic_counts::config] in the `[non_perm]` table of the
+//! configuration file, or with environment variables (which can be set in a .env file) named for
+//! its settings, e.g. `NON_PERM_DATA_DIR` for `data_dir`. At a minimum, `db_username`,
+//! `db_password`, `data_dir` (the directory watched for files), and `log_dir` need to be set; the
+//! optional settings mentioned below, along with `review_dir` (the subdirectory files are moved to
+//! for review, "for_review" by default) and `poll_interval` (seconds between checks for files, 20
+//! by default), can be too, as can the thresholds of the [data checks][traffic_counts::non_perm::check_data]
+//! made after a count is imported, for all counts or for those of a particular functional class or
+//! facility. If the configuration is invalid, every problem with it is reported and
+//! the program exits.
+//!
+//! ## Rejected Rows
+//!
+//! By default, a file with any row of data that can't be extracted (e.g. a malformed date or a
+//! non-numeric volume) is not imported, and is moved for review. If `max_rejected_pct` is set,
+//! such rows are instead skipped, so long as no more than that
+//! percentage of the file's data rows are. The skipped rows - their line, column, the reason
+//! they were rejected, and their text - are written to `<filename>.rejected.csv` in the
+//! directory for review, and the file is moved there too. See
+//! [`RowPolicy`][traffic_counts::non_perm::extract_from_file::RowPolicy].
+//!
+//! ## Speed Distributions
+//!
+//! Speeds of individual vehicles are always binned into the speed range counts (0-15 mph, then
+//! 5-mph bins up to 75+). For a different breakdown, set `speed_bins` to a list of
+//! built-in [`SpeedBinScheme`][traffic_counts::non_perm::speed_bins::SpeedBinScheme]s - "10mph" and/or "10kph" - and the
+//! volume of each of their bins, for each 15-minute interval and lane, will also be stored in the
+//! TC_SPEEDDIST table.
+//!
+//! ## Headways and Gaps
+//!
+//! The time between individual vehicles is summarized by hour: headways (between vehicles in the
+//! same lane) in the TC_HEADWAY table and gaps (between vehicles in any lane) in the TC_GAPS
+//! table. Vehicles with a headway under 3 seconds are considered to be in a platoon and gaps of
+//! at least 10 seconds to be acceptable; set `platoon_headway` and/or `critical_gap` (in seconds) to
+//! use other thresholds. See [`HeadwayOptions`][traffic_counts::non_perm::headway::HeadwayOptions].
+//!
+//! A [log][`LOG`] of the program's work is kept in the log directory.
 //! The program is able to log most errors and continue its execution,
 //! so that an error in one file will not prevent it from successfully processing another.
 //! The program itself should only fail if it is misconfigured, meaning that,
@@ -41,6 +98,48 @@
 //! recordnums separated by an underscore, with the vehicle recordnum first and then the bicycle
 //! recordnum, e.g. 123456_654321.csv.
 //!
+//! ### Class Mappings
+//!
+//! The class numbers in JAMAR per-vehicle files are assumed to be those of "Modified Scheme F"
+//! (or "Modified Scheme F - with bikes", for files with both vehicles and bicycles). To import a
+//! file that numbers classes differently, add the name of a [`ClassMapping`] to the filename,
+//! after the recordnum(s) and a period, e.g. 166905.my-counter.csv. Mappings are defined in a
+//! TOML file (see [`traffic_counts::non_perm::class_scheme`] for its format) whose path is set in
+//! `class_mappings`; the built-in mappings are named "modified-f" and
+//! "modified-f-bikes".
+//!
+//! ### Merging Files
+//!
+//! Importing a file normally replaces all of its count's existing data. When a count's data is
+//! split across files - e.g. a counter was swapped partway through the count, or the file for one
+//! of its lanes arrives later - add "merge" to the filename, after the recordnum(s) (and the name
+//! of the class mapping, if any) and a period, e.g. 166905.merge.csv or
+//! 166905.my-counter.merge.csv. The file's data is then [merged][traffic_counts::db::unit_of_work::merge_count] into the
+//! count's existing data: only existing records within the times (and lanes, where a table has
+//! them) that the file covers are replaced, and the count's hourly volumes, peak hours, and AADV
+//! are regenerated from all of its data. The speed statistics of the whole count and the gaps
+//! between vehicles, which can only be calculated from all of its vehicles at once, aren't
+//! merged. If any existing records are replaced, or any aren't merged, they are logged and the
+//! file is moved for review.
+//!
+//! ## Data Checks and Holding Files for Approval
+//!
+//! Before any of a file's data is stored, it's [checked][traffic_counts::non_perm::check_data] for
+//! potential issues, e.g. an unusually low share of class 2 vehicles or consecutive hours without
+//! any volume. Any found are logged. By default, the file is then imported anyway and moved for
+//! review. If `hold_warnings` is set to true, it's instead moved, without being imported, to the
+//! staging directory (`staging_dir`, "staging" by default), in a subdirectory named for the one it
+//! was uploaded to, so that it doesn't replace a count's existing data before anyone reviews it.
+//! To approve a held file, add "approved" to its name, after the recordnum(s) (and any other
+//! options) and a period, e.g. rename staging/jamar_vehicle/166905.csv to
+//! staging/jamar_vehicle/166905.approved.csv. It will then be imported, despite the warnings.
+//! To reject it, delete it. Any rows rejected from a held file are written next to it (e.g.
+//! staging/jamar_vehicle/166905.csv.rejected.csv), and moved for review along with it once it's
+//! approved.
+//!
+//! A merged file's data is checked again after it's stored, as its count's data is only complete
+//! once it has been.
+//!
 //! ## Exporting from STARneXt
 //!
 //! To begin, open the STARneXt app from JAMAR and then open a .snj or .tf2 file. From there, it
@@ -88,6 +187,23 @@
 //!     - Comma as delimiter
 //!   - click **Save** to save the file locally.
 //!  
+//! ## Manual Class Counts
+//!
+//! Save the tally sheet as CSV, with a header row that includes the start of each interval
+//! ("Time", "Start Time", "Interval Start", or "Date/Time"), and a column for each of FHWA classes
+//! 1 through 13 (e.g. "Class 1"). Optionally, it can also include "Date", "Unclassified",
+//! "Direction" (required for counts in more than one direction), and "Total" columns. See
+//! [`TimeBinnedVehicleClassCount::extract`][traffic_counts::non_perm::TimeBinnedVehicleClassCount::extract]
+//! for details.
+//!
+//! ## Exporting Turning Movement Counts
+//!
+//!   - export the count's 15-minute data as CSV, with one column per approach and movement
+//!     (e.g. "Northbound Left" or "Northbound" above "Left"), any approach total columns, and the
+//!     time or date and time of each interval in the first column. If the count is broken down by
+//!     class of vehicle, the class names go in a row above the approaches.
+//!   - the lane directions ("cldir1", etc.) need not be set for these counts.
+//!
 //! ## Exporting from Eco-Counter
 //!
 //! For both bicycle and pedestrian counts, in [Eco-Vizio](https://www.eco-visio.net):
@@ -116,10 +232,11 @@
 //!     columns. So long as the "onewaybike" field in the TC_HEADER table is false, the total will
 //!     just be ignored and the in or out direction used.
 
-use std::env;
+use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::thread;
 use std::time;
 
@@ -130,21 +247,25 @@ use simplelog::{
 };
 
 use traffic_counts::{
-    db::{self, crud::NonPermCrud},
+    config::NonPermConfig,
+    db::{
+        self,
+        store::CountStore,
+        unit_of_work::{import_count, merge_count, Merged},
+    },
     non_perm::{
-        check_data::check,
-        create_binned_bicycle_vol_count, create_speed_and_class_count,
-        extract_from_file::{Bicycles, InputCount},
-        log_msg, Directions, FifteenMinuteBicycle, FifteenMinutePedestrian, FifteenMinuteVehicle,
-        HourlyAvgSpeed, HourlyVehicle, IndividualBicycle, IndividualVehicle,
-        TimeBinnedSpeedRangeCount, TimeBinnedVehicleClassCount, TimeInterval,
+        check_data::{check, check_parsed, CheckRegistry},
+        class_scheme::ClassMapping,
+        extract_from_file::{write_rejected_rows, RejectedRow},
+        log_msg,
+        parser::{CountInfo, ExtractOptions, ParsedCount, ParserRegistry},
     },
     CountError, FileNameProblem,
 };
 
 const LOG: &str = "import.log";
-const REVIEW_DIR: &str = "for_review"; // Directory to place files to review.
-const TIME_BETWEEN_LOOPS: u64 = 20;
+const MERGE: &str = "merge"; // Filename option to merge a file into its count's existing data.
+const APPROVED: &str = "approved"; // Filename option to import a held file.
 
 enum CleanMethod {
     Delete,
@@ -152,16 +273,14 @@ enum CleanMethod {
 }
 
 fn main() {
-    // Load file containing environment variables, panic if it doesn't exist.
-    dotenvy::dotenv().expect("Unable to load .env file.");
-
-    // Get env var for path where CSVs will be, panic if it doesn't exist.
-    let data_dir =
-        env::var("NON_PERM_DATA_DIR").expect("Unable to load data directory path from .env file.");
-
-    // Get env var for path where log will be, panic if it doesn't exist.
-    let log_dir =
-        env::var("NON_PERM_LOG_DIR").expect("Unable to load log directory path from .env file.");
+    // Load the configuration, reporting every problem with it and exiting if it's invalid.
+    let config = match NonPermConfig::load() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
 
     // Set up logging, panic if it fails.
     let import_config = ConfigBuilder::new().set_time_format_rfc3339().build();
@@ -178,38 +297,26 @@ fn main() {
             OpenOptions::new()
                 .append(true)
                 .create(true)
-                .open(format!("{log_dir}/{LOG}"))
+                .open(config.log_dir.join(LOG))
                 .expect("Could not open log file."),
         ),
     ]);
 
-    // The database env vars aren't needed for a while, but if they aren't available, return
-    // early before doing any work.
-    let username = match env::var("NON_PERM_DB_USERNAME") {
-        Ok(v) => v,
-        Err(e) => {
-            import_log.log(
-                &Record::builder()
-                    .args(format_args!("Unable to load username from .env file: {e}"))
-                    .level(Level::Error)
-                    .build(),
-            );
-            return;
-        }
-    };
-    let password = match env::var("NON_PERM_DB_PASSWORD") {
-        Ok(v) => v,
-        Err(e) => {
-            import_log.log(
-                &Record::builder()
-                    .args(format_args!("Unable to load password from .env file: {e}"))
-                    .level(Level::Error)
-                    .build(),
-            );
-            return;
-        }
+    let review_dir = &config.data_dir.join(&config.review_dir);
+    let staging_dir = &config.data_dir.join(&config.staging_dir);
+    let batch_size = config.batch_size;
+    let class_mappings = config
+        .class_mappings
+        .iter()
+        .map(|mapping| (mapping.name().to_string(), mapping.clone()))
+        .collect::<HashMap<_, _>>();
+    let mut options = ExtractOptions {
+        policy: config.policy,
+        speed_bins: config.speed_bins.clone(),
+        headway: config.headway,
+        classes: None,
     };
-    let pool = match db::create_pool(username, password, 5) {
+    let pool = match db::create_pool(&config.db, 5) {
         Ok(v) => v,
         Err(e) => {
             import_log.log(
@@ -234,6 +341,11 @@ fn main() {
         }
     };
 
+    // The parsers for each kind of file that can be imported.
+    let registry = ParserRegistry::default();
+    // The data checks to apply to each kind of count.
+    let check_registry = CheckRegistry::default();
+
     loop {
         // Check the connection and try to get another if not "normal".
         match conn.status() {
@@ -276,33 +388,63 @@ fn main() {
         let _ = OpenOptions::new()
             .append(true)
             .create(true)
-            .open(format!("{log_dir}/{LOG}"))
+            .open(config.log_dir.join(LOG))
             .expect("Could not open log file.");
 
         // Get all the paths of the files that need to be processed.
         let mut paths = vec![];
-        let paths = match collect_paths(data_dir.clone().into(), &mut paths) {
-            Ok(v) => v,
-            Err(e) => {
-                import_log.log(
-                    &Record::builder()
-                        .args(format_args!("{e}"))
-                        .level(Level::Error)
-                        .build(),
-                );
-                return;
-            }
-        };
+        let paths =
+            match collect_paths(config.data_dir.clone(), review_dir, staging_dir, &mut paths) {
+                Ok(v) => v,
+                Err(e) => {
+                    import_log.log(
+                        &Record::builder()
+                            .args(format_args!("{e}"))
+                            .level(Level::Error)
+                            .build(),
+                    );
+                    return;
+                }
+            };
 
         // Iterate through all paths, extracting the data from the files, transforming it into the
         // desired shape, and inserting it into the database.
-        // Exactly how the data is processed depends on what `InputCount` it is.
+        // Exactly how the data is processed depends on the parser for the file.
         'paths_loop: for path in paths {
             // Don't try to process the log files.
             if path.extension().is_some_and(|x| x == "log") {
                 continue;
             }
-            let count_type = match InputCount::from_parent_dir(path) {
+            // The rows rejected from an approved file when it was held go along with it.
+            if path.starts_with(staging_dir) && has_option(path, APPROVED) {
+                move_held_rejected(path, review_dir, &import_log);
+            }
+            // Use the class mapping named in the filename, if any. It's needed to detect the
+            // file's format as well as to extract it.
+            options.classes = match get_class_mapping(path) {
+                None => None,
+                Some(name) => match class_mappings
+                    .get(name)
+                    .cloned()
+                    .or_else(|| ClassMapping::builtin(name))
+                {
+                    Some(v) => Some(v),
+                    None => {
+                        import_log.log(
+                            &Record::builder()
+                                .args(format_args!(
+                                    "{path:?} not processed: no class mapping named '{name}'"
+                                ))
+                                .level(Level::Error)
+                                .build(),
+                        );
+                        cleanup(CleanMethod::Move, path, review_dir, &import_log);
+                        continue;
+                    }
+                },
+            };
+
+            let parser = match registry.parser_for(path, &options) {
                 Ok(v) => v,
                 Err(e) => {
                     import_log.log(
@@ -311,7 +453,7 @@ fn main() {
                             .level(Level::Error)
                             .build(),
                     );
-                    cleanup(CleanMethod::Move, path, &import_log);
+                    cleanup(CleanMethod::Move, path, review_dir, &import_log);
                     continue;
                 }
             };
@@ -322,8 +464,11 @@ fn main() {
              extracted from one file. Here we get at least the first and optionally the second, to
              then process simultaneously.
             */
-            let (recordnum1, recordnum2) = match get_recordnum(path) {
-                Ok(v) => v,
+            let recordnums = match get_recordnum(path) {
+                Ok((recordnum1, recordnum2)) => [Some(recordnum1), recordnum2]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>(),
                 Err(e) => {
                     import_log.log(
                         &Record::builder()
@@ -331,57 +476,41 @@ fn main() {
                             .level(Level::Error)
                             .build(),
                     );
-                    cleanup(CleanMethod::Move, path, &import_log);
+                    cleanup(CleanMethod::Move, path, review_dir, &import_log);
                     continue;
                 }
             };
 
-            // Err if recordnum2 is None and this is supposed to be both vehicle and bicycle data.
-            if count_type == InputCount::IndividualVehicleAndIndividualBicycle
-                && recordnum2.is_none()
-            {
+            // Err if the number of recordnums doesn't match what the file contains, e.g. if this
+            // is supposed to be both vehicle and bicycle data but only one recordnum was given.
+            if recordnums.len() != parser.num_counts() {
                 import_log.log(
                     &Record::builder()
                         .args(format_args!(
-                            "{path:?} not processed: Only one recordnum found."
+                            "{path:?} not processed: {} recordnum(s) found, {} expected.",
+                            recordnums.len(),
+                            parser.num_counts()
                         ))
                         .level(Level::Error)
                         .build(),
                 );
-                cleanup(CleanMethod::Move, path, &import_log);
+                cleanup(CleanMethod::Move, path, review_dir, &import_log);
                 continue;
             }
 
-            // Check that the count(s) are already included in meta table in database - abort otherwise.
-            if conn
-                .query_row_as::<Option<String>>(
-                    "select recordnum from tc_header where recordnum = :1",
-                    &[&recordnum1],
-                )
-                .is_err()
-            {
-                log_msg(
-                    recordnum1,
-                    &import_log,
-                    Level::Error,
-                    &format!(
-                        "{path:?} not processed: recordnum probably not found in TC_HEADER table)"
-                    ),
-                    &conn,
-                );
-                cleanup(CleanMethod::Move, path, &import_log);
-                continue;
-            }
-            if let Some(v) = recordnum2 {
+            // Check that the count(s) are already included in meta table in database - abort
+            // otherwise - and get their metadata and lane directions.
+            let mut counts = vec![];
+            for &recordnum in &recordnums {
                 if conn
                     .query_row_as::<Option<String>>(
                         "select recordnum from tc_header where recordnum = :1",
-                        &[&v],
+                        &[&recordnum],
                     )
                     .is_err()
                 {
                     log_msg(
-                        v,
+                        recordnum,
                         &import_log,
                         Level::Error,
                         &format!(
@@ -389,897 +518,326 @@ fn main() {
                         ),
                         &conn,
                     );
-                    cleanup(CleanMethod::Move, path, &import_log);
-                    continue;
+                    cleanup(CleanMethod::Move, path, review_dir, &import_log);
+                    continue 'paths_loop;
+                }
+                match CountInfo::from_store(recordnum, &conn) {
+                    Ok(v) => counts.push(v),
+                    Err(e) => {
+                        log_msg(
+                            recordnum,
+                            &import_log,
+                            Level::Error,
+                            &format!("{path:?} not processed: {e}"),
+                            &conn,
+                        );
+                        cleanup(CleanMethod::Move, path, review_dir, &import_log);
+                        continue 'paths_loop;
+                    }
                 }
             }
 
-            // Get all the lane directions of the count(s).
-            let directions1 = match Directions::from_db(recordnum1, &conn) {
+            // Process the file according to its parser.
+            for &recordnum in &recordnums {
+                log_msg(
+                    recordnum,
+                    &import_log,
+                    Level::Info,
+                    &format!(
+                        "Extracting data for count {recordnum} from {path:?}, a {} count",
+                        parser.name()
+                    ),
+                    &conn,
+                );
+            }
+
+            let mut parsed_counts = match parser.extract(path, &counts, &options) {
                 Ok(v) => v,
                 Err(e) => {
+                    if let CountError::TooManyRejectedRows { rejected, .. } = &e {
+                        write_rejected(path, rejected, review_dir, &import_log);
+                    }
                     log_msg(
-                        recordnum1,
+                        recordnums[0],
                         &import_log,
                         Level::Error,
-                        &format!("{path:?} not processed: {e}"),
+                        &format!("Not processed: {e}"),
                         &conn,
                     );
-                    cleanup(CleanMethod::Move, path, &import_log);
+                    cleanup(CleanMethod::Move, path, review_dir, &import_log);
                     continue;
                 }
             };
 
-            // Process the file according to InputCount.
-            log_msg(
-                recordnum1,
-                &import_log,
-                Level::Info,
-                &format!(
-                    "Extracting data for count {recordnum1} from {path:?}, a {count_type:?} count"
-                ),
-                &conn,
-            );
-            if let Some(v) = recordnum2 {
-                log_msg(
-                    v,
-                    &import_log,
-                    Level::Info,
-                    &format!("Extracting data for count {v} from {path:?}, a {count_type:?} count"),
-                    &conn,
-                );
-            }
+            // Initiate variable that determines whether the file will be moved or deleted
+            // following the remainder of the program.
+            let mut move_file = false;
 
-            match count_type {
-                InputCount::IndividualVehicle
-                | InputCount::IndividualVehicleAndIndividualBicycle => {
-                    // There will always be vehicle data; do it first.
-                    // Start by setting variable for whether or not bicycles are included for the
-                    // vehicle extraction part of it.
-                    let bicycles = if count_type == InputCount::IndividualVehicle {
-                        Bicycles::Without
-                    } else {
-                        Bicycles::With
-                    };
+            // Any rows that were rejected are kept, along with the file, wherever it ends up.
+            let mut rejected = parsed_counts
+                .iter()
+                .flat_map(|parsed| parsed.rejected.iter().cloned())
+                .collect::<Vec<_>>();
+            rejected.sort_unstable_by_key(|row| row.location.line);
+            rejected.dedup_by_key(|row| row.location.line);
+
+            // Aggregate volume data by hour, to insert into another table, and make sure all
+            // tables of volumes agree before inserting any of them.
+            for parsed in &mut parsed_counts {
+                if let Err(e) = parsed.add_hourly() {
+                    log_msg(
+                        parsed.recordnum,
+                        &import_log,
+                        Level::Error,
+                        &format!("Not processed: {e}"),
+                        &conn,
+                    );
+                    write_rejected(path, &rejected, review_dir, &import_log);
+                    cleanup(CleanMethod::Move, path, review_dir, &import_log);
+                    continue 'paths_loop;
+                }
+            }
 
-                    // Extract data from CSV/text file.
-                    let individual_vehicles = match IndividualVehicle::extract(path, bicycles) {
+            // Check the data before anything is stored. If any of it gets a warning, hold the
+            // file in the staging directory until it's approved, if configured to, otherwise
+            // import it and keep the file for review.
+            let mut warned = false;
+            for parsed in &parsed_counts {
+                match check_parsed(
+                    parsed,
+                    &conn,
+                    &config.log_dir,
+                    &check_registry,
+                    &config.checks,
+                ) {
+                    Ok(warnings) => warned |= !warnings.is_empty(),
+                    Err(e) => {
+                        log_msg(parsed.recordnum, &import_log, Level::Error, &format!("An error occurred while checking data: {e}; warnings likely to be incomplete or incorrect."), &conn);
+                        warned = true;
+                    }
+                }
+            }
+            if warned {
+                if config.hold_warnings && !has_option(path, APPROVED) {
+                    let staged = match stage(path, staging_dir) {
                         Ok(v) => v,
                         Err(e) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!("Not processed: {e}"),
-                                &conn,
-                            );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    };
-
-                    // Create two counts from this: 15-minute speed count and 15-minute class count
-                    let (speed_range_count, vehicle_class_count) =
-                        match create_speed_and_class_count(
-                            TimeInterval::FifteenMin,
-                            recordnum1,
-                            &directions1,
-                            individual_vehicles.clone(),
-                        ) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!("Error creating speed/class count: {e:?}; further processing has been abandoned"),
-                                &conn,
-                            );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue 'paths_loop;
-                            }
-                        };
-
-                    // Delete existing records from db.
-                    TimeBinnedVehicleClassCount::delete(&conn, recordnum1).unwrap();
-                    TimeBinnedSpeedRangeCount::delete(&conn, recordnum1).unwrap();
-                    HourlyVehicle::delete(&conn, recordnum1).unwrap();
-                    HourlyAvgSpeed::delete(&conn, recordnum1).unwrap();
-
-                    // Create prepared statements and use them to insert counts.
-                    let mut prepared = TimeBinnedVehicleClassCount::prepare_insert(&conn).unwrap();
-                    for count in vehicle_class_count {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"),
-                                &conn,
+                            import_log.log(
+                                &Record::builder()
+                                    .args(format_args!("Unable to stage file {path:?} {e}"))
+                                    .level(Level::Error)
+                                    .build(),
                             );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <TimeBinnedVehicleClassCount as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(
-                                recordnum1, &import_log, Level::Info, &format!("Successfully committed class data insert to database ({table} table)"), &conn);
-                        }
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error committing class data insert to database ({table} table): {e}"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-
-                    let mut prepared = TimeBinnedSpeedRangeCount::prepare_insert(&conn).unwrap();
-                    for count in speed_range_count {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
+                            write_rejected(path, &rejected, review_dir, &import_log);
+                            cleanup(CleanMethod::Move, path, review_dir, &import_log);
                             continue 'paths_loop;
                         }
-                    }
-                    let table = <TimeBinnedSpeedRangeCount as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(recordnum1, &import_log, Level::Info, &format!("Successfully committed speed range data insert to database ({table} table)"), &conn);
-                        }
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error committing speed range data insert to database ({table} table): {e}"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-
-                    // Aggregate volume data by hour.
-                    let volcount = match HourlyVehicle::from_db(
-                        recordnum1,
-                        "tc_clacount_new",
-                        "total",
-                        &conn,
-                    ) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error getting data from tc_clacount table for {recordnum1}: {e}"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
                     };
-
-                    // Create prepared statements and use them to insert counts.
-                    let mut prepared = HourlyVehicle::prepare_insert(&conn).unwrap();
-                    for count in volcount {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <HourlyVehicle as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(recordnum1, &import_log, Level::Info, &format!("Successfully committed hourly volume data into database ({table} table)"), &conn);
-                        }
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error committing hourly volume data into database ({table} table): {e}"), &conn);
-
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-
-                    // Average speed data by hour.
-                    let avg_speed =
-                        HourlyAvgSpeed::create(recordnum1, directions1, individual_vehicles);
-
-                    // Create prepared statements and use them to insert counts.
-                    let mut prepared = HourlyAvgSpeed::prepare_insert(&conn).unwrap();
-                    for count in avg_speed {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <HourlyAvgSpeed as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(recordnum1, &import_log, Level::Info, &format!("Successfully committed hourly speed averages into database ({table} table)"), &conn);
-                        }
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error committing hourly speed averages into database ({table} table): {e}"), &conn);
-
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
+                    // Keep the rejected rows next to the held file.
+                    let held_dir = staged.parent().unwrap_or(staging_dir);
+                    keep_rejected(
+                        path,
+                        &parsed_counts,
+                        &rejected,
+                        held_dir,
+                        &conn,
+                        &import_log,
+                    );
+                    for parsed in &parsed_counts {
+                        log_msg(
+                            parsed.recordnum,
+                            &import_log,
+                            Level::Warn,
+                            &format!(
+                                "Not imported: held as {staged:?} until approved; to approve, rename it {:?}",
+                                approved_name(&staged).unwrap_or_default()
+                            ),
+                            &conn,
+                        );
                     }
+                    continue 'paths_loop;
+                }
+                move_file = true;
+            }
+            if !rejected.is_empty() {
+                keep_rejected(
+                    path,
+                    &parsed_counts,
+                    &rejected,
+                    review_dir,
+                    &conn,
+                    &import_log,
+                );
+                move_file = true;
+            }
 
-                    // Process bicycle data, if any.
-                    if count_type == InputCount::IndividualVehicleAndIndividualBicycle {
-                        // For this inputcount, recordnum2 *has* to be Some, so just unwrap it.
-                        let recordnum2 = recordnum2.unwrap();
-
-                        // Ensure that the count type is correct in the database.
-                        // (It's previously been mostly incorrect.)
-                        match conn.query_row_as::<String>(
-                            "select type from tc_header where recordnum = :1",
-                            &[&recordnum2],
-                        ) {
-                            Ok(v) => {
-                                if v != "Bicycle 5" {
-                                    log_msg(
-                                        recordnum2, &import_log, Level::Error, &format!("{recordnum2} not processed: type in database is incorrect, should be 'Bicycle 5'"), &conn,
-                                    );
-                                    cleanup(CleanMethod::Move, path, &import_log);
-                                    continue;
-                                }
-                            }
-                            Err(e) => {
-                                log_msg(
-                                        recordnum2, &import_log, Level::Error, &format!("{recordnum2} not processed: error checking type in database: {e}"), &conn,
-                                    );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        }
-
-                        // Extract data from CSV/text file.
-                        let counts = match IndividualBicycle::extract(path) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log_msg(
-                                    recordnum2,
-                                    &import_log,
-                                    Level::Error,
-                                    &format!("Not processed: {e}"),
-                                    &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        };
-                        // Get all the lane directions of the count(s).
-                        let directions2 = match Directions::from_db(recordnum2, &conn) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log_msg(
-                                    recordnum2,
-                                    &import_log,
-                                    Level::Error,
-                                    &format!("{path:?} not processed: {e}"),
-                                    &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        };
-
-                        // Create aggregated 15-minute bicycle count from this.
-                        let fifteen_min_volcount = create_binned_bicycle_vol_count(
-                            TimeInterval::FifteenMin,
-                            recordnum2,
-                            &directions2,
-                            counts,
+            let merge = has_option(path, MERGE);
+            for parsed in &mut parsed_counts {
+                let recordnum = parsed.recordnum;
+
+                // Replace (or merge into) existing records in db with those from the file, update
+                // metadata, and update the intermediate table used for calculating AADV and
+                // setdate, all in one transaction.
+                let imported = if merge {
+                    merge_count(&conn, parsed, batch_size)
+                } else {
+                    import_count(&conn, parsed, batch_size).map(|_| Merged::default())
+                };
+                let merged = match imported {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log_msg(
+                            recordnum,
+                            &import_log,
+                            Level::Error,
+                            &format!("{e}; further processing has been abandoned"),
+                            &conn,
                         );
-
-                        // Delete existing records from db.
-                        FifteenMinuteBicycle::delete(&conn, recordnum2).unwrap();
-
-                        // Create prepared statements and use them to insert counts.
-                        let mut prepared = FifteenMinuteBicycle::prepare_insert(&conn).unwrap();
-                        for count in fifteen_min_volcount {
-                            if let Err(e) = count.insert(&mut prepared) {
-                                log_msg(recordnum2,  &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue 'paths_loop;
-                            }
-                        }
-                        let table = <FifteenMinuteBicycle as NonPermCrud>::COUNT_TABLE;
-
-                        match conn.commit() {
-                            Ok(()) => {
-                                log_msg(
-                                    recordnum2,
-                                    &import_log,
-                                    Level::Info,
-                                    &format!(
-                                    "Successfully committed data insert to database ({table} table)"
-                                ),
-                                    &conn,
-                                );
-                            }
-                            Err(e) => {
-                                log_msg(
-                                    recordnum2,
-                                    &import_log,
-                                    Level::Error,
-                                    &format!(
-                                        "Error committing data insert to database ({table} table): {e}"
-                                    ),
-                                    &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        }
+                        cleanup(CleanMethod::Move, path, review_dir, &import_log);
+                        continue 'paths_loop;
+                    }
+                };
+                let action = if merge { "merge" } else { "insert" };
+                for records in &parsed.records {
+                    let table = records.table();
+                    if merged.unmerged.contains(&table) {
+                        continue;
                     }
+                    log_msg(
+                        recordnum,
+                        &import_log,
+                        Level::Info,
+                        &format!(
+                            "Successfully committed data {action} to database ({table} table)"
+                        ),
+                        &conn,
+                    );
+                }
+                // Existing data that was replaced by the file's may be a mistake, so keep the
+                // file for review.
+                for overlap in merged.overlaps {
+                    log_msg(
+                        recordnum,
+                        &import_log,
+                        Level::Warn,
+                        &format!(
+                            "{} existing record(s) of {} table from {} to {} replaced by merge",
+                            overlap.replaced, overlap.table, overlap.span.start, overlap.span.end
+                        ),
+                        &conn,
+                    );
+                    move_file = true;
+                }
+                // As are the records that couldn't be merged, which only this file had.
+                for table in merged.unmerged {
+                    log_msg(
+                        recordnum,
+                        &import_log,
+                        Level::Warn,
+                        &format!(
+                            "{table} table not merged, as its records are of every vehicle of the count at once; those stored are unchanged"
+                        ),
+                        &conn,
+                    );
+                    move_file = true;
+                }
+                for msg in [
+                    "Metadata updated (tc_header table)",
+                    "Intermediate table TC_COUNTDATE updated",
+                    "Field SETDATE updated",
+                ] {
+                    log_msg(recordnum, &import_log, Level::Info, msg, &conn);
                 }
-                InputCount::FifteenMinuteVehicle => {
-                    // Extract data from CSV/text file.
-                    let fifteen_min_volcount =
-                        match FifteenMinuteVehicle::extract(path, recordnum1, &directions1) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log_msg(
-                                    recordnum1,
-                                    &import_log,
-                                    Level::Error,
-                                    &format!("Not processed: {e}"),
-                                    &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        };
 
-                    // As they are already binned by 15-minute period, these need no further
-                    // processing; just insert into database.
-                    FifteenMinuteVehicle::delete(&conn, recordnum1).unwrap();
-                    let mut prepared = FifteenMinuteVehicle::prepare_insert(&conn).unwrap();
-                    for count in fifteen_min_volcount {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1,  &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <FifteenMinuteVehicle as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
+                // Calculate and insert the annual average daily volume, except for counts
+                // (bicycle) which first require an additional field in the database to be set
+                // after the import.
+                if parsed.calc_aadv {
+                    match conn.calc_aadv(recordnum) {
                         Ok(()) => {
                             log_msg(
-                                recordnum1,
+                                recordnum,
                                 &import_log,
                                 Level::Info,
-                                &format!(
-                                "Successfully committed data insert to database ({table} table)"
-                            ),
+                                "AADV calculated and inserted",
                                 &conn,
                             );
                         }
                         Err(e) => {
                             log_msg(
-                                recordnum1,
+                                recordnum,
                                 &import_log,
                                 Level::Error,
-                                &format!(
-                                    "Error committing data insert to database ({table} table): {e}"
-                                ),
+                                &format!("Failed to calculate/insert AADV: {e}"),
                                 &conn,
                             );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
+                            move_file = true;
                         }
                     }
+                }
 
-                    // Delete existing records from db.
-                    HourlyVehicle::delete(&conn, recordnum1).unwrap();
-
-                    // Aggregate into hourly data, to insert into another table.
-                    let volcount = match HourlyVehicle::from_db(
-                        recordnum1,
-                        "tc_15minvolcount_new",
-                        "volume",
-                        &conn,
-                    ) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error getting data from tc_15minvolcount_new table for {recordnum1}: {e}"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    };
-
-                    // Create prepared statements and use them to insert counts.
-                    let mut prepared = HourlyVehicle::prepare_insert(&conn).unwrap();
-                    for count in volcount {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <HourlyVehicle as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(recordnum1, &import_log, Level::Info, &format!("Successfully committed hourly volume data into database ({table} table)"), &conn);
-                        }
-                        Err(e) => {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error committing class-hourly volume data into database ({table} table): {e}"), &conn);
-
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-                }
-                InputCount::IndividualBicycle => {
-                    // Ensure that the count type is correct in the database.
-                    // (It's previously been mostly incorrect.)
-                    match conn.query_row_as::<String>(
-                        "select type from tc_header where recordnum = :1",
-                        &[&recordnum1],
-                    ) {
-                        Ok(v) => {
-                            if v != "Bicycle 5" {
-                                log_msg(
-                                    recordnum1, &import_log, Level::Error, &format!("{recordnum1} not processed: type in database is incorrect, should be 'Bicycle 5'"), &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        }
-                        Err(e) => {
-                            log_msg(
-                                    recordnum1, &import_log, Level::Error, &format!("{recordnum1} not processed: error checking type in database: {e}"), &conn,
-                                );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-
-                    // Extract data from CSV/text file.
-                    let counts = match IndividualBicycle::extract(path) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!("Not processed: {e}"),
-                                &conn,
-                            );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    };
-
-                    // Create aggregated 15-minute bicycle count from this.
-                    let fifteen_min_volcount = create_binned_bicycle_vol_count(
-                        TimeInterval::FifteenMin,
-                        recordnum1,
-                        &directions1,
-                        counts,
-                    );
-
-                    // Delete existing records from db.
-                    FifteenMinuteBicycle::delete(&conn, recordnum1).unwrap();
-
-                    // Create prepared statements and use them to insert counts.
-                    let mut prepared = FifteenMinuteBicycle::prepare_insert(&conn).unwrap();
-                    for count in fifteen_min_volcount {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1,  &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <FifteenMinuteBicycle as NonPermCrud>::COUNT_TABLE;
-
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Info,
-                                &format!(
-                                "Successfully committed data insert to database ({table} table)"
-                            ),
-                                &conn,
-                            );
-                        }
-                        Err(e) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!(
-                                    "Error committing data insert to database ({table} table): {e}"
-                                ),
-                                &conn,
-                            );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-                }
-                InputCount::FifteenMinuteBicycle => {
-                    // Extract data from CSV/text file.
-                    let fifteen_min_volcount =
-                        match FifteenMinuteBicycle::extract(path, recordnum1, &directions1) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log_msg(
-                                    recordnum1,
-                                    &import_log,
-                                    Level::Error,
-                                    &format!("Not processed: {e}"),
-                                    &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        };
-
-                    // As they are already binned by 15-minute period, these need no further
-                    // processing; just insert into database.
-                    FifteenMinuteBicycle::delete(&conn, recordnum1).unwrap();
-                    let mut prepared = FifteenMinuteBicycle::prepare_insert(&conn).unwrap();
-                    for count in fifteen_min_volcount {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(recordnum1, &import_log, Level::Error, &format!("Error inserting count {count:?}: {e}; further processing has been abandoned"), &conn);
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <FifteenMinuteBicycle as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Info,
-                                &format!(
-                                "Successfully committed data insert to database ({table} table)"
-                            ),
-                                &conn,
-                            );
-                        }
-                        Err(e) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!(
-                                    "Error committing data insert to database ({table} table): {e}"
-                                ),
-                                &conn,
-                            );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-                }
-                InputCount::FifteenMinutePedestrian => {
-                    // Extract data from CSV/text file.
-                    let fifteen_min_volcount =
-                        match FifteenMinutePedestrian::extract(path, recordnum1, &directions1) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log_msg(
-                                    recordnum1,
-                                    &import_log,
-                                    Level::Error,
-                                    &format!("Not processed: {e}"),
-                                    &conn,
-                                );
-                                cleanup(CleanMethod::Move, path, &import_log);
-                                continue;
-                            }
-                        };
-
-                    // As they are already binned by 15-minute period, these need no further
-                    // processing; just insert into database.
-                    FifteenMinutePedestrian::delete(&conn, recordnum1).unwrap();
-                    let mut prepared = FifteenMinutePedestrian::prepare_insert(&conn).unwrap();
-                    for count in fifteen_min_volcount {
-                        if let Err(e) = count.insert(&mut prepared) {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!("Error inserting count {count:?}: {e}"),
-                                &conn,
-                            );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue 'paths_loop;
-                        }
-                    }
-                    let table = <FifteenMinutePedestrian as NonPermCrud>::COUNT_TABLE;
-                    match conn.commit() {
-                        Ok(()) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Info,
-                                &format!(
-                                "Successfully committed data insert to database ({table} table)"
-                            ),
-                                &conn,
-                            );
-                        }
-                        Err(e) => {
-                            log_msg(
-                                recordnum1,
-                                &import_log,
-                                Level::Error,
-                                &format!(
-                                    "Error committing data insert to database ({table} table): {e}"
-                                ),
-                                &conn,
-                            );
-                            cleanup(CleanMethod::Move, path, &import_log);
-                            continue;
-                        }
-                    }
-                }
-            }
-
-            // Initiate variable that determines whether the file will be moved or deleted
-            // following the remainder of the program.
-            let mut move_file = false;
-
-            // Update metadata table in db.
-            match conn.execute(
-                "update tc_header SET
-                importdatadate = (select current_date from dual),
-                status = :1
-                where recordnum = :2",
-                &[&"imported", &recordnum1],
-            ) {
-                Ok(_) => match conn.commit() {
-                    Ok(()) => log_msg(
-                        recordnum1,
-                        &import_log,
-                        Level::Info,
-                        "Metadata updated (tc_header table)",
-                        &conn,
-                    ),
-                    Err(e) => {
-                        log_msg(
-                            recordnum1,
-                            &import_log,
-                            Level::Error,
-                            &format!("Error committing metadata (tc_header table) update: {e}"),
-                            &conn,
-                        );
-                        move_file = true;
-                    }
-                },
-                Err(e) => {
+                // The data was checked before being stored, but a merged count's data is only
+                // complete once it has been, so check it again and log any issues for review.
+                if merge {
                     log_msg(
-                        recordnum1,
-                        &import_log,
-                        Level::Error,
-                        &format!("Error updating metadata (tc_header table): {e}"),
-                        &conn,
-                    );
-                    move_file = true;
-                }
-            }
-            if let Some(v) = recordnum2 {
-                match conn.execute(
-                    "update tc_header SET
-                    importdatadate = (select current_date from dual),
-                    status = :1
-                    where recordnum = :2",
-                    &[&"imported", &v],
-                ) {
-                    Ok(_) => match conn.commit() {
-                        Ok(()) => log_msg(
-                            v,
-                            &import_log,
-                            Level::Info,
-                            "Metadata updated (tc_header table)",
-                            &conn,
-                        ),
-                        Err(e) => {
-                            log_msg(
-                                v,
-                                &import_log,
-                                Level::Error,
-                                &format!("Error committing metadata (tc_header table) update: {e}"),
-                                &conn,
-                            );
-                            move_file = true;
-                        }
-                    },
-                    Err(e) => {
-                        log_msg(
-                            v,
-                            &import_log,
-                            Level::Error,
-                            &format!("Error updating metadata (tc_header table): {e}"),
-                            &conn,
-                        );
-                        move_file = true;
-                    }
-                }
-            }
-
-            // Update the intermediate table used for calculating AADV in all cases.
-            match db::update_intermediate_aadv(recordnum1, &conn) {
-                Ok(_) => {
-                    log_msg(
-                        recordnum1,
+                        recordnum,
                         &import_log,
                         Level::Info,
-                        "Intermediate table TC_COUNTDATE updated",
+                        &format!("Checking data ({recordnum})"),
                         &conn,
                     );
-                }
-                Err(e) => {
-                    log_msg(
-                        recordnum1,
-                        &import_log,
-                        Level::Error,
-                        &format!("Failed to update intermediate table TC_COUNTDATE: {e}"),
-                        &conn,
-                    );
-                    move_file = true;
-                }
-            }
-            if let Some(v) = recordnum2 {
-                match db::update_intermediate_aadv(v, &conn) {
-                    Ok(_) => {
-                        log_msg(
-                            v,
-                            &import_log,
-                            Level::Info,
-                            "Intermediate table TC_COUNTDATE updated",
-                            &conn,
-                        );
-                    }
-                    Err(e) => {
-                        log_msg(
-                            v,
-                            &import_log,
-                            Level::Error,
-                            &format!("Failed to update intermediate table TC_COUNTDATE: {e}"),
-                            &conn,
-                        );
-                        move_file = true;
-                    }
-                }
-            }
 
-            // Update setdate.
-            match db::update_setdate(recordnum1, &conn) {
-                Ok(_) => {
-                    log_msg(
-                        recordnum1,
-                        &import_log,
-                        Level::Info,
-                        "Field SETDATE updated",
-                        &conn,
-                    );
-                }
-                Err(e) => {
-                    log_msg(
-                        recordnum1,
-                        &import_log,
-                        Level::Error,
-                        &format!("Failed to update field SETDATE: {e}"),
+                    match check(
+                        recordnum,
                         &conn,
-                    );
-                    move_file = true;
-                }
-            }
-            if let Some(v) = recordnum2 {
-                match db::update_setdate(v, &conn) {
-                    Ok(_) => {
-                        log_msg(v, &import_log, Level::Info, "Field SETDATE updated", &conn);
-                    }
-                    Err(e) => {
-                        log_msg(
-                            v,
-                            &import_log,
-                            Level::Error,
-                            &format!("Failed to update field SETDATE: {e}"),
-                            &conn,
-                        );
-                        move_file = true;
-                    }
-                }
-            }
-
-            // Calculate and insert the annual average daily volume, except for bicycle counts,
-            // which first require an additional field in the database to be set after the import.
-            if count_type != InputCount::FifteenMinuteBicycle {
-                match db::calc_aadv(recordnum1, &conn) {
-                    Ok(()) => {
-                        log_msg(
-                            recordnum1,
-                            &import_log,
-                            Level::Info,
-                            "AADV calculated and inserted",
-                            &conn,
-                        );
-                    }
-                    Err(e) => {
-                        log_msg(
-                            recordnum1,
-                            &import_log,
-                            Level::Error,
-                            &format!("Failed to calculate/insert AADV: {e}"),
-                            &conn,
-                        );
-                        move_file = true;
-                    }
+                        &config.log_dir,
+                        &check_registry,
+                        &config.checks,
+                    ) {
+                        Ok(Level::Warn) => {
+                            move_file = true;
+                        }
+                        Err(e) => {
+                            log_msg(recordnum,  &import_log, Level::Error, &format!("An error occurred while checking data: {e}; warnings likely to be incomplete or incorrect."), &conn);
+                            move_file = true;
+                        }
+                        Ok(_) => (),
+                    };
                 }
             }
 
-            // Check for potential issues with data, after it has been inserted into the database,
-            // and log them for review.
-
-            log_msg(
-                recordnum1,
-                &import_log,
-                Level::Info,
-                &format!("Checking data ({recordnum1})"),
-                &conn,
-            );
-
-            match check(recordnum1, &conn) {
-                Ok(Level::Warn) => {
-                    move_file = true;
-                }
-                Err(e) => {
-                    log_msg(recordnum1,  &import_log, Level::Error, &format!("An error occurred while checking data: {e}; warnings likely to be incomplete or incorrect."), &conn);
-                    move_file = true;
-                }
-                Ok(_) => (),
-            };
-
-            if let Some(v) = recordnum2 {
-                log_msg(
-                    v,
-                    &import_log,
-                    Level::Info,
-                    &format!("Checking data ({v})"),
-                    &conn,
-                );
-                match check(v, &conn) {
-                    Ok(Level::Warn) => {
-                        move_file = true;
-                    }
-                    Err(e) => {
-                        log_msg(recordnum1,  &import_log, Level::Error, &format!("An error occurred while checking data: {e}; warnings likely to be incomplete or incorrect."), &conn);
-                        move_file = true;
-                    }
-                    Ok(_) => (),
-                };
-            }
-
             // Move or delete the file.
             if move_file {
-                cleanup(CleanMethod::Move, path, &import_log);
+                cleanup(CleanMethod::Move, path, review_dir, &import_log);
             } else {
-                cleanup(CleanMethod::Delete, path, &import_log);
+                cleanup(CleanMethod::Delete, path, review_dir, &import_log);
             }
         }
         // Wait to try again
-        thread::sleep(time::Duration::from_secs(TIME_BETWEEN_LOOPS));
+        thread::sleep(config.poll_interval);
     }
 }
 
 /// Collect all the file paths to extract data from.
-fn collect_paths(dir: PathBuf, paths: &mut Vec<PathBuf>) -> io::Result<&mut Vec<PathBuf>> {
+fn collect_paths<'a>(
+    dir: PathBuf,
+    review_dir: &Path,
+    staging_dir: &Path,
+    paths: &'a mut Vec<PathBuf>,
+) -> io::Result<&'a mut Vec<PathBuf>> {
     for entry in fs::read_dir(dir)? {
         let path = entry?.path();
 
         // Ignore files in the directory for reviewing files.
-        if let Some(v) = path.to_str() {
-            if v.contains(REVIEW_DIR) {
-                continue;
-            }
+        if path.starts_with(review_dir) {
+            continue;
         }
 
         if path.is_dir() {
-            collect_paths(path, paths)?;
+            collect_paths(path, review_dir, staging_dir, paths)?;
         } else if let Some(v) = path.file_name() {
-            if v != LOG {
+            // Ignore files held in the staging directory until they're approved.
+            if v != LOG && (!path.starts_with(staging_dir) || has_option(&path, APPROVED)) {
                 paths.push(path)
             }
         }
@@ -1287,7 +845,7 @@ fn collect_paths(dir: PathBuf, paths: &mut Vec<PathBuf>) -> io::Result<&mut Vec<
     Ok(paths)
 }
 
-fn cleanup(method: CleanMethod, path: &PathBuf, log: impl Log) {
+fn cleanup(method: CleanMethod, path: &PathBuf, review_dir: &Path, log: impl Log) {
     match method {
         CleanMethod::Delete => {
             if let Err(e) = fs::remove_file(path) {
@@ -1300,20 +858,10 @@ fn cleanup(method: CleanMethod, path: &PathBuf, log: impl Log) {
             }
         }
         CleanMethod::Move => {
-            // Get current filename and then construct new path.
-            let filename = match path.as_path().file_name() {
+            let new_path = match review_path(path, review_dir, "") {
                 Some(v) => v,
                 None => return,
             };
-            let mut ancestors = path.as_path().ancestors();
-            ancestors.next(); // Remove filename.
-            ancestors.next(); // Remove parent directory.
-
-            let new_path = if let Some(v) = ancestors.next() {
-                v.join(REVIEW_DIR).join(filename)
-            } else {
-                return;
-            };
 
             // Move it.
             if let Err(e) = fs::rename(path, new_path) {
@@ -1328,6 +876,144 @@ fn cleanup(method: CleanMethod, path: &PathBuf, log: impl Log) {
     }
 }
 
+/// Get the path in a directory (usually the review directory) for a file, with a suffix appended
+/// to its name.
+fn review_path(path: &Path, dir: &Path, suffix: &str) -> Option<PathBuf> {
+    // Get current filename and then construct new path.
+    let mut filename = path.file_name()?.to_owned();
+    filename.push(suffix);
+    Some(dir.join(filename))
+}
+
+/// Move a file to the staging directory, to be held until approved, returning its new path.
+///
+/// It's put in a subdirectory with the same name as the one it was uploaded to, so that the same
+/// parser is used for it once approved.
+fn stage(path: &Path, staging_dir: &Path) -> io::Result<PathBuf> {
+    let (Some(filename), Some(upload_dir)) =
+        (path.file_name(), path.parent().and_then(Path::file_name))
+    else {
+        return Err(io::ErrorKind::InvalidInput.into());
+    };
+    let dir = staging_dir.join(upload_dir);
+    fs::create_dir_all(&dir)?;
+    let staged = dir.join(filename);
+    fs::rename(path, &staged)?;
+    Ok(staged)
+}
+
+/// The name to give a held file to approve it.
+fn approved_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => Some(format!("{stem}.{APPROVED}.{ext}")),
+        None => Some(format!("{stem}.{APPROVED}")),
+    }
+}
+
+/// The name a held file had before it was approved.
+fn unapproved_name(path: &Path) -> Option<String> {
+    let filename = path.file_name()?.to_str()?;
+    Some(
+        filename
+            .split('.')
+            .filter(|part| *part != APPROVED)
+            .collect::<Vec<_>>()
+            .join("."),
+    )
+}
+
+/// Move the rows rejected from an approved file when it was held to the review directory.
+///
+/// They're replaced there by the file's rejected rows if any are rejected again on import.
+fn move_held_rejected(path: &Path, review_dir: &Path, log: impl Log) {
+    let (Some(held_name), Some(dir)) = (unapproved_name(path), path.parent()) else {
+        return;
+    };
+    let (Some(held), Some(new_path)) = (
+        review_path(Path::new(&held_name), dir, ".rejected.csv"),
+        review_path(path, review_dir, ".rejected.csv"),
+    ) else {
+        return;
+    };
+    if !held.exists() {
+        return;
+    }
+    if let Err(e) = fs::rename(&held, new_path) {
+        log.log(
+            &Record::builder()
+                .args(format_args!("Unable to move file {held:?} {e}"))
+                .level(Level::Error)
+                .build(),
+        );
+    }
+}
+
+/// Log the rows rejected from a file, and write them to a CSV in `dir`, next to where the file
+/// is kept.
+fn keep_rejected(
+    path: &Path,
+    parsed_counts: &[ParsedCount],
+    rejected: &[RejectedRow],
+    dir: &Path,
+    store: &impl CountStore,
+    log: impl Log + Copy,
+) {
+    if rejected.is_empty() {
+        return;
+    }
+    for parsed in parsed_counts {
+        log_msg(
+            parsed.recordnum,
+            log,
+            Level::Warn,
+            &format!(
+                "{} row(s) of {path:?} rejected; see {:?}",
+                parsed.rejected.len(),
+                review_path(path, dir, ".rejected.csv").unwrap_or_default()
+            ),
+            store,
+        );
+    }
+    write_rejected(path, rejected, dir, log);
+}
+
+/// Write the rows rejected from a file to a CSV in `dir`, named for the file.
+fn write_rejected(path: &Path, rejected: &[RejectedRow], dir: &Path, log: impl Log) {
+    if rejected.is_empty() {
+        return;
+    }
+    let Some(rejected_path) = review_path(path, dir, ".rejected.csv") else {
+        return;
+    };
+    if let Err(e) = write_rejected_rows(&rejected_path, rejected) {
+        log.log(
+            &Record::builder()
+                .args(format_args!(
+                    "Unable to write rejected rows of {path:?} to {rejected_path:?}: {e}"
+                ))
+                .level(Level::Error)
+                .build(),
+        );
+    }
+}
+
+/// Get the name of the class mapping from a Path, if it has one.
+fn get_class_mapping(path: &Path) -> Option<&str> {
+    let (_, options) = path.file_stem()?.to_str()?.split_once('.')?;
+    options
+        .split('.')
+        .find(|option| ![MERGE, APPROVED].contains(option))
+}
+
+/// Whether a Path has an option (e.g. [`MERGE`]) in its name.
+fn has_option(path: &Path, option: &str) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.split_once('.'))
+        .is_some_and(|(_, options)| options.split('.').any(|v| v == option))
+}
+
 /// Get a single or possibly two recordnums from a Path.
 fn get_recordnum(path: &Path) -> Result<(u32, Option<u32>), CountError> {
     let stem = path
@@ -1335,6 +1021,10 @@ fn get_recordnum(path: &Path) -> Result<(u32, Option<u32>), CountError> {
         .ok_or(CountError::BadPath(path.to_owned()))?
         .to_str()
         .ok_or(CountError::BadPath(path.to_owned()))?;
+    // Ignore the name of the class mapping, if any.
+    let stem = stem
+        .split_once('.')
+        .map_or(stem, |(recordnums, _)| recordnums);
 
     // Both vehicle and bicycle data from JAMAR, and thus two separate recordnums.
     if stem.contains('_') {
@@ -1383,4 +1073,63 @@ mod tests {
         let path = Path::new("123_456_789.csv");
         assert!(matches!(get_recordnum(path), Err(CountError::BadPath(_))))
     }
+
+    #[test]
+    fn class_mapping_from_filename() {
+        let path = Path::new("jamar_vehicle/123456_654321.my-counter.csv");
+        assert_eq!(get_class_mapping(path), Some("my-counter"));
+        assert!(matches!(get_recordnum(path), Ok((123456, Some(654321)))));
+        assert_eq!(get_class_mapping(Path::new("123456.csv")), None);
+    }
+
+    #[test]
+    fn merge_from_filename() {
+        let path = Path::new("jamar_vehicle/123456.my-counter.merge.csv");
+        assert!(has_option(path, MERGE));
+        assert_eq!(get_class_mapping(path), Some("my-counter"));
+        assert!(matches!(get_recordnum(path), Ok((123456, None))));
+
+        let path = Path::new("jamar_15minutevehicle/123456.merge.csv");
+        assert!(has_option(path, MERGE));
+        assert_eq!(get_class_mapping(path), None);
+        assert!(!has_option(Path::new("123456.my-counter.csv"), MERGE));
+    }
+
+    #[test]
+    fn approved_from_filename() {
+        let path = Path::new("data/staging/jamar_vehicle/123456.my-counter.csv");
+        let approved = approved_name(path).unwrap();
+        assert_eq!(approved, "123456.my-counter.approved.csv");
+
+        let path = path.with_file_name(approved);
+        assert!(has_option(&path, APPROVED));
+        assert!(!has_option(&path, MERGE));
+        assert_eq!(get_class_mapping(&path), Some("my-counter"));
+        assert!(matches!(get_recordnum(&path), Ok((123456, None))));
+        assert_eq!(
+            unapproved_name(&path).as_deref(),
+            Some("123456.my-counter.csv")
+        );
+    }
+
+    #[test]
+    fn held_rejected_rows_moved_for_review_on_approval() {
+        let dir = std::env::temp_dir().join("held_rejected_rows_moved_for_review_on_approval");
+        let (held_dir, review_dir) = (dir.join("staging/jamar_vehicle"), dir.join("for_review"));
+        fs::create_dir_all(&held_dir).unwrap();
+        fs::create_dir_all(&review_dir).unwrap();
+        fs::write(held_dir.join("123456.csv.rejected.csv"), "rejected").unwrap();
+
+        move_held_rejected(
+            &held_dir.join("123456.approved.csv"),
+            &review_dir,
+            log::logger(),
+        );
+        assert!(!held_dir.join("123456.csv.rejected.csv").exists());
+        assert_eq!(
+            fs::read_to_string(review_dir.join("123456.approved.csv.rejected.csv")).unwrap(),
+            "rejected"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
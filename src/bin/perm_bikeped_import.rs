@@ -5,46 +5,43 @@
 //! after removing any existing records matching the dates for the counts. We currently do this
 //! with monthly data, however a different frequency could be used.
 //!
-//! It runs continuously, checking for the expected CSV file in the expected location (set by an
-//! environment variable - see below). If the CSV is not found, it waits 15 seconds and tries again.
+//! It runs continuously, checking for the expected CSV file (export.csv) in the configured data
+//! directory. If the CSV is not found, it waits 15 seconds (by default) and tries again.
 //! It handles the majority of errors gracefully: logging the error, removing the CSV file, and
 //! continuing its loop. However, some errors will cause the program to abort: if it is unable to
-//! create/open the log file, if there is no .env file, or if the .env file doesn't contain the
-//! expected variables. (Note that there are some `.unwrap()`s in threads, but these will propagate
-//! up to the main thread to be handled.)
+//! create/open the log file, or if its configuration is invalid, in which case every problem with
+//! it is reported.
+//!
+//! All of the deletes and inserts for a CSV happen in one transaction on a single connection, with
+//! records sent to the database 1000 at a time by default.
 //!
 //! An Oracle client needs to be installed on the machine this runs on, with configured
 //! tnsnames.ora and sqlnet.ora. (See
 //! <https://odpi-c.readthedocs.io/en/latest/user_guide/installation.html#linux>.)
-//! Additionally, a .env file needs to be created, holding variables
-//! `PERM_BIKEPED_DB_USERNAME`,`PERM_BIKEPED_DB_PASSWORD`, and `PERM_BIKEPED_DATA_DIR`.
+//! Additionally, the program needs to be [configured][traffic_counts::config], in the
+//! `[perm_bikeped]` table of the configuration file or with environment variables (which can be
+//! set in a .env file): at a minimum, `PERM_BIKEPED_DB_USERNAME`, `PERM_BIKEPED_DB_PASSWORD`, and
+//! `PERM_BIKEPED_DATA_DIR`. Its log is kept in `PERM_BIKEPED_LOG_DIR`, which defaults to the data
+//! directory.
 
 use std::collections::HashMap;
-use std::env;
 use std::fs::{self, File, OpenOptions};
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
-};
+use std::process;
 use std::thread;
 use std::time;
 
 use chrono::prelude::*;
-use crossbeam::channel;
 use csv::StringRecord;
 use log::{debug, error, info, LevelFilter};
-use oracle::pool::{CloseMode, GetMode, PoolBuilder};
+use oracle::Connection;
 use simplelog::*;
 
 use traffic_counts::{
+    config::PermBikePedConfig,
     db::crud,
     perm_bikeped::{AggregatedPermBikePedCount, PermBikePedCount},
 };
 
-// Threads are limited to this number in order to limit number of concurrent connections to
-// database, otherwise this could easily triple to improve performance.
-const NUM_THREADS: usize = 10;
-
 const EXPECTED_HEADER: &[&str] = &[
     "Time",
     "Bartram's Garden", // 16 (locationid)
@@ -146,15 +143,18 @@ const EXPECTED_HEADER: &[&str] = &[
     "",
 ];
 
-const TIME_BETWEEN_LOOPS: u64 = 15;
-
 fn main() {
-    // Load file containing environment variables, panic if it doesn't exist.
-    dotenvy::dotenv().expect("Unable to load .env file.");
-
-    // Get env var for path where CSV and log will be, panic if it doesn't exist.
-    let storage_path =
-        env::var("PERM_BIKEPED_DATA_DIR").expect("Unable to load storage path from .env file.");
+    // Load the configuration, reporting every problem with it and exiting if it's invalid.
+    let import_config = match PermBikePedConfig::load() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    let csv_path = import_config.data_dir.join("export.csv");
+    let db = &import_config.db;
+    let batch_size = import_config.batch_size;
 
     // Set up logging, panic if it fails.
     let config = ConfigBuilder::new().set_time_format_rfc3339().build();
@@ -171,7 +171,7 @@ fn main() {
             OpenOptions::new()
                 .append(true)
                 .create(true)
-                .open(format!("{storage_path}/import.log"))
+                .open(import_config.log_dir.join("import.log"))
                 .expect("Could not open log file."),
         ),
     ])
@@ -181,42 +181,27 @@ fn main() {
     let remove_csv = || {
         // Remove the csv
         info!("Deleting CSV file.");
-        fs::remove_file(format!("{storage_path}/export.csv")).ok()
-    };
-
-    let username = match env::var("PERM_BIKEPED_DB_USERNAME") {
-        Ok(v) => v,
-        Err(e) => {
-            error!("Unable to load username from .env file: {e}");
-            return;
-        }
-    };
-    let password = match env::var("PERM_BIKEPED_DB_PASSWORD") {
-        Ok(v) => v,
-        Err(e) => {
-            error!("Unable to load password from .env file: {e}");
-            return;
-        }
+        fs::remove_file(&csv_path).ok()
     };
 
     'mainloop: loop {
         // Open CSV file and create reader over it, or wait and try again.
-        let data_file = match File::open(format!("{storage_path}/export.csv")) {
+        let data_file = match File::open(&csv_path) {
             Ok(v) => v,
             Err(_) => {
                 debug!("CSV file not located to import data from.");
-                thread::sleep(time::Duration::from_secs(TIME_BETWEEN_LOOPS));
+                thread::sleep(import_config.poll_interval);
                 continue 'mainloop;
             }
         };
 
-        let pool = PoolBuilder::new(username.clone(), password.clone(), "dvrpcprod_tp_tls")
-            .max_connections(10)
-            .get_mode(GetMode::TimedWait(std::time::Duration::from_millis(5000)))
-            .timeout(std::time::Duration::from_millis(15000))
-            .unwrap()
-            .build()
-            .unwrap();
+        let conn = match Connection::connect(&db.username, &db.password, &db.tns_alias) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Unable to get db connection: {e}.");
+                return;
+            }
+        };
 
         // Elapsed time will be logged.
         let start = time::Instant::now();
@@ -592,249 +577,56 @@ fn main() {
         dates.sort();
         dates.dedup();
 
-        // Create a channel to handle moving dates into threads
-        let (tx, rx) = channel::unbounded();
-
-        // Create thread to send dates through the channel
-        let sender_thread_handle = thread::spawn(move || {
-            for date in dates {
-                match tx.send(date) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("Error sending date to channel: {e}.");
-                        return;
-                    }
-                }
-            }
-        });
-
-        // Fork: spawn new threads, with each one adding a receiver, taking a date from the channel,
-        // and deleting existing records for that date.
+        // Replace any existing records for these dates in one transaction, so that a failure
+        // partway through doesn't leave some of them deleted or only partially inserted.
         info!("Deleting all existing records w/ same date from tables TBLCOUNTDATA & TBLHEADER).");
-        let mut receiver_thread_handles = vec![];
-        let num_deletes = Arc::new(AtomicUsize::new(0));
-        for _ in 0..NUM_THREADS {
-            let num_deletes = num_deletes.clone();
-            let receiver = rx.clone();
-            let conn = match pool.get() {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("Unable to get db connection: {e}.");
-                    return;
-                }
-            };
-
-            receiver_thread_handles.push(thread::spawn(move || {
-                while let Ok(date) = receiver.recv() {
-                    // Delete from TBLCOUNTDATA and TBLHEADER.
-                    // If error, log it and then propagate it to main thread.
-                    conn.execute(
-                        "delete from TBLCOUNTDATA where to_char(COUNTDATE, 'DD-MON-YY')=:1",
-                        &[&date],
-                    )
-                    .map_err(|e| {
-                        error!("Error deleting existing records from TBLCOUNTDATA for {date}: {e}");
-                    })
-                    .unwrap();
-
-                    conn.execute(
-                        "delete from TBLHEADER where to_char(COUNTDATE, 'DD-MON-YY')=:1",
-                        &[&date],
-                    )
-                    .map_err(|e| {
-                        error!("Error deleting existing records from TBLHEADER for {date}: {e}");
-                    })
-                    .unwrap();
-
-                    // Commit. If error, log it and then propagate it to main thread.
-                    conn.commit()
-                        .map_err(|e| {
-                            error!(
-                                "Error committing deletion of existing record for {date} from db: {e}"
-                            )
-                        })
-                        .unwrap();
-                    // Increment number of counts (for reporting).
-                    num_deletes.fetch_add(1, Ordering::Relaxed);
-                }
-                })
-            );
-        }
-
-        // Join: wait for delete sender/receiver threads to finish
-        match sender_thread_handle.join() {
-            Ok(_) => (),
-            Err(e) => {
-                error!("{:?}", e);
-                remove_csv();
-                continue 'mainloop;
-            }
-        }
-        for handle in receiver_thread_handles {
-            match handle.join() {
-                Ok(_) => (),
-                Err(e) => {
-                    error!("{:?}", e);
-                    remove_csv();
-                    continue 'mainloop;
-                }
-            }
+        if let Err(e) = crud::delete_perm_bikeped_dates(&conn, &dates, batch_size) {
+            error!("Error deleting existing records from TBLCOUNTDATA and TBLHEADER: {e}");
+            conn.rollback().ok();
+            remove_csv();
+            continue 'mainloop;
         }
 
-        // Create a channel to handle moving all_counts into threads
-        let (tx, rx) = channel::unbounded();
-
-        // Create thread to send Counts through the channel
-        let sender_thread_handle = thread::spawn(move || {
-            for count in all_counts {
-                match tx.send(count) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("Error sending individual data to channel: {e}.");
-                        return;
-                    }
-                }
-            }
-        });
-
-        // Fork: spawn new threads, with each one adding a receiver, taking a Count from the channel,
-        // and inserting it into the database.
         info!("Inserting individual counts into database.");
-        let mut receiver_thread_handles = vec![];
-        let num_individual_inserts = Arc::new(AtomicUsize::new(0));
-        for _ in 0..NUM_THREADS {
-            let num_individual_inserts = num_individual_inserts.clone();
-            let receiver = rx.clone();
-            let conn = match pool.get() {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("Unable to get db connection: {e}.");
-                    return;
-                }
-            };
-            receiver_thread_handles.push(thread::spawn(move || {
-                while let Ok(count) = receiver.recv() {
-                    // Insert. If error, log it and then propagate it to main thread.
-                    crud::insert_perm_bikeped_count(&conn, count.clone())
-                        .map_err(|e| {
-                            error!("Could not insert count {count:?}: {e}");
-                        })
-                        .unwrap();
-
-                    // Increment number of counts (for reporting).
-                    num_individual_inserts.fetch_add(1, Ordering::Relaxed);
-                }
-
-                // Commit. If error, log it and then propagate it to main thread.
-                conn.commit()
-                    .map_err(|e| error!("Error committing insert to database: {e}"))
-                    .unwrap();
-            }));
-        }
-
-        // Join: wait for insert sender/receiver threads to finish
-        match sender_thread_handle.join() {
-            Ok(_) => (),
-            Err(e) => {
-                error!("{:?}", e);
-                remove_csv();
-                continue 'mainloop;
-            }
-        }
-        for handle in receiver_thread_handles {
-            match handle.join() {
-                Ok(_) => (),
-                Err(e) => {
-                    error!("{:?}", e);
-                    remove_csv();
-                    continue 'mainloop;
-                }
-            }
+        if let Err(e) = crud::insert_perm_bikeped_counts(&conn, &all_counts, batch_size) {
+            error!("Could not insert individual counts: {e}");
+            conn.rollback().ok();
+            remove_csv();
+            continue 'mainloop;
         }
 
-        // Create a channel to handle moving flattened_daily_counts into threads
-        let (tx, rx) = channel::unbounded();
-
-        // Create thread to send counts through the channel
-        let sender_thread_handle = thread::spawn(move || {
-            for count in flattened_daily_counts {
-                match tx.send(count) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("Error sending aggregated data to channel: {e}.");
-                        return;
-                    }
-                }
-            }
-        });
-
-        // Fork: spawn new threads, with each one adding a receiver, taking a count from the channel,
-        // and inserting it into the database.
         info!("Inserting aggregated counts into database.");
-        let mut receiver_thread_handles = vec![];
-        let num_aggregated_inserts = Arc::new(AtomicUsize::new(0));
-        for _ in 0..NUM_THREADS {
-            let num_aggregated_inserts = num_aggregated_inserts.clone();
-            let receiver = rx.clone();
-            let conn = match pool.get() {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("Unable to get db connection: {e}.");
-                    return;
-                }
-            };
-            receiver_thread_handles.push(thread::spawn(move || {
-                while let Ok(count) = receiver.recv() {
-                    // Insert. If error, log it and then propagate it to main thread.
-                    crud::insert_aggregated_bikeped_count(&conn, count)
-                        .map_err(|e| {
-                            error!("Could not insert count: {e}");
-                        })
-                        .unwrap();
-
-                    // Increment number of counts (for reporting).
-                    num_aggregated_inserts.fetch_add(1, Ordering::Relaxed);
-                }
-
-                // Commit. If error, log it and then propagate it to main thread.
-                conn.commit()
-                    .map_err(|e| error!("Error committing insert to database: {e}"))
-                    .unwrap();
-            }));
+        if let Err(e) =
+            crud::insert_aggregated_bikeped_counts(&conn, &flattened_daily_counts, batch_size)
+        {
+            error!("Could not insert aggregated counts: {e}");
+            conn.rollback().ok();
+            remove_csv();
+            continue 'mainloop;
         }
 
-        // Join: wait for insert sender/receiver threads to finish.
-        match sender_thread_handle.join() {
-            Ok(_) => (),
-            Err(e) => {
-                error!("{:?}", e);
-                remove_csv();
-                continue 'mainloop;
-            }
-        }
-        for handle in receiver_thread_handles {
-            match handle.join() {
-                Ok(_) => (),
-                Err(e) => {
-                    error!("{:?}", e);
-                    remove_csv();
-                    continue 'mainloop;
-                }
-            }
+        if let Err(e) = conn.commit() {
+            error!("Error committing import to database: {e}");
+            conn.rollback().ok();
+            remove_csv();
+            continue 'mainloop;
         }
 
         info!("Import completed successfully.");
-        info!("Records for {:?} dates deleted.", num_deletes);
-        info!("{:?} individual counts inserted.", num_individual_inserts);
-        info!("{:?} aggregated counts inserted.", num_aggregated_inserts);
+        info!("Records for {} dates deleted.", dates.len());
+        info!("{} individual counts inserted.", all_counts.len());
+        info!(
+            "{} aggregated counts inserted.",
+            flattened_daily_counts.len()
+        );
         info!("Elapsed time: {:?}", start.elapsed());
 
         // Remove the csv
         remove_csv();
 
-        pool.close(&CloseMode::Force).unwrap();
+        conn.close().ok();
 
         // Wait to try again
-        thread::sleep(time::Duration::from_secs(TIME_BETWEEN_LOOPS));
+        thread::sleep(import_config.poll_interval);
     }
 }
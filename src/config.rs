@@ -0,0 +1,677 @@
+//! Configuration of the programs, from a TOML file and environment variables.
+//!
+//! Settings are read from the TOML file at the path in the `TRAFFIC_COUNTS_CONFIG` environment
+//! variable, or else traffic_counts.toml in the working directory, if there is one. It has a
+//! table of settings for each program, and one for the database they share:
+//!
+//! ```toml
+//! [database]
+//! tns_alias = "dvrpcprod_tp_tls" # optional
+//!
+//! [non_perm]
+//! db_username = "DVRPCTC_TEST"
+//! db_password = "password here"
+//! data_dir = "data"
+//! log_dir = "data"
+//! review_dir = "for_review" # optional; subdirectory of data_dir
+//! poll_interval = 20 # optional; seconds
+//! hold_warnings = false # optional; see the non_perm_import docs
+//! staging_dir = "staging" # optional; subdirectory of data_dir
+//! batch_size = 1000 # optional; records inserted at a time
+//! max_rejected_pct = 1 # optional; see the non_perm_import docs
+//! speed_bins = ["10mph", "10kph"] # optional
+//! platoon_headway = 3 # optional; seconds
+//! critical_gap = 10 # optional; seconds
+//! class_mappings = "/path/to/class_mappings.toml" # optional
+//! class2_min_pct = 75 # optional; the thresholds of the data checks, see check_data
+//! unclassed_max_pct = 10 # optional
+//! dir_proportion_lower_bound = 0.4 # optional
+//! bike_count_max = 20 # optional
+//! zero_hours_start = 4 # optional
+//! zero_hours_end = 22 # optional
+//!
+//! # optional; thresholds for counts of a functional class and/or bikeped facility
+//! [[non_perm.check_overrides]]
+//! facility = "Trail"
+//! bike_count_max = 60
+//!
+//! [[non_perm.check_overrides]]
+//! fc = 1
+//! dir_proportion_lower_bound = 0.3
+//!
+//! [perm_bikeped]
+//! db_username = "BIKEPED_TEST"
+//! db_password = "password here"
+//! data_dir = "data"
+//! log_dir = "data" # optional; data_dir by default
+//! poll_interval = 15 # optional; seconds
+//! batch_size = 1000 # optional
+//! ```
+//!
+//! Any setting can be overridden by an environment variable - which can also be set in a .env
+//! file - named for its table and key in upper case, e.g. `NON_PERM_DB_USERNAME` for
+//! `db_username` in `[non_perm]` or `DATABASE_TNS_ALIAS` for `tns_alias` in `[database]`. The
+//! file is therefore optional, and a .env file with every required setting is enough on its own.
+//! A list, like `speed_bins`, is comma-separated in an environment variable. `check_overrides`
+//! (see [`ThresholdOverride`]) can only be set in the file.
+//!
+//! Loading a program's configuration ([`NonPermConfig::load`], [`PermBikePedConfig::load`])
+//! validates all of it, and fails with every problem found - missing settings, invalid values,
+//! and unknown keys in the program's tables - rather than only the first.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    db::crud::DEFAULT_BATCH_SIZE,
+    non_perm::{
+        check_data::{CheckConfig, CheckThresholds, ThresholdOverride},
+        class_scheme::ClassMapping,
+        extract_from_file::RowPolicy,
+        headway::HeadwayOptions,
+        speed_bins::SpeedBinScheme,
+    },
+    CountError,
+};
+
+/// The environment variable with the path of the configuration file.
+pub const CONFIG_VAR: &str = "TRAFFIC_COUNTS_CONFIG";
+/// The configuration file used if [`CONFIG_VAR`] isn't set.
+pub const DEFAULT_CONFIG_FILE: &str = "traffic_counts.toml";
+/// The TNS alias of the database, unless configured otherwise.
+pub const DEFAULT_TNS_ALIAS: &str = "dvrpcprod_tp_tls";
+
+const DATABASE: &str = "database";
+const NON_PERM: &str = "non_perm";
+const PERM_BIKEPED: &str = "perm_bikeped";
+
+/// How to connect to the database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbConfig {
+    pub username: String,
+    pub password: String,
+    pub tns_alias: String,
+}
+
+impl DbConfig {
+    fn from_settings(settings: &mut Settings, table: &str) -> Self {
+        Self {
+            username: settings.required(table, "db_username"),
+            password: settings.required(table, "db_password"),
+            tns_alias: settings.or(DATABASE, "tns_alias", DEFAULT_TNS_ALIAS.to_string()),
+        }
+    }
+}
+
+/// Configuration of the program that imports non-permanent counts.
+#[derive(Debug, Clone)]
+pub struct NonPermConfig {
+    pub db: DbConfig,
+    /// The directory watched for files to import.
+    pub data_dir: PathBuf,
+    pub log_dir: PathBuf,
+    /// The subdirectory of `data_dir` that files are moved to for review.
+    pub review_dir: String,
+    /// How long to wait between checks for files.
+    pub poll_interval: Duration,
+    /// Whether files whose data gets a warning from the data checks are held, rather than
+    /// imported, until approved.
+    pub hold_warnings: bool,
+    /// The subdirectory of `data_dir` that held files are moved to.
+    pub staging_dir: String,
+    /// The number of records sent to the database at a time.
+    pub batch_size: usize,
+    /// How rows that can't be extracted are handled.
+    pub policy: RowPolicy,
+    /// Alternative schemes to create speed distributions for.
+    pub speed_bins: Vec<SpeedBinScheme>,
+    pub headway: HeadwayOptions,
+    /// Mappings of vendor class numbers that files can be imported with, besides the built-in
+    /// ones.
+    pub class_mappings: Vec<ClassMapping>,
+    /// The thresholds of the data checks.
+    pub checks: CheckConfig,
+}
+
+impl NonPermConfig {
+    /// Load and validate the configuration.
+    pub fn load() -> Result<Self, CountError> {
+        Self::from_settings(Settings::load())
+    }
+
+    fn from_settings(mut settings: Settings) -> Result<Self, CountError> {
+        let s = &mut settings;
+        let db = DbConfig::from_settings(s, NON_PERM);
+        let data_dir = s.required(NON_PERM, "data_dir");
+        let log_dir = s.required(NON_PERM, "log_dir");
+        let review_dir = s.or(NON_PERM, "review_dir", "for_review".to_string());
+        let poll_interval = s.poll_interval(NON_PERM, 20);
+        let hold_warnings = s.or(NON_PERM, "hold_warnings", false);
+        let staging_dir = s.or(NON_PERM, "staging_dir", "staging".to_string());
+        s.ensure(
+            NON_PERM,
+            "staging_dir",
+            staging_dir != review_dir,
+            "must differ from review_dir",
+        );
+        let batch_size = s.batch_size(NON_PERM);
+
+        let policy = match s.optional::<f32>(NON_PERM, "max_rejected_pct") {
+            Some(max_rejected_pct) => {
+                s.ensure(
+                    NON_PERM,
+                    "max_rejected_pct",
+                    (0.0..=100.0).contains(&max_rejected_pct),
+                    "must be from 0 to 100",
+                );
+                RowPolicy::Lenient { max_rejected_pct }
+            }
+            None => RowPolicy::Strict,
+        };
+        let speed_bins = s
+            .list(NON_PERM, "speed_bins")
+            .iter()
+            .filter_map(|name| match SpeedBinScheme::from_str(name) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    s.problem(NON_PERM, "speed_bins", e);
+                    None
+                }
+            })
+            .collect();
+
+        let defaults = HeadwayOptions::default();
+        let headway = HeadwayOptions {
+            platoon_threshold: s.positive(NON_PERM, "platoon_headway", defaults.platoon_threshold),
+            critical_gap: s.positive(NON_PERM, "critical_gap", defaults.critical_gap),
+        };
+
+        let class_mappings = match s.optional::<PathBuf>(NON_PERM, "class_mappings") {
+            Some(path) => ClassMapping::load(&path).unwrap_or_else(|e| {
+                s.problem(NON_PERM, "class_mappings", e);
+                vec![]
+            }),
+            None => vec![],
+        };
+
+        let defaults = CheckThresholds::default();
+        let check_defaults = CheckThresholds {
+            class2_min_pct: s.or(NON_PERM, "class2_min_pct", defaults.class2_min_pct),
+            unclassed_max_pct: s.or(NON_PERM, "unclassed_max_pct", defaults.unclassed_max_pct),
+            dir_proportion_lower_bound: s.or(
+                NON_PERM,
+                "dir_proportion_lower_bound",
+                defaults.dir_proportion_lower_bound,
+            ),
+            bike_count_max: s.or(NON_PERM, "bike_count_max", defaults.bike_count_max),
+            zero_hours_start: s.or(NON_PERM, "zero_hours_start", defaults.zero_hours_start),
+            zero_hours_end: s.or(NON_PERM, "zero_hours_end", defaults.zero_hours_end),
+        };
+        for problem in check_defaults.problems() {
+            s.problems.push(format!("{NON_PERM}: {problem}"));
+        }
+        let overrides = s
+            .deserialize::<Vec<ThresholdOverride>>(NON_PERM, "check_overrides")
+            .unwrap_or_default();
+        for (i, thresholds_override) in overrides.iter().enumerate() {
+            let key = format!("{NON_PERM}.check_overrides[{i}]");
+            if thresholds_override.fc.is_none() && thresholds_override.facility.is_none() {
+                s.problems
+                    .push(format!("{key}: fc and/or facility must be set"));
+            }
+            let mut thresholds = check_defaults;
+            thresholds_override.apply(&mut thresholds);
+            for problem in thresholds.problems() {
+                s.problems.push(format!("{key}: {problem}"));
+            }
+        }
+        let checks = CheckConfig {
+            defaults: check_defaults,
+            overrides,
+        };
+
+        settings.finish(&[DATABASE, NON_PERM])?;
+        Ok(Self {
+            db,
+            data_dir,
+            log_dir,
+            review_dir,
+            poll_interval,
+            hold_warnings,
+            staging_dir,
+            batch_size,
+            policy,
+            speed_bins,
+            headway,
+            class_mappings,
+            checks,
+        })
+    }
+}
+
+/// Configuration of the program that imports permanent bicycle and pedestrian counts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermBikePedConfig {
+    pub db: DbConfig,
+    /// The directory checked for the CSV file to import.
+    pub data_dir: PathBuf,
+    pub log_dir: PathBuf,
+    /// How long to wait between checks for the file.
+    pub poll_interval: Duration,
+    /// The number of records sent to the database at a time.
+    pub batch_size: usize,
+}
+
+impl PermBikePedConfig {
+    /// Load and validate the configuration.
+    pub fn load() -> Result<Self, CountError> {
+        Self::from_settings(Settings::load())
+    }
+
+    fn from_settings(mut settings: Settings) -> Result<Self, CountError> {
+        let s = &mut settings;
+        let db = DbConfig::from_settings(s, PERM_BIKEPED);
+        let data_dir: PathBuf = s.required(PERM_BIKEPED, "data_dir");
+        let log_dir = s.or(PERM_BIKEPED, "log_dir", data_dir.clone());
+        let poll_interval = s.poll_interval(PERM_BIKEPED, 15);
+        let batch_size = s.batch_size(PERM_BIKEPED);
+
+        settings.finish(&[DATABASE, PERM_BIKEPED])?;
+        Ok(Self {
+            db,
+            data_dir,
+            log_dir,
+            poll_interval,
+            batch_size,
+        })
+    }
+}
+
+/// Load the configuration of the database connection for non-permanent counts.
+pub fn non_perm_db() -> Result<DbConfig, CountError> {
+    let mut settings = Settings::load();
+    let db = DbConfig::from_settings(&mut settings, NON_PERM);
+    settings.finish(&[])?;
+    Ok(db)
+}
+
+/// The settings of the configuration file, with any overridden by environment variables, and
+/// the problems found with them.
+struct Settings {
+    file: toml::Table,
+    env: HashMap<String, String>,
+    read: HashSet<(String, String)>,
+    problems: Vec<String>,
+}
+
+impl Settings {
+    /// Load the settings from the configuration file (if any), the .env file (if any), and the
+    /// environment.
+    fn load() -> Self {
+        let _ = dotenvy::dotenv();
+        let env = env::vars().collect::<HashMap<_, _>>();
+
+        let (path, explicit) = match env.get(CONFIG_VAR) {
+            Some(v) => (PathBuf::from(v), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        let mut problems = vec![];
+        let text = match fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) if explicit || e.kind() != io::ErrorKind::NotFound => {
+                problems.push(format!("unable to read configuration file {path:?}: {e}"));
+                String::new()
+            }
+            Err(_) => String::new(),
+        };
+
+        let mut settings = Self::new(&text, env);
+        problems.append(&mut settings.problems);
+        settings.problems = problems;
+        settings
+    }
+
+    fn new(text: &str, env: HashMap<String, String>) -> Self {
+        let (file, problems) = match text.parse::<toml::Table>() {
+            Ok(v) => (v, vec![]),
+            Err(e) => (
+                toml::Table::new(),
+                vec![format!("invalid configuration file: {e}")],
+            ),
+        };
+        Self {
+            file,
+            env,
+            read: HashSet::new(),
+            problems,
+        }
+    }
+
+    /// The value of a setting, from its environment variable if set, otherwise from the file.
+    fn value(&mut self, table: &str, key: &str) -> Option<String> {
+        self.read.insert((table.to_string(), key.to_string()));
+        if let Some(v) = self.env.get(&env_var(table, key)) {
+            return Some(v.clone());
+        }
+        let text = |value: &toml::Value| match value {
+            toml::Value::String(v) => v.clone(),
+            v => v.to_string(),
+        };
+        match self.file.get(table)?.get(key)? {
+            toml::Value::Array(values) => {
+                Some(values.iter().map(text).collect::<Vec<_>>().join(","))
+            }
+            value => Some(text(value)),
+        }
+    }
+
+    /// A setting deserialized directly from the file, for those that can't be set by an
+    /// environment variable.
+    fn deserialize<T: DeserializeOwned>(&mut self, table: &str, key: &str) -> Option<T> {
+        self.read.insert((table.to_string(), key.to_string()));
+        let value = self.file.get(table)?.get(key)?.clone();
+        match value.try_into() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.problems.push(format!("{table}.{key}: {e}"));
+                None
+            }
+        }
+    }
+
+    fn optional<T>(&mut self, table: &str, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.value(table, key)?;
+        match value.trim().parse() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.problem(table, key, format!("invalid value '{value}': {e}"));
+                None
+            }
+        }
+    }
+
+    fn or<T>(&mut self, table: &str, key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.optional(table, key).unwrap_or(default)
+    }
+
+    fn required<T>(&mut self, table: &str, key: &str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: Display,
+    {
+        if self.value(table, key).is_none() {
+            self.problem(table, key, "required, but not set");
+        }
+        self.optional(table, key).unwrap_or_default()
+    }
+
+    /// A comma-separated list.
+    fn list(&mut self, table: &str, key: &str) -> Vec<String> {
+        self.value(table, key)
+            .map(|v| {
+                v.split(',')
+                    .map(|item| item.trim().to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn positive(&mut self, table: &str, key: &str, default: f32) -> f32 {
+        let value = self.or(table, key, default);
+        self.ensure(table, key, value > 0.0, "must be greater than 0");
+        value
+    }
+
+    fn poll_interval(&mut self, table: &str, default_secs: u64) -> Duration {
+        let secs = self.or(table, "poll_interval", default_secs);
+        self.ensure(table, "poll_interval", secs > 0, "must be greater than 0");
+        Duration::from_secs(secs)
+    }
+
+    fn batch_size(&mut self, table: &str) -> usize {
+        self.optional::<NonZeroUsize>(table, "batch_size")
+            .map_or(DEFAULT_BATCH_SIZE, NonZeroUsize::get)
+    }
+
+    fn ensure(&mut self, table: &str, key: &str, valid: bool, requirement: &str) {
+        if !valid {
+            self.problem(table, key, requirement);
+        }
+    }
+
+    fn problem(&mut self, table: &str, key: &str, problem: impl Display) {
+        self.problems.push(format!(
+            "{table}.{key} ({}): {problem}",
+            env_var(table, key)
+        ));
+    }
+
+    /// Err with every problem found, including keys of `tables` in the file that weren't read.
+    fn finish(mut self, tables: &[&str]) -> Result<(), CountError> {
+        for &table in tables {
+            let Some(toml::Value::Table(settings)) = self.file.get(table) else {
+                continue;
+            };
+            for key in settings.keys() {
+                if !self.read.contains(&(table.to_string(), key.clone())) {
+                    self.problems
+                        .push(format!("{table}.{key}: unknown setting"));
+                }
+            }
+        }
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CountError::InvalidConfig(self.problems))
+        }
+    }
+}
+
+/// The environment variable that overrides a setting.
+fn env_var(table: &str, key: &str) -> String {
+    format!("{table}_{key}").to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(text: &str, env: &[(&str, &str)]) -> Settings {
+        let env = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Settings::new(text, env)
+    }
+
+    #[test]
+    fn settings_from_file_overridden_by_env_with_defaults() {
+        let text = r#"
+            [database]
+            tns_alias = "test_alias"
+
+            [non_perm]
+            db_username = "DVRPCTC_TEST"
+            db_password = "secret"
+            data_dir = "data"
+            log_dir = "logs"
+            batch_size = 500
+            speed_bins = ["10mph", "10kph"]
+        "#;
+        let config = NonPermConfig::from_settings(settings(
+            text,
+            &[
+                ("NON_PERM_LOG_DIR", "other_logs"),
+                ("NON_PERM_CRITICAL_GAP", "12"),
+            ],
+        ))
+        .unwrap();
+
+        assert_eq!(
+            config.db,
+            DbConfig {
+                username: "DVRPCTC_TEST".to_string(),
+                password: "secret".to_string(),
+                tns_alias: "test_alias".to_string(),
+            }
+        );
+        assert_eq!(config.log_dir, PathBuf::from("other_logs"));
+        assert_eq!(config.batch_size, 500);
+        assert_eq!(config.speed_bins.len(), 2);
+        assert_eq!(config.headway.critical_gap, 12.0);
+        assert_eq!(config.headway.platoon_threshold, 3.0);
+        assert_eq!(config.review_dir, "for_review");
+        assert_eq!(config.poll_interval, Duration::from_secs(20));
+        assert!(!config.hold_warnings);
+        assert_eq!(config.staging_dir, "staging");
+        assert!(matches!(config.policy, RowPolicy::Strict));
+        assert_eq!(config.checks, CheckConfig::default());
+    }
+
+    #[test]
+    fn env_alone_is_enough() {
+        let config = PermBikePedConfig::from_settings(settings(
+            "",
+            &[
+                ("PERM_BIKEPED_DB_USERNAME", "BIKEPED_TEST"),
+                ("PERM_BIKEPED_DB_PASSWORD", "secret"),
+                ("PERM_BIKEPED_DATA_DIR", "data"),
+                ("PERM_BIKEPED_BATCH_SIZE", "250"),
+            ],
+        ))
+        .unwrap();
+        assert_eq!(config.db.tns_alias, DEFAULT_TNS_ALIAS);
+        assert_eq!(config.log_dir, PathBuf::from("data"));
+        assert_eq!(config.batch_size, 250);
+        assert_eq!(config.poll_interval, Duration::from_secs(15));
+    }
+
+    #[test]
+    fn every_problem_reported() {
+        let text = r#"
+            [non_perm]
+            db_username = "DVRPCTC_TEST"
+            data_dir = "data"
+            batch_size = 0
+            max_rejected_pct = 101
+            speed_bins = "10mph,20furlongs"
+            poll_intervall = 30
+
+            [perm_bikeped]
+            anything = "not checked by non_perm"
+        "#;
+        let Err(CountError::InvalidConfig(problems)) =
+            NonPermConfig::from_settings(settings(text, &[("NON_PERM_CRITICAL_GAP", "-1")]))
+        else {
+            panic!("configuration should be invalid");
+        };
+        let keys = problems
+            .iter()
+            .map(|p| p.split_whitespace().next().unwrap().trim_end_matches(':'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "non_perm.db_password",
+                "non_perm.log_dir",
+                "non_perm.batch_size",
+                "non_perm.max_rejected_pct",
+                "non_perm.speed_bins",
+                "non_perm.critical_gap",
+                "non_perm.poll_intervall",
+            ]
+        );
+        assert!(problems[0].contains("NON_PERM_DB_PASSWORD"));
+    }
+
+    #[test]
+    fn check_overrides_from_file() {
+        let required = r#"
+            [non_perm]
+            db_username = "DVRPCTC_TEST"
+            db_password = "secret"
+            data_dir = "data"
+            log_dir = "data"
+            bike_count_max = 25
+        "#;
+        let text = format!(
+            r#"{required}
+            [[non_perm.check_overrides]]
+            facility = "Trail"
+            bike_count_max = 60
+
+            [[non_perm.check_overrides]]
+            fc = 1
+            zero_hours_start = 6
+        "#
+        );
+        let config = NonPermConfig::from_settings(settings(&text, &[])).unwrap();
+        assert_eq!(config.checks.defaults.bike_count_max, 25);
+        assert_eq!(config.checks.overrides.len(), 2);
+        assert_eq!(config.checks.overrides[0].bike_count_max, Some(60));
+        assert_eq!(config.checks.overrides[1].fc, Some(1));
+
+        let text = format!(
+            r#"{required}
+            [[non_perm.check_overrides]]
+            bike_count_max = 60
+
+            [[non_perm.check_overrides]]
+            fc = 1
+            dir_proportion_lower_bound = 0.6
+        "#
+        );
+        let Err(CountError::InvalidConfig(problems)) =
+            NonPermConfig::from_settings(settings(&text, &[]))
+        else {
+            panic!("configuration should be invalid");
+        };
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("non_perm.check_overrides[0]: fc and/or facility"));
+        assert!(problems[1].starts_with("non_perm.check_overrides[1]: dir_proportion"));
+
+        let text = format!(
+            r#"{required}
+            [[non_perm.check_overrides]]
+            fc = 1
+            bike_count = 60
+        "#
+        );
+        let Err(CountError::InvalidConfig(problems)) =
+            NonPermConfig::from_settings(settings(&text, &[]))
+        else {
+            panic!("configuration should be invalid");
+        };
+        assert!(problems[0].contains("bike_count"));
+    }
+
+    #[test]
+    fn invalid_file_reported() {
+        let Err(CountError::InvalidConfig(problems)) =
+            PermBikePedConfig::from_settings(settings("[perm_bikeped", &[]))
+        else {
+            panic!("configuration should be invalid");
+        };
+        assert!(problems[0].starts_with("invalid configuration file"));
+        // Along with the required settings that are therefore missing.
+        assert_eq!(problems.len(), 4);
+    }
+}
@@ -1,21 +1,67 @@
 //! Basic CRUD db operations on count data tables.
 
-use oracle::{sql_type::Timestamp, Connection, Statement};
+use std::collections::BTreeSet;
+
+use oracle::{sql_type::Timestamp, Batch, Connection};
 
 use chrono::{Datelike, NaiveDateTime, Timelike};
 
 use crate::non_perm::{
+    class_scheme::TimeBinnedLengthClassCount,
+    headway::{HourlyGaps, HourlyHeadway},
+    speed_bins::TimeBinnedSpeedDistribution,
+    speed_class::TimeBinnedSpeedClassCount,
+    speed_stats::{CountSpeedStats, HourlySpeedStats},
+    turning_movement::{PeakHour, TurningMovementCount},
     FifteenMinuteBicycle, FifteenMinutePedestrian, FifteenMinuteVehicle, HourlyAvgSpeed,
     HourlyVehicle, TimeBinnedSpeedRangeCount, TimeBinnedVehicleClassCount,
 };
 use crate::perm_bikeped::{AggregatedPermBikePedCount, PermBikePedCount};
 use crate::CountError;
 
+/// The number of records sent to the database at a time when inserting, by default.
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// The datetimes and lanes covered by some records of a count, such as those from one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountSpan {
+    /// The datetime of the first record.
+    pub start: NaiveDateTime,
+    /// The datetime of the last record.
+    pub end: NaiveDateTime,
+    /// The lanes of the records, or `None` for all lanes (if the table doesn't have lanes or any
+    /// record is without one).
+    pub lanes: Option<BTreeSet<u8>>,
+}
+
+impl CountSpan {
+    /// The span of records, or `None` if there aren't any.
+    pub fn of<T: NonPermCrud>(counts: &[T]) -> Option<Self> {
+        let start = counts.iter().map(|c| c.countdatetime()).min()?;
+        let end = counts.iter().map(|c| c.countdatetime()).max()?;
+        let lanes = counts.iter().map(|c| c.countlane()).collect();
+        Some(Self { start, end, lanes })
+    }
+
+    /// Whether a record falls within the span.
+    pub fn contains<T: NonPermCrud>(&self, count: &T) -> bool {
+        (self.start..=self.end).contains(&count.countdatetime())
+            && self
+                .lanes
+                .as_ref()
+                .is_none_or(|lanes| count.countlane().is_some_and(|lane| lanes.contains(&lane)))
+    }
+}
+
 /// A trait for handling basic CRUD db operations on non-permanent count data tables.
 pub trait NonPermCrud {
     /// The name of the table in the database that this count type corresponds to.
     const COUNT_TABLE: &'static str; // associated constant
 
+    /// The columns of the table that records are inserted into and selected from (by their
+    /// [`RowValue`](oracle::RowValue)).
+    const COLUMNS: &'static [&'static str];
+
     /// Select all records from the table.
     fn select(conn: &Connection, recordnum: u32) -> Result<Vec<Self>, CountError>
     where
@@ -33,23 +79,107 @@ pub trait NonPermCrud {
     }
 
     /// Delete all records in the table with a particular recordnum.
+    ///
+    /// This is not committed, so that it can be part of a larger transaction.
     fn delete(conn: &Connection, recordnum: u32) -> Result<(), oracle::Error> {
         let sql = &format!("delete from {} where recordnum = :1", &Self::COUNT_TABLE,);
         conn.execute(sql, &[&recordnum])?;
-        conn.commit()
+        Ok(())
+    }
+
+    /// Delete the records of a count in the table within a [`CountSpan`].
+    ///
+    /// This is not committed, so that it can be part of a larger transaction.
+    fn delete_span(
+        conn: &Connection,
+        recordnum: u32,
+        span: &CountSpan,
+    ) -> Result<(), oracle::Error> {
+        let sql = &format!(
+            "delete from {} where recordnum = :1 and countdatetime between :2 and :3",
+            &Self::COUNT_TABLE,
+        );
+        match &span.lanes {
+            None => {
+                conn.execute(sql, &[&recordnum, &span.start, &span.end])?;
+            }
+            Some(lanes) => {
+                let mut stmt = conn
+                    .statement(&format!("{sql} and countlane = :4"))
+                    .build()?;
+                for lane in lanes {
+                    stmt.execute(&[&recordnum, &span.start, &span.end, lane])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a batch to insert records with, executed every `batch_size` records.
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error>;
+
+    /// Add a record to a batch to insert into the table.
+    ///
+    /// The batch is executed once it's full; see [`NonPermCrud::insert_all`] to execute the rest.
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error>;
+
+    /// Insert records into the table, sending `batch_size` of them at a time.
+    ///
+    /// This is not committed, so that it can be part of a larger transaction.
+    fn insert_all(
+        conn: &Connection,
+        counts: &[Self],
+        batch_size: usize,
+    ) -> Result<(), oracle::Error>
+    where
+        Self: std::marker::Sized,
+    {
+        let mut batch = Self::prepare_insert(conn, batch_size)?;
+        for count in counts {
+            count.insert(&mut batch)?;
+        }
+        batch.execute()
     }
 
-    /// Create prepared statement to use for insert.
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error>;
+    /// The recordnum of the count this record belongs to.
+    fn recordnum(&self) -> u32;
+
+    /// The datetime of the record (the start of its interval, for binned records).
+    fn countdatetime(&self) -> NaiveDateTime;
 
-    /// Insert a record into the table using prepared statement.
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error>;
+    /// The lane of the record, for tables that have one.
+    fn countlane(&self) -> Option<u8> {
+        None
+    }
 }
 
 impl NonPermCrud for TimeBinnedVehicleClassCount {
     const COUNT_TABLE: &'static str = "tc_clacount_new";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "total",
+        "cntdir",
+        "bikes",
+        "cars_and_tlrs",
+        "ax2_long",
+        "buses",
+        "ax2_6_tire",
+        "ax3_single",
+        "ax4_single",
+        "lt_5_ax_double",
+        "ax5_double",
+        "gt_5_ax_double",
+        "lt_6_ax_multi",
+        "ax6_multi",
+        "gt_6_ax_multi",
+        "unclassified",
+        "countdate",
+        "counttime",
+    ];
 
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {} (recordnum, countdatetime, countlane, total, cntdir, \
             bikes, cars_and_tlrs, ax2_long, buses, ax2_6_tire, ax3_single, ax4_single, \
@@ -60,10 +190,10 @@ impl NonPermCrud for TimeBinnedVehicleClassCount {
             :19, :20)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
     }
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
             &self.recordnum,
             &NaiveDateTime::new(self.date, self.time.time()),
             &self.lane,
@@ -85,12 +215,102 @@ impl NonPermCrud for TimeBinnedVehicleClassCount {
             &self.c15,
         ])
     }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        NaiveDateTime::new(self.date, self.time.time())
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        self.lane
+    }
+}
+
+impl NonPermCrud for TimeBinnedLengthClassCount {
+    const COUNT_TABLE: &'static str = "tc_lenclacount";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "total",
+        "cntdir",
+        "lenbin1",
+        "lenbin2",
+        "lenbin3",
+        "lenbin4",
+        "unclassified",
+        "countdate",
+        "counttime",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {} (recordnum, countdatetime, countlane, total, cntdir, \
+            lenbin1, lenbin2, lenbin3, lenbin4, unclassified)
+            VALUES (:1, :2, :3, :4, :5, :6, :7, :8, :9, :10)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &NaiveDateTime::new(self.date, self.time.time()),
+            &self.lane,
+            &self.total,
+            &self.direction,
+            &self.short,
+            &self.medium,
+            &self.long,
+            &self.very_long,
+            &self.unclassified,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        NaiveDateTime::new(self.date, self.time.time())
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        self.lane
+    }
 }
 
 impl NonPermCrud for TimeBinnedSpeedRangeCount {
     const COUNT_TABLE: &'static str = "tc_specount_new";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "total",
+        "cntdir",
+        "s1",
+        "s2",
+        "s3",
+        "s4",
+        "s5",
+        "s6",
+        "s7",
+        "s8",
+        "s9",
+        "s10",
+        "s11",
+        "s12",
+        "s13",
+        "s14",
+        "countdate",
+        "counttime",
+    ];
 
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {} (
             recordnum, countdatetime, countlane, total, cntdir, \
@@ -100,11 +320,11 @@ impl NonPermCrud for TimeBinnedSpeedRangeCount {
             :19, :20)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
     }
 
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
             &self.recordnum,
             &NaiveDateTime::new(self.date, self.time.time()),
             &self.lane,
@@ -126,22 +346,41 @@ impl NonPermCrud for TimeBinnedSpeedRangeCount {
             &self.s14,
         ])
     }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        NaiveDateTime::new(self.date, self.time.time())
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        self.lane
+    }
 }
 
 impl NonPermCrud for HourlyAvgSpeed {
     const COUNT_TABLE: &'static str = "tc_spesum_new";
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "avgspeed",
+        "countlane",
+        "cntdir",
+    ];
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {}
             (recordnum, countdatetime, avgspeed, countlane, cntdir) \
             VALUES (:1, :2, :3, :4, :5)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
     }
 
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
             &self.recordnum,
             &self.datetime,
             &self.speed,
@@ -149,23 +388,45 @@ impl NonPermCrud for HourlyAvgSpeed {
             &self.direction,
         ])
     }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        Some(self.lane)
+    }
 }
 
 impl NonPermCrud for FifteenMinuteVehicle {
     const COUNT_TABLE: &'static str = "tc_15minvolcount_new";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "volume",
+        "cntdir",
+        "countlane",
+        "countdate",
+        "counttime",
+        "volcount",
+    ];
 
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {}
             (recordnum, countdatetime, volume, cntdir, countlane) \
             VALUES (:1, :2, :3, :4, :5)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
     }
 
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
             &self.recordnum,
             &NaiveDateTime::new(self.date, self.time.time()),
             &self.count,
@@ -173,23 +434,42 @@ impl NonPermCrud for FifteenMinuteVehicle {
             &self.lane,
         ])
     }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        NaiveDateTime::new(self.date, self.time.time())
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        self.lane
+    }
 }
 
 impl NonPermCrud for HourlyVehicle {
     const COUNT_TABLE: &'static str = "tc_volcount_new";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "volume",
+        "countlane",
+        "cntdir",
+    ];
 
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {}
             (recordnum, countdatetime, volume, countlane, cntdir) \
             VALUES (:1, :2, :3, :4, :5)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
     }
 
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
             &self.recordnum,
             &self.datetime,
             &self.count,
@@ -197,75 +477,576 @@ impl NonPermCrud for HourlyVehicle {
             &self.direction,
         ])
     }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        Some(self.lane)
+    }
 }
 
 impl NonPermCrud for FifteenMinuteBicycle {
     const COUNT_TABLE: &'static str = "tc_bikecount_new";
+    const COLUMNS: &'static [&'static str] = &["recordnum", "countdatetime", "volume", "cntdir"];
 
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {}
             (recordnum, countdatetime, volume, cntdir) \
             VALUES (:1, :2, :3, :4)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[&self.recordnum, &self.datetime, &self.volume, &self.cntdir])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
     }
 
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[&self.recordnum, &self.datetime, &self.volume, &self.cntdir])
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
     }
 }
 
 impl NonPermCrud for FifteenMinutePedestrian {
     const COUNT_TABLE: &'static str = "tc_pedcount_new";
+    const COLUMNS: &'static [&'static str] = &["recordnum", "countdatetime", "volume", "cntdir"];
 
-    fn prepare_insert(conn: &Connection) -> Result<Statement, oracle::Error> {
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
         let sql = &format!(
             "insert into {}
             (recordnum, countdatetime, volume, cntdir) \
             VALUES (:1, :2, :3, :4)",
             &Self::COUNT_TABLE,
         );
-        conn.statement(sql).build()
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[&self.recordnum, &self.datetime, &self.volume, &self.cntdir])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+}
+
+impl NonPermCrud for TurningMovementCount {
+    const COUNT_TABLE: &'static str = "tc_tmccount_new";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "approach",
+        "movement",
+        "vehclass",
+        "volume",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {}
+            (recordnum, countdatetime, approach, movement, vehclass, volume) \
+            VALUES (:1, :2, :3, :4, :5, :6)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.approach,
+            &self.movement,
+            &self.vehicle_class,
+            &self.volume,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+}
+
+impl NonPermCrud for PeakHour {
+    const COUNT_TABLE: &'static str = "tc_tmcpeakhour";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "approach",
+        "countdatetime",
+        "volume",
+        "peak15min",
+        "phf",
+        "left",
+        "through",
+        "right",
+        "uturn",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {}
+            (recordnum, approach, countdatetime, volume, peak15min, phf, left, through, right, \
+            uturn) \
+            VALUES (:1, :2, :3, :4, :5, :6, :7, :8, :9, :10)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.approach,
+            &self.start,
+            &self.volume,
+            &self.peak_15_min_volume,
+            &self.phf,
+            &self.left,
+            &self.through,
+            &self.right,
+            &self.uturn,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.start
+    }
+}
+
+impl NonPermCrud for TimeBinnedSpeedDistribution {
+    const COUNT_TABLE: &'static str = "tc_speeddist";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "cntdir",
+        "scheme",
+        "binnum",
+        "speedbin",
+        "volume",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {}
+            (recordnum, countdatetime, countlane, cntdir, scheme, binnum, speedbin, volume) \
+            VALUES (:1, :2, :3, :4, :5, :6, :7, :8)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.lane,
+            &self.direction,
+            &self.scheme,
+            &self.bin_num,
+            &self.bin,
+            &self.volume,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        Some(self.lane)
+    }
+}
+
+impl NonPermCrud for CountSpeedStats {
+    const COUNT_TABLE: &'static str = "tc_countspeedstats";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "vehclass",
+        "volume",
+        "avgspeed",
+        "speed50",
+        "speed85",
+        "pacestart",
+        "pacepct",
+        "overlimitpct",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {}
+            (recordnum, countdatetime, vehclass, volume, avgspeed, speed50, speed85, pacestart, \
+            pacepct, overlimitpct) \
+            VALUES (:1, :2, :3, :4, :5, :6, :7, :8, :9, :10)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.class,
+            &self.volume,
+            &self.mean,
+            &self.p50,
+            &self.p85,
+            &self.pace_start,
+            &self.pace_pct,
+            &self.over_limit_pct,
+        ])
     }
 
-    fn insert(&self, stmt: &mut Statement) -> Result<(), oracle::Error> {
-        stmt.execute(&[&self.recordnum, &self.datetime, &self.volume, &self.cntdir])
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
     }
 }
 
-/// Insert individual permanent bikeped count into database.
-pub fn insert_perm_bikeped_count(
+impl NonPermCrud for HourlySpeedStats {
+    const COUNT_TABLE: &'static str = "tc_speedstats";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "cntdir",
+        "volume",
+        "speed50",
+        "speed85",
+        "pacestart",
+        "pacepct",
+        "overlimitpct",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {}
+            (recordnum, countdatetime, countlane, cntdir, volume, speed50, speed85, pacestart, \
+            pacepct, overlimitpct) \
+            VALUES (:1, :2, :3, :4, :5, :6, :7, :8, :9, :10)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.lane,
+            &self.direction,
+            &self.volume,
+            &self.p50,
+            &self.p85,
+            &self.pace_start,
+            &self.pace_pct,
+            &self.over_limit_pct,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        Some(self.lane)
+    }
+}
+
+impl NonPermCrud for HourlyHeadway {
+    const COUNT_TABLE: &'static str = "tc_headway";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "cntdir",
+        "headways",
+        "meanheadway",
+        "platoonpct",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "h7",
+        "h8",
+        "h9",
+        "h10",
+        "h11",
+        "h12",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {} (
+            recordnum, countdatetime, countlane, cntdir, headways, meanheadway, platoonpct, \
+            h1, h2, h3, h4, h5, h6, h7, h8, h9, h10, h11, h12)
+            VALUES \
+            (:1, :2, :3, :4, :5, :6, :7, :8, :9, :10, :11, :12, :13, :14, :15, :16, :17, :18, \
+            :19)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.lane,
+            &self.direction,
+            &self.headways,
+            &self.mean,
+            &self.platoon_pct,
+            &self.h1,
+            &self.h2,
+            &self.h3,
+            &self.h4,
+            &self.h5,
+            &self.h6,
+            &self.h7,
+            &self.h8,
+            &self.h9,
+            &self.h10,
+            &self.h11,
+            &self.h12,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        Some(self.lane)
+    }
+}
+
+impl NonPermCrud for HourlyGaps {
+    const COUNT_TABLE: &'static str = "tc_gaps";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "criticalgap",
+        "gaps",
+        "acceptedgaps",
+        "maxgap",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {}
+            (recordnum, countdatetime, criticalgap, gaps, acceptedgaps, maxgap) \
+            VALUES (:1, :2, :3, :4, :5, :6)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.critical_gap,
+            &self.gaps,
+            &self.accepted,
+            &self.max_gap,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+}
+
+impl NonPermCrud for TimeBinnedSpeedClassCount {
+    const COUNT_TABLE: &'static str = "tc_speedclass";
+    const COLUMNS: &'static [&'static str] = &[
+        "recordnum",
+        "countdatetime",
+        "countlane",
+        "cntdir",
+        "vehclass",
+        "total",
+        "avgspeed",
+        "speed85",
+        "s1",
+        "s2",
+        "s3",
+        "s4",
+        "s5",
+        "s6",
+        "s7",
+        "s8",
+        "s9",
+        "s10",
+        "s11",
+        "s12",
+        "s13",
+        "s14",
+    ];
+
+    fn prepare_insert(conn: &Connection, batch_size: usize) -> Result<Batch<'_>, oracle::Error> {
+        let sql = &format!(
+            "insert into {} (
+            recordnum, countdatetime, countlane, cntdir, vehclass, total, avgspeed, speed85, \
+            s1, s2, s3, s4, s5, s6, s7, s8, s9, s10, s11, s12, s13, s14)
+            VALUES \
+            (:1, :2, :3, :4, :5, :6, :7, :8, :9, :10, :11, :12, :13, :14, :15, :16, :17, :18, \
+            :19, :20, :21, :22)",
+            &Self::COUNT_TABLE,
+        );
+        conn.batch(sql, batch_size).build()
+    }
+
+    fn insert(&self, batch: &mut Batch) -> Result<(), oracle::Error> {
+        batch.append_row(&[
+            &self.recordnum,
+            &self.datetime,
+            &self.lane,
+            &self.direction,
+            &self.class,
+            &self.total,
+            &self.avg_speed,
+            &self.p85,
+            &self.s1,
+            &self.s2,
+            &self.s3,
+            &self.s4,
+            &self.s5,
+            &self.s6,
+            &self.s7,
+            &self.s8,
+            &self.s9,
+            &self.s10,
+            &self.s11,
+            &self.s12,
+            &self.s13,
+            &self.s14,
+        ])
+    }
+
+    fn recordnum(&self) -> u32 {
+        self.recordnum
+    }
+
+    fn countdatetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn countlane(&self) -> Option<u8> {
+        Some(self.lane)
+    }
+}
+
+/// Delete the permanent bikeped counts, individual and aggregated, of dates (formatted as
+/// DD-MON-YY), sending `batch_size` dates at a time.
+///
+/// This is not committed, so that it can be part of a larger transaction.
+pub fn delete_perm_bikeped_dates(
+    conn: &Connection,
+    dates: &[String],
+    batch_size: usize,
+) -> Result<(), oracle::Error> {
+    for table in ["TBLCOUNTDATA", "TBLHEADER"] {
+        let mut batch = conn
+            .batch(
+                &format!("delete from {table} where to_char(COUNTDATE, 'DD-MON-YY')=:1"),
+                batch_size,
+            )
+            .build()?;
+        for date in dates {
+            batch.append_row(&[date])?;
+        }
+        batch.execute()?;
+    }
+    Ok(())
+}
+
+/// Insert individual permanent bikeped counts into database, sending `batch_size` of them at a
+/// time.
+///
+/// This is not committed, so that it can be part of a larger transaction.
+pub fn insert_perm_bikeped_counts(
     conn: &Connection,
-    count: PermBikePedCount,
-) -> Result<Statement, oracle::Error> {
-    // convert datetime to date
-    // the COUNTDATE field needs to be date only, allowing the database to set the default time
-    // because existing programs rely on that to do daily/hourly aggregation
-    let oracle_date = Timestamp::new(
-        count.datetime.year(),
-        count.datetime.month(),
-        count.datetime.day(),
-        0,
-        0,
-        0,
-        0,
-    )?;
-
-    // COUNTTIME is ok to be full datetime
-    let oracle_dt = Timestamp::new(
-        count.datetime.year(),
-        count.datetime.month(),
-        count.datetime.day(),
-        count.datetime.hour(),
-        count.datetime.minute(),
-        count.datetime.second(),
-        0,
-    )?;
-
-    conn.execute("insert into TBLCOUNTDATA (locationid, countdate, total, pedin, pedout, bikein, bikeout, counttime) values (:1, :2, :3, :4, :5, :6, :7, :8)",
-        &[
+    counts: &[PermBikePedCount],
+    batch_size: usize,
+) -> Result<(), oracle::Error> {
+    let mut batch = conn
+        .batch("insert into TBLCOUNTDATA (locationid, countdate, total, pedin, pedout, bikein, bikeout, counttime) values (:1, :2, :3, :4, :5, :6, :7, :8)", batch_size)
+        .build()?;
+
+    for count in counts {
+        // convert datetime to date
+        // the COUNTDATE field needs to be date only, allowing the database to set the default
+        // time because existing programs rely on that to do daily/hourly aggregation
+        let oracle_date = Timestamp::new(
+            count.datetime.year(),
+            count.datetime.month(),
+            count.datetime.day(),
+            0,
+            0,
+            0,
+            0,
+        )?;
+
+        // COUNTTIME is ok to be full datetime
+        let oracle_dt = Timestamp::new(
+            count.datetime.year(),
+            count.datetime.month(),
+            count.datetime.day(),
+            count.datetime.hour(),
+            count.datetime.minute(),
+            count.datetime.second(),
+            0,
+        )?;
+
+        batch.append_row(&[
             &count.location_id,
             &oracle_date,
             &count.total,
@@ -274,33 +1055,43 @@ pub fn insert_perm_bikeped_count(
             &count.bike_in,
             &count.bike_out,
             &oracle_dt,
-        ],
-    )
+        ])?;
+    }
+    batch.execute()
 }
 
-/// Insert aggregated permanent bikeped count into database.
-pub fn insert_aggregated_bikeped_count(
+/// Insert aggregated permanent bikeped counts into database, sending `batch_size` of them at a
+/// time.
+///
+/// This is not committed, so that it can be part of a larger transaction.
+pub fn insert_aggregated_bikeped_counts(
     conn: &Connection,
-    count: AggregatedPermBikePedCount,
-) -> Result<Statement, oracle::Error> {
-    // convert datetime
-    let oracle_dt = Timestamp::new(
-        count.date.year(),
-        count.date.month(),
-        count.date.day(),
-        0,
-        0,
-        0,
-        0,
-    )?;
-
-    conn.execute("insert into TBLHEADER (locationid, countdate, totalped, totalbike, total) values (:1, :2, :3, :4, :5)",
-        &[
+    counts: &[AggregatedPermBikePedCount],
+    batch_size: usize,
+) -> Result<(), oracle::Error> {
+    let mut batch = conn
+        .batch("insert into TBLHEADER (locationid, countdate, totalped, totalbike, total) values (:1, :2, :3, :4, :5)", batch_size)
+        .build()?;
+
+    for count in counts {
+        // convert datetime
+        let oracle_dt = Timestamp::new(
+            count.date.year(),
+            count.date.month(),
+            count.date.day(),
+            0,
+            0,
+            0,
+            0,
+        )?;
+
+        batch.append_row(&[
             &count.location_id,
             &oracle_dt,
             &count.total_ped,
             &count.total_bike,
             &count.total,
-        ],
-    )
+        ])?;
+    }
+    batch.execute()
 }
@@ -0,0 +1,522 @@
+//! An in-memory [`CountStore`], for running imports and data checks without a database.
+//!
+//! Records inserted into a [`MemoryStore`] are immediately visible, but, as in the database,
+//! changes can be [rolled back](CountStore::rollback) until they are committed (which inserting an
+//! import log entry or data check results also does). The AADV-related procedures can't be run
+//! outside of the database, so they are only recorded (see [`MemoryStore::procedure_calls`]), which
+//! is enough to verify that an import ran them.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::{Local, NaiveDateTime};
+
+use crate::{
+    db::{
+        crud::{CountSpan, NonPermCrud},
+        store::{CountRecord, CountStore},
+        ImportLogEntry,
+    },
+    non_perm::{
+        check_results::{CheckRecord, CheckResultFilter},
+        Directions, FifteenMinuteVehicle, HourlyVehicle, Metadata, NonPermCountKind,
+        TimeBinnedVehicleClassCount,
+    },
+    CountError,
+};
+
+/// Count metadata, data, and logs held in memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    metadata: RefCell<HashMap<u32, Metadata>>,
+    directions: RefCell<HashMap<u32, Directions>>,
+    // Records keyed by the name of the table they'd be in.
+    tables: RefCell<HashMap<&'static str, Vec<Rc<dyn Any>>>>,
+    // The status column of tc_header, which isn't part of Metadata.
+    statuses: RefCell<HashMap<u32, String>>,
+    import_log: RefCell<Vec<ImportLogEntry>>,
+    check_results: RefCell<Vec<CheckRecord>>,
+    check_runs: Cell<u32>,
+    procedure_calls: RefCell<Vec<(u32, &'static str)>>,
+    // Everything that can be rolled back, as of the last commit, if it's changed since.
+    committed: RefCell<Option<Snapshot>>,
+}
+
+#[derive(Clone)]
+struct Snapshot {
+    metadata: HashMap<u32, Metadata>,
+    directions: HashMap<u32, Directions>,
+    statuses: HashMap<u32, String>,
+    tables: HashMap<&'static str, Vec<Rc<dyn Any>>>,
+    procedure_calls: Vec<(u32, &'static str)>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a count's metadata and lane directions (the equivalent of a record in tc_header).
+    pub fn add_count(&self, recordnum: u32, mut metadata: Metadata, directions: Directions) {
+        metadata.recordnum = Some(recordnum);
+        self.metadata.borrow_mut().insert(recordnum, metadata);
+        self.directions.borrow_mut().insert(recordnum, directions);
+    }
+
+    /// The status of a count, as set when it's [imported](CountStore::mark_imported).
+    pub fn status(&self, recordnum: u32) -> Option<String> {
+        self.statuses.borrow().get(&recordnum).cloned()
+    }
+
+    /// The database procedures that would have been called, with the recordnum they were called
+    /// for, in the order they were called.
+    pub fn procedure_calls(&self) -> Vec<(u32, &'static str)> {
+        self.procedure_calls.borrow().clone()
+    }
+
+    fn record_call(&self, recordnum: u32, procedure: &'static str) -> Result<(), CountError> {
+        if !self.metadata.borrow().contains_key(&recordnum) {
+            return Err(CountError::DbError(format!(
+                "{recordnum} not found in metadata"
+            )));
+        }
+        self.begin();
+        self.procedure_calls
+            .borrow_mut()
+            .push((recordnum, procedure));
+        Ok(())
+    }
+
+    /// Keep the committed state before making the first change since the last commit.
+    fn begin(&self) {
+        let mut committed = self.committed.borrow_mut();
+        if committed.is_none() {
+            *committed = Some(Snapshot {
+                metadata: self.metadata.borrow().clone(),
+                directions: self.directions.borrow().clone(),
+                statuses: self.statuses.borrow().clone(),
+                tables: self.tables.borrow().clone(),
+                procedure_calls: self.procedure_calls.borrow().clone(),
+            });
+        }
+    }
+}
+
+impl CountStore for MemoryStore {
+    fn get_metadata(&self, recordnum: u32) -> Result<Metadata, CountError> {
+        self.metadata
+            .borrow()
+            .get(&recordnum)
+            .cloned()
+            .ok_or(CountError::DbError(format!(
+                "{recordnum} not found in metadata"
+            )))
+    }
+
+    fn get_count_kind(&self, recordnum: u32) -> Result<Option<NonPermCountKind>, CountError> {
+        Ok(self.get_metadata(recordnum)?.count_kind)
+    }
+
+    fn get_directions(&self, recordnum: u32) -> Result<Directions, CountError> {
+        self.directions
+            .borrow()
+            .get(&recordnum)
+            .cloned()
+            .ok_or(CountError::MissingDirection)
+    }
+
+    fn set_directions(&self, recordnum: u32, directions: &Directions) -> Result<(), CountError> {
+        if !self.metadata.borrow().contains_key(&recordnum) {
+            return Err(CountError::DbError(format!(
+                "{recordnum} not found in metadata"
+            )));
+        }
+        self.begin();
+        self.directions
+            .borrow_mut()
+            .insert(recordnum, directions.clone());
+        Ok(())
+    }
+
+    fn select_counts<T: CountRecord>(&self, recordnum: u32) -> Result<Vec<T>, CountError> {
+        Ok(self
+            .tables
+            .borrow()
+            .get(T::COUNT_TABLE)
+            .map(|records| {
+                records
+                    .iter()
+                    .filter_map(|record| record.downcast_ref::<T>())
+                    .filter(|record| record.recordnum() == recordnum)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn delete_counts<T: CountRecord>(&self, recordnum: u32) -> Result<(), CountError> {
+        self.begin();
+        if let Some(records) = self.tables.borrow_mut().get_mut(T::COUNT_TABLE) {
+            records.retain(|record| {
+                record
+                    .downcast_ref::<T>()
+                    .is_none_or(|record| record.recordnum() != recordnum)
+            });
+        }
+        Ok(())
+    }
+
+    fn delete_counts_in<T: CountRecord>(
+        &self,
+        recordnum: u32,
+        span: &CountSpan,
+    ) -> Result<(), CountError> {
+        self.begin();
+        if let Some(records) = self.tables.borrow_mut().get_mut(T::COUNT_TABLE) {
+            records.retain(|record| {
+                record
+                    .downcast_ref::<T>()
+                    .is_none_or(|record| record.recordnum() != recordnum || !span.contains(record))
+            });
+        }
+        Ok(())
+    }
+
+    fn insert_counts_batched<T: CountRecord>(
+        &self,
+        counts: &[T],
+        _batch_size: usize,
+    ) -> Result<(), CountError> {
+        self.begin();
+        let mut tables = self.tables.borrow_mut();
+        let records = tables.entry(T::COUNT_TABLE).or_default();
+        for count in counts {
+            records.push(Rc::new(count.clone()));
+        }
+        Ok(())
+    }
+
+    fn commit(&self) -> Result<(), CountError> {
+        self.committed.take();
+        Ok(())
+    }
+
+    fn rollback(&self) -> Result<(), CountError> {
+        if let Some(committed) = self.committed.take() {
+            *self.metadata.borrow_mut() = committed.metadata;
+            *self.directions.borrow_mut() = committed.directions;
+            *self.statuses.borrow_mut() = committed.statuses;
+            *self.tables.borrow_mut() = committed.tables;
+            *self.procedure_calls.borrow_mut() = committed.procedure_calls;
+        }
+        Ok(())
+    }
+
+    fn hourly_volumes(
+        &self,
+        recordnum: u32,
+        table: &str,
+        vol_field: &str,
+    ) -> Result<Vec<HourlyVehicle>, CountError> {
+        let no_column = || CountError::DbError(format!("no column {vol_field} in {table}"));
+        let volumes = if table == TimeBinnedVehicleClassCount::COUNT_TABLE {
+            self.select_counts::<TimeBinnedVehicleClassCount>(recordnum)?
+                .iter()
+                .map(|c| {
+                    let volume = class_count_column(c, vol_field).ok_or_else(no_column)?;
+                    let datetime = NaiveDateTime::new(c.date, c.time.time());
+                    Ok((datetime, c.direction, c.lane, volume))
+                })
+                .collect::<Result<Vec<_>, CountError>>()?
+        } else if table == FifteenMinuteVehicle::COUNT_TABLE {
+            if vol_field != "volcount" {
+                return Err(no_column());
+            }
+            self.select_counts::<FifteenMinuteVehicle>(recordnum)?
+                .iter()
+                .map(|c| {
+                    let datetime = NaiveDateTime::new(c.date, c.time.time());
+                    (datetime, c.direction, c.lane, c.count as u32)
+                })
+                .collect()
+        } else {
+            return Err(CountError::DbError(format!(
+                "unable to aggregate hourly volumes from {table}"
+            )));
+        };
+        HourlyVehicle::aggregate(recordnum, volumes)
+    }
+
+    fn mark_imported(&self, recordnum: u32) -> Result<(), CountError> {
+        self.begin();
+        match self.metadata.borrow_mut().get_mut(&recordnum) {
+            Some(v) => {
+                v.importdatadate = Some(Local::now().date_naive());
+                self.statuses
+                    .borrow_mut()
+                    .insert(recordnum, "imported".to_string());
+                Ok(())
+            }
+            None => Err(CountError::DbError(format!(
+                "{recordnum} not found in metadata"
+            ))),
+        }
+    }
+
+    fn update_intermediate_aadv(&self, recordnum: u32) -> Result<(), CountError> {
+        self.record_call(recordnum, "update_tc_countdate")
+    }
+
+    fn update_setdate(&self, recordnum: u32) -> Result<(), CountError> {
+        self.record_call(recordnum, "update_setdate")
+    }
+
+    fn calc_aadv(&self, recordnum: u32) -> Result<(), CountError> {
+        self.record_call(recordnum, "calc_aadv")
+    }
+
+    fn insert_import_log_entry(&self, mut log_record: ImportLogEntry) -> Result<(), CountError> {
+        log_record.datetime = Some(Local::now().naive_local());
+        self.import_log.borrow_mut().push(log_record);
+        self.commit()
+    }
+
+    fn get_import_log(&self, recordnum: Option<u32>) -> Result<Vec<ImportLogEntry>, CountError> {
+        // Most recent first, like the database query.
+        Ok(self
+            .import_log
+            .borrow()
+            .iter()
+            .rev()
+            .filter(|entry| recordnum.is_none_or(|v| v == entry.recordnum))
+            .cloned()
+            .collect())
+    }
+
+    fn next_check_run_id(&self) -> Result<u32, CountError> {
+        self.check_runs.set(self.check_runs.get() + 1);
+        Ok(self.check_runs.get())
+    }
+
+    fn insert_check_results(&self, results: &[CheckRecord]) -> Result<(), CountError> {
+        let checked = Local::now().naive_local();
+        self.check_results
+            .borrow_mut()
+            .extend(results.iter().cloned().map(|mut result| {
+                result.checked = Some(checked);
+                result
+            }));
+        self.commit()
+    }
+
+    fn select_check_results(
+        &self,
+        filter: &CheckResultFilter,
+    ) -> Result<Vec<CheckRecord>, CountError> {
+        let mut results = self
+            .check_results
+            .borrow()
+            .iter()
+            .filter(|result| filter.matches(result))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Ordered like the database query.
+        results.sort_by(|a, b| {
+            b.run_id
+                .cmp(&a.run_id)
+                .then_with(|| a.check_id.cmp(&b.check_id))
+        });
+        Ok(results)
+    }
+}
+
+/// The value of a column of a [`TimeBinnedVehicleClassCount`] in its table, by the column's name.
+fn class_count_column(count: &TimeBinnedVehicleClassCount, column: &str) -> Option<u32> {
+    let value = match column {
+        "bikes" => count.c1,
+        "cars_and_tlrs" => count.c2,
+        "ax2_long" => count.c3,
+        "buses" => count.c4,
+        "ax2_6_tire" => count.c5,
+        "ax3_single" => count.c6,
+        "ax4_single" => count.c7,
+        "lt_5_ax_double" => count.c8,
+        "ax5_double" => count.c9,
+        "gt_5_ax_double" => count.c10,
+        "lt_6_ax_multi" => count.c11,
+        "ax6_multi" => count.c12,
+        "gt_6_ax_multi" => count.c13,
+        // A null is nothing to a sum.
+        "unclassified" => count.c15.unwrap_or(0),
+        "total" => count.total,
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::non_perm::{FifteenMinuteBicycle, LaneDirection};
+    use crate::test_support::fifteen_min_vehicle;
+
+    fn store_with_count(recordnum: u32) -> MemoryStore {
+        let store = MemoryStore::new();
+        store.add_count(
+            recordnum,
+            Metadata {
+                count_kind: Some(NonPermCountKind::FifteenMinVolume),
+                ..Default::default()
+            },
+            Directions::new(vec![LaneDirection::East, LaneDirection::West], false).unwrap(),
+        );
+        store
+    }
+
+    #[test]
+    fn insert_select_and_delete_only_affect_one_recordnum() {
+        let store = store_with_count(1);
+        store
+            .insert_counts(&[
+                fifteen_min_vehicle(1, "2024-01-03 11:30", 49, 1),
+                fifteen_min_vehicle(2, "2024-01-03 11:30", 68, 1),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            store
+                .select_counts::<FifteenMinuteVehicle>(1)
+                .unwrap()
+                .len(),
+            1
+        );
+        // Different table, same recordnum.
+        assert!(store
+            .select_counts::<FifteenMinuteBicycle>(1)
+            .unwrap()
+            .is_empty());
+
+        store.delete_counts::<FifteenMinuteVehicle>(1).unwrap();
+        assert!(store
+            .select_counts::<FifteenMinuteVehicle>(1)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store
+                .select_counts::<FifteenMinuteVehicle>(2)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn hourly_volumes_sum_by_hour_direction_and_lane() {
+        let store = store_with_count(1);
+        store
+            .insert_counts(&[
+                fifteen_min_vehicle(1, "2024-01-03 11:30", 49, 1),
+                fifteen_min_vehicle(1, "2024-01-03 11:30", 68, 2),
+                fifteen_min_vehicle(1, "2024-01-03 11:45", 68, 1),
+                fifteen_min_vehicle(1, "2024-01-03 11:45", 78, 2),
+                fifteen_min_vehicle(1, "2024-01-03 12:00", 67, 1),
+            ])
+            .unwrap();
+
+        let mut hourly = store
+            .hourly_volumes(1, FifteenMinuteVehicle::COUNT_TABLE, "volcount")
+            .unwrap();
+        hourly.sort_unstable_by_key(|c| (c.datetime, c.lane));
+        assert_eq!(hourly.len(), 3);
+        assert_eq!(hourly[0].count, 117);
+        assert_eq!(hourly[0].direction, LaneDirection::East);
+        assert_eq!(hourly[1].count, 146);
+        assert_eq!(hourly[1].lane, 2);
+        assert_eq!(
+            hourly[2].datetime,
+            NaiveDate::from_ymd_opt(2024, 1, 3)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn hourly_volumes_sum_vol_field() {
+        let store = store_with_count(1);
+        let time = NaiveDate::from_ymd_opt(2024, 1, 3)
+            .unwrap()
+            .and_hms_opt(11, 30, 0)
+            .unwrap();
+        let count = TimeBinnedVehicleClassCount {
+            date: time.date(),
+            time,
+            lane: Some(1),
+            recordnum: 1,
+            direction: Some(LaneDirection::East),
+            c1: 0,
+            c2: 40,
+            c3: 0,
+            c4: 0,
+            c5: 0,
+            c6: 0,
+            c7: 0,
+            c8: 0,
+            c9: 0,
+            c10: 0,
+            c11: 0,
+            c12: 0,
+            c13: 0,
+            c15: Some(3),
+            total: 43,
+        };
+        store.insert_counts(&[count.clone(), count]).unwrap();
+
+        let table = TimeBinnedVehicleClassCount::COUNT_TABLE;
+        let volume = |field| store.hourly_volumes(1, table, field).map(|v| v[0].count);
+        assert_eq!(volume("total").unwrap(), 86);
+        assert_eq!(volume("cars_and_tlrs").unwrap(), 80);
+        assert_eq!(volume("unclassified").unwrap(), 6);
+        assert!(volume("c2").is_err());
+        assert!(store
+            .hourly_volumes(1, FifteenMinuteVehicle::COUNT_TABLE, "total")
+            .is_err());
+    }
+
+    #[test]
+    fn mark_imported_sets_import_date_and_status() {
+        let store = store_with_count(1);
+        store.mark_imported(1).unwrap();
+        assert_eq!(
+            store.get_metadata(1).unwrap().importdatadate,
+            Some(Local::now().date_naive())
+        );
+        assert_eq!(store.status(1).as_deref(), Some("imported"));
+        store.rollback().unwrap();
+        assert!(store.get_metadata(1).unwrap().importdatadate.is_none());
+        assert!(store.status(1).is_none());
+        assert!(store.mark_imported(2).is_err());
+    }
+
+    #[test]
+    fn set_directions_replaces_lanes() {
+        use LaneDirection::*;
+        let store = store_with_count(1);
+        let directions = Directions::new(vec![North, North, North, South, South], false).unwrap();
+        store.set_directions(1, &directions).unwrap();
+        assert_eq!(store.get_directions(1).unwrap().num_lanes(), 5);
+        assert!(store.set_directions(2, &directions).is_err());
+    }
+
+    #[test]
+    fn procedures_err_for_unknown_recordnum() {
+        let store = store_with_count(1);
+        assert!(store.update_intermediate_aadv(1).is_ok());
+        assert!(store.calc_aadv(2).is_err());
+        assert_eq!(store.procedure_calls(), vec![(1, "update_tc_countdate")]);
+    }
+}
@@ -0,0 +1,520 @@
+//! Numbered schema migrations, and tracking which of them have been applied.
+//!
+//! Changes to the schema of the non-permanent counts database are made by migrations: SQL files
+//! in the migrations/ directory, named with their version number (e.g.
+//! 0003_speed_distributions.sql), which are embedded in the crate as [`MIGRATIONS`]. The versions
+//! applied to a database are kept in its schema_version table (created when first needed), so
+//! that the test and production databases are brought to the same schema by
+//! [applying](apply_pending) whatever is [pending] in each. [`verify`] then checks that a
+//! database has every table and column that the crate expects.
+//!
+//! The schema that db_migrations.sql records is the baseline that the migrations start from. A
+//! database that has already had some of the migrations made to it by hand can be marked as
+//! having had them with [`baseline`], rather than applying them again.
+//!
+//! Each statement of a migration ends with a semicolon at the end of a line, except PL/SQL blocks
+//! (`begin`, `declare`, or `create or replace` of a trigger, procedure, function, or package),
+//! which end with a line of only `/`, as in SQL*Plus. Oracle commits DDL statements as they are
+//! run, so a migration that fails partway through may be partly applied; it isn't recorded as
+//! applied, and needs to be cleaned up by hand before it is applied again.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use chrono::NaiveDateTime;
+use oracle::Connection;
+
+use crate::{
+    db::crud::NonPermCrud,
+    non_perm::{
+        class_scheme::TimeBinnedLengthClassCount,
+        headway::{HourlyGaps, HourlyHeadway},
+        speed_bins::TimeBinnedSpeedDistribution,
+        speed_class::TimeBinnedSpeedClassCount,
+        speed_stats::{CountSpeedStats, HourlySpeedStats},
+        turning_movement::{PeakHour, TurningMovementCount},
+        FifteenMinuteBicycle, FifteenMinutePedestrian, FifteenMinuteVehicle, HourlyAvgSpeed,
+        HourlyVehicle, Metadata, TimeBinnedSpeedRangeCount, TimeBinnedVehicleClassCount,
+    },
+    CountError,
+};
+
+/// A change to the schema of the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// The statements of the migration, in order, without their terminating semicolon or `/`.
+    pub fn statements(&self) -> Vec<String> {
+        split_statements(self.sql)
+    }
+}
+
+/// Every migration, in order of version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "turning_movement_counts",
+        sql: include_str!("../../migrations/0001_turning_movement_counts.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "lane_directions",
+        sql: include_str!("../../migrations/0002_lane_directions.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "speed_distributions",
+        sql: include_str!("../../migrations/0003_speed_distributions.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "speed_stats",
+        sql: include_str!("../../migrations/0004_speed_stats.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "speed_class",
+        sql: include_str!("../../migrations/0005_speed_class.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "headways_and_gaps",
+        sql: include_str!("../../migrations/0006_headways_and_gaps.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "length_class_counts",
+        sql: include_str!("../../migrations/0007_length_class_counts.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "data_check_results",
+        sql: include_str!("../../migrations/0008_data_check_results.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "turning_movement_peak_hours",
+        sql: include_str!("../../migrations/0009_turning_movement_peak_hours.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "count_speed_stats",
+        sql: include_str!("../../migrations/0010_count_speed_stats.sql"),
+    },
+];
+
+/// A migration that has been applied to a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub applied: NaiveDateTime,
+}
+
+/// Get the migrations that have been applied to a database, in order of version.
+pub fn applied(conn: &Connection) -> Result<Vec<AppliedMigration>, CountError> {
+    create_version_table(conn)?;
+    let rows = conn.query_as::<(u32, String, NaiveDateTime)>(
+        "select version, name, applied from schema_version order by version",
+        &[],
+    )?;
+    let mut applied = vec![];
+    for row in rows {
+        let (version, name, applied_at) = row?;
+        applied.push(AppliedMigration {
+            version,
+            name,
+            applied: applied_at,
+        });
+    }
+    Ok(applied)
+}
+
+/// Get the migrations that haven't been applied to a database, in order of version.
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, CountError> {
+    let applied = applied(conn)?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect::<Vec<_>>();
+    Ok(not_applied(&applied))
+}
+
+/// Apply the pending migrations to a database, in order of version, returning those applied.
+///
+/// Each migration is recorded in the schema_version table, and committed, once all of its
+/// statements have run; if one fails, those before it remain applied.
+pub fn apply_pending(conn: &Connection) -> Result<Vec<&'static Migration>, CountError> {
+    let pending = pending(conn)?;
+    for migration in &pending {
+        for statement in migration.statements() {
+            conn.execute(&statement, &[])
+                .map_err(|source| CountError::MigrationFailed {
+                    version: migration.version,
+                    name: migration.name,
+                    statement: statement.clone(),
+                    source: Box::new(source),
+                })?;
+        }
+        record(conn, migration)?;
+        conn.commit()?;
+    }
+    Ok(pending)
+}
+
+/// Record the migrations up to and including `version` as applied to a database, without
+/// applying them, returning those recorded.
+///
+/// This is for a database that already had the changes of these migrations made to it.
+pub fn baseline(conn: &Connection, version: u32) -> Result<Vec<&'static Migration>, CountError> {
+    if !MIGRATIONS
+        .iter()
+        .any(|migration| migration.version == version)
+    {
+        return Err(CountError::UnknownMigration(version));
+    }
+    let recorded = pending(conn)?
+        .into_iter()
+        .filter(|migration| migration.version <= version)
+        .collect::<Vec<_>>();
+    for migration in &recorded {
+        record(conn, migration)?;
+    }
+    conn.commit()?;
+    Ok(recorded)
+}
+
+/// The migrations whose version isn't one of those `applied`.
+fn not_applied(applied: &[u32]) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect()
+}
+
+fn record(conn: &Connection, migration: &Migration) -> Result<(), CountError> {
+    conn.execute(
+        "insert into schema_version (version, name) values (:1, :2)",
+        &[&migration.version, &migration.name],
+    )?;
+    Ok(())
+}
+
+/// Create the table that tracks applied migrations, if it doesn't exist.
+fn create_version_table(conn: &Connection) -> Result<(), CountError> {
+    let exists = conn.query_row_as::<u32>(
+        "select count(*) from user_tables where table_name = 'SCHEMA_VERSION'",
+        &[],
+    )?;
+    if exists == 0 {
+        conn.execute(
+            "create table schema_version (
+                version number primary key,
+                name varchar2(100) not null,
+                applied date default current_date not null
+            )",
+            &[],
+        )?;
+    }
+    Ok(())
+}
+
+/// Split SQL into statements.
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = vec![];
+    let mut statement = String::new();
+    for line in sql.lines() {
+        let trimmed = line.trim();
+        if statement.is_empty() && (trimmed.is_empty() || trimmed.starts_with("--")) {
+            continue;
+        }
+        if is_plsql(&statement) {
+            if trimmed == "/" {
+                statements.push(statement.trim_end().to_string());
+                statement.clear();
+            } else {
+                statement.push_str(line);
+                statement.push('\n');
+            }
+            continue;
+        }
+        // Ignore the semicolon of a comment.
+        let code = line.split("--").next().unwrap_or_default().trim_end();
+        if code.ends_with(';') && !is_plsql(&format!("{statement}{code}")) {
+            statement.push_str(&code[..code.len() - 1]);
+            statements.push(statement.trim_end().to_string());
+            statement.clear();
+        } else {
+            statement.push_str(line);
+            statement.push('\n');
+        }
+    }
+    if !statement.trim().is_empty() {
+        statements.push(statement.trim().to_string());
+    }
+    statements
+}
+
+/// Whether a statement is a PL/SQL block, which ends with a `/` line rather than a semicolon.
+fn is_plsql(statement: &str) -> bool {
+    let words = statement
+        .split_whitespace()
+        .take(5)
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+    match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["begin", ..] | ["declare", ..] => true,
+        ["create", "or", "replace", kind, ..] => matches!(
+            kind,
+            "trigger" | "procedure" | "function" | "package" | "type"
+        ),
+        _ => false,
+    }
+}
+
+/// The columns of a table that the crate expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableSchema {
+    pub table: &'static str,
+    pub columns: Vec<&'static str>,
+}
+
+impl TableSchema {
+    fn of<T: NonPermCrud>() -> Self {
+        Self {
+            table: T::COUNT_TABLE,
+            columns: T::COLUMNS.to_vec(),
+        }
+    }
+}
+
+/// The tables and columns the crate expects: those of every [`NonPermCrud`] impl and of
+/// [`Metadata`], as well as others it queries directly.
+pub fn expected_schema() -> Vec<TableSchema> {
+    let mut tc_header = Metadata::COLUMNS.to_vec();
+    tc_header.extend(["cldir1", "cldir2", "cldir3", "onewaybike", "status"]);
+    vec![
+        TableSchema::of::<CountSpeedStats>(),
+        TableSchema::of::<FifteenMinuteBicycle>(),
+        TableSchema::of::<FifteenMinutePedestrian>(),
+        TableSchema::of::<FifteenMinuteVehicle>(),
+        TableSchema::of::<HourlyAvgSpeed>(),
+        TableSchema::of::<HourlyGaps>(),
+        TableSchema::of::<HourlyHeadway>(),
+        TableSchema::of::<HourlySpeedStats>(),
+        TableSchema::of::<HourlyVehicle>(),
+        TableSchema::of::<PeakHour>(),
+        TableSchema::of::<TimeBinnedLengthClassCount>(),
+        TableSchema::of::<TimeBinnedSpeedClassCount>(),
+        TableSchema::of::<TimeBinnedSpeedDistribution>(),
+        TableSchema::of::<TimeBinnedSpeedRangeCount>(),
+        TableSchema::of::<TimeBinnedVehicleClassCount>(),
+        TableSchema::of::<TurningMovementCount>(),
+        TableSchema {
+            table: "tc_header",
+            columns: tc_header,
+        },
+        TableSchema {
+            table: "tc_lanedir",
+            columns: vec!["recordnum", "lane", "direction"],
+        },
+        TableSchema {
+            table: "import_log",
+            columns: vec!["recordnum", "datetime", "message", "log_level"],
+        },
+        TableSchema {
+            table: "tc_datacheck",
+            columns: vec![
+                "runid",
+                "recordnum",
+                "checkid",
+                "stage",
+                "severity",
+                "measured",
+                "threshold",
+                "message",
+                "checkdatetime",
+            ],
+        },
+        TableSchema {
+            table: "tc_datacheck_period",
+            columns: vec!["runid", "checkid", "periodstart", "periodend"],
+        },
+        TableSchema {
+            table: "schema_version",
+            columns: vec!["version", "name", "applied"],
+        },
+    ]
+}
+
+/// A difference between the schema of a database and what the crate expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaMismatch {
+    MissingTable(&'static str),
+    MissingColumn {
+        table: &'static str,
+        column: &'static str,
+    },
+}
+
+impl Display for SchemaMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaMismatch::MissingTable(table) => write!(f, "table {table} is missing"),
+            SchemaMismatch::MissingColumn { table, column } => {
+                write!(f, "column {column} of table {table} is missing")
+            }
+        }
+    }
+}
+
+/// Verify that a database has the [tables and columns the crate expects](expected_schema),
+/// returning any that it doesn't.
+pub fn verify(conn: &Connection) -> Result<Vec<SchemaMismatch>, CountError> {
+    create_version_table(conn)?;
+    let mut mismatches = vec![];
+    for schema in expected_schema() {
+        let rows = conn.query_as::<String>(
+            "select lower(column_name) from user_tab_columns where table_name = upper(:1)",
+            &[&schema.table],
+        )?;
+        let mut columns = HashSet::new();
+        for row in rows {
+            columns.insert(row?);
+        }
+        mismatches.extend(missing(&schema, &columns));
+    }
+    Ok(mismatches)
+}
+
+/// The ways that the columns a table has fall short of its schema.
+fn missing(schema: &TableSchema, columns: &HashSet<String>) -> Vec<SchemaMismatch> {
+    if columns.is_empty() {
+        return vec![SchemaMismatch::MissingTable(schema.table)];
+    }
+    schema
+        .columns
+        .iter()
+        .filter(|column| !columns.contains(**column))
+        .map(|column| SchemaMismatch::MissingColumn {
+            table: schema.table,
+            column,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn every_migration_file_included_in_order() {
+        let mut files = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        let expected = MIGRATIONS
+            .iter()
+            .map(|m| format!("{:04}_{}.sql", m.version, m.name))
+            .collect::<Vec<_>>();
+        assert_eq!(files, expected);
+        assert!(MIGRATIONS
+            .iter()
+            .enumerate()
+            .all(|(i, m)| m.version as usize == i + 1));
+        assert!(MIGRATIONS.iter().all(|m| !m.statements().is_empty()));
+    }
+
+    #[test]
+    fn lane_constraints_relaxed_on_count_tables() {
+        let statements = MIGRATIONS[1].statements();
+        assert_eq!(statements.len(), 7);
+        assert!(statements[1].starts_with("begin") && statements[1].ends_with("end;"));
+        for table in [
+            TimeBinnedSpeedRangeCount::COUNT_TABLE,
+            TimeBinnedVehicleClassCount::COUNT_TABLE,
+            FifteenMinuteVehicle::COUNT_TABLE,
+            HourlyVehicle::COUNT_TABLE,
+            HourlyAvgSpeed::COUNT_TABLE,
+        ] {
+            assert!(
+                statements[1].contains(&format!("'{}'", table.to_uppercase())),
+                "{table}"
+            );
+            assert!(
+                statements[2..]
+                    .iter()
+                    .any(|s| s.split_whitespace().nth(2) == Some(table)),
+                "{table}"
+            );
+        }
+    }
+
+    #[test]
+    fn statements_split_at_semicolons_and_plsql_at_slashes() {
+        let sql = "-- A comment.
+create table t (
+    a number -- not the end;
+);
+alter table t add b number;
+
+create or replace trigger t_trigger
+    before insert on t
+    for each row
+begin
+    select 1 into :new.a from dual;
+end;
+/
+update t set a = 1;
+";
+        assert_eq!(
+            split_statements(sql),
+            vec![
+                "create table t (\n    a number -- not the end;\n)",
+                "alter table t add b number",
+                "create or replace trigger t_trigger\n    before insert on t\n    for each row\n\
+                begin\n    select 1 into :new.a from dual;\nend;",
+                "update t set a = 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_are_those_not_applied() {
+        let versions =
+            |migrations: Vec<&Migration>| migrations.iter().map(|m| m.version).collect::<Vec<_>>();
+        assert_eq!(versions(not_applied(&[])).len(), MIGRATIONS.len());
+        // Including any skipped, e.g. from a branch merged after later migrations were applied.
+        assert_eq!(
+            versions(not_applied(&[2, 3, 4, 5, 6])),
+            vec![1, 7, 8, 9, 10]
+        );
+    }
+
+    #[test]
+    fn missing_tables_and_columns_found() {
+        let schema = TableSchema {
+            table: "tc_lanedir",
+            columns: vec!["recordnum", "lane", "direction"],
+        };
+        assert_eq!(
+            missing(&schema, &HashSet::new()),
+            vec![SchemaMismatch::MissingTable("tc_lanedir")]
+        );
+        let columns = ["recordnum", "direction", "other"].map(String::from).into();
+        assert_eq!(
+            missing(&schema, &columns),
+            vec![SchemaMismatch::MissingColumn {
+                table: "tc_lanedir",
+                column: "lane"
+            }]
+        );
+    }
+}
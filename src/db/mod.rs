@@ -9,40 +9,46 @@
 //! hours may not be a full hour of count data.
 
 pub mod crud;
+pub mod memory_store;
+pub mod migrations;
 pub mod oracle_impls;
+pub mod store;
+pub mod unit_of_work;
 
-use std::env;
+use std::collections::HashMap;
 use std::fmt::Display;
 
 use chrono::NaiveDateTime;
 use log::Level;
 use oracle::{
     pool::{Pool, PoolBuilder},
+    sql_type::ToSql,
     Connection, Error as OracleError,
 };
 use serde::Serialize;
 
 use crate::{
-    non_perm::{Metadata, NonPermCountKind},
+    config::{self, DbConfig},
+    non_perm::{
+        check_results::{CheckPeriod, CheckRecord, CheckResultFilter},
+        Metadata, NonPermCountKind,
+    },
     CountError,
 };
 
 /// The maximum number of empty metadata records allowed to be created.
 pub const RECORD_CREATION_LIMIT: u32 = 50;
 
-/// Get database credentials from environment variable.
-pub fn get_non_perm_creds() -> (String, String) {
-    dotenvy::dotenv().expect("Unable to load .env file.");
-
-    (
-        env::var("NON_PERM_DB_USERNAME").unwrap(),
-        env::var("NON_PERM_DB_PASSWORD").unwrap(),
-    )
+/// Get the configuration of the non-permanent counts database.
+///
+/// Panics, listing every problem with the configuration, if it's invalid.
+pub fn get_non_perm_creds() -> DbConfig {
+    config::non_perm_db().unwrap_or_else(|e| panic!("{e}"))
 }
 
 /// Create a connection pool.
-pub fn create_pool(username: String, password: String, max_conn: u32) -> Result<Pool, OracleError> {
-    PoolBuilder::new(username, password, "dvrpcprod_tp_tls")
+pub fn create_pool(db: &DbConfig, max_conn: u32) -> Result<Pool, OracleError> {
+    PoolBuilder::new(&db.username, &db.password, &db.tns_alias)
         .max_connections(max_conn)
         .build()
 }
@@ -141,6 +147,123 @@ pub fn get_import_log(
     Ok(log_records)
 }
 
+/// Get a new id for a run of the data checks.
+pub fn next_check_run_id(conn: &Connection) -> Result<u32, oracle::Error> {
+    conn.query_row_as::<u32>("select tc_datacheck_run_seq.nextval from dual", &[])
+}
+
+/// Insert the [results](CheckRecord) of a run of the data checks, with the periods they concern,
+/// and commit.
+pub fn insert_check_results(
+    conn: &Connection,
+    results: &[CheckRecord],
+) -> Result<(), oracle::Error> {
+    let mut result_stmt = conn
+        .statement(
+            "insert into tc_datacheck \
+            (runid, recordnum, checkid, stage, severity, measured, threshold, message) \
+            values (:1, :2, :3, :4, :5, :6, :7, :8)",
+        )
+        .build()?;
+    let mut period_stmt = conn
+        .statement(
+            "insert into tc_datacheck_period (runid, checkid, periodstart, periodend) \
+            values (:1, :2, :3, :4)",
+        )
+        .build()?;
+    for result in results {
+        // The message column holds at most 4000 bytes.
+        let mut end = result.message.len().min(4000);
+        while !result.message.is_char_boundary(end) {
+            end -= 1;
+        }
+        result_stmt.execute(&[
+            &result.run_id,
+            &result.recordnum,
+            &result.check_id,
+            &result.stage,
+            &result.severity,
+            &result.measured,
+            &result.threshold,
+            &&result.message[..end],
+        ])?;
+        for period in &result.periods {
+            period_stmt.execute(&[&result.run_id, &result.check_id, &period.start, &period.end])?;
+        }
+    }
+    conn.commit()
+}
+
+/// Get the [results](CheckRecord) of data checks that match a filter, most recent run first and
+/// then by check.
+pub fn select_check_results(
+    conn: &Connection,
+    filter: &CheckResultFilter,
+) -> Result<Vec<CheckRecord>, oracle::Error> {
+    let severity = filter.severity.map(|v| v.as_str());
+    let criteria: [(&str, Option<&dyn ToSql>); 9] = [
+        ("d.recordnum =", filter.recordnum.as_ref().map(|v| v as _)),
+        ("d.runid =", filter.run_id.as_ref().map(|v| v as _)),
+        ("d.checkid =", filter.check_id.as_ref().map(|v| v as _)),
+        ("d.stage =", filter.stage.as_ref().map(|v| v as _)),
+        ("d.severity =", severity.as_ref().map(|v| v as _)),
+        (
+            "d.measured >=",
+            filter.min_measured.as_ref().map(|v| v as _),
+        ),
+        (
+            "d.measured <=",
+            filter.max_measured.as_ref().map(|v| v as _),
+        ),
+        ("d.checkdatetime >=", filter.from.as_ref().map(|v| v as _)),
+        ("d.checkdatetime <", filter.to.as_ref().map(|v| v as _)),
+    ];
+    let mut conditions = vec![];
+    let mut params = vec![];
+    for (condition, param) in criteria {
+        if let Some(param) = param {
+            params.push(param);
+            conditions.push(format!("{condition} :{}", params.len()));
+        }
+    }
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" where {}", conditions.join(" and "))
+    };
+
+    let mut periods: HashMap<(u32, String), Vec<CheckPeriod>> = HashMap::new();
+    let rows = conn.query_as::<(u32, String, NaiveDateTime, NaiveDateTime)>(
+        &format!(
+            "select p.runid, p.checkid, p.periodstart, p.periodend from tc_datacheck_period p \
+            join tc_datacheck d on d.runid = p.runid and d.checkid = p.checkid{where_clause} \
+            order by p.periodstart"
+        ),
+        &params,
+    )?;
+    for row in rows {
+        let (run_id, check_id, start, end) = row?;
+        periods
+            .entry((run_id, check_id))
+            .or_default()
+            .push(CheckPeriod { start, end });
+    }
+
+    let rows = conn.query_as::<CheckRecord>(
+        &format!("select d.* from tc_datacheck d{where_clause} order by d.runid desc, d.checkid"),
+        &params,
+    )?;
+    let mut results = vec![];
+    for row in rows {
+        let mut result = row?;
+        if let Some(v) = periods.remove(&(result.run_id, result.check_id.clone())) {
+            result.periods = v;
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
 /// Get total number of records in [`Metadata`] table.
 pub fn get_metadata_total_recs(conn: &Connection) -> Result<u32, CountError> {
     Ok(conn.query_row_as::<u32>("select count(*) from tc_header", &[])?)
@@ -306,14 +429,14 @@ mod tests {
 
     #[test]
     fn create_pool_succeeds() {
-        let (username, password) = get_creds();
-        assert!(create_pool(username, password, 1).is_ok())
+        let db_config = get_non_perm_creds();
+        assert!(create_pool(&db_config, 1).is_ok())
     }
 
     #[test]
     fn select_type_correct() {
-        let (username, password) = get_creds();
-        let pool = create_pool(username, password, 1).unwrap();
+        let db_config = get_non_perm_creds();
+        let pool = create_pool(&db_config, 1).unwrap();
         let conn = pool.get().unwrap();
 
         let count_type = conn
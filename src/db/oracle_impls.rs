@@ -5,12 +5,16 @@ use chrono::NaiveDateTime;
 use log::Level;
 use oracle::{
     sql_type::{FromSql, OracleType, ToSql, ToSqlNull},
-    Connection, Error as OracleError, RowValue, SqlValue,
+    Connection, Error as OracleError, ErrorKind, RowValue, SqlValue,
 };
 
 use crate::{
     db::ImportLogEntry,
-    non_perm::{LaneDirection, NonPermCountKind, RoadDirection},
+    non_perm::{
+        check_results::{CheckRecord, CheckStage},
+        turning_movement::Movement,
+        LaneDirection, NonPermCountKind, RoadDirection,
+    },
     CountError,
 };
 
@@ -18,8 +22,10 @@ impl FromSql for NonPermCountKind {
     fn from_sql(val: &SqlValue<'_>) -> oracle::Result<Self> {
         match NonPermCountKind::from_str(&val.to_string()) {
             Ok(v) => Ok(v),
-            Err(CountError::UnknownCountType(_)) => Err(OracleError::NullValue),
-            Err(e) => Err(OracleError::ParseError(Box::new(e))),
+            Err(CountError::UnknownCountType(_)) => {
+                Err(OracleError::new(ErrorKind::NullValue, "NULL value found"))
+            }
+            Err(e) => Err(OracleError::with_source(ErrorKind::ParseError, e)),
         }
     }
 }
@@ -37,12 +43,50 @@ impl RowValue for ImportLogEntry {
     }
 }
 
+impl RowValue for CheckRecord {
+    fn get(row: &oracle::Row) -> oracle::Result<Self> {
+        let level: String = row.get("severity")?;
+        let level = Level::from_str(level.as_str())
+            .map_err(|e| OracleError::with_source(ErrorKind::ParseError, e))?;
+        let mut record = CheckRecord::new(
+            row.get("runid")?,
+            row.get("recordnum")?,
+            &row.get::<_, String>("checkid")?,
+            row.get("stage")?,
+            level,
+            row.get("message")?,
+        );
+        record.measured = row.get("measured")?;
+        record.threshold = row.get("threshold")?;
+        record.checked = Some(row.get("checkdatetime")?);
+        Ok(record)
+    }
+}
+
+impl FromSql for CheckStage {
+    fn from_sql(val: &SqlValue<'_>) -> oracle::Result<Self> {
+        CheckStage::from_str(&val.to_string())
+            .map_err(|e| OracleError::with_source(ErrorKind::ParseError, e))
+    }
+}
+
+impl ToSql for CheckStage {
+    fn oratype(&self, _conn: &Connection) -> oracle::Result<OracleType> {
+        Ok(OracleType::NVarchar2(format!("{self}").len() as u32))
+    }
+    fn to_sql(&self, val: &mut SqlValue<'_>) -> oracle::Result<()> {
+        format!("{self}").to_sql(val)
+    }
+}
+
 impl FromSql for LaneDirection {
     fn from_sql(val: &SqlValue<'_>) -> oracle::Result<Self> {
         match LaneDirection::from_str(&val.to_string()) {
             Ok(v) => Ok(v),
-            Err(CountError::BadDirection(_)) => Err(OracleError::NullValue),
-            Err(e) => Err(OracleError::ParseError(Box::new(e))),
+            Err(CountError::BadDirection(_)) => {
+                Err(OracleError::new(ErrorKind::NullValue, "NULL value found"))
+            }
+            Err(e) => Err(OracleError::with_source(ErrorKind::ParseError, e)),
         }
     }
 }
@@ -66,8 +110,10 @@ impl FromSql for RoadDirection {
     fn from_sql(val: &SqlValue<'_>) -> oracle::Result<Self> {
         match RoadDirection::from_str(&val.to_string()) {
             Ok(v) => Ok(v),
-            Err(CountError::BadDirection(_)) => Err(OracleError::NullValue),
-            Err(e) => Err(OracleError::ParseError(Box::new(e))),
+            Err(CountError::BadDirection(_)) => {
+                Err(OracleError::new(ErrorKind::NullValue, "NULL value found"))
+            }
+            Err(e) => Err(OracleError::with_source(ErrorKind::ParseError, e)),
         }
     }
 }
@@ -101,3 +147,30 @@ impl ToSqlNull for NonPermCountKind {
         Ok(OracleType::NVarchar2(0))
     }
 }
+
+impl FromSql for Movement {
+    fn from_sql(val: &SqlValue<'_>) -> oracle::Result<Self> {
+        match Movement::from_str(&val.to_string()) {
+            Ok(v) => Ok(v),
+            Err(CountError::BadMovement(_)) => {
+                Err(OracleError::new(ErrorKind::NullValue, "NULL value found"))
+            }
+            Err(e) => Err(OracleError::with_source(ErrorKind::ParseError, e)),
+        }
+    }
+}
+
+impl ToSql for Movement {
+    fn oratype(&self, _conn: &Connection) -> oracle::Result<OracleType> {
+        Ok(OracleType::NVarchar2(format!("{self}").len() as u32))
+    }
+    fn to_sql(&self, val: &mut SqlValue<'_>) -> oracle::Result<()> {
+        format!("{self}").to_sql(val)
+    }
+}
+
+impl ToSqlNull for Movement {
+    fn oratype_for_null(_conn: &Connection) -> oracle::Result<OracleType> {
+        Ok(OracleType::NVarchar2(0))
+    }
+}
@@ -0,0 +1,232 @@
+//! Storage backends for non-permanent counts.
+//!
+//! [`CountStore`] covers everything the import program and the
+//! [data checks][crate::non_perm::check_data] need from a database: count metadata, the count
+//! tables (via [`NonPermCrud`]), the import log, the results of the data checks, and the
+//! procedures that update AADV. It is implemented for an Oracle [`Connection`], which is what the
+//! programs use, and for [`MemoryStore`](super::memory_store::MemoryStore), which allows the import
+//! pipeline and data checks to be run without a database.
+
+use oracle::{Connection, RowValue};
+
+use crate::{
+    db::{
+        self,
+        crud::{CountSpan, NonPermCrud, DEFAULT_BATCH_SIZE},
+        ImportLogEntry,
+    },
+    non_perm::{
+        check_results::{CheckRecord, CheckResultFilter},
+        Directions, HourlyVehicle, Metadata, NonPermCountKind,
+    },
+    CountError,
+};
+
+/// A record of one of the count tables that can be kept in a [`CountStore`].
+pub trait CountRecord: NonPermCrud + RowValue + Clone + 'static {}
+
+impl<T> CountRecord for T where T: NonPermCrud + RowValue + Clone + 'static {}
+
+/// A trait for storing and retrieving non-permanent counts.
+pub trait CountStore {
+    /// Get the [`Metadata`] of a count.
+    fn get_metadata(&self, recordnum: u32) -> Result<Metadata, CountError>;
+
+    /// Get the type of count for a given record number.
+    fn get_count_kind(&self, recordnum: u32) -> Result<Option<NonPermCountKind>, CountError>;
+
+    /// Get the lane [`Directions`] of a count.
+    fn get_directions(&self, recordnum: u32) -> Result<Directions, CountError>;
+
+    /// Set the lane [`Directions`] of a count, replacing any existing ones.
+    ///
+    /// This is not committed; see [`CountStore::commit`].
+    fn set_directions(&self, recordnum: u32, directions: &Directions) -> Result<(), CountError>;
+
+    /// Select all records of a count from the table of `T`.
+    fn select_counts<T: CountRecord>(&self, recordnum: u32) -> Result<Vec<T>, CountError>;
+
+    /// Delete all records of a count from the table of `T`.
+    ///
+    /// This is not committed; see [`CountStore::commit`].
+    fn delete_counts<T: CountRecord>(&self, recordnum: u32) -> Result<(), CountError>;
+
+    /// Delete the records of a count within a [`CountSpan`] from the table of `T`.
+    ///
+    /// This is not committed; see [`CountStore::commit`].
+    fn delete_counts_in<T: CountRecord>(
+        &self,
+        recordnum: u32,
+        span: &CountSpan,
+    ) -> Result<(), CountError>;
+
+    /// Insert records into the table of `T`, sending `batch_size` of them to the database at a
+    /// time.
+    ///
+    /// These are not committed; see [`CountStore::commit`].
+    fn insert_counts_batched<T: CountRecord>(
+        &self,
+        counts: &[T],
+        batch_size: usize,
+    ) -> Result<(), CountError>;
+
+    /// Insert records into the table of `T`, [`DEFAULT_BATCH_SIZE`] at a time.
+    ///
+    /// These are not committed; see [`CountStore::commit`].
+    fn insert_counts<T: CountRecord>(&self, counts: &[T]) -> Result<(), CountError> {
+        self.insert_counts_batched(counts, DEFAULT_BATCH_SIZE)
+    }
+
+    /// Commit any outstanding inserts/updates.
+    fn commit(&self) -> Result<(), CountError>;
+
+    /// Undo any outstanding inserts/updates.
+    fn rollback(&self) -> Result<(), CountError>;
+
+    /// Aggregate the 15-minute volumes in `table` (summing `vol_field`) into [`HourlyVehicle`]s.
+    fn hourly_volumes(
+        &self,
+        recordnum: u32,
+        table: &str,
+        vol_field: &str,
+    ) -> Result<Vec<HourlyVehicle>, CountError>;
+
+    /// Set the import date and status of a count's metadata.
+    ///
+    /// This is not committed; see [`CountStore::commit`].
+    fn mark_imported(&self, recordnum: u32) -> Result<(), CountError>;
+
+    /// Update the intermediate table that AADV calculation depends on.
+    fn update_intermediate_aadv(&self, recordnum: u32) -> Result<(), CountError>;
+
+    /// Update setdate - first day of full data, not falling on certain days.
+    fn update_setdate(&self, recordnum: u32) -> Result<(), CountError>;
+
+    /// Calculate and insert AADV.
+    fn calc_aadv(&self, recordnum: u32) -> Result<(), CountError>;
+
+    /// Insert an [`ImportLogEntry`], committing it along with anything outstanding.
+    fn insert_import_log_entry(&self, log_record: ImportLogEntry) -> Result<(), CountError>;
+
+    /// Get [Import Log Entries](ImportLogEntry), optionally only those of one count.
+    fn get_import_log(&self, recordnum: Option<u32>) -> Result<Vec<ImportLogEntry>, CountError>;
+
+    /// Get a new id for a run of the data checks.
+    fn next_check_run_id(&self) -> Result<u32, CountError>;
+
+    /// Insert the [results](CheckRecord) of a run of the data checks, committing them along with
+    /// anything outstanding.
+    fn insert_check_results(&self, results: &[CheckRecord]) -> Result<(), CountError>;
+
+    /// Get the [results](CheckRecord) of data checks that match a filter, most recent run first
+    /// and then by check.
+    fn select_check_results(
+        &self,
+        filter: &CheckResultFilter,
+    ) -> Result<Vec<CheckRecord>, CountError>;
+}
+
+impl CountStore for Connection {
+    fn get_metadata(&self, recordnum: u32) -> Result<Metadata, CountError> {
+        db::get_metadata(self, recordnum)
+    }
+
+    fn get_count_kind(&self, recordnum: u32) -> Result<Option<NonPermCountKind>, CountError> {
+        db::get_count_kind(self, recordnum)
+    }
+
+    fn get_directions(&self, recordnum: u32) -> Result<Directions, CountError> {
+        Directions::from_db(recordnum, self)
+    }
+
+    fn set_directions(&self, recordnum: u32, directions: &Directions) -> Result<(), CountError> {
+        directions.update_db(recordnum, self)
+    }
+
+    fn select_counts<T: CountRecord>(&self, recordnum: u32) -> Result<Vec<T>, CountError> {
+        T::select(self, recordnum)
+    }
+
+    fn delete_counts<T: CountRecord>(&self, recordnum: u32) -> Result<(), CountError> {
+        Ok(T::delete(self, recordnum)?)
+    }
+
+    fn delete_counts_in<T: CountRecord>(
+        &self,
+        recordnum: u32,
+        span: &CountSpan,
+    ) -> Result<(), CountError> {
+        Ok(T::delete_span(self, recordnum, span)?)
+    }
+
+    fn insert_counts_batched<T: CountRecord>(
+        &self,
+        counts: &[T],
+        batch_size: usize,
+    ) -> Result<(), CountError> {
+        Ok(T::insert_all(self, counts, batch_size)?)
+    }
+
+    fn commit(&self) -> Result<(), CountError> {
+        Ok(Connection::commit(self)?)
+    }
+
+    fn rollback(&self) -> Result<(), CountError> {
+        Ok(Connection::rollback(self)?)
+    }
+
+    fn hourly_volumes(
+        &self,
+        recordnum: u32,
+        table: &str,
+        vol_field: &str,
+    ) -> Result<Vec<HourlyVehicle>, CountError> {
+        HourlyVehicle::from_db(recordnum, table, vol_field, self)
+    }
+
+    fn mark_imported(&self, recordnum: u32) -> Result<(), CountError> {
+        self.execute(
+            "update tc_header SET
+            importdatadate = (select current_date from dual),
+            status = :1
+            where recordnum = :2",
+            &[&"imported", &recordnum],
+        )?;
+        Ok(())
+    }
+
+    fn update_intermediate_aadv(&self, recordnum: u32) -> Result<(), CountError> {
+        db::update_intermediate_aadv(recordnum, self)
+    }
+
+    fn update_setdate(&self, recordnum: u32) -> Result<(), CountError> {
+        db::update_setdate(recordnum, self)
+    }
+
+    fn calc_aadv(&self, recordnum: u32) -> Result<(), CountError> {
+        db::calc_aadv(recordnum, self)
+    }
+
+    fn insert_import_log_entry(&self, log_record: ImportLogEntry) -> Result<(), CountError> {
+        Ok(db::insert_import_log_entry(self, log_record)?)
+    }
+
+    fn get_import_log(&self, recordnum: Option<u32>) -> Result<Vec<ImportLogEntry>, CountError> {
+        Ok(db::get_import_log(self, recordnum)?)
+    }
+
+    fn next_check_run_id(&self) -> Result<u32, CountError> {
+        Ok(db::next_check_run_id(self)?)
+    }
+
+    fn insert_check_results(&self, results: &[CheckRecord]) -> Result<(), CountError> {
+        Ok(db::insert_check_results(self, results)?)
+    }
+
+    fn select_check_results(
+        &self,
+        filter: &CheckResultFilter,
+    ) -> Result<Vec<CheckRecord>, CountError> {
+        Ok(db::select_check_results(self, filter)?)
+    }
+}
@@ -0,0 +1,514 @@
+//! Importing a count as a single unit of work.
+//!
+//! Importing a count touches many tables: its records replace any existing ones in each of the
+//! count tables, its hourly volumes are aggregated and stored, its metadata is marked as
+//! imported, and the procedures that AADV depends on are run. [`import_count`] does all of this
+//! in one transaction, so that an error partway through leaves the count as it was rather than,
+//! say, with class data but no speed or hourly data. [`merge_count`] does the same for a count
+//! whose data is split across files, merging rather than replacing its records.
+//!
+//! Nothing may commit during the unit of work, which includes logging to the database (see
+//! [`CountStore::insert_import_log_entry`]); log its steps once it has returned.
+
+use log::error;
+
+use crate::{
+    db::{crud::CountSpan, store::CountStore},
+    non_perm::{
+        parser::{ParsedCount, Records},
+        turning_movement::{PeakHour, TurningMovementCount},
+        HourlyVehicle,
+    },
+    CountError,
+};
+
+/// Import a [`ParsedCount`] in one transaction, committing only if every step succeeds and
+/// otherwise rolling all of them back.
+///
+/// The count's [hourly volumes](ParsedCount::add_hourly) are added to its records first (if they
+/// aren't already), and its volume totals verified, before anything is stored. Then all of its
+/// records replace those in the store, it is marked as imported, and the intermediate AADV table
+/// and setdate are updated.
+///
+/// Records are sent to the store `batch_size` at a time (see
+/// [`DEFAULT_BATCH_SIZE`](crate::db::crud::DEFAULT_BATCH_SIZE)). AADV itself
+/// isn't calculated, as not every count can have it calculated on import (see
+/// [`ParsedCount::calc_aadv`]).
+pub fn import_count(
+    store: &impl CountStore,
+    parsed: &mut ParsedCount,
+    batch_size: usize,
+) -> Result<(), CountError> {
+    parsed
+        .add_hourly()
+        .map_err(|e| rolled_back("verifying volume totals", e))?;
+
+    let recordnum = parsed.recordnum;
+    let result = parsed
+        .records
+        .iter()
+        .try_for_each(|records| {
+            records
+                .replace_batched(recordnum, store, batch_size)
+                .map_err(|e| rolled_back(&format!("replacing {} records", records.table()), e))
+        })
+        .and_then(|_| complete(store, recordnum));
+
+    rollback_on_err(store, recordnum, result)
+}
+
+/// Existing records of a count replaced by those being [merged](merge_count) into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    pub table: &'static str,
+    /// The span of the merged records, which the replaced ones fell within.
+    pub span: CountSpan,
+    /// The number of existing records replaced.
+    pub replaced: usize,
+}
+
+/// What [merging](merge_count) a [`ParsedCount`] into its count's stored records did besides
+/// adding them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Merged {
+    /// Existing records that were replaced.
+    pub overlaps: Vec<Overlap>,
+    /// The tables whose records weren't merged at all, leaving those stored as they were.
+    pub unmerged: Vec<&'static str>,
+}
+
+/// Merge a [`ParsedCount`] into the records already stored for it, in one transaction,
+/// committing only if every step succeeds and otherwise rolling all of them back.
+///
+/// This is for counts whose data is split across more than one file, e.g. when a counter was
+/// swapped partway through a count or the file for one of its lanes arrives later. Rather than
+/// replacing all of the count's records in each table, as [`import_count`] does, only those
+/// within the [span](CountSpan) (datetimes and lanes) of the parsed records are; any of them that
+/// there were are returned as [`Overlap`]s. That includes the hourly records (e.g. average
+/// speeds) of a lane in an hour split between files, which can't be combined, so the hour's
+/// records are only those of the file merged last.
+///
+/// Records that aren't of particular times and lanes can't be merged that way:
+///   - the count's hourly volumes and [peak hours](PeakHour) are regenerated from all of its
+///     merged 15-minute volume, class, or turning movement records.
+///   - the speed statistics of the whole count and hourly gaps between vehicles in any lane can
+///     only be calculated from every vehicle counted, which no one file has, so they aren't
+///     merged; the tables are returned as [`Merged::unmerged`].
+///
+/// The count is then marked as imported and its intermediate AADV table and setdate are updated.
+pub fn merge_count(
+    store: &impl CountStore,
+    parsed: &mut ParsedCount,
+    batch_size: usize,
+) -> Result<Merged, CountError> {
+    parsed
+        .add_hourly()
+        .map_err(|e| rolled_back("verifying volume totals", e))?;
+
+    let recordnum = parsed.recordnum;
+    let mut merged = Merged::default();
+    let result = parsed
+        .records
+        .iter()
+        .try_for_each(|records| {
+            match records {
+                // Regenerated once the rest are merged.
+                Records::HourlyVehicle(_) | Records::PeakHour(_) => return Ok(()),
+                Records::CountSpeedStats(_) | Records::Gaps(_) => {
+                    merged.unmerged.push(records.table());
+                    return Ok(());
+                }
+                _ => (),
+            }
+            let replaced = records
+                .merge_batched(recordnum, store, batch_size)
+                .map_err(|e| rolled_back(&format!("merging {} records", records.table()), e))?;
+            if let (Some(span), true) = (records.span(), replaced > 0) {
+                merged.overlaps.push(Overlap {
+                    table: records.table(),
+                    span,
+                    replaced,
+                });
+            }
+            Ok(())
+        })
+        .and_then(|_| {
+            merge_hourly(store, parsed, batch_size)
+                .map_err(|e| rolled_back("regenerating hourly volumes", e))
+        })
+        .and_then(|_| {
+            merge_peak_hours(store, parsed, batch_size)
+                .map_err(|e| rolled_back("regenerating peak hours", e))
+        })
+        .and_then(|_| complete(store, recordnum));
+
+    rollback_on_err(store, recordnum, result).map(|_| merged)
+}
+
+/// Replace a count's hourly volumes with those aggregated from all of its stored 15-minute
+/// volume or class records, or, if the parsed count has neither, merge its hourly volumes.
+fn merge_hourly(
+    store: &impl CountStore,
+    parsed: &ParsedCount,
+    batch_size: usize,
+) -> Result<(), CountError> {
+    let recordnum = parsed.recordnum;
+    for records in &parsed.records {
+        let hourly = match records {
+            Records::FifteenMinuteVehicle(_) => {
+                HourlyVehicle::from_fifteen_min(recordnum, &store.select_counts(recordnum)?)?
+            }
+            Records::VehicleClass(_) => {
+                HourlyVehicle::from_class_counts(recordnum, &store.select_counts(recordnum)?)?
+            }
+            _ => continue,
+        };
+        store.delete_counts::<HourlyVehicle>(recordnum)?;
+        return store.insert_counts_batched(&hourly, batch_size);
+    }
+    for records in &parsed.records {
+        if let Records::HourlyVehicle(_) = records {
+            records.merge_batched(recordnum, store, batch_size)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace a count's peak hours with those of all of its stored turning movement counts, if the
+/// parsed count has any.
+fn merge_peak_hours(
+    store: &impl CountStore,
+    parsed: &ParsedCount,
+    batch_size: usize,
+) -> Result<(), CountError> {
+    let recordnum = parsed.recordnum;
+    if !parsed
+        .records
+        .iter()
+        .any(|records| matches!(records, Records::TurningMovement(_)))
+    {
+        return Ok(());
+    }
+    let peak_hours = PeakHour::all(
+        &store.select_counts::<TurningMovementCount>(recordnum)?,
+        None,
+    );
+    store.delete_counts::<PeakHour>(recordnum)?;
+    store.insert_counts_batched(&peak_hours, batch_size)
+}
+
+/// Mark a count as imported, update what AADV depends on, and commit.
+fn complete(store: &impl CountStore, recordnum: u32) -> Result<(), CountError> {
+    store
+        .mark_imported(recordnum)
+        .map_err(|e| rolled_back("updating metadata (tc_header table)", e))?;
+    store
+        .update_intermediate_aadv(recordnum)
+        .map_err(|e| rolled_back("updating intermediate table TC_COUNTDATE", e))?;
+    store
+        .update_setdate(recordnum)
+        .map_err(|e| rolled_back("updating field SETDATE", e))?;
+    store.commit().map_err(|e| rolled_back("committing", e))
+}
+
+fn rollback_on_err(
+    store: &impl CountStore,
+    recordnum: u32,
+    result: Result<(), CountError>,
+) -> Result<(), CountError> {
+    if result.is_err() {
+        if let Err(e) = store.rollback() {
+            error!("{recordnum}: Unable to roll back import: {e}");
+        }
+    }
+    result
+}
+
+fn rolled_back(step: &str, source: CountError) -> CountError {
+    CountError::ImportRolledBack {
+        step: step.to_string(),
+        source: Box::new(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        crud::{NonPermCrud, DEFAULT_BATCH_SIZE},
+        memory_store::MemoryStore,
+    };
+    use chrono::{NaiveDateTime, TimeDelta, Timelike};
+
+    use crate::non_perm::{
+        headway::HourlyGaps,
+        speed_stats::{CountSpeedStats, SpeedStats},
+        turning_movement::Movement,
+        Directions, FifteenMinuteVehicle, LaneDirection, Metadata,
+    };
+    use crate::test_support::{fifteen_min_vehicle, parsed_count};
+
+    #[test]
+    fn all_tables_and_procedures_committed_together() {
+        let store = MemoryStore::new();
+        let directions = Directions::new(vec![LaneDirection::East], false).unwrap();
+        store.add_count(1, Metadata::default(), directions);
+        store
+            .insert_counts(&[fifteen_min_vehicle(1, "2024-01-02 08:00", 5, 1)])
+            .unwrap();
+        store.commit().unwrap();
+
+        let mut parsed = parsed_count(
+            1,
+            vec![
+                fifteen_min_vehicle(1, "2024-01-03 11:30", 49, 1),
+                fifteen_min_vehicle(1, "2024-01-03 11:45", 68, 1),
+            ],
+        );
+        import_count(&store, &mut parsed, DEFAULT_BATCH_SIZE).unwrap();
+        store.rollback().unwrap();
+
+        assert_eq!(
+            store
+                .select_counts::<FifteenMinuteVehicle>(1)
+                .unwrap()
+                .len(),
+            2
+        );
+        let hourly = store.select_counts::<HourlyVehicle>(1).unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].count, 117);
+        assert!(store.get_metadata(1).unwrap().importdatadate.is_some());
+        assert_eq!(
+            store.procedure_calls(),
+            vec![(1, "update_tc_countdate"), (1, "update_setdate")]
+        );
+    }
+
+    #[test]
+    fn failure_partway_rolls_back_everything() {
+        // Without metadata, the records can be replaced but the count can't be marked imported.
+        let store = MemoryStore::new();
+        store
+            .insert_counts(&[fifteen_min_vehicle(1, "2024-01-02 08:00", 5, 1)])
+            .unwrap();
+        store.commit().unwrap();
+
+        let mut parsed = parsed_count(1, vec![fifteen_min_vehicle(1, "2024-01-03 11:30", 49, 1)]);
+        assert!(matches!(
+            import_count(&store, &mut parsed, DEFAULT_BATCH_SIZE),
+            Err(CountError::ImportRolledBack { step, .. }) if step.contains("metadata")
+        ));
+
+        let counts = store.select_counts::<FifteenMinuteVehicle>(1).unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].count, 5);
+        assert!(store.select_counts::<HourlyVehicle>(1).unwrap().is_empty());
+        assert!(store.procedure_calls().is_empty());
+    }
+
+    #[test]
+    fn merged_counts_keep_other_lanes_and_times_and_regenerate_hourly() {
+        let store = MemoryStore::new();
+        let directions =
+            Directions::new(vec![LaneDirection::East, LaneDirection::West], false).unwrap();
+        store.add_count(1, Metadata::default(), directions);
+        let mut first = parsed_count(
+            1,
+            vec![
+                fifteen_min_vehicle(1, "2024-01-03 10:00", 10, 1),
+                fifteen_min_vehicle(1, "2024-01-03 10:15", 20, 1),
+            ],
+        );
+        import_count(&store, &mut first, DEFAULT_BATCH_SIZE).unwrap();
+
+        // Another lane's file, over the same time.
+        let mut second = parsed_count(1, vec![fifteen_min_vehicle(1, "2024-01-03 10:00", 5, 2)]);
+        let overlaps = merge_count(&store, &mut second, DEFAULT_BATCH_SIZE)
+            .unwrap()
+            .overlaps;
+        assert!(overlaps.is_empty());
+
+        // The first lane's counter swapped, with a file overlapping the first at 10:15.
+        let mut third = parsed_count(
+            1,
+            vec![
+                fifteen_min_vehicle(1, "2024-01-03 10:15", 25, 1),
+                fifteen_min_vehicle(1, "2024-01-03 10:30", 30, 1),
+            ],
+        );
+        let overlaps = merge_count(&store, &mut third, DEFAULT_BATCH_SIZE)
+            .unwrap()
+            .overlaps;
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].table, FifteenMinuteVehicle::COUNT_TABLE);
+        assert_eq!(overlaps[0].replaced, 1);
+
+        let mut counts = store
+            .select_counts::<FifteenMinuteVehicle>(1)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.time.format("%H:%M").to_string(), c.lane, c.count))
+            .collect::<Vec<_>>();
+        counts.sort();
+        assert_eq!(
+            counts,
+            vec![
+                ("10:00".to_string(), Some(1), 10),
+                ("10:00".to_string(), Some(2), 5),
+                ("10:15".to_string(), Some(1), 25),
+                ("10:30".to_string(), Some(1), 30),
+            ]
+        );
+        let hourly = store.select_counts::<HourlyVehicle>(1).unwrap();
+        assert_eq!(
+            hourly.iter().map(|h| (h.lane, h.count)).collect::<Vec<_>>(),
+            vec![(1, 65), (2, 5)]
+        );
+    }
+
+    #[test]
+    fn failed_merge_rolls_back_everything() {
+        let store = MemoryStore::new();
+        store
+            .insert_counts(&[fifteen_min_vehicle(1, "2024-01-02 08:00", 5, 1)])
+            .unwrap();
+        store.commit().unwrap();
+
+        let mut parsed = parsed_count(1, vec![fifteen_min_vehicle(1, "2024-01-02 08:00", 9, 1)]);
+        assert!(merge_count(&store, &mut parsed, DEFAULT_BATCH_SIZE).is_err());
+
+        let counts = store.select_counts::<FifteenMinuteVehicle>(1).unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].count, 5);
+        assert!(store.select_counts::<HourlyVehicle>(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merged_turning_movement_counts_have_one_peak_hour_per_approach() {
+        let store = MemoryStore::new();
+        let directions =
+            Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap();
+        store.add_count(1, Metadata::default(), directions);
+        // An hour of each approach's movements, busier in the afternoon.
+        let tmc_file = |start: &str, volume| {
+            let start = NaiveDateTime::parse_from_str(start, "%Y-%m-%d %H:%M").unwrap();
+            let counts = (0..4)
+                .flat_map(|i| {
+                    [LaneDirection::North, LaneDirection::South].map(|approach| {
+                        TurningMovementCount::new(
+                            1,
+                            start + TimeDelta::minutes(15 * i),
+                            approach,
+                            Movement::Through,
+                            "All".to_string(),
+                            volume,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            ParsedCount {
+                recordnum: 1,
+                records: vec![
+                    Records::PeakHour(PeakHour::all(&counts, None)),
+                    Records::TurningMovement(counts),
+                ],
+                calc_aadv: false,
+                rejected: vec![],
+            }
+        };
+        import_count(
+            &store,
+            &mut tmc_file("2024-01-03 07:00", 10),
+            DEFAULT_BATCH_SIZE,
+        )
+        .unwrap();
+        merge_count(
+            &store,
+            &mut tmc_file("2024-01-03 16:00", 20),
+            DEFAULT_BATCH_SIZE,
+        )
+        .unwrap();
+
+        assert_eq!(
+            store
+                .select_counts::<TurningMovementCount>(1)
+                .unwrap()
+                .len(),
+            16
+        );
+        let mut peak_hours = store
+            .select_counts::<PeakHour>(1)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.approach.map(|a| a.to_string()), p.start.hour(), p.volume))
+            .collect::<Vec<_>>();
+        peak_hours.sort();
+        assert_eq!(
+            peak_hours,
+            vec![
+                (None, 16, 160),
+                (Some("north".to_string()), 16, 80),
+                (Some("south".to_string()), 16, 80),
+            ]
+        );
+    }
+
+    #[test]
+    fn whole_count_speed_stats_and_gaps_not_merged() {
+        let store = MemoryStore::new();
+        let directions =
+            Directions::new(vec![LaneDirection::East, LaneDirection::West], false).unwrap();
+        store.add_count(1, Metadata::default(), directions);
+        // The file of each lane has the same start, and speed statistics and gaps of its own.
+        let lane_file = |lane, volume| {
+            let counts = vec![fifteen_min_vehicle(1, "2024-01-03 10:00", volume, lane)];
+            let datetime = counts[0].time;
+            let stats = SpeedStats {
+                volume: volume as u32,
+                mean: 30.0 + lane as f32,
+                p50: 30.0,
+                p85: 35.0,
+                pace_start: 25.0,
+                pace_pct: 80.0,
+                over_limit_pct: None,
+            };
+            let gaps = HourlyGaps {
+                recordnum: 1,
+                datetime,
+                critical_gap: 5.0,
+                gaps: volume as u32 - 1,
+                accepted: lane as u32,
+                max_gap: None,
+            };
+            let mut parsed = parsed_count(1, counts);
+            parsed.records.extend([
+                Records::CountSpeedStats(vec![CountSpeedStats::new(1, datetime, None, stats)]),
+                Records::Gaps(vec![gaps]),
+            ]);
+            parsed
+        };
+        import_count(&store, &mut lane_file(1, 10), DEFAULT_BATCH_SIZE).unwrap();
+        let merged = merge_count(&store, &mut lane_file(2, 20), DEFAULT_BATCH_SIZE).unwrap();
+
+        assert_eq!(
+            merged.unmerged,
+            vec![CountSpeedStats::COUNT_TABLE, HourlyGaps::COUNT_TABLE]
+        );
+        assert!(merged.overlaps.is_empty());
+        let stats = store.select_counts::<CountSpeedStats>(1).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].class, stats[0].volume), (None, 10));
+        let gaps = store.select_counts::<HourlyGaps>(1).unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].accepted, 1);
+        // The rest of the file's records are merged.
+        assert_eq!(
+            store
+                .select_counts::<FifteenMinuteVehicle>(1)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -2,7 +2,7 @@
 //! and enables performing various kinds of operations on them, like
 //! [extracting][non_perm::extract_from_file] data from files,
 //! [CRUD db operations][db::crud],
-//! [aggregating volume data by hour][non_perm::HourlyVehicle::from_db], and
+//! [aggregating volume data by hour][non_perm::parser::ParsedCount::add_hourly], and
 //! [averaging speed data by hour][non_perm::HourlyAvgSpeed::create].
 //!
 //! There are currently two binary programs that extract data from files, transform it, and insert
@@ -10,20 +10,30 @@
 //! traffic counts, while the [perm_bikeped_import](../perm_bikeped_import/index.html) program
 //! handles permanent bicycle/pedestrian counts. See each program's documentation for further
 //! details, including filename specifications, the specific types of counts they handle/create,
-//! and how they are run.
+//! and how they are run. A third program, [migrate](../migrate/index.html), applies and tracks
+//! [migrations][db::migrations] of the non-permanent counts database's schema, and a fourth,
+//! [check_report](../check_report/index.html), reports the stored
+//! [results of the data checks][non_perm::check_results] as JSON. All four are
+//! [configured][config] by a TOML file and/or environment variables.
 //!
 //! See <https://www.dvrpc.org/traffic/> for additional information about traffic counting.
 
+use std::fmt::Display;
 use std::io;
 use std::num::{ParseFloatError, ParseIntError};
 use std::path::PathBuf;
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use thiserror::Error;
 
+use crate::non_perm::{extract_from_file::RejectedRow, NonPermCountKind};
+
+pub mod config;
 pub mod db;
 pub mod non_perm;
 pub mod perm_bikeped;
+#[cfg(test)]
+mod test_support;
 
 /// A trait for getting a [`NaiveDate`](https://docs.rs/chrono/latest/chrono/struct.NaiveDate.html)
 /// from a type.
@@ -51,12 +61,40 @@ pub enum CountError {
     BadHeader(PathBuf),
     #[error("no such direction '{0}'")]
     BadDirection(String),
+    #[error("no such movement '{0}'")]
+    BadMovement(String),
     #[error("missing directions")]
     MissingDirection,
-    #[error("mismatch in count types between file location ('{0}') and header of that file")]
-    LocationHeaderMisMatch(PathBuf),
+    #[error("type of count {recordnum} in database is incorrect, should be '{expected}'")]
+    IncorrectCountKind {
+        recordnum: u32,
+        expected: NonPermCountKind,
+    },
+    #[error("mismatch in count types between file location ('{path}', {location}) and contents of that file ({detected})")]
+    LocationHeaderMisMatch {
+        path: PathBuf,
+        location: String,
+        detected: String,
+    },
     #[error("mismatch in number of directions between database and data in that file")]
     DirectionLenMisMatch,
+    #[error("{step} failed, so the import was rolled back: {source}")]
+    ImportRolledBack {
+        step: String,
+        source: Box<CountError>,
+    },
+    #[error("{source} ({location})")]
+    InRow {
+        location: RowLocation,
+        source: Box<CountError>,
+    },
+    #[error("{} of {rows} rows rejected from {path:?}, more than the maximum {max_rejected_pct}%", .rejected.len())]
+    TooManyRejectedRows {
+        path: PathBuf,
+        rejected: Vec<RejectedRow>,
+        rows: usize,
+        max_rejected_pct: f32,
+    },
     #[error("data does not exist in expected column")]
     MissingDataColumn,
     #[error("cannot parse value as number")]
@@ -67,8 +105,27 @@ pub enum CountError {
     ChronoParseError(chrono::format::ParseErrorKind),
     #[error("no such vehicle class '{0}'")]
     BadVehicleClass(u8),
+    #[error("total at {0} does not match the sum of its vehicle classes")]
+    TallyTotalMisMatch(NaiveDateTime),
+    #[error("more unclassified vehicles at {0} than vehicles of classes 1-3")]
+    UnclassifiedMisMatch(NaiveDateTime),
+    #[error(
+        "total volume of {table} ({total}) does not match that of {expected_table} ({expected})"
+    )]
+    VolumeTotalMisMatch {
+        table: &'static str,
+        total: u64,
+        expected_table: &'static str,
+        expected: u64,
+    },
+    #[error("invalid class mapping: {0}")]
+    BadClassMapping(String),
+    #[error("invalid speed bins: {0}")]
+    BadSpeedBins(String),
     #[error("unable to determine interval from count")]
     BadIntervalCount,
+    #[error("volume of {0} at {1} is too large")]
+    VolumeTooLarge(u32, NaiveDateTime),
     #[error("error converting header row to string")]
     HeadertoStringRecordError(#[from] csv::Error),
     #[error("invalid MCD ({0})")]
@@ -89,6 +146,73 @@ pub enum CountError {
     OracleError(#[from] oracle::Error),
     #[error("{0}")]
     DataCheckError(String),
+    #[error("no such data check stage '{0}'")]
+    BadCheckStage(String),
+    #[error("migration {version} ({name}) failed at '{statement}': {source}")]
+    MigrationFailed {
+        version: u32,
+        name: &'static str,
+        statement: String,
+        source: Box<oracle::Error>,
+    },
+    #[error("no migration with version {0}")]
+    UnknownMigration(u32),
+    #[error("invalid configuration:\n  {}", .0.join("\n  "))]
+    InvalidConfig(Vec<String>),
+}
+
+impl CountError {
+    /// The location of the row of data in a file that caused the error, if any.
+    pub fn location(&self) -> Option<&RowLocation> {
+        match self {
+            CountError::InRow { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The error without the location of the row of data that caused it, if any.
+    pub fn without_location(self) -> Self {
+        match self {
+            CountError::InRow { source, .. } => source.without_location(),
+            e => e,
+        }
+    }
+}
+
+/// Where in a file data that caused an error was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowLocation {
+    pub path: PathBuf,
+    /// The physical line of the file (starting at 1), including any nondata rows.
+    pub line: u64,
+    /// The index of the column (starting at 0), if the error is with a particular one.
+    pub column: Option<usize>,
+    /// The raw text of the column, or of the whole row if no particular column.
+    pub text: String,
+}
+
+impl RowLocation {
+    /// The most characters of `text` to display.
+    const MAX_TEXT_LEN: usize = 100;
+}
+
+impl Display for RowLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}, line {}", self.path, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ", column index {column}")?;
+        }
+        if self.text.chars().count() > Self::MAX_TEXT_LEN {
+            let text = self
+                .text
+                .chars()
+                .take(Self::MAX_TEXT_LEN)
+                .collect::<String>();
+            write!(f, ": '{text}...'")
+        } else {
+            write!(f, ": '{}'", self.text)
+        }
+    }
 }
 
 /// Identifying the problem when there's an error with a filename.
@@ -1,60 +1,525 @@
 //! Checks on data integrity/validity.
-use std::env;
+//!
+//! Each check is a [`DataCheck`], applied to the [`CheckData`] of a count of the kinds it applies
+//! to. A [`CheckRegistry`] holds the checks to run; its default holds the built-in ones:
+//!   - [`Class2Share`] and [`UnclassedShare`], for class counts
+//!   - [`VehicleDirProportionality`], for class and volume counts
+//!   - [`BikeDirProportionality`] and [`ExcessiveBicycles`], for bicycle counts
+//!   - [`ConsecutiveZeroHours`], for all of them.
+//!
+//! Checks can be applied to a count's records before they are stored ([`check_parsed`]), so that a
+//! file with suspect data can be held for review rather than replace a count's existing data, as
+//! well as to its stored data ([`check`]).
+//!
+//! Every result of every check is also stored, with what it measured, the threshold it judged that
+//! by, and the periods of time it concerns, so that the results can be queried and reported on
+//! later (see [`check_results`](crate::non_perm::check_results)).
+//!
+//! The limits the checks judge data by are [`CheckThresholds`]. A [`CheckConfig`] has default
+//! thresholds and [`ThresholdOverride`]s of them for counts of particular functional classes
+//! (`fc`) or bicycle/pedestrian facilities (`bikepedfacility`), so that e.g. a count on a trail
+//! and one on a freeway aren't held to the same numbers.
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
 use std::fs::OpenOptions;
-use std::{collections::HashMap, str::FromStr};
+use std::path::Path;
 
-use chrono::{NaiveDate, NaiveDateTime};
-use log::{Level, LevelFilter};
-use oracle::Connection;
+use chrono::{NaiveDateTime, TimeDelta, Timelike};
+use log::{Level, LevelFilter, Log};
+use serde::Deserialize;
 use simplelog::{
     ColorChoice, CombinedLogger, ConfigBuilder, TermLogger, TerminalMode, WriteLogger,
 };
 
 use crate::{
-    db,
-    non_perm::{log_msg, LaneDirection, NonPermCountKind},
+    db::store::CountStore,
+    non_perm::{
+        check_results::{CheckPeriod, CheckRecord, CheckStage},
+        log_msg,
+        parser::{ParsedCount, Records},
+        FifteenMinuteBicycle, HourlyVehicle, LaneDirection, Metadata, NonPermCountKind,
+        RoadDirection, TimeBinnedVehicleClassCount,
+    },
     CountError,
 };
 
-// If a count is bidirectional, the totals for both directions should be relatively proportional.
-// One direction having less than this level is considered abnormal.
-const DIR_PROPORTION_LOWER_BOUND: f32 = 0.40;
-// Unusually high count for bicycles in a 15-minute period.
-const BIKE_COUNT_MAX: u32 = 20;
+/// Thresholds the data checks use to decide whether data is abnormal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CheckThresholds {
+    /// Class 2 vehicles making up less than this percentage of the total is abnormal.
+    pub class2_min_pct: f32,
+    /// Unclassed vehicles making up more than this percentage of the total is abnormal.
+    pub unclassed_max_pct: f32,
+    /// If a count is bidirectional, the totals for both directions should be relatively
+    /// proportional. One direction having less than this share of the total is considered
+    /// abnormal.
+    pub dir_proportion_lower_bound: f32,
+    /// Unusually high count for bicycles in a 15-minute period.
+    pub bike_count_max: u32,
+    /// The first hour of the day in which consecutive hours of zero volume are abnormal.
+    pub zero_hours_start: u32,
+    /// The last hour of the day in which consecutive hours of zero volume are abnormal.
+    pub zero_hours_end: u32,
+}
+
+impl Default for CheckThresholds {
+    fn default() -> Self {
+        Self {
+            class2_min_pct: 75.0,
+            unclassed_max_pct: 10.0,
+            dir_proportion_lower_bound: 0.40,
+            bike_count_max: 20,
+            zero_hours_start: 4,
+            zero_hours_end: 22,
+        }
+    }
+}
+
+impl CheckThresholds {
+    /// Any problems with the thresholds' values.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for (name, pct) in [
+            ("class2_min_pct", self.class2_min_pct),
+            ("unclassed_max_pct", self.unclassed_max_pct),
+        ] {
+            if !(0.0..=100.0).contains(&pct) {
+                problems.push(format!("{name} must be from 0 to 100"));
+            }
+        }
+        if !(0.0..=0.5).contains(&self.dir_proportion_lower_bound) {
+            problems.push("dir_proportion_lower_bound must be from 0 to 0.5".to_string());
+        }
+        if self.zero_hours_start > self.zero_hours_end || self.zero_hours_end > 23 {
+            problems.push(
+                "zero_hours_start must be no later than zero_hours_end, which must be from 0 to 23"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+}
+
+/// Thresholds that replace the defaults for counts of a functional class and/or
+/// bicycle/pedestrian facility.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdOverride {
+    /// The functional class (`fc`) of counts this applies to.
+    pub fc: Option<u32>,
+    /// The bicycle/pedestrian facility (`bikepedfacility`) of counts this applies to, ignoring
+    /// case.
+    pub facility: Option<String>,
+    pub class2_min_pct: Option<f32>,
+    pub unclassed_max_pct: Option<f32>,
+    pub dir_proportion_lower_bound: Option<f32>,
+    pub bike_count_max: Option<u32>,
+    pub zero_hours_start: Option<u32>,
+    pub zero_hours_end: Option<u32>,
+}
+
+impl ThresholdOverride {
+    /// Whether this applies to a count. It applies if the count has all of the functional class
+    /// and facility that are set, and never if neither is.
+    pub fn applies_to(&self, metadata: &Metadata) -> bool {
+        if self.fc.is_none() && self.facility.is_none() {
+            return false;
+        }
+        let fc_matches = self.fc.is_none() || self.fc == metadata.fc;
+        let facility_matches = match (&self.facility, &metadata.bikepedfacility) {
+            (None, _) => true,
+            (Some(facility), Some(v)) => facility.trim().eq_ignore_ascii_case(v.trim()),
+            (Some(_), None) => false,
+        };
+        fc_matches && facility_matches
+    }
+
+    /// Replace thresholds with those set in this.
+    pub fn apply(&self, thresholds: &mut CheckThresholds) {
+        if let Some(v) = self.class2_min_pct {
+            thresholds.class2_min_pct = v;
+        }
+        if let Some(v) = self.unclassed_max_pct {
+            thresholds.unclassed_max_pct = v;
+        }
+        if let Some(v) = self.dir_proportion_lower_bound {
+            thresholds.dir_proportion_lower_bound = v;
+        }
+        if let Some(v) = self.bike_count_max {
+            thresholds.bike_count_max = v;
+        }
+        if let Some(v) = self.zero_hours_start {
+            thresholds.zero_hours_start = v;
+        }
+        if let Some(v) = self.zero_hours_end {
+            thresholds.zero_hours_end = v;
+        }
+    }
+}
+
+/// Default thresholds and the overrides of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckConfig {
+    pub defaults: CheckThresholds,
+    /// Applied in order, so a later override takes precedence over an earlier one.
+    pub overrides: Vec<ThresholdOverride>,
+}
+
+impl CheckConfig {
+    /// The thresholds for a count: the defaults, with every override that applies to it applied.
+    pub fn thresholds_for(&self, metadata: &Metadata) -> CheckThresholds {
+        let mut thresholds = self.defaults;
+        for thresholds_override in &self.overrides {
+            if thresholds_override.applies_to(metadata) {
+                thresholds_override.apply(&mut thresholds);
+            }
+        }
+        thresholds
+    }
+}
 
 /// Result of a particular check.
 #[derive(Debug)]
-struct CheckResult {
-    level: Level,
-    message: String,
+pub struct CheckResult {
+    pub level: Level,
+    pub message: String,
+    /// The value the check measured, if any.
+    pub measured: Option<f64>,
+    /// The threshold `measured` was judged by, if any.
+    pub threshold: Option<f64>,
+    /// The periods of time the result concerns, if it's about particular ones.
+    pub periods: Vec<CheckPeriod>,
 }
 
-/// Used for checking shares by class.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ClassCountCheck {
-    datetime: NaiveDateTime,
-    lane: u8,
-    dir: LaneDirection,
-    c2: u32,
-    c15: u32,
-    total: u32,
+impl CheckResult {
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+            measured: None,
+            threshold: None,
+            periods: vec![],
+        }
+    }
+
+    /// Set the value measured and the threshold it was judged by.
+    ///
+    /// They're rounded to 6 decimal places, as they're computed in single precision, and a
+    /// measurement that isn't a number (e.g. a share of nothing) is left unset.
+    pub fn measured(mut self, measured: f32, threshold: f32) -> Self {
+        let round = |v: f32| (f64::from(v) * 1e6).round() / 1e6;
+        self.measured = measured.is_finite().then(|| round(measured));
+        self.threshold = Some(round(threshold));
+        self
+    }
+
+    /// Set the periods of time the result concerns.
+    pub fn periods(mut self, periods: Vec<CheckPeriod>) -> Self {
+        self.periods = periods;
+        self
+    }
 }
 
-/// Apply various data checks and log any issues found.
-pub fn check(recordnum: u32, conn: &Connection) -> Result<Level, CountError> {
-    // Initiate variable that reports if any warnings generated from data.
-    let mut level = Level::Info;
+/// The data of a count that checks are applied to.
+///
+/// Only the records of the tables relevant to the count's kind are included: 15-minute class
+/// counts and hourly volumes for class counts, hourly volumes for volume counts, and 15-minute
+/// bicycle counts for bicycle counts.
+#[derive(Debug, Clone)]
+pub struct CheckData {
+    pub recordnum: u32,
+    pub kind: NonPermCountKind,
+    pub metadata: Metadata,
+    pub class_counts: Vec<TimeBinnedVehicleClassCount>,
+    pub hourly_vehicles: Vec<HourlyVehicle>,
+    pub bicycles: Vec<FifteenMinuteBicycle>,
+}
 
-    // Load file containing environment variables, panic if it doesn't exist.
-    dotenvy::dotenv().expect("Unable to load .env file.");
+impl CheckData {
+    /// Load a count's data from a store.
+    pub fn load(recordnum: u32, store: &impl CountStore) -> Result<Self, CountError> {
+        let kind = match store.get_count_kind(recordnum) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                return Err(CountError::DataCheckError(
+                    "unable to identify type of count".to_string(),
+                ));
+            }
+            Err(e) => {
+                return Err(CountError::DbError(format!("{e}")));
+            }
+        };
+        let mut data = Self {
+            recordnum,
+            metadata: store.get_metadata(recordnum)?,
+            kind,
+            class_counts: vec![],
+            hourly_vehicles: vec![],
+            bicycles: vec![],
+        };
+        match data.kind {
+            NonPermCountKind::Class => {
+                data.class_counts = store.select_counts(recordnum)?;
+                data.hourly_vehicles = store.select_counts(recordnum)?;
+            }
+            NonPermCountKind::Volume | NonPermCountKind::FifteenMinVolume => {
+                data.hourly_vehicles = store.select_counts(recordnum)?;
+            }
+            _ if is_bicycle(&data.kind) => {
+                data.bicycles = store.select_counts(recordnum)?;
+            }
+            _ => (),
+        }
+        Ok(data)
+    }
+
+    /// Get a count's data from its records before they are stored, and its kind and metadata
+    /// from a store.
+    ///
+    /// Hourly volumes are aggregated from the records if they aren't among them.
+    pub fn from_parsed(parsed: &ParsedCount, store: &impl CountStore) -> Result<Self, CountError> {
+        let recordnum = parsed.recordnum;
+        let kind = store
+            .get_count_kind(recordnum)?
+            .ok_or(CountError::DataCheckError(
+                "unable to identify type of count".to_string(),
+            ))?;
+        let mut data = Self {
+            recordnum,
+            metadata: store.get_metadata(recordnum)?,
+            kind,
+            class_counts: vec![],
+            hourly_vehicles: vec![],
+            bicycles: vec![],
+        };
+        for records in &parsed.records {
+            match records {
+                Records::VehicleClass(v) => data.class_counts.clone_from(v),
+                Records::HourlyVehicle(v) => data.hourly_vehicles.clone_from(v),
+                Records::FifteenMinuteBicycle(v) => data.bicycles.clone_from(v),
+                _ => (),
+            }
+        }
+        if data.hourly_vehicles.is_empty() {
+            data.hourly_vehicles = parsed.hourly_vehicles()?.unwrap_or_default();
+        }
+        Ok(data)
+    }
+}
+
+/// A check of a count's data.
+pub trait DataCheck {
+    /// A short name for the check, unique within a [`CheckRegistry`].
+    fn name(&self) -> &'static str;
+
+    /// Whether the check applies to a kind of count.
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool;
+
+    /// Check a count's data, judging it by `thresholds`.
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError>;
+}
+
+/// The data checks to apply to counts, by the kinds of counts they apply to.
+pub struct CheckRegistry {
+    checks: Vec<Box<dyn DataCheck>>,
+}
+
+impl CheckRegistry {
+    /// Create a registry with no checks.
+    pub fn empty() -> Self {
+        Self { checks: vec![] }
+    }
+
+    /// Add a check, replacing any with the same name.
+    pub fn register(&mut self, check: impl DataCheck + 'static) {
+        self.checks.retain(|c| c.name() != check.name());
+        self.checks.push(Box::new(check));
+    }
 
-    // Get env var for path where log will be, panic if it doesn't exist.
-    let log_dir =
-        env::var("NON_PERM_LOG_DIR").expect("Unable to load log directory path from .env file.");
+    /// The checks in the registry.
+    pub fn checks(&self) -> impl Iterator<Item = &dyn DataCheck> {
+        self.checks.iter().map(|check| check.as_ref())
+    }
+
+    /// The checks that apply to a kind of count, in the order they were registered.
+    pub fn checks_for<'a>(
+        &'a self,
+        kind: &'a NonPermCountKind,
+    ) -> impl Iterator<Item = &'a dyn DataCheck> {
+        self.checks().filter(move |check| check.applies_to(kind))
+    }
+
+    /// Apply the checks for a count's kind to its data, with its thresholds from `config`.
+    ///
+    /// Returns the name of each check with its result. A check that fails has an [`Level::Error`]
+    /// result saying so.
+    pub fn run(&self, data: &CheckData, config: &CheckConfig) -> Vec<(&'static str, CheckResult)> {
+        let thresholds = config.thresholds_for(&data.metadata);
+        self.checks_for(&data.kind)
+            .map(|check| {
+                let result = check.run(data, &thresholds).unwrap_or_else(|e| {
+                    CheckResult::new(
+                        Level::Error,
+                        format!("Unable to run {} check: {e}", check.name()),
+                    )
+                });
+                (check.name(), result)
+            })
+            .collect()
+    }
+}
+
+impl Default for CheckRegistry {
+    /// A registry of the built-in checks.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(UnclassedShare);
+        registry.register(Class2Share);
+        registry.register(VehicleDirProportionality);
+        registry.register(BikeDirProportionality);
+        registry.register(ExcessiveBicycles);
+        registry.register(ConsecutiveZeroHours);
+        registry
+    }
+}
+
+/// Apply the data checks to a count's stored data and log any issues found, to the terminal and
+/// data_check.log in `log_dir`.
+pub fn check(
+    recordnum: u32,
+    store: &impl CountStore,
+    log_dir: &Path,
+    registry: &CheckRegistry,
+    config: &CheckConfig,
+) -> Result<Level, CountError> {
+    run_checks(recordnum, store, &data_check_log(log_dir), registry, config)
+}
+
+/// Apply the data checks to a count's records before they are stored and log any issues found,
+/// to the terminal and data_check.log in `log_dir`.
+///
+/// Returns the warnings produced.
+pub fn check_parsed(
+    parsed: &ParsedCount,
+    store: &impl CountStore,
+    log_dir: &Path,
+    registry: &CheckRegistry,
+    config: &CheckConfig,
+) -> Result<Vec<String>, CountError> {
+    run_parsed_checks(parsed, store, &data_check_log(log_dir), registry, config)
+}
+
+/// Apply the data checks for a count's kind to its stored data, logging any issues found to
+/// `log` and storing the results.
+///
+/// Returns [`Level::Warn`] if any check produced a warning or failed, otherwise [`Level::Info`].
+pub fn run_checks(
+    recordnum: u32,
+    store: &impl CountStore,
+    log: &impl Log,
+    registry: &CheckRegistry,
+    config: &CheckConfig,
+) -> Result<Level, CountError> {
+    let data = CheckData::load(recordnum, store)?;
+    let warnings = apply_checks(&data, CheckStage::Stored, store, log, registry, config);
+    if warnings.is_empty() {
+        Ok(Level::Info)
+    } else {
+        Ok(Level::Warn)
+    }
+}
+
+/// Apply the data checks for a count's kind to its records before they are stored, logging any
+/// issues found to `log` and storing the results.
+///
+/// Returns the warnings produced.
+pub fn run_parsed_checks(
+    parsed: &ParsedCount,
+    store: &impl CountStore,
+    log: &impl Log,
+    registry: &CheckRegistry,
+    config: &CheckConfig,
+) -> Result<Vec<String>, CountError> {
+    let data = CheckData::from_parsed(parsed, store)?;
+    Ok(apply_checks(
+        &data,
+        CheckStage::Parsed,
+        store,
+        log,
+        registry,
+        config,
+    ))
+}
+
+/// Apply the data checks to a count's data as one run, log the messages of those that produced a
+/// warning or failed, and store every result.
+///
+/// Returns the messages logged. Failing to store the results is logged, but doesn't otherwise
+/// affect the checks.
+fn apply_checks(
+    data: &CheckData,
+    stage: CheckStage,
+    store: &impl CountStore,
+    log: &impl Log,
+    registry: &CheckRegistry,
+    config: &CheckConfig,
+) -> Vec<String> {
+    let results = registry.run(data, config);
+
+    let mut warnings = vec![];
+    for (_, result) in &results {
+        if result.level == Level::Info {
+            continue;
+        }
+        let msg = match stage {
+            CheckStage::Parsed => format!("Before import: {}", result.message),
+            CheckStage::Stored => result.message.clone(),
+        };
+        log_msg(data.recordnum, log, result.level, &msg, store);
+        warnings.push(result.message.clone());
+    }
+
+    let stored = store.next_check_run_id().and_then(|run_id| {
+        let records = results
+            .into_iter()
+            .map(|(check_id, result)| {
+                let mut record = CheckRecord::new(
+                    run_id,
+                    data.recordnum,
+                    check_id,
+                    stage,
+                    result.level,
+                    result.message,
+                );
+                record.measured = result.measured;
+                record.threshold = result.threshold;
+                record.periods = result.periods;
+                record
+            })
+            .collect::<Vec<_>>();
+        store.insert_check_results(&records)
+    });
+    if let Err(e) = stored {
+        log_msg(
+            data.recordnum,
+            log,
+            Level::Error,
+            &format!("Unable to store data check results: {e}"),
+            store,
+        );
+    }
+    warnings
+}
+
+/// The logger of the data checks.
+fn data_check_log(log_dir: &Path) -> Box<CombinedLogger> {
     // Set up logging, panic if it fails.
     let check_config = ConfigBuilder::new().set_time_format_rfc3339().build();
-    let data_check_log = CombinedLogger::new(vec![
+    CombinedLogger::new(vec![
         TermLogger::new(
             LevelFilter::Debug,
             check_config.clone(),
@@ -67,440 +532,669 @@ pub fn check(recordnum: u32, conn: &Connection) -> Result<Level, CountError> {
             OpenOptions::new()
                 .append(true)
                 .create(true)
-                .open(format!("{log_dir}/data_check.log"))
+                .open(log_dir.join("data_check.log"))
                 .expect("Could not open log file."),
         ),
-    ]);
+    ])
+}
 
-    // Determine what kind of count this is.
-    let count_kind = match db::get_count_kind(conn, recordnum) {
-        Ok(Some(v)) => v,
-        Ok(None) => {
-            return Err(CountError::DataCheckError(
-                "unable to identify type of count".to_string(),
-            ));
-        }
-        Err(e) => {
-            return Err(CountError::DbError(format!("{e}")));
-        }
-    };
-
-    // Run checks according to kind of count.
-    match count_kind {
-        NonPermCountKind::Class => {
-            match check_share_unclassed_vehicles(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_share_class2_vehicles(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_vehicle_dir_proportionality(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_0_hours(recordnum, &count_kind, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-        }
-        NonPermCountKind::Volume => {
-            match check_vehicle_dir_proportionality(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_0_hours(recordnum, &count_kind, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-        }
-        NonPermCountKind::FifteenMinVolume => {
-            match check_vehicle_dir_proportionality(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_0_hours(recordnum, &count_kind, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-        }
+/// Whether a kind of count is of bicycles.
+fn is_bicycle(kind: &NonPermCountKind) -> bool {
+    matches!(
+        kind,
         NonPermCountKind::Bicycle1
-        | NonPermCountKind::Bicycle2
-        | NonPermCountKind::Bicycle3
-        | NonPermCountKind::Bicycle4
-        | NonPermCountKind::Bicycle5
-        | NonPermCountKind::Bicycle6 => {
-            match check_bike_dir_proportionality(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_excessive_bicycles(recordnum, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-            match check_0_hours(recordnum, &count_kind, conn) {
-                Ok(v) if v.level == Level::Warn => {
-                    log_msg(recordnum, &data_check_log, Level::Warn, &v.message, conn);
-                    level = Level::Warn;
-                }
-                _ => (),
-            }
-        }
-        _ => (),
-    }
-
-    Ok(level)
+            | NonPermCountKind::Bicycle2
+            | NonPermCountKind::Bicycle3
+            | NonPermCountKind::Bicycle4
+            | NonPermCountKind::Bicycle5
+            | NonPermCountKind::Bicycle6
+    )
 }
 
 /// Check if share of class 2 vehicles is too low.
-fn check_share_class2_vehicles(
-    recordnum: u32,
-    conn: &Connection,
-) -> Result<CheckResult, CountError> {
-    let counts = get_c2_c15_total_counts(recordnum, conn)?;
+pub struct Class2Share;
 
-    // Check share of class 2 of total.
-    let c2_sum = counts.iter().map(|count| count.c2).sum::<u32>();
-    let total_sum = counts.iter().map(|count| count.total).sum::<u32>();
+impl DataCheck for Class2Share {
+    fn name(&self) -> &'static str {
+        "class 2 share"
+    }
 
-    let c2_percent = c2_sum as f32 / total_sum as f32 * 100.0;
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool {
+        *kind == NonPermCountKind::Class
+    }
 
-    if c2_percent < 75.0 {
-        Ok(CheckResult {
-            level: Level::Warn,
-            message: format!("Class 2 vehicles are less than 75% ({c2_percent:.1}%) of total."),
-        })
-    } else {
-        Ok(CheckResult {
-            level: Level::Info,
-            message: "Share of class 2 vehicles is within expectations".to_string(),
-        })
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError> {
+        let counts = &data.class_counts;
+
+        // Check share of class 2 of total.
+        let c2_sum = counts.iter().map(|count| count.c2).sum::<u32>();
+        let total_sum = counts.iter().map(|count| count.total).sum::<u32>();
+
+        let c2_percent = c2_sum as f32 / total_sum as f32 * 100.0;
+
+        let result = if c2_percent < thresholds.class2_min_pct {
+            CheckResult::new(
+                Level::Warn,
+                format!(
+                    "Class 2 vehicles are less than {}% ({c2_percent:.1}%) of total.",
+                    thresholds.class2_min_pct
+                ),
+            )
+        } else {
+            CheckResult::new(
+                Level::Info,
+                "Share of class 2 vehicles is within expectations",
+            )
+        };
+        Ok(result.measured(c2_percent, thresholds.class2_min_pct))
     }
 }
 
 /// Check if share of unclassed vehicles is too high.
-fn check_share_unclassed_vehicles(
-    recordnum: u32,
-    conn: &Connection,
-) -> Result<CheckResult, CountError> {
-    let counts = get_c2_c15_total_counts(recordnum, conn)?;
-
-    // Check share of class 15 of total.
-    let c15_sum = counts.iter().map(|count| count.c15).sum::<u32>();
-    let total_sum = counts.iter().map(|count| count.total).sum::<u32>();
-
-    let c15_percent = c15_sum as f32 / total_sum as f32 * 100.0;
-
-    if c15_percent > 10.0 {
-        Ok(CheckResult {
-            level: Level::Warn,
-            message: format!(
-                "Unclassed vehicles are greater than 10% ({c15_percent:.1}%) of total."
-            ),
-        })
-    } else {
-        Ok(CheckResult {
-            level: Level::Info,
-            message: "Share of unclassed vehicles is within expectations".to_string(),
-        })
+pub struct UnclassedShare;
+
+impl DataCheck for UnclassedShare {
+    fn name(&self) -> &'static str {
+        "unclassed share"
+    }
+
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool {
+        *kind == NonPermCountKind::Class
+    }
+
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError> {
+        let counts = &data.class_counts;
+
+        // Check share of class 15 of total.
+        let c15_sum = counts
+            .iter()
+            .map(|count| count.c15.unwrap_or(0))
+            .sum::<u32>();
+        let total_sum = counts.iter().map(|count| count.total).sum::<u32>();
+
+        let c15_percent = c15_sum as f32 / total_sum as f32 * 100.0;
+
+        let result = if c15_percent > thresholds.unclassed_max_pct {
+            CheckResult::new(
+                Level::Warn,
+                format!(
+                    "Unclassed vehicles are greater than {}% ({c15_percent:.1}%) of total.",
+                    thresholds.unclassed_max_pct
+                ),
+            )
+        } else {
+            CheckResult::new(
+                Level::Info,
+                "Share of unclassed vehicles is within expectations",
+            )
+        };
+        Ok(result.measured(c15_percent, thresholds.unclassed_max_pct))
     }
 }
 
 /// Check if motor vehicle counts have relatively even proportion of total per direction.
-fn check_vehicle_dir_proportionality(
-    recordnum: u32,
-    conn: &Connection,
-) -> Result<CheckResult, CountError> {
-    let results = conn.query_as::<(u32, String)>(
-        "select totalcount, cntdir from tc_volcount_new where recordnum = :1",
-        &[&recordnum],
-    )?;
-
-    let mut count_by_dir = HashMap::new();
-    for result in results {
-        let (total, direction) = result?;
-        *count_by_dir.entry(direction).or_insert(total) += total;
-    }
-
-    if count_by_dir.is_empty() {
-        return Ok(CheckResult {
-            level: Level::Info,
-            message: "Count is empty".to_string(),
-        });
+pub struct VehicleDirProportionality;
+
+impl DataCheck for VehicleDirProportionality {
+    fn name(&self) -> &'static str {
+        "vehicle direction proportionality"
     }
 
-    let larger = count_by_dir.iter().max_by(|a, b| a.1.cmp(b.1)).unwrap();
-    let smaller = count_by_dir.iter().min_by(|a, b| a.1.cmp(b.1)).unwrap();
-
-    if count_by_dir.keys().len() > 1 {
-        let total = smaller.1 + larger.1;
-        let smaller_share = *smaller.1 as f32 / total as f32;
-        let larger_share = *larger.1 as f32 / total as f32;
-        if smaller_share < DIR_PROPORTION_LOWER_BOUND {
-            let msg =  format!("Abnormal direction proportions: {} has {:.1}% of total, {} has {:.1}%. (Expectation is that proportions are no less/more than {}%/{}%.)",
-                smaller.0,
-                smaller_share * 100_f32,
-                larger.0,
-                larger_share * 100_f32,
-                DIR_PROPORTION_LOWER_BOUND * 100_f32,
-                100_f32 - DIR_PROPORTION_LOWER_BOUND * 100_f32);
-            Ok(CheckResult {
-                level: Level::Warn,
-                message: msg,
-            })
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool {
+        matches!(
+            kind,
+            NonPermCountKind::Class | NonPermCountKind::Volume | NonPermCountKind::FifteenMinVolume
+        )
+    }
+
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError> {
+        let mut count_by_dir = HashMap::new();
+        for count in &data.hourly_vehicles {
+            *count_by_dir.entry(count.direction).or_insert(0) += count.count;
+        }
+
+        if count_by_dir.is_empty() {
+            return Ok(CheckResult::new(Level::Info, "Count is empty"));
+        }
+
+        let larger = count_by_dir.iter().max_by(|a, b| a.1.cmp(b.1)).unwrap();
+        let smaller = count_by_dir.iter().min_by(|a, b| a.1.cmp(b.1)).unwrap();
+
+        if count_by_dir.keys().len() > 1 {
+            let total = smaller.1 + larger.1;
+            let smaller_share = *smaller.1 as f32 / total as f32;
+            let larger_share = *larger.1 as f32 / total as f32;
+            let result = if smaller_share < thresholds.dir_proportion_lower_bound {
+                let msg =  format!("Abnormal direction proportions: {} has {:.1}% of total, {} has {:.1}%. (Expectation is that proportions are no less/more than {}%/{}%.)",
+                    smaller.0,
+                    smaller_share * 100_f32,
+                    larger.0,
+                    larger_share * 100_f32,
+                    thresholds.dir_proportion_lower_bound * 100_f32,
+                    100_f32 - thresholds.dir_proportion_lower_bound * 100_f32);
+                CheckResult::new(Level::Warn, msg)
+            } else {
+                CheckResult::new(Level::Info, "Direction proportions is within expectations")
+            };
+            Ok(result.measured(smaller_share, thresholds.dir_proportion_lower_bound))
         } else {
-            Ok(CheckResult {
-                level: Level::Info,
-                message: "Direction proportions is within expectations".to_string(),
-            })
+            Ok(CheckResult::new(
+                Level::Info,
+                "Skipping disproportional directionality check - count only one direction.",
+            ))
         }
-    } else {
-        Ok(CheckResult {
-            level: Level::Info,
-            message: "Skipping disproportional directionality check - count only one direction."
-                .to_string(),
-        })
     }
 }
 
 /// Check if bicycle counts have relatively even proportion of total per direction.
-fn check_bike_dir_proportionality(
-    recordnum: u32,
-    conn: &Connection,
-) -> Result<CheckResult, CountError> {
-    // Check to see if count is bidirectional.
-    let (cntdir, dir1, dir2) = conn.query_row_as::<(String, String, String)>(
-        "select cntdir, indir, outdir from tc_header where recordnum = :1",
-        &[&recordnum],
-    )?;
-
-    if cntdir == *"both" {
-        let dir1_sum = conn.query_row_as::<u32>(
-            "select sum(volume) from tc_bikecount_new where cntdir = :1 and recordnum = :2",
-            &[&dir1, &recordnum],
-        )?;
-        let dir2_sum = conn.query_row_as::<u32>(
-            "select sum(volume) from tc_bikecount_new where cntdir = :1 and recordnum = :2",
-            &[&dir2, &recordnum],
-        )?;
-
-        let total = dir1_sum as f32 + dir2_sum as f32;
-        let dir1_share = dir1_sum as f32 / total;
-        let dir2_share = dir2_sum as f32 / total;
-
-        if dir1_share < DIR_PROPORTION_LOWER_BOUND || dir2_share < DIR_PROPORTION_LOWER_BOUND {
-            Ok(CheckResult {
-                level: Level::Warn,
-                message: format!("Abnormal direction proportions: {} has {:.1}% of total, {} has {:.1}%. (Expectation is that proportions are no less/more than {}%/{}%.)",
-                            dir1,
-                            dir1_share * 100_f32,
-                            dir2,
-                            dir2_share * 100_f32,
-                            DIR_PROPORTION_LOWER_BOUND * 100_f32,
-                            100_f32 - DIR_PROPORTION_LOWER_BOUND * 100_f32),
-            })
-        } else {
-            Ok(CheckResult {
-                level: Level::Info,
-                message: "Direction proportions is within expectations".to_string(),
-            })
+pub struct BikeDirProportionality;
+
+impl DataCheck for BikeDirProportionality {
+    fn name(&self) -> &'static str {
+        "bicycle direction proportionality"
+    }
+
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool {
+        is_bicycle(kind)
+    }
+
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError> {
+        // Check to see if count is bidirectional.
+        let metadata = &data.metadata;
+
+        match (metadata.cntdir, metadata.indir, metadata.outdir) {
+            (Some(RoadDirection::Both), Some(dir1), Some(dir2)) => {
+                let dir_sum = |dir: LaneDirection| {
+                    data.bicycles
+                        .iter()
+                        .filter(|count| count.cntdir == dir)
+                        .map(|count| count.volume as u32)
+                        .sum::<u32>()
+                };
+                let dir1_sum = dir_sum(dir1);
+                let dir2_sum = dir_sum(dir2);
+
+                let total = dir1_sum as f32 + dir2_sum as f32;
+                let dir1_share = dir1_sum as f32 / total;
+                let dir2_share = dir2_sum as f32 / total;
+                let lower_bound = thresholds.dir_proportion_lower_bound;
+
+                let result = if dir1_share < lower_bound || dir2_share < lower_bound {
+                    CheckResult::new(
+                        Level::Warn,
+                        format!("Abnormal direction proportions: {} has {:.1}% of total, {} has {:.1}%. (Expectation is that proportions are no less/more than {}%/{}%.)",
+                                    dir1,
+                                    dir1_share * 100_f32,
+                                    dir2,
+                                    dir2_share * 100_f32,
+                                    lower_bound * 100_f32,
+                                    100_f32 - lower_bound * 100_f32),
+                    )
+                } else {
+                    CheckResult::new(Level::Info, "Direction proportions is within expectations")
+                };
+                Ok(result.measured(dir1_share.min(dir2_share), lower_bound))
+            }
+            _ => Ok(CheckResult::new(
+                Level::Info,
+                "Skipping disproportional directionality check - count only one direction.",
+            )),
         }
-    } else {
-        Ok(CheckResult {
-            level: Level::Info,
-            message: "Skipping disproportional directionality check - count only one direction."
-                .to_string(),
-        })
     }
 }
 
-/// Check if more than 1 consecutive 0-count/hour between 4am and 10pm for motor vehicles.
+/// Check if more than 1 consecutive 0-count/hour between 4am and 10pm (by default).
 ///
-/// The table the data is pulled from depends on what kind of count it is.
-fn check_0_hours(
-    recordnum: u32,
-    count_kind: &NonPermCountKind,
-    conn: &Connection,
-) -> Result<CheckResult, CountError> {
-    let start_hour = 4;
-    let end_hour = 22;
+/// The records checked depend on what kind of count it is: 15-minute bicycle counts for bicycle
+/// counts, and hourly volumes for motor vehicle counts.
+pub struct ConsecutiveZeroHours;
 
-    // Set up the query for different tables.
-    let queries = ["tc_bikecount_new", "tc_volcount_new"].into_iter().map(|table| {
-    format!("select trunc(countdatetime, 'HH24'), cntdir, sum(volume) from {table} where recordnum = :1 and to_char(countdatetime, 'hh24') >= '{start_hour:02}' and to_char(countdatetime, 'hh24') <= '{end_hour}' group by trunc(countdatetime, 'hh24'), cntdir order by cntdir, trunc(countdatetime, 'hh24')")}).collect::<Vec<_>>();
+impl DataCheck for ConsecutiveZeroHours {
+    fn name(&self) -> &'static str {
+        "consecutive zero hours"
+    }
 
-    let results = match count_kind {
-        NonPermCountKind::Bicycle1
-        | NonPermCountKind::Bicycle2
-        | NonPermCountKind::Bicycle3
-        | NonPermCountKind::Bicycle4
-        | NonPermCountKind::Bicycle5
-        | NonPermCountKind::Bicycle6 => {
-            conn.query_as::<(NaiveDateTime, String, u32)>(&queries[0], &[&recordnum])?
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool {
+        is_bicycle(kind)
+            || matches!(
+                kind,
+                NonPermCountKind::Class
+                    | NonPermCountKind::Volume
+                    | NonPermCountKind::FifteenMinVolume
+            )
+    }
+
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError> {
+        let start_hour = thresholds.zero_hours_start;
+        let end_hour = thresholds.zero_hours_end;
+
+        // (datetime, direction, volume) of every record.
+        let counts = if is_bicycle(&data.kind) {
+            data.bicycles
+                .iter()
+                .map(|c| (c.datetime, c.cntdir, c.volume as u32))
+                .collect::<Vec<_>>()
+        } else {
+            data.hourly_vehicles
+                .iter()
+                .map(|c| (c.datetime, c.direction, c.count))
+                .collect::<Vec<_>>()
+        };
+
+        // Sum volume by direction and hour, ordered by direction and then hour.
+        let mut hourly = BTreeMap::new();
+        for (datetime, direction, volume) in counts {
+            if datetime.hour() < start_hour || datetime.hour() > end_hour {
+                continue;
+            }
+            let hour = datetime
+                .with_minute(0)
+                .unwrap()
+                .with_second(0)
+                .unwrap()
+                .with_nanosecond(0)
+                .unwrap();
+            *hourly.entry((direction, hour)).or_insert(0_u32) += volume;
         }
-        NonPermCountKind::Class | NonPermCountKind::Volume | NonPermCountKind::FifteenMinVolume => {
-            conn.query_as::<(NaiveDateTime, String, u32)>(&queries[1], &[&recordnum])?
+
+        // Runs of hours with zero volume in a direction, as (direction, first hour, last hour,
+        // number of hours). The hours outside those checked don't interrupt a run.
+        let mut runs: Vec<(LaneDirection, NaiveDateTime, NaiveDateTime, u32)> = vec![];
+        let mut in_run = false;
+        for ((direction, hour), volume) in hourly {
+            if volume != 0 {
+                in_run = false;
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if in_run && run.0 == direction => {
+                    run.2 = hour;
+                    run.3 += 1;
+                }
+                _ => runs.push((direction, hour, hour, 1)),
+            }
+            in_run = true;
         }
-        _ => {
-            return Ok(CheckResult {
-                level: Level::Info,
-                message: "Consecutive zero hour check not implemented for {count_kind}."
-                    .to_string(),
+        let longest = runs.iter().map(|run| run.3).max().unwrap_or(0);
+        let periods = runs
+            .iter()
+            .filter(|run| run.3 > 1)
+            .map(|(_, first, last, _)| CheckPeriod {
+                start: *first,
+                end: *last + TimeDelta::hours(1),
             })
-        }
-    };
+            .collect::<Vec<_>>();
 
-    let mut consecutive_zeros = 0_u32;
-    for result in results {
-        let (_, _, volume) = result?;
-        if volume == 0 {
-            consecutive_zeros += 1;
+        let result = if periods.is_empty() {
+            CheckResult::new(
+                Level::Info,
+                "No counts with consecutive hourly periods of 0 volume counted.",
+            )
         } else {
-            consecutive_zeros = 0;
-        }
-        if consecutive_zeros > 1 {
-            return Ok(CheckResult {
-                level: Level::Warn,
-                message: format!(
+            CheckResult::new(
+                Level::Warn,
+                format!(
                     "Consecutive periods between the hours of {start_hour}:00 and {end_hour}:00 with zero volumes.",
                 ),
-            });
-        }
+            )
+        };
+        Ok(result.measured(longest as f32, 1.0).periods(periods))
     }
-    // The loop above would have short-circuited if consecutive hours with 0 volume.
-    Ok(CheckResult {
-        level: Level::Info,
-        message: "No counts with consecutive hourly periods of 0 volume counted.".to_string(),
-    })
 }
 
 /// Check if there is an excessive number of bicycles in any 15-minute period.
-fn check_excessive_bicycles(recordnum: u32, conn: &Connection) -> Result<CheckResult, CountError> {
-    let results = conn.query_as::<(NaiveDateTime, String, u32)>(
-        "select countdatetime, cntdir, volume from tc_bikecount_new where recordnum = :1 order by countdatetime",
-        &[&recordnum],
-    )?;
-
-    let mut excessive_bicycles = vec![];
-
-    for result in results {
-        let (dt, direction, volume) = result?;
-        if volume > BIKE_COUNT_MAX {
-            excessive_bicycles.push((dt, volume, direction))
-        }
+pub struct ExcessiveBicycles;
+
+impl DataCheck for ExcessiveBicycles {
+    fn name(&self) -> &'static str {
+        "excessive bicycles"
     }
 
-    if excessive_bicycles.is_empty() {
-        Ok(CheckResult {
-            level: Level::Info,
-            message: "All counts under excessive threshold".to_string(),
-        })
-    } else {
-        let excessive_bicycles =
-            excessive_bicycles
-                .iter()
-                .fold(String::new(), |mut output, (dt, volume, direction)| {
+    fn applies_to(&self, kind: &NonPermCountKind) -> bool {
+        is_bicycle(kind)
+    }
+
+    fn run(
+        &self,
+        data: &CheckData,
+        thresholds: &CheckThresholds,
+    ) -> Result<CheckResult, CountError> {
+        let mut counts = data.bicycles.iter().collect::<Vec<_>>();
+        counts.sort_unstable_by_key(|count| count.datetime);
+
+        let mut excessive_bicycles: Vec<(NaiveDateTime, u32, LaneDirection)> = vec![];
+
+        for count in counts {
+            if count.volume as u32 > thresholds.bike_count_max {
+                excessive_bicycles.push((count.datetime, count.volume as u32, count.cntdir))
+            }
+        }
+
+        let max_volume = data
+            .bicycles
+            .iter()
+            .map(|count| count.volume)
+            .max()
+            .unwrap_or(0);
+        let periods = excessive_bicycles
+            .iter()
+            .map(|(dt, _, _)| CheckPeriod {
+                start: *dt,
+                end: *dt + TimeDelta::minutes(15),
+            })
+            .collect::<Vec<_>>();
+
+        let result = if excessive_bicycles.is_empty() {
+            CheckResult::new(Level::Info, "All counts under excessive threshold")
+        } else {
+            let excessive_bicycles = excessive_bicycles.iter().fold(
+                String::new(),
+                |mut output, (dt, volume, direction)| {
                     let _ = write!(output, "{}: {} ({}); ", dt, volume, direction);
                     output
-                });
+                },
+            );
 
-        let message = format!("Found more than {BIKE_COUNT_MAX} bicycles counted in the following periods: {excessive_bicycles}");
-        Ok(CheckResult {
-            level: Level::Warn,
-            message,
-        })
+            let message = format!(
+                "Found more than {} bicycles counted in the following periods: {excessive_bicycles}",
+                thresholds.bike_count_max
+            );
+            CheckResult::new(Level::Warn, message)
+        };
+        Ok(result
+            .measured(max_volume as f32, thresholds.bike_count_max as f32)
+            .periods(periods))
     }
 }
 
-fn get_c2_c15_total_counts(
-    recordnum: u32,
-    conn: &Connection,
-) -> Result<Vec<ClassCountCheck>, CountError> {
-    let results = conn.query_as::<(NaiveDate, NaiveDateTime, u8, String, u32, u32, u32)>(
-    "select countdate, counttime, countlane, cntdir, total, cars_and_tlrs, unclassified from tc_clacount_new where recordnum = :1",
-    &[&recordnum],
-)?;
-
-    let mut counts = vec![];
-    for result in results {
-        let (count_date, count_time, lane, direction, total, c2, c15) = result?;
-        let datetime = NaiveDateTime::new(count_date, count_time.time());
-        counts.push(ClassCountCheck {
-            datetime,
-            lane,
-            dir: LaneDirection::from_str(&direction).unwrap(),
-            c2,
-            c15,
-            total,
-        })
-    }
-
-    Ok(counts)
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::db;
 
+    use chrono::NaiveDate;
+
+    fn bicycle_data(volumes: &[u16], facility: Option<&str>) -> CheckData {
+        let start = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        CheckData {
+            recordnum: 1,
+            kind: NonPermCountKind::Bicycle2,
+            metadata: Metadata {
+                bikepedfacility: facility.map(String::from),
+                ..Default::default()
+            },
+            class_counts: vec![],
+            hourly_vehicles: vec![],
+            bicycles: volumes
+                .iter()
+                .enumerate()
+                .map(|(i, volume)| FifteenMinuteBicycle {
+                    recordnum: 1,
+                    datetime: start + chrono::Duration::minutes(15 * i as i64),
+                    volume: *volume,
+                    cntdir: LaneDirection::North,
+                })
+                .collect(),
+        }
+    }
+
     #[test]
     fn fifteen_min_bicycle_disproportionate_direction_found() {
-        let (username, password) = db::get_non_perm_creds();
-        let pool = db::create_pool(username, password, 1).unwrap();
-        let conn = pool.get().unwrap();
+        // 20 bicycles north and 5 south, a fifth of the total.
+        let mut data = bicycle_data(&[12, 8], None);
+        data.bicycles.push(FifteenMinuteBicycle {
+            cntdir: LaneDirection::South,
+            ..bicycle_data(&[5], None).bicycles[0].clone()
+        });
+        data.metadata.cntdir = Some(RoadDirection::Both);
+        data.metadata.indir = Some(LaneDirection::North);
+        data.metadata.outdir = Some(LaneDirection::South);
+        let result = BikeDirProportionality
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Warn);
+        assert_eq!(result.measured, Some(0.2));
 
-        let result = check_bike_dir_proportionality(158971, &conn).unwrap();
-        assert!(matches!(result.level, Level::Warn))
+        // Only one direction counted.
+        data.metadata.cntdir = Some(RoadDirection::North);
+        let result = BikeDirProportionality
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Info);
+        assert_eq!(result.measured, None);
     }
 
     #[test]
     fn fifteen_min_bicycle_excessive() {
-        let (username, password) = db::get_non_perm_creds();
-        let pool = db::create_pool(username, password, 1).unwrap();
-        let conn = pool.get().unwrap();
-
-        let result = check_excessive_bicycles(111722, &conn).unwrap();
-        assert!(matches!(result.level, Level::Warn))
+        let data = bicycle_data(&[5, 21, 20], None);
+        let result = ExcessiveBicycles
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Warn);
+        assert_eq!(result.measured, Some(21.0));
+        assert_eq!(result.periods.len(), 1);
+        assert_eq!(result.periods[0].start, data.bicycles[1].datetime);
     }
 
     #[test]
     fn bicycle_consecutive_0_hours_found() {
-        let (username, password) = db::get_non_perm_creds();
-        let pool = db::create_pool(username, password, 1).unwrap();
+        // Some in the 10:00 hour, none in the 11:00 and 12:00 hours, and one at 13:00.
+        let mut volumes = vec![1; 4];
+        volumes.extend([0; 8]);
+        volumes.push(1);
+        let result = ConsecutiveZeroHours
+            .run(&bicycle_data(&volumes, None), &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Warn);
+
+        let result = ConsecutiveZeroHours
+            .run(&bicycle_data(&[1; 12], None), &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Info);
+    }
+
+    #[test]
+    #[ignore = "requires the test database"]
+    fn checks_of_stored_bicycle_counts_warn() {
+        let db_config = db::get_non_perm_creds();
+        let pool = db::create_pool(&db_config, 1).unwrap();
         let conn = pool.get().unwrap();
-        let result = check_0_hours(167607, &NonPermCountKind::Bicycle2, &conn);
-        let result = result.unwrap();
+
+        let data = CheckData::load(158971, &conn).unwrap();
+        let result = BikeDirProportionality
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert!(matches!(result.level, Level::Warn));
+
+        let data = CheckData::load(111722, &conn).unwrap();
+        let result = ExcessiveBicycles
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert!(matches!(result.level, Level::Warn));
+
+        let data = CheckData::load(167607, &conn).unwrap();
+        let result = ConsecutiveZeroHours
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
         assert!(matches!(result.level, Level::Warn))
     }
+
+    #[test]
+    fn vehicle_direction_totals_count_every_hour_once() {
+        let hour = |h| {
+            NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let hourly = |h, count, direction| HourlyVehicle {
+            recordnum: 1,
+            datetime: hour(h),
+            count,
+            direction,
+            lane: 1,
+        };
+        let data = CheckData {
+            recordnum: 1,
+            kind: NonPermCountKind::Volume,
+            metadata: Metadata::default(),
+            class_counts: vec![],
+            hourly_vehicles: vec![
+                hourly(10, 60, LaneDirection::East),
+                hourly(10, 20, LaneDirection::West),
+                hourly(11, 20, LaneDirection::West),
+            ],
+            bicycles: vec![],
+        };
+        // 60 east and 40 west: exactly at the default lower bound of 40%.
+        let result = VehicleDirProportionality
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Info);
+        assert_eq!(result.measured, Some(0.4));
+    }
+
+    #[test]
+    fn registry_maps_count_kinds_to_checks() {
+        let registry = CheckRegistry::default();
+        let names = |kind| {
+            registry
+                .checks_for(&kind)
+                .map(|check| check.name())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(NonPermCountKind::Class),
+            vec![
+                "unclassed share",
+                "class 2 share",
+                "vehicle direction proportionality",
+                "consecutive zero hours"
+            ]
+        );
+        assert_eq!(
+            names(NonPermCountKind::Volume),
+            vec![
+                "vehicle direction proportionality",
+                "consecutive zero hours"
+            ]
+        );
+        assert_eq!(
+            names(NonPermCountKind::Bicycle4),
+            vec![
+                "bicycle direction proportionality",
+                "excessive bicycles",
+                "consecutive zero hours"
+            ]
+        );
+        assert!(names(NonPermCountKind::TurningMovement).is_empty());
+    }
+
+    #[test]
+    fn consecutive_zero_hours_measured_with_periods() {
+        // 10:00-12:00 without any bicycles, then one at 12:00.
+        let mut volumes = vec![0; 8];
+        volumes.push(1);
+        let data = bicycle_data(&volumes, None);
+        let result = ConsecutiveZeroHours
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        let hour = |h| {
+            NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        assert_eq!(result.level, Level::Warn);
+        assert_eq!(result.measured, Some(2.0));
+        assert_eq!(result.threshold, Some(1.0));
+        assert_eq!(
+            result.periods,
+            vec![CheckPeriod {
+                start: hour(10),
+                end: hour(12)
+            }]
+        );
+
+        let result = ExcessiveBicycles
+            .run(&data, &CheckThresholds::default())
+            .unwrap();
+        assert_eq!(result.level, Level::Info);
+        assert_eq!(result.measured, Some(1.0));
+        assert!(result.periods.is_empty());
+    }
+
+    #[test]
+    fn thresholds_overridden_by_facility_and_fc() {
+        let config = CheckConfig {
+            defaults: CheckThresholds::default(),
+            overrides: vec![
+                ThresholdOverride {
+                    facility: Some("Trail".to_string()),
+                    bike_count_max: Some(60),
+                    ..Default::default()
+                },
+                ThresholdOverride {
+                    fc: Some(1),
+                    dir_proportion_lower_bound: Some(0.3),
+                    ..Default::default()
+                },
+                ThresholdOverride {
+                    fc: Some(1),
+                    facility: Some("trail".to_string()),
+                    bike_count_max: Some(100),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let trail = bicycle_data(&[45, 30], Some("trail"));
+        let thresholds = config.thresholds_for(&trail.metadata);
+        assert_eq!(thresholds.bike_count_max, 60);
+        assert_eq!(thresholds.dir_proportion_lower_bound, 0.40);
+        assert_eq!(
+            ExcessiveBicycles.run(&trail, &thresholds).unwrap().level,
+            Level::Info
+        );
+
+        let on_road = bicycle_data(&[45, 30], Some("Bike Lane"));
+        let thresholds = config.thresholds_for(&on_road.metadata);
+        assert_eq!(thresholds, CheckThresholds::default());
+        assert_eq!(
+            ExcessiveBicycles.run(&on_road, &thresholds).unwrap().level,
+            Level::Warn
+        );
+
+        let metadata = Metadata {
+            fc: Some(1),
+            bikepedfacility: Some("Trail".to_string()),
+            ..Default::default()
+        };
+        let thresholds = config.thresholds_for(&metadata);
+        assert_eq!(thresholds.bike_count_max, 100);
+        assert_eq!(thresholds.dir_proportion_lower_bound, 0.3);
+    }
 }
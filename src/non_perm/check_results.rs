@@ -0,0 +1,341 @@
+//! Stored results of the [data checks][crate::non_perm::check_data].
+//!
+//! Every time the checks are applied to a count - a run - the result of each check is stored as a
+//! [`CheckRecord`]: its severity, the value it measured and the threshold it judged that by, and
+//! the time periods it concerns, along with the id of the run. These can be read back with a
+//! [`CheckResultFilter`] (see
+//! [`CountStore::select_check_results`](crate::db::store::CountStore::select_check_results)) and
+//! summarized in a [`CheckReport`], e.g. to find every count whose share of unclassed vehicles was
+//! above 10% in a year, or to chart how many counts each check has flagged.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{Local, NaiveDateTime};
+use log::Level;
+use serde::Serialize;
+
+use crate::CountError;
+
+/// Which data a run of the checks was applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStage {
+    /// A count's records from a file, before they were stored.
+    Parsed,
+    /// A count's data in the database.
+    Stored,
+}
+
+impl FromStr for CheckStage {
+    type Err = CountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "parsed" => Ok(CheckStage::Parsed),
+            "stored" => Ok(CheckStage::Stored),
+            _ => Err(CountError::BadCheckStage(s.to_string())),
+        }
+    }
+}
+
+impl Display for CheckStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStage::Parsed => write!(f, "parsed"),
+            CheckStage::Stored => write!(f, "stored"),
+        }
+    }
+}
+
+/// A period of time a check's result concerns, from `start` up to (not including) `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CheckPeriod {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+/// The result of one check in a run of the checks on a count, as stored.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckRecord {
+    /// The run this is part of; all the checks applied to a count at one time share it.
+    pub run_id: u32,
+    pub recordnum: u32,
+    /// The [name](crate::non_perm::check_data::DataCheck::name) of the check.
+    pub check_id: String,
+    pub stage: CheckStage,
+    /// The [`Level`] of the result: INFO, WARN, or ERROR (the check couldn't be run).
+    pub severity: String,
+    /// The value the check measured, if any.
+    pub measured: Option<f64>,
+    /// The threshold `measured` was judged by, if any.
+    pub threshold: Option<f64>,
+    pub message: String,
+    pub periods: Vec<CheckPeriod>,
+    /// When the check was run, set when stored.
+    pub checked: Option<NaiveDateTime>,
+}
+
+impl CheckRecord {
+    pub fn new(
+        run_id: u32,
+        recordnum: u32,
+        check_id: &str,
+        stage: CheckStage,
+        level: Level,
+        message: String,
+    ) -> Self {
+        Self {
+            run_id,
+            recordnum,
+            check_id: check_id.to_string(),
+            stage,
+            severity: level.to_string(),
+            measured: None,
+            threshold: None,
+            message,
+            periods: vec![],
+            checked: None,
+        }
+    }
+}
+
+/// Which stored check results to get. Every criterion that is set must match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckResultFilter {
+    pub recordnum: Option<u32>,
+    pub run_id: Option<u32>,
+    pub check_id: Option<String>,
+    pub stage: Option<CheckStage>,
+    pub severity: Option<Level>,
+    /// Results that measured at least this.
+    pub min_measured: Option<f64>,
+    /// Results that measured at most this.
+    pub max_measured: Option<f64>,
+    /// Results checked at or after this.
+    pub from: Option<NaiveDateTime>,
+    /// Results checked before this.
+    pub to: Option<NaiveDateTime>,
+}
+
+impl CheckResultFilter {
+    /// Whether a result matches the filter.
+    pub fn matches(&self, record: &CheckRecord) -> bool {
+        self.recordnum.is_none_or(|v| v == record.recordnum)
+            && self.run_id.is_none_or(|v| v == record.run_id)
+            && self.check_id.as_ref().is_none_or(|v| *v == record.check_id)
+            && self.stage.is_none_or(|v| v == record.stage)
+            && self.severity.is_none_or(|v| v.as_str() == record.severity)
+            && self
+                .min_measured
+                .is_none_or(|min| record.measured.is_some_and(|v| v >= min))
+            && self
+                .max_measured
+                .is_none_or(|max| record.measured.is_some_and(|v| v <= max))
+            && self
+                .from
+                .is_none_or(|from| record.checked.is_some_and(|v| v >= from))
+            && self
+                .to
+                .is_none_or(|to| record.checked.is_some_and(|v| v < to))
+    }
+}
+
+/// Summary of the results of one check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckSummary {
+    pub check_id: String,
+    pub results: usize,
+    pub warnings: usize,
+    pub errors: usize,
+    /// The number of different counts with results.
+    pub counts: usize,
+    pub min_measured: Option<f64>,
+    pub max_measured: Option<f64>,
+    pub mean_measured: Option<f64>,
+}
+
+/// A report of check results: a summary by check, and the results themselves.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckReport {
+    pub generated: NaiveDateTime,
+    /// By check, ordered by check id.
+    pub summary: Vec<CheckSummary>,
+    pub results: Vec<CheckRecord>,
+}
+
+impl CheckReport {
+    pub fn new(results: Vec<CheckRecord>) -> Self {
+        let mut by_check: BTreeMap<&str, Vec<&CheckRecord>> = BTreeMap::new();
+        for record in &results {
+            by_check.entry(&record.check_id).or_default().push(record);
+        }
+        let summary = by_check
+            .into_iter()
+            .map(|(check_id, records)| {
+                let severity_count = |level: Level| {
+                    records
+                        .iter()
+                        .filter(|r| r.severity == level.as_str())
+                        .count()
+                };
+                let measured = records
+                    .iter()
+                    .filter_map(|r| r.measured)
+                    .collect::<Vec<_>>();
+                CheckSummary {
+                    check_id: check_id.to_string(),
+                    results: records.len(),
+                    warnings: severity_count(Level::Warn),
+                    errors: severity_count(Level::Error),
+                    counts: records
+                        .iter()
+                        .map(|r| r.recordnum)
+                        .collect::<HashSet<_>>()
+                        .len(),
+                    min_measured: measured.iter().copied().reduce(f64::min),
+                    max_measured: measured.iter().copied().reduce(f64::max),
+                    mean_measured: (!measured.is_empty())
+                        .then(|| measured.iter().sum::<f64>() / measured.len() as f64),
+                }
+            })
+            .collect();
+        Self {
+            generated: Local::now().naive_local(),
+            summary,
+            results,
+        }
+    }
+
+    /// The report as (pretty-printed) JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn record(
+        run_id: u32,
+        recordnum: u32,
+        check_id: &str,
+        level: Level,
+        measured: Option<f64>,
+    ) -> CheckRecord {
+        let mut record = CheckRecord::new(
+            run_id,
+            recordnum,
+            check_id,
+            CheckStage::Stored,
+            level,
+            "message".to_string(),
+        );
+        record.measured = measured;
+        record.threshold = Some(10.0);
+        record.checked = NaiveDate::from_ymd_opt(2025, 6, 1)
+            .unwrap()
+            .and_hms_opt(9, 0, 0);
+        record
+    }
+
+    #[test]
+    fn filter_matches_every_criterion_set() {
+        let result = record(3, 160001, "unclassed share", Level::Warn, Some(12.5));
+        assert!(CheckResultFilter::default().matches(&result));
+        assert!(CheckResultFilter {
+            recordnum: Some(160001),
+            check_id: Some("unclassed share".to_string()),
+            severity: Some(Level::Warn),
+            stage: Some(CheckStage::Stored),
+            min_measured: Some(10.0),
+            from: NaiveDate::from_ymd_opt(2025, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0),
+            to: NaiveDate::from_ymd_opt(2026, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0),
+            ..Default::default()
+        }
+        .matches(&result));
+        for filter in [
+            CheckResultFilter {
+                run_id: Some(4),
+                ..Default::default()
+            },
+            CheckResultFilter {
+                severity: Some(Level::Info),
+                ..Default::default()
+            },
+            CheckResultFilter {
+                stage: Some(CheckStage::Parsed),
+                ..Default::default()
+            },
+            CheckResultFilter {
+                max_measured: Some(10.0),
+                ..Default::default()
+            },
+            CheckResultFilter {
+                to: NaiveDate::from_ymd_opt(2025, 6, 1)
+                    .unwrap()
+                    .and_hms_opt(9, 0, 0),
+                ..Default::default()
+            },
+        ] {
+            assert!(!filter.matches(&result), "{filter:?}");
+        }
+        // Nothing measured doesn't match a bound on what was.
+        let unmeasured = record(3, 160001, "unclassed share", Level::Info, None);
+        assert!(!CheckResultFilter {
+            min_measured: Some(0.0),
+            ..Default::default()
+        }
+        .matches(&unmeasured));
+    }
+
+    #[test]
+    fn report_summarizes_by_check() {
+        let report = CheckReport::new(vec![
+            record(1, 160001, "unclassed share", Level::Warn, Some(12.0)),
+            record(1, 160001, "class 2 share", Level::Info, Some(80.0)),
+            record(2, 160002, "unclassed share", Level::Info, Some(4.0)),
+            record(3, 160001, "unclassed share", Level::Error, None),
+        ]);
+        assert_eq!(
+            report.summary,
+            vec![
+                CheckSummary {
+                    check_id: "class 2 share".to_string(),
+                    results: 1,
+                    warnings: 0,
+                    errors: 0,
+                    counts: 1,
+                    min_measured: Some(80.0),
+                    max_measured: Some(80.0),
+                    mean_measured: Some(80.0),
+                },
+                CheckSummary {
+                    check_id: "unclassed share".to_string(),
+                    results: 3,
+                    warnings: 1,
+                    errors: 1,
+                    counts: 2,
+                    min_measured: Some(4.0),
+                    max_measured: Some(12.0),
+                    mean_measured: Some(8.0),
+                },
+            ]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        let result = &json["results"][0];
+        assert_eq!(result["check_id"], "unclassed share");
+        assert_eq!(result["stage"], "stored");
+        assert_eq!(result["severity"], "WARN");
+        assert_eq!(result["measured"], 12.0);
+        assert_eq!(result["checked"], "2025-06-01T09:00:00");
+    }
+}
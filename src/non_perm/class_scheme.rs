@@ -0,0 +1,507 @@
+//! Schemes of vehicle classification, and counts of vehicles by length.
+//!
+//! Most of our classification counts use the FHWA 13-class scheme ([`VehicleClass`]), but some
+//! counters (and partner agencies) classify vehicles only by their length, into four
+//! [`LengthBin`]s. Both implement [`ClassScheme`], which maps every class to the length bin its
+//! vehicles fall in, so that a [`TimeBinnedVehicleClassCount`] can be converted into a
+//! [`TimeBinnedLengthClassCount`] and compared with counts that were only ever length-based. Every
+//! class count imported is also stored by length this way.
+//!
+//! The length bins and the FHWA classes in each are those of FHWA's Traffic Monitoring Guide:
+//!
+//! | Bin        | Length     | FHWA classes                               |
+//! |------------|------------|--------------------------------------------|
+//! | Short      | < 22 ft    | 1-3: motorcycles, cars, pickups and vans   |
+//! | Medium     | 22-40 ft   | 4-7: buses and single-unit trucks          |
+//! | Long       | 40-62 ft   | 8-10: single-trailer trucks                |
+//! | Very long  | 62+ ft     | 11-13: multi-trailer trucks                |
+//!
+//! Counters don't necessarily number classes the way FHWA does, so the class numbers in files of
+//! individual vehicles are interpreted by a [`ClassMapping`]. Besides the built-in mappings of
+//! JAMAR's "Modified Scheme F", mappings can be [loaded](ClassMapping::from_toml) from a TOML
+//! file, in which each table is a named mapping of the class numbers in a file to an FHWA class
+//! (15 for unclassified), "unclassified", "bicycle", or "discard":
+//!
+//! ```toml
+//! [my-counter]
+//! 1 = 1
+//! 2 = 2
+//! 3 = 3
+//! 4 = 5
+//! 9 = "bicycle"
+//! 10 = "discard"
+//! 11 = "unclassified"
+//! ```
+//!
+//! Class numbers that aren't mapped are errors.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use oracle::RowValue;
+use serde::Deserialize;
+
+use crate::{
+    non_perm::{LaneDirection, TimeBinnedVehicleClassCount, VehicleClass},
+    CountError, GetDate,
+};
+
+/// The lower edges of all but the first [`LengthBin`], in feet.
+pub const LENGTH_EDGES: [f32; 3] = [22.0, 40.0, 62.0];
+
+/// A scheme of vehicle classification.
+pub trait ClassScheme: Sized {
+    /// The name of the scheme.
+    const NAME: &'static str;
+
+    /// Create a class from the number it's recorded as.
+    fn from_num(num: u8) -> Result<Self, CountError>;
+
+    /// The number the class is recorded as.
+    fn num(&self) -> u8;
+
+    /// The length bin that vehicles of this class fall in, if known.
+    fn length_bin(&self) -> Option<LengthBin>;
+}
+
+impl ClassScheme for VehicleClass {
+    const NAME: &'static str = "fhwa13";
+
+    fn from_num(num: u8) -> Result<Self, CountError> {
+        VehicleClass::from_num(num)
+    }
+
+    fn num(&self) -> u8 {
+        self.clone() as u8
+    }
+
+    fn length_bin(&self) -> Option<LengthBin> {
+        match self {
+            VehicleClass::Motorcycles
+            | VehicleClass::PassengerCars
+            | VehicleClass::OtherFourTireSingleUnitVehicles => Some(LengthBin::Short),
+            VehicleClass::Buses
+            | VehicleClass::TwoAxleSixTireSingleUnitTrucks
+            | VehicleClass::ThreeAxleSingleUnitTrucks
+            | VehicleClass::FourOrMoreAxleSingleUnitTrucks => Some(LengthBin::Medium),
+            VehicleClass::FourOrFewerAxleSingleTrailerTrucks
+            | VehicleClass::FiveAxleSingleTrailerTrucks
+            | VehicleClass::SixOrMoreAxleSingleTrailerTrucks => Some(LengthBin::Long),
+            VehicleClass::FiveOrFewerAxleMultiTrailerTrucks
+            | VehicleClass::SixAxleMultiTrailerTrucks
+            | VehicleClass::SevenOrMoreAxleMultiTrailerTrucks => Some(LengthBin::VeryLong),
+            VehicleClass::UnclassifiedVehicle => None,
+        }
+    }
+}
+
+/// Length-based vehicle classes.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthBin {
+    Short = 1,
+    Medium = 2,
+    Long = 3,
+    VeryLong = 4,
+}
+
+impl LengthBin {
+    /// The bin a vehicle of some length, in feet, falls in.
+    ///
+    /// A length equal to an edge of [`LENGTH_EDGES`] is in the bin above it.
+    pub fn from_length(feet: f32) -> Self {
+        match LENGTH_EDGES.partition_point(|edge| *edge <= feet) {
+            0 => LengthBin::Short,
+            1 => LengthBin::Medium,
+            2 => LengthBin::Long,
+            _ => LengthBin::VeryLong,
+        }
+    }
+}
+
+impl ClassScheme for LengthBin {
+    const NAME: &'static str = "length";
+
+    fn from_num(num: u8) -> Result<Self, CountError> {
+        match num {
+            1 => Ok(LengthBin::Short),
+            2 => Ok(LengthBin::Medium),
+            3 => Ok(LengthBin::Long),
+            4 => Ok(LengthBin::VeryLong),
+            other => Err(CountError::BadVehicleClass(other)),
+        }
+    }
+
+    fn num(&self) -> u8 {
+        *self as u8
+    }
+
+    fn length_bin(&self) -> Option<LengthBin> {
+        Some(*self)
+    }
+}
+
+/// Count of [vehicles by length][`LengthBin`], binned into 15-minute or hourly intervals.
+///
+/// Unlike [`TimeBinnedVehicleClassCount`], unclassified vehicles are counted only in
+/// `unclassified`, so the total is the sum of all the other fields.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct TimeBinnedLengthClassCount {
+    #[row_value(rename = "countdate")]
+    pub date: NaiveDate,
+    #[row_value(rename = "counttime")]
+    pub time: NaiveDateTime,
+    #[row_value(rename = "countlane")]
+    pub lane: Option<u8>,
+    pub recordnum: u32,
+    #[row_value(rename = "cntdir")]
+    pub direction: Option<LaneDirection>,
+    #[row_value(rename = "lenbin1")]
+    pub short: u32,
+    #[row_value(rename = "lenbin2")]
+    pub medium: u32,
+    #[row_value(rename = "lenbin3")]
+    pub long: u32,
+    #[row_value(rename = "lenbin4")]
+    pub very_long: u32,
+    pub unclassified: u32,
+    pub total: u32,
+}
+
+impl GetDate for TimeBinnedLengthClassCount {
+    fn get_date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+impl TimeBinnedLengthClassCount {
+    /// Create one with 0 count for all bins.
+    pub fn new(
+        recordnum: u32,
+        date: NaiveDate,
+        time: NaiveDateTime,
+        lane: Option<u8>,
+        direction: Option<LaneDirection>,
+    ) -> Self {
+        Self {
+            date,
+            time,
+            lane,
+            recordnum,
+            direction,
+            short: 0,
+            medium: 0,
+            long: 0,
+            very_long: 0,
+            unclassified: 0,
+            total: 0,
+        }
+    }
+
+    /// Add a number of vehicles of the same class, of any scheme, to the count.
+    pub fn add(&mut self, class: &impl ClassScheme, volume: u32) {
+        match class.length_bin() {
+            Some(LengthBin::Short) => self.short += volume,
+            Some(LengthBin::Medium) => self.medium += volume,
+            Some(LengthBin::Long) => self.long += volume,
+            Some(LengthBin::VeryLong) => self.very_long += volume,
+            None => self.unclassified += volume,
+        }
+        self.total += volume;
+    }
+}
+
+impl TimeBinnedLengthClassCount {
+    /// Combine the FHWA classes of each of a set of counts into length bins.
+    pub fn from_class_counts(
+        counts: &[TimeBinnedVehicleClassCount],
+    ) -> Result<Vec<Self>, CountError> {
+        counts.iter().map(Self::try_from).collect()
+    }
+}
+
+impl TryFrom<&TimeBinnedVehicleClassCount> for TimeBinnedLengthClassCount {
+    type Error = CountError;
+
+    /// Combine the FHWA classes of a count into length bins.
+    fn try_from(count: &TimeBinnedVehicleClassCount) -> Result<Self, Self::Error> {
+        // Unclassified vehicles are also included in class 2.
+        let unclassified = count.c15.unwrap_or(0);
+        let short = (count.c1 + count.c2 + count.c3)
+            .checked_sub(unclassified)
+            .ok_or(CountError::UnclassifiedMisMatch(count.time))?;
+        Ok(Self {
+            date: count.date,
+            time: count.time,
+            lane: count.lane,
+            recordnum: count.recordnum,
+            direction: count.direction,
+            short,
+            medium: count.c4 + count.c5 + count.c6 + count.c7,
+            long: count.c8 + count.c9 + count.c10,
+            very_long: count.c11 + count.c12 + count.c13,
+            unclassified,
+            total: count.total,
+        })
+    }
+}
+
+/// What a class number in a file of individual vehicles represents.
+#[derive(Debug, Clone)]
+pub enum MappedClass {
+    Vehicle(VehicleClass),
+    /// Bicycles are counted separately from motor vehicles, if at all.
+    Bicycle,
+    /// Not counted at all.
+    Discard,
+}
+
+/// A named mapping of the class numbers that a counter records to what they represent.
+#[derive(Debug, Clone)]
+pub struct ClassMapping {
+    name: String,
+    classes: HashMap<u8, MappedClass>,
+}
+
+impl Default for ClassMapping {
+    fn default() -> Self {
+        Self::modified_scheme_f()
+    }
+}
+
+impl ClassMapping {
+    pub fn new(name: &str, classes: impl IntoIterator<Item = (u8, MappedClass)>) -> Self {
+        Self {
+            name: name.to_string(),
+            classes: classes.into_iter().collect(),
+        }
+    }
+
+    /// JAMAR's "Modified Scheme F": FHWA classes 1-13, with 0, 14, and 15 all unclassified.
+    pub fn modified_scheme_f() -> Self {
+        Self::new(
+            "modified-f",
+            (0..=15).map(|num| (num, MappedClass::Vehicle(fhwa_class(num)))),
+        )
+    }
+
+    /// JAMAR's "Modified Scheme F - With Bikes": the same as
+    /// [`modified_scheme_f`](Self::modified_scheme_f), except that 14 is bicycles.
+    pub fn modified_scheme_f_with_bikes() -> Self {
+        let mut mapping = Self::modified_scheme_f();
+        mapping.name = "modified-f-bikes".to_string();
+        mapping.classes.insert(14, MappedClass::Bicycle);
+        mapping
+    }
+
+    /// Get a built-in mapping by name.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "modified-f" => Some(Self::modified_scheme_f()),
+            "modified-f-bikes" => Some(Self::modified_scheme_f_with_bikes()),
+            _ => None,
+        }
+    }
+
+    /// Parse the mappings of a TOML document, one per table.
+    pub fn from_toml(s: &str) -> Result<Vec<Self>, CountError> {
+        let raw: BTreeMap<String, BTreeMap<String, RawMappedClass>> =
+            toml::from_str(s).map_err(|e| CountError::BadClassMapping(e.to_string()))?;
+        raw.into_iter()
+            .map(|(name, classes)| {
+                let classes = classes
+                    .into_iter()
+                    .map(|(num, class)| {
+                        let num = num.parse().map_err(|_| {
+                            CountError::BadClassMapping(format!(
+                                "'{num}' in {name} is not a class number"
+                            ))
+                        })?;
+                        Ok((num, class.mapped(&name)?))
+                    })
+                    .collect::<Result<Vec<_>, CountError>>()?;
+                Ok(Self::new(&name, classes))
+            })
+            .collect()
+    }
+
+    /// Load the mappings of a TOML file.
+    pub fn load(path: &Path) -> Result<Vec<Self>, CountError> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// What a class number represents.
+    pub fn map(&self, num: u8) -> Result<&MappedClass, CountError> {
+        self.classes
+            .get(&num)
+            .ok_or(CountError::BadVehicleClass(num))
+    }
+
+    /// Whether a class number represents bicycles.
+    pub fn is_bicycle(&self, num: u8) -> bool {
+        matches!(self.classes.get(&num), Some(MappedClass::Bicycle))
+    }
+}
+
+/// The FHWA class of a class number of Modified Scheme F.
+fn fhwa_class(num: u8) -> VehicleClass {
+    VehicleClass::from_num(num).expect("0-15 are all classes")
+}
+
+/// A class as written in a TOML mapping: an FHWA class number or the name of another class.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMappedClass {
+    Fhwa(u8),
+    Other(String),
+}
+
+impl RawMappedClass {
+    fn mapped(self, scheme: &str) -> Result<MappedClass, CountError> {
+        match self {
+            RawMappedClass::Fhwa(num @ (1..=13 | 15)) => Ok(MappedClass::Vehicle(fhwa_class(num))),
+            RawMappedClass::Other(v) if v == "unclassified" => {
+                Ok(MappedClass::Vehicle(VehicleClass::UnclassifiedVehicle))
+            }
+            RawMappedClass::Other(v) if v == "bicycle" => Ok(MappedClass::Bicycle),
+            RawMappedClass::Other(v) if v == "discard" => Ok(MappedClass::Discard),
+            RawMappedClass::Fhwa(num) => Err(CountError::BadClassMapping(format!(
+                "{num} in {scheme} is not an FHWA class"
+            ))),
+            RawMappedClass::Other(v) => Err(CountError::BadClassMapping(format!(
+                "'{v}' in {scheme} is not a class"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::non_perm::intermediate::{BinnedCountKey, VehicleClassCount};
+
+    #[test]
+    fn lengths_binned_at_edges() {
+        assert_eq!(LengthBin::from_length(17.5), LengthBin::Short);
+        assert_eq!(LengthBin::from_length(22.0), LengthBin::Medium);
+        assert_eq!(LengthBin::from_length(61.9), LengthBin::Long);
+        assert_eq!(LengthBin::from_length(75.0), LengthBin::VeryLong);
+        assert_eq!(<LengthBin as ClassScheme>::from_num(3).unwrap().num(), 3);
+        assert!(<LengthBin as ClassScheme>::from_num(5).is_err());
+    }
+
+    #[test]
+    fn class_mappings_parsed_from_toml() {
+        let mappings = ClassMapping::from_toml(
+            r#"
+            [b-counter]
+            0 = "discard"
+            1 = 2
+            2 = 15
+
+            [a-counter]
+            1 = "bicycle"
+            2 = "unclassified"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(mappings.len(), 2);
+        let a = &mappings[0];
+        assert_eq!(a.name(), "a-counter");
+        assert!(a.is_bicycle(1));
+        assert!(matches!(
+            a.map(2),
+            Ok(MappedClass::Vehicle(VehicleClass::UnclassifiedVehicle))
+        ));
+        assert!(matches!(a.map(3), Err(CountError::BadVehicleClass(3))));
+        let b = &mappings[1];
+        assert!(matches!(b.map(0), Ok(MappedClass::Discard)));
+        assert!(matches!(
+            b.map(1),
+            Ok(MappedClass::Vehicle(VehicleClass::PassengerCars))
+        ));
+
+        for bad in ["[a]\nx = 1", "[a]\n1 = 14", "[a]\n1 = \"truck\"", "1 = 1"] {
+            assert!(
+                matches!(
+                    ClassMapping::from_toml(bad),
+                    Err(CountError::BadClassMapping(_))
+                ),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn modified_scheme_f_bicycles_only_with_bikes() {
+        let without = ClassMapping::builtin("modified-f").unwrap();
+        assert!(matches!(
+            without.map(14),
+            Ok(MappedClass::Vehicle(VehicleClass::UnclassifiedVehicle))
+        ));
+        assert!(ClassMapping::builtin("modified-f-bikes")
+            .unwrap()
+            .is_bicycle(14));
+        assert!(without.map(16).is_err());
+    }
+
+    #[test]
+    fn fhwa_class_count_converted_to_length_bins_same_as_counted_by_length() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+        let time = date.and_hms_opt(10, 15, 0).unwrap();
+        let classes = [2, 2, 3, 0, 5, 9, 9, 13];
+
+        let mut class_count = VehicleClassCount::new(1, LaneDirection::North);
+        let mut length_count =
+            TimeBinnedLengthClassCount::new(1, date, time, Some(1), Some(LaneDirection::North));
+        for class in classes {
+            let class = VehicleClass::from_num(class).unwrap();
+            length_count.add(&class, 1);
+            class_count.insert(class);
+        }
+        let class_count = TimeBinnedVehicleClassCount::from_intermediate(
+            BinnedCountKey {
+                date,
+                time,
+                lane: 1,
+            },
+            class_count,
+        );
+
+        let converted = TimeBinnedLengthClassCount::try_from(&class_count).unwrap();
+        assert_eq!(converted, length_count);
+        assert_eq!(
+            (converted.short, converted.medium, converted.long),
+            (3, 1, 2)
+        );
+        assert_eq!((converted.very_long, converted.unclassified), (1, 1));
+        assert_eq!(converted.total, 8);
+    }
+
+    #[test]
+    fn more_unclassified_than_in_class_2_errs() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+        let time = date.and_hms_opt(10, 15, 0).unwrap();
+        let mut class_count = VehicleClassCount::new(1, LaneDirection::North);
+        class_count.insert(VehicleClass::PassengerCars);
+        let mut class_count = TimeBinnedVehicleClassCount::from_intermediate(
+            BinnedCountKey {
+                date,
+                time,
+                lane: 1,
+            },
+            class_count,
+        );
+        class_count.c15 = Some(2);
+        assert!(matches!(
+            TimeBinnedLengthClassCount::try_from(&class_count),
+            Err(CountError::UnclassifiedMisMatch(t)) if t == time
+        ));
+    }
+}
@@ -6,20 +6,40 @@
 //! - [FifteenMinuteVehicle::extract]
 //! - [IndividualBicycle::extract]
 //! - [IndividualVehicle::extract]
-
-use std::fs::{self, File};
+//! - [TimeBinnedVehicleClassCount::extract]
+//! - [TurningMovementCount::extract]
+//!
+//! Each also has an `extract_with_policy` variant, which takes a [`RowPolicy`] for rows of data
+//! that can't be extracted: either abort at the first one (as `extract` does), or skip and
+//! collect them as [`RejectedRow`]s, so long as there aren't too many.
+//!
+//! Files are read in a single pass, header included, one row at a time. For the large files of
+//! individual vehicles and pre-binned counts, `stream` returns a [`RecordStream`] of the records
+//! rather than collecting them, so that they can be binned as they are read (see
+//! [`SpeedAndClassBinner`](crate::non_perm::SpeedAndClassBinner)) without the whole count ever
+//! being in memory.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
 
 use chrono::{format::ParseErrorKind, NaiveDate, NaiveDateTime, NaiveTime};
-use csv::{Reader, ReaderBuilder};
-use log::error;
+use csv::{Reader, ReaderBuilder, StringRecord};
 
 use crate::{
     non_perm::{
+        bin_time,
+        class_scheme::{ClassMapping, MappedClass},
+        intermediate::{BinnedCountKey, VehicleClassCount},
+        turning_movement::{parse_approach, Movement, TurningMovementCount},
         Directions, FifteenMinuteBicycle, FifteenMinutePedestrian, FifteenMinuteVehicle,
-        IndividualBicycle, IndividualVehicle,
+        IndividualBicycle, IndividualVehicle, LaneDirection, TimeBinnedVehicleClassCount,
+        TimeInterval, VehicleClass,
     },
-    CountError,
+    CountError, RowLocation,
 };
 
 // Headers stripped of double quotes and spaces.
@@ -28,6 +48,13 @@ const FIFTEEN_MINUTE_VEHICLE_HEADER2: &str = "Number,Date,Time,Channel2";
 const FIFTEEN_MINUTE_BIKE_OR_PED_HEADER: &str = "Time,";
 const IND_VEH_OR_IND_BIKE: &str = "Veh.No.,Date,Time,Channel,Class,Speed";
 
+// FHWA classes that must each have a column for a row to be considered the header of a manual
+// class tally sheet. A column for unclassified vehicles (15) is optional.
+const TALLY_CLASSES: std::ops::RangeInclusive<u8> = 1..=13;
+
+// Minimum number of movement columns for a row to be considered the header of a TMC file.
+const MIN_TMC_MOVEMENTS: usize = 4;
+
 /// The kinds of counts this module can handle as inputs.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InputCount {
@@ -54,231 +81,380 @@ pub enum InputCount {
     ///
     /// See [`IndividualVehicle`] and [`IndividualBicycle`], the corresponding types.
     IndividualVehicleAndIndividualBicycle,
+    /// Manual class counts, tallied by FHWA class in 15-minute (or shorter) intervals.
+    ///
+    /// See [`TimeBinnedVehicleClassCount`], the corresponding type.
+    ManualClass,
 }
 
 impl InputCount {
+    /// All of the variants.
+    pub const ALL: [InputCount; 7] = [
+        InputCount::FifteenMinuteBicycle,
+        InputCount::FifteenMinutePedestrian,
+        InputCount::FifteenMinuteVehicle,
+        InputCount::IndividualVehicle,
+        InputCount::IndividualBicycle,
+        InputCount::IndividualVehicleAndIndividualBicycle,
+        InputCount::ManualClass,
+    ];
+
     /// Get the `InputCount` variant from the parent directory where a file is located.
     pub fn from_parent_dir(path: &Path) -> Result<Self, CountError> {
-        // Get the directory immediately above the file.
-        let parent = path
-            .parent()
-            .ok_or(CountError::BadPath(path.to_owned()))?
-            .components()
-            .next_back()
-            .ok_or(CountError::BadPath(path.to_owned()))?
-            .as_os_str()
-            .to_str()
-            .ok_or(CountError::BadPath(path.to_owned()))?;
-
-        match parent {
-            "ecocounter_15minutebicycle" => Ok(InputCount::FifteenMinuteBicycle),
-            "ecocounter_15minutepedestrian" => Ok(InputCount::FifteenMinutePedestrian),
-            "jamar_15minutevehicle" => Ok(InputCount::FifteenMinuteVehicle),
-            "jamar_vehicle" => Ok(InputCount::IndividualVehicle),
-            "jamar_bicycle" => Ok(InputCount::IndividualBicycle),
-            "jamar_vehicle_and_bicycle" => Ok(InputCount::IndividualVehicleAndIndividualBicycle),
-            _ => Err(CountError::BadLocation(parent.to_string())),
-        }
-    }
-}
-
-// Extract FifteenMinuteVehicle records from a file.
+        let parent = parent_dir(path)?;
+
+        InputCount::ALL
+            .into_iter()
+            .find(|input_count| input_count.directory() == parent)
+            .ok_or(CountError::BadLocation(parent.to_string()))
+    }
+
+    /// The directory that files of this variant are uploaded to.
+    pub fn directory(&self) -> &'static str {
+        match self {
+            InputCount::FifteenMinuteBicycle => "ecocounter_15minutebicycle",
+            InputCount::FifteenMinutePedestrian => "ecocounter_15minutepedestrian",
+            InputCount::FifteenMinuteVehicle => "jamar_15minutevehicle",
+            InputCount::IndividualVehicle => "jamar_vehicle",
+            InputCount::IndividualBicycle => "jamar_bicycle",
+            InputCount::IndividualVehicleAndIndividualBicycle => "jamar_vehicle_and_bicycle",
+            InputCount::ManualClass => "manual_class",
+        }
+    }
+
+    /// Get the `InputCount` variant from a file's location, verified against its contents.
+    ///
+    /// The location determines the variant, but it is an error
+    /// ([`LocationHeaderMisMatch`](CountError::LocationHeaderMisMatch)) if the file's
+    /// [`FileLayout`] is not that of the location, or if the contents indicate another variant
+    /// with at least [`Confidence::Medium`]. See [`detect`](Self::detect) for `classes`.
+    pub fn from_path(path: &Path, classes: &ClassMapping) -> Result<Self, CountError> {
+        let location = Self::from_parent_dir(path)?;
+        let detected = Self::detect(path, classes)?;
+
+        if detected.input_count.layout() != location.layout()
+            || (detected.confidence > Confidence::Low && detected.input_count != location)
+        {
+            return Err(CountError::LocationHeaderMisMatch {
+                path: path.to_owned(),
+                location: format!("{location:?}"),
+                detected: format!("{:?}", detected.input_count),
+            });
+        }
+        Ok(location)
+    }
+
+    /// Detect the `InputCount` variant of a file from its header and data.
+    ///
+    /// The header determines the [`FileLayout`]. Where a layout is shared by more than one
+    /// variant, the data is used to narrow it down if possible, and the [`Confidence`] of the
+    /// [`Detection`] is lowered accordingly:
+    /// - JAMAR 15-minute files are always [`InputCount::FifteenMinuteVehicle`].
+    /// - JAMAR per-vehicle files containing bicycles and no motor vehicles other than
+    ///   unclassified ones, by `classes`, are [`InputCount::IndividualBicycle`]. Otherwise, the
+    ///   file contains motor vehicles (and whether it also contains bicycles cannot be
+    ///   determined) or no bicycles or vehicles at all, and so is
+    ///   [`InputCount::IndividualVehicle`] with [`Confidence::Low`].
+    /// - Eco-Counter files have the same layout for bicycles and pedestrians.
+    /// - Manual class tally sheets are always [`InputCount::ManualClass`].
+    pub fn detect(path: &Path, classes: &ClassMapping) -> Result<Detection, CountError> {
+        let mut rows = create_reader(File::open(path)?).into_records();
+        let (nondata_rows, header) =
+            read_header(&mut rows)?.ok_or(CountError::BadHeader(path.to_owned()))?;
+
+        if let Some(tally_header) = TallyHeader::parse(&header) {
+            return Ok(Detection {
+                input_count: InputCount::ManualClass,
+                confidence: Confidence::High,
+                directions: tally_header.direction.is_none().then_some(1),
+                nondata_rows,
+            });
+        }
+
+        if header.starts_with(FIFTEEN_MINUTE_BIKE_OR_PED_HEADER) {
+            // Time, total, and then one column per direction.
+            let directions = match header.split(',').filter(|f| !f.is_empty()).count() {
+                3 => 1,
+                4 => 2,
+                _ => return Err(CountError::BadHeader(path.to_owned())),
+            };
+            return Ok(Detection {
+                input_count: InputCount::FifteenMinuteBicycle,
+                confidence: Confidence::Low,
+                directions: Some(directions),
+                nondata_rows,
+            });
+        }
+
+        if header.contains(FIFTEEN_MINUTE_VEHICLE_HEADER1)
+            || header.contains(FIFTEEN_MINUTE_VEHICLE_HEADER2)
+        {
+            let directions = header
+                .split(',')
+                .filter(|f| f.starts_with("Channel"))
+                .count();
+            return Ok(Detection {
+                input_count: InputCount::FifteenMinuteVehicle,
+                confidence: Confidence::High,
+                directions: Some(directions),
+                nondata_rows,
+            });
+        }
+
+        // Otherwise, the header is that of individual vehicles/bicycles; check classes counted.
+        let mut bicycles = false;
+        let mut vehicles = false;
+        for row in rows {
+            if let Some(Ok(num)) = row?.get(4).map(|v| v.parse::<u8>()) {
+                match classes.map(num) {
+                    Ok(MappedClass::Bicycle) => bicycles = true,
+                    Ok(MappedClass::Vehicle(class))
+                        if !matches!(class, VehicleClass::UnclassifiedVehicle) =>
+                    {
+                        vehicles = true;
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        if bicycles && !vehicles {
+            Ok(Detection {
+                input_count: InputCount::IndividualBicycle,
+                confidence: Confidence::Medium,
+                directions: None,
+                nondata_rows,
+            })
+        } else {
+            Ok(Detection {
+                input_count: InputCount::IndividualVehicle,
+                confidence: Confidence::Low,
+                directions: None,
+                nondata_rows,
+            })
+        }
+    }
+
+    /// The [`FileLayout`] of files of this variant.
+    pub fn layout(&self) -> FileLayout {
+        match self {
+            InputCount::FifteenMinuteBicycle | InputCount::FifteenMinutePedestrian => {
+                FileLayout::EcoCounter
+            }
+            InputCount::FifteenMinuteVehicle => FileLayout::JamarFifteenMinute,
+            InputCount::IndividualVehicle
+            | InputCount::IndividualBicycle
+            | InputCount::IndividualVehicleAndIndividualBicycle => FileLayout::JamarIndividual,
+            InputCount::ManualClass => FileLayout::ManualClassTally,
+        }
+    }
+}
+
+/// The layout of a file - its header and columns - which may be shared by multiple
+/// [`InputCount`]s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileLayout {
+    /// Pre-binned, 15-minute counts from Eco-Counter: a total and one column per direction.
+    EcoCounter,
+    /// Pre-binned, 15-minute counts from StarNext/JAMAR: one column per channel.
+    JamarFifteenMinute,
+    /// Individual vehicles and/or bicycles from StarNext/JAMAR.
+    JamarIndividual,
+    /// Manual class tally sheets: the start of each interval and one column per FHWA class.
+    ManualClassTally,
+}
+
+/// How certain [`InputCount::detect`] is of the variant it detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// The layout matched, but it is shared by other variants that the data can't distinguish.
+    Low,
+    /// The layout matched, and the variant was inferred from the data.
+    Medium,
+    /// The header matches only this variant.
+    High,
+}
+
+/// The result of detecting the [`InputCount`] of a file from its contents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub input_count: InputCount,
+    pub confidence: Confidence,
+    /// The number of directions/channels in pre-binned counts (or manual class counts without
+    /// a direction column).
+    pub directions: Option<usize>,
+    /// The number of nondata rows (see [`num_nondata_rows`]).
+    pub nondata_rows: usize,
+}
+
+/// Extract FifteenMinuteVehicle records from a file.
 impl FifteenMinuteVehicle {
     pub fn extract(
         path: &Path,
         recordnum: u32,
         directions: &Directions,
     ) -> Result<Vec<Self>, CountError> {
-        let data_file = File::open(path)?;
-        let mut rdr = create_reader(&data_file);
+        Ok(Self::extract_with_policy(path, recordnum, directions, RowPolicy::Strict)?.records)
+    }
 
-        // Iterate through data rows.
-        let mut counts = vec![];
-        for row in rdr.records().skip(num_nondata_rows(path)?) {
-            let row = row?;
-            let datetime = NaiveDateTime::new(
-                parse_date(row.get(1).ok_or(CountError::MissingDirection)?)?,
-                parse_time(row.get(2).ok_or(CountError::MissingDataColumn)?)?,
-            );
-
-            // There will always be at least one count per row.
-            // Extract the first (and perhaps only) direction.
-            match row.get(3) {
-                Some(count) => match count.parse() {
-                    Ok(count) => match FifteenMinuteVehicle::new(
-                        recordnum,
-                        datetime.date(),
-                        datetime,
-                        count,
-                        Some(directions.direction1),
-                        Some(1),
-                    ) {
-                        Ok(v) => counts.push(v),
-                        Err(e) => {
-                            error!("{e}");
-                            continue;
-                        }
-                    },
-                    Err(e) => return Err(CountError::ParseIntError(e)),
-                },
-                None => return Err(CountError::DirectionLenMisMatch),
-            }
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        Self::stream(path, recordnum, directions, policy)?.extraction()
+    }
 
-            // There may also be a second count within the row.
-            if let Some(direction) = directions.direction2 {
-                match row.get(4) {
-                    Some(count) => match count.parse() {
-                        Ok(count) => match FifteenMinuteVehicle::new(
-                            recordnum,
-                            datetime.date(),
-                            datetime,
-                            count,
-                            Some(direction),
-                            Some(2),
-                        ) {
-                            Ok(v) => counts.push(v),
-                            Err(e) => {
-                                error!("{e}");
-                                continue;
-                            }
-                        },
-                        Err(e) => return Err(CountError::ParseIntError(e)),
-                    },
-                    None => return Err(CountError::DirectionLenMisMatch),
-                }
-            }
-            // There may also be a third count within the row.
-            if let Some(direction) = directions.direction3 {
-                match row.get(5) {
-                    Some(count) => match count.parse() {
-                        Ok(count) => match FifteenMinuteVehicle::new(
-                            recordnum,
-                            datetime.date(),
-                            datetime,
-                            count,
-                            Some(direction),
-                            Some(3),
-                        ) {
-                            Ok(v) => counts.push(v),
-                            Err(e) => {
-                                error!("{e}");
-                                continue;
-                            }
-                        },
-                        Err(e) => return Err(CountError::ParseIntError(e)),
-                    },
-                    None => return Err(CountError::DirectionLenMisMatch),
-                }
+    /// Stream records, handling rows that can't be extracted according to `policy`.
+    pub fn stream(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<RecordStream<Self>, CountError> {
+        let (rows, _) = read_past_header(path)?;
+        let directions = directions.clone();
+        Ok(RecordStream::new(rows, policy, move |row| {
+            Self::from_row(row, recordnum, &directions)
+        }))
+    }
+
+    /// Extract the count of each direction from a row.
+    fn from_row(
+        row: &FileRow,
+        recordnum: u32,
+        directions: &Directions,
+    ) -> Result<Vec<Self>, CountError> {
+        let datetime = NaiveDateTime::new(
+            row.parse_with(1, parse_date)?,
+            row.parse_with(2, parse_time)?,
+        );
+
+        // There will always be at least one count per row, and one more for each additional lane,
+        // each in the column after the previous one.
+        let mut counts = vec![];
+        for (lane, direction) in directions.lanes() {
+            let column = lane as usize + 2;
+            if row.get(column).is_none() {
+                return Err(row.error(None, CountError::DirectionLenMisMatch));
             }
+            counts.push(FifteenMinuteVehicle::new(
+                recordnum,
+                datetime.date(),
+                datetime,
+                row.parse(column)?,
+                Some(direction),
+                Some(lane),
+            )?);
         }
         Ok(counts)
     }
 }
 
-/// The number for unknown class depends on which schema is used.
-pub enum Bicycles {
-    Without, // Modified Schema F
-    With,    // Modified Schema F - With Bikes
-}
-
+/// Extract IndividualVehicle records from a file, interpreting its class numbers by a
+/// [`ClassMapping`] - usually one of the built-in "Modified Scheme F" mappings, with or without
+/// bicycles.
 impl IndividualVehicle {
-    pub fn extract(path: &Path, bicycles: Bicycles) -> Result<Vec<Self>, CountError> {
-        let data_file = File::open(path)?;
-        let mut rdr = create_reader(&data_file);
+    pub fn extract(path: &Path, classes: &ClassMapping) -> Result<Vec<Self>, CountError> {
+        Ok(Self::extract_with_policy(path, classes, RowPolicy::Strict)?.records)
+    }
 
-        // Iterate through data rows.
-        let mut counts = vec![];
-        for row in rdr.records().skip(num_nondata_rows(path)?) {
-            let row = row?;
-            let datetime = NaiveDateTime::new(
-                parse_date(row.get(1).ok_or(CountError::MissingDataColumn)?)?,
-                parse_time(row.get(2).ok_or(CountError::MissingDataColumn)?)?,
-            );
-
-            // If bicycles are included, they are given class 14. They should not be included at
-            // all - not as bicycles nor as unclassified, because they are included in separate
-            // recordnum and count.
-            let count = match bicycles {
-                Bicycles::With => match row.get(4).ok_or(CountError::MissingDataColumn)?.parse() {
-                    Ok(14) => continue,
-                    Ok(v) => match IndividualVehicle::new(
-                        datetime.date(),
-                        datetime,
-                        row.get(3).ok_or(CountError::MissingDataColumn)?.parse()?,
-                        v,
-                        row.get(5).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    ) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            error!("{e}");
-                            continue;
-                        }
-                    },
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
-                    }
-                },
-                Bicycles::Without => match IndividualVehicle::new(
-                    datetime.date(),
-                    datetime,
-                    row.get(3).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    row.get(4).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    row.get(5).ok_or(CountError::MissingDataColumn)?.parse()?,
-                ) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
-                    }
-                },
-            };
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        classes: &ClassMapping,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        Self::stream(path, classes, policy)?.extraction()
+    }
 
-            counts.push(count);
-        }
-        Ok(counts)
+    /// Stream records, handling rows that can't be extracted according to `policy`.
+    pub fn stream(
+        path: &Path,
+        classes: &ClassMapping,
+        policy: RowPolicy,
+    ) -> Result<RecordStream<Self>, CountError> {
+        let (rows, _) = read_past_header(path)?;
+        let classes = classes.clone();
+        Ok(RecordStream::new(rows, policy, move |row| {
+            Ok(Self::from_row(row, &classes)?.into_iter().collect())
+        }))
+    }
+
+    /// Extract the vehicle in a row, if it is one.
+    fn from_row(row: &FileRow, classes: &ClassMapping) -> Result<Option<Self>, CountError> {
+        let datetime = NaiveDateTime::new(
+            row.parse_with(1, parse_date)?,
+            row.parse_with(2, parse_time)?,
+        );
+
+        // Bicycles should not be included at all - not as bicycles nor as unclassified, because
+        // they are included in separate recordnum and count.
+        let class = match classes.map(row.parse(4)?)? {
+            MappedClass::Vehicle(v) => v.clone(),
+            MappedClass::Bicycle | MappedClass::Discard => return Ok(None),
+        };
+
+        Ok(Some(IndividualVehicle {
+            date: datetime.date(),
+            time: datetime,
+            lane: row.parse(3)?,
+            class,
+            speed: row.parse(5)?,
+        }))
     }
 }
 
 /// Extract IndividualBicycle records from a file.
+///
+/// Unless streamed with another [`ClassMapping`], bicycles are those in class 14, as in
+/// [Modified Scheme F - With Bikes](ClassMapping::modified_scheme_f_with_bikes).
 impl IndividualBicycle {
     pub fn extract(path: &Path) -> Result<Vec<Self>, CountError> {
-        let data_file = File::open(path)?;
-        let mut rdr = create_reader(&data_file);
+        Ok(Self::extract_with_policy(path, RowPolicy::Strict)?.records)
+    }
 
-        // Iterate through data rows.
-        let mut counts = vec![];
-        for row in rdr.records().skip(num_nondata_rows(path)?) {
-            let row = row?;
-            // Bicycles are given class 14. Skip if not 14.
-            if row
-                .get(4)
-                .ok_or(CountError::MissingDataColumn)?
-                .parse::<u16>()?
-                != 14
-            {
-                continue;
-            }
-            let datetime = NaiveDateTime::new(
-                parse_date(row.get(1).ok_or(CountError::MissingDataColumn)?)?,
-                parse_time(row.get(2).ok_or(CountError::MissingDataColumn)?)?,
-            );
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        Self::stream(path, &ClassMapping::modified_scheme_f_with_bikes(), policy)?.extraction()
+    }
 
-            let count = match IndividualBicycle::new(
-                datetime.date(),
-                datetime,
-                row.get(3).ok_or(CountError::MissingDataColumn)?.parse()?,
-            ) {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("{e}");
-                    continue;
-                }
-            };
+    /// Stream records, handling rows that can't be extracted according to `policy`.
+    pub fn stream(
+        path: &Path,
+        classes: &ClassMapping,
+        policy: RowPolicy,
+    ) -> Result<RecordStream<Self>, CountError> {
+        let (rows, _) = read_past_header(path)?;
+        let classes = classes.clone();
+        Ok(RecordStream::new(rows, policy, move |row| {
+            Ok(Self::from_row(row, &classes)?.into_iter().collect())
+        }))
+    }
 
-            counts.push(count);
+    /// Extract the bicycle in a row, if it is one.
+    fn from_row(row: &FileRow, classes: &ClassMapping) -> Result<Option<Self>, CountError> {
+        // Skip anything that isn't a bicycle, including classes that aren't mapped.
+        let is_bicycle = u8::try_from(row.parse::<u16>(4)?).is_ok_and(|v| classes.is_bicycle(v));
+        if !is_bicycle {
+            return Ok(None);
         }
-        Ok(counts)
+        let datetime = NaiveDateTime::new(
+            row.parse_with(1, parse_date)?,
+            row.parse_with(2, parse_time)?,
+        );
+
+        Ok(Some(IndividualBicycle::new(
+            datetime.date(),
+            datetime,
+            row.parse(3)?,
+        )?))
     }
 }
 
@@ -289,73 +465,73 @@ impl FifteenMinuteBicycle {
         recordnum: u32,
         directions: &Directions,
     ) -> Result<Vec<Self>, CountError> {
-        let data_file = File::open(path)?;
-        let mut rdr = create_reader(&data_file);
+        Ok(Self::extract_with_policy(path, recordnum, directions, RowPolicy::Strict)?.records)
+    }
 
-        // Iterate through data rows.
-        let mut counts = vec![];
-        for row in rdr.records().skip(num_nondata_rows(path)?) {
-            let row = row?;
-            let datetime = parse_datetime(row.get(0).ok_or(CountError::MissingDataColumn)?)?;
-
-            // Ignore row if no value in total count column.
-            match row
-                .get(1)
-                .ok_or(CountError::MissingDataColumn)?
-                .parse::<u32>()
-            {
-                Ok(_) => (),
-                Err(_) => {
-                    continue;
-                }
-            };
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        Self::stream(path, recordnum, directions, policy)?.extraction()
+    }
 
-            // If this is a one-way, use the total rather than in direction. (Bicycles can go
-            // the wrong way, and we want to capture all of them in this case.)
-            if directions.one_way_bicycle {
-                match FifteenMinuteBicycle::new(
-                    recordnum,
-                    datetime,
-                    row.get(1).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    directions.direction1,
-                ) {
-                    Ok(v) => counts.push(v),
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
-                    }
-                }
-            // Not a one-way, use individual directions.
-            } else {
-                // Direction1/indir.
-                match FifteenMinuteBicycle::new(
-                    recordnum,
-                    datetime,
-                    row.get(2).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    directions.direction1,
-                ) {
-                    Ok(v) => counts.push(v),
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
-                    }
-                }
-                // Optionally direction2/outdir.
-                if let Some(dir2) = directions.direction2 {
-                    match FifteenMinuteBicycle::new(
-                        recordnum,
-                        datetime,
-                        row.get(3).ok_or(CountError::MissingDataColumn)?.parse()?,
-                        dir2,
-                    ) {
-                        Ok(v) => counts.push(v),
-                        Err(e) => {
-                            error!("{e}");
-                            continue;
-                        }
-                    }
-                }
-            }
+    /// Stream records, handling rows that can't be extracted according to `policy`.
+    pub fn stream(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<RecordStream<Self>, CountError> {
+        let (rows, _) = read_past_header(path)?;
+        let directions = directions.clone();
+        Ok(RecordStream::new(rows, policy, move |row| {
+            Self::from_row(row, recordnum, &directions)
+        }))
+    }
+
+    /// Extract the count of each direction from a row.
+    fn from_row(
+        row: &FileRow,
+        recordnum: u32,
+        directions: &Directions,
+    ) -> Result<Vec<Self>, CountError> {
+        let datetime = row.parse_with(0, parse_datetime)?;
+
+        // Ignore row if no value in total count column.
+        if row.value(1)?.parse::<u32>().is_err() {
+            return Ok(vec![]);
+        }
+
+        // If this is a one-way, use the total rather than in direction. (Bicycles can go
+        // the wrong way, and we want to capture all of them in this case.)
+        if directions.one_way_bicycle {
+            return Ok(vec![FifteenMinuteBicycle::new(
+                recordnum,
+                datetime,
+                row.parse(1)?,
+                directions.first(),
+            )?]);
+        }
+
+        // Not a one-way, use individual directions.
+        // Direction1/indir.
+        let mut counts = vec![FifteenMinuteBicycle::new(
+            recordnum,
+            datetime,
+            row.parse(2)?,
+            directions.first(),
+        )?];
+        // Optionally direction2/outdir.
+        if let Some(dir2) = directions.lane(2) {
+            counts.push(FifteenMinuteBicycle::new(
+                recordnum,
+                datetime,
+                row.parse(3)?,
+                dir2,
+            )?);
         }
         Ok(counts)
     }
@@ -368,83 +544,833 @@ impl FifteenMinutePedestrian {
         recordnum: u32,
         directions: &Directions,
     ) -> Result<Vec<Self>, CountError> {
-        let data_file = File::open(path)?;
-        let mut rdr = create_reader(&data_file);
+        Ok(Self::extract_with_policy(path, recordnum, directions, RowPolicy::Strict)?.records)
+    }
 
-        // Iterate through data rows.
-        let mut counts = vec![];
-        for row in rdr.records().skip(num_nondata_rows(path)?) {
-            let row = row?;
-            let datetime = parse_datetime(row.get(0).ok_or(CountError::MissingDataColumn)?)?;
-
-            // Ignore row if no value in total count column.
-            match row
-                .get(1)
-                .ok_or(CountError::MissingDataColumn)?
-                .parse::<u32>()
-            {
-                Ok(_) => (),
-                Err(_) => {
-                    continue;
-                }
-            };
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        Self::stream(path, recordnum, directions, policy)?.extraction()
+    }
+
+    /// Stream records, handling rows that can't be extracted according to `policy`.
+    pub fn stream(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<RecordStream<Self>, CountError> {
+        let (rows, _) = read_past_header(path)?;
+        let directions = directions.clone();
+        Ok(RecordStream::new(rows, policy, move |row| {
+            Self::from_row(row, recordnum, &directions)
+        }))
+    }
 
-            // If there is a direction2, get the two directions separately.
-            if let Some(dir2) = directions.direction2 {
+    /// Extract the count of each direction from a row.
+    fn from_row(
+        row: &FileRow,
+        recordnum: u32,
+        directions: &Directions,
+    ) -> Result<Vec<Self>, CountError> {
+        let datetime = row.parse_with(0, parse_datetime)?;
+
+        // Ignore row if no value in total count column.
+        if row.value(1)?.parse::<u32>().is_err() {
+            return Ok(vec![]);
+        }
+
+        // If there is a direction2, get the two directions separately.
+        if let Some(dir2) = directions.lane(2) {
+            Ok(vec![
                 // Direction1/indir.
-                match FifteenMinutePedestrian::new(
+                FifteenMinutePedestrian::new(
                     recordnum,
                     datetime,
-                    row.get(2).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    directions.direction1,
-                ) {
-                    Ok(v) => counts.push(v),
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
+                    row.parse(2)?,
+                    directions.first(),
+                )?,
+                // Direction2/outdir.
+                FifteenMinutePedestrian::new(recordnum, datetime, row.parse(3)?, dir2)?,
+            ])
+        // Otherwise, this was a unidirectional count. However, there's a chance that
+        // pedestrians can go the wrong way, so use the total (both directions).
+        } else {
+            Ok(vec![FifteenMinutePedestrian::new(
+                recordnum,
+                datetime,
+                row.parse(1)?,
+                directions.first(),
+            )?])
+        }
+    }
+}
+
+/// Extract TimeBinnedVehicleClassCount records from a manual class tally sheet.
+///
+/// The header of the sheet is the first row with a column for each of FHWA classes 1 through 13
+/// (e.g. "Class 1", "C1", or "FHWA 1") and a column for the start of the interval ("Time",
+/// "Start Time", "Interval Start", or "Date/Time"). It may also have columns for the date
+/// ("Date"), unclassified vehicles ("Class 15" or "Unclassified"), the direction of travel
+/// ("Direction"), and the total ("Total"). Other columns are ignored.
+///
+/// Without a direction column, all vehicles are in the count's first direction (lane 1), so the
+/// count can only have one direction. With one, the direction of each row determines its lane.
+/// If there is a total column, it is verified against the sum of the classes in its row. Rows
+/// with an empty interval start or with "Total" in that column are ignored, as are empty cells.
+///
+/// Intervals shorter than 15 minutes are summed into 15-minute ones; as everywhere else,
+/// unclassified vehicles are also included in class 2.
+impl TimeBinnedVehicleClassCount {
+    pub fn extract(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+    ) -> Result<Vec<Self>, CountError> {
+        Ok(Self::extract_with_policy(path, recordnum, directions, RowPolicy::Strict)?.records)
+    }
+
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        recordnum: u32,
+        directions: &Directions,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        let (rows, header) = read_past_header(path)?;
+        let header = TallyHeader::parse(&header).ok_or(CountError::BadHeader(path.to_owned()))?;
+        if header.direction.is_none() && directions.num_lanes() > 1 {
+            return Err(CountError::DirectionLenMisMatch);
+        }
+
+        // Iterate through data rows, binning as we go.
+        let directions = directions.clone();
+        let mut tallies = RecordStream::new(rows, policy, move |row| {
+            Ok(header.read(row, &directions)?.into_iter().collect())
+        });
+        let mut class_counts: HashMap<BinnedCountKey, VehicleClassCount> = HashMap::new();
+        for tally in &mut tallies {
+            let count = class_counts
+                .entry(tally.key)
+                .or_insert(VehicleClassCount::new(recordnum, tally.direction));
+            for (class, volume) in tally.volumes {
+                count.add(class, volume);
+            }
+        }
+
+        let mut counts = class_counts
+            .into_iter()
+            .map(|(key, value)| TimeBinnedVehicleClassCount::from_intermediate(key, value))
+            .collect::<Vec<_>>();
+        counts.sort_unstable_by_key(|c| (c.time, c.lane));
+        let rows = tallies.rows();
+        Ok(Extraction {
+            records: counts,
+            rejected: tallies.finish()?,
+            rows,
+        })
+    }
+}
+
+/// The indexes of the columns of a manual class tally sheet.
+#[derive(Debug, Clone)]
+struct TallyHeader {
+    date: Option<usize>,
+    time: usize,
+    direction: Option<usize>,
+    classes: Vec<(usize, VehicleClass)>,
+    total: Option<usize>,
+}
+
+impl TallyHeader {
+    /// Parse a row (stripped of double quotes and spaces) as the header, if it is one.
+    fn parse(row: &str) -> Option<Self> {
+        let mut date = None;
+        let mut time = None;
+        let mut direction = None;
+        let mut classes = vec![];
+        let mut total = None;
+
+        for (index, cell) in row.split(',').enumerate() {
+            let cell = cell.to_lowercase();
+            match cell.as_str() {
+                "date" => date = Some(index),
+                "time" | "starttime" | "intervalstart" | "date/time" | "datetime" => {
+                    time = Some(index)
+                }
+                "direction" | "dir" => direction = Some(index),
+                "total" => total = Some(index),
+                _ => {
+                    if let Some(class) = parse_tally_class(&cell) {
+                        classes.push((index, class))
                     }
                 }
-                // Direction2/outdir.
-                match FifteenMinutePedestrian::new(
-                    recordnum,
-                    datetime,
-                    row.get(3).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    dir2,
-                ) {
-                    Ok(v) => counts.push(v),
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
+            }
+        }
+
+        if !TALLY_CLASSES
+            .into_iter()
+            .all(|num| classes.iter().any(|(_, class)| class.clone() as u8 == num))
+        {
+            return None;
+        }
+        Some(Self {
+            date,
+            time: time?,
+            direction,
+            classes,
+            total,
+        })
+    }
+
+    /// Read the volumes of each class from a row of data, if it has any.
+    fn read(&self, row: &FileRow, directions: &Directions) -> Result<Option<TallyRow>, CountError> {
+        let time = row.value(self.time)?;
+        if time.is_empty() || time.eq_ignore_ascii_case("total") {
+            return Ok(None);
+        }
+        let datetime = match self.date {
+            Some(i) => NaiveDateTime::new(
+                row.parse_with(i, parse_date)?,
+                row.parse_with(self.time, parse_time)?,
+            ),
+            None => row.parse_with(self.time, parse_datetime)?,
+        };
+
+        let (lane, direction) = match self.direction {
+            Some(i) => {
+                let direction = row.parse_with(i, |v| {
+                    LaneDirection::from_str(v).or_else(|_| parse_approach(v))
+                })?;
+                let lane = directions
+                    .lane_of(direction)
+                    .ok_or_else(|| row.error(Some(i), CountError::DirectionLenMisMatch))?;
+                (lane, direction)
+            }
+            None => (1, directions.first()),
+        };
+
+        let mut volumes = vec![];
+        for (index, class) in &self.classes {
+            let volume = match row.get(*index) {
+                Some("") | None => 0,
+                Some(_) => row.parse(*index)?,
+            };
+            volumes.push((class.clone(), volume));
+        }
+
+        if let Some(i) = self
+            .total
+            .filter(|i| row.get(*i).is_some_and(|v| !v.is_empty()))
+        {
+            if row.parse::<u32>(i)? != volumes.iter().map(|(_, v)| v).sum::<u32>() {
+                return Err(row.error(Some(i), CountError::TallyTotalMisMatch(datetime)));
+            }
+        }
+
+        Ok(Some(TallyRow {
+            key: BinnedCountKey {
+                date: datetime.date(),
+                time: NaiveDateTime::new(
+                    datetime.date(),
+                    bin_time(datetime.time(), TimeInterval::FifteenMin),
+                ),
+                lane,
+            },
+            direction,
+            volumes,
+        }))
+    }
+}
+
+/// The volumes of each class in a row of a manual class tally sheet, and the 15-minute interval
+/// and lane they belong to.
+struct TallyRow {
+    key: BinnedCountKey,
+    direction: LaneDirection,
+    volumes: Vec<(VehicleClass, u32)>,
+}
+
+/// Parse a (lowercased) column header of a manual class tally sheet as a vehicle class.
+fn parse_tally_class(s: &str) -> Option<VehicleClass> {
+    if s == "unclassified" {
+        return Some(VehicleClass::UnclassifiedVehicle);
+    }
+    let num = ["class", "fhwa", "c"]
+        .iter()
+        .find_map(|prefix| s.strip_prefix(prefix))?
+        .parse::<u8>()
+        .ok()?;
+    if TALLY_CLASSES.contains(&num) || num == 15 {
+        VehicleClass::from_num(num).ok()
+    } else {
+        None
+    }
+}
+
+/// Extract TurningMovementCount records from a file.
+///
+/// These are pre-binned, 15-minute counts, in the layout common to JAMAR and Miovision exports:
+/// the first column is the start of the interval, and the remaining columns each contain the
+/// volume of one movement from one approach. The header identifying those columns is either a
+/// single row of approach and movement (e.g. "Northbound Left" or "NB Thru"), or a row of
+/// movements ("Left", "Thru", "Right", "U-Turn") under a row of approaches ("Northbound",
+/// "Southbound", etc.), each of which applies to the columns up to the next approach. Above
+/// the approaches, there may be a row of vehicle classes (e.g. "Lights", "Heavy"), which apply
+/// the same way; otherwise the class is "All". Other columns, like approach totals, are ignored.
+///
+/// The start of the interval can be either a datetime or a time; if a time, the date is the
+/// last one found in a row by itself or in the rows above the header. Rows after the header
+/// without a datetime or time, like totals, are ignored.
+impl TurningMovementCount {
+    pub fn extract(path: &Path, recordnum: u32) -> Result<Vec<Self>, CountError> {
+        Ok(Self::extract_with_policy(path, recordnum, RowPolicy::Strict)?.records)
+    }
+
+    /// Extract records, handling rows that can't be extracted according to `policy`.
+    pub fn extract_with_policy(
+        path: &Path,
+        recordnum: u32,
+        policy: RowPolicy,
+    ) -> Result<Extraction<Self>, CountError> {
+        let mut rows = SourceRows::open(path)?;
+        let header =
+            TmcHeader::find(&mut rows.records())?.ok_or(CountError::BadHeader(path.to_owned()))?;
+        let mut date = header.date;
+
+        RecordStream::new(rows, policy, move |row| {
+            header.read(row, recordnum, &mut date)
+        })
+        .extraction()
+    }
+}
+
+/// A column of volumes in a TMC file.
+#[derive(Debug, Clone, PartialEq)]
+struct TmcColumn {
+    index: usize,
+    approach: LaneDirection,
+    movement: Movement,
+    vehicle_class: String,
+}
+
+/// The header of a TMC file.
+#[derive(Debug)]
+struct TmcHeader {
+    columns: Vec<TmcColumn>,
+    /// The last date found above the header.
+    date: Option<NaiveDate>,
+}
+
+impl TmcHeader {
+    /// Find the header in (at most the first 50) rows, consuming them.
+    fn find<E>(
+        rows: &mut impl Iterator<Item = Result<StringRecord, E>>,
+    ) -> Result<Option<Self>, CountError>
+    where
+        CountError: From<E>,
+    {
+        let mut previous: Vec<StringRecord> = vec![];
+        let mut date = None;
+
+        for row in rows.take(50) {
+            let row = row?;
+            let movements = row
+                .iter()
+                .skip(1)
+                .filter(|cell| parse_approach_movement(cell).is_some())
+                .count();
+
+            if movements < MIN_TMC_MOVEMENTS {
+                if let Some(v) = row.iter().find_map(|cell| parse_date(cell).ok()) {
+                    date = Some(v);
+                }
+                previous.push(row);
+                continue;
+            }
+
+            // The row of approaches immediately precedes the movements, and classes precede that.
+            let approaches = previous
+                .last()
+                .filter(|r| r.iter().skip(1).any(|cell| parse_approach(cell).is_ok()));
+            let classes = approaches.and(previous.iter().rev().nth(1)).filter(|r| {
+                r.iter()
+                    .skip(1)
+                    .any(|cell| !cell.is_empty() && parse_approach(cell).is_err())
+            });
+
+            let mut columns = vec![];
+            let mut approach = None;
+            let mut vehicle_class = "All".to_string();
+            for (index, cell) in row.iter().enumerate().skip(1) {
+                if let Some(v) = approaches.and_then(|r| r.get(index)) {
+                    if let Ok(v) = parse_approach(v) {
+                        approach = Some(v);
                     }
                 }
-            // Otherwise, this was a unidirectional count. However, there's a chance that
-            // pedestrians can go the wrong way, so use the total (both directions).
-            } else {
-                match FifteenMinutePedestrian::new(
-                    recordnum,
-                    datetime,
-                    row.get(1).ok_or(CountError::MissingDataColumn)?.parse()?,
-                    directions.direction1,
-                ) {
-                    Ok(v) => counts.push(v),
-                    Err(e) => {
-                        error!("{e}");
-                        continue;
+                if let Some(v) = classes.and_then(|r| r.get(index)) {
+                    if !v.is_empty() {
+                        vehicle_class = v.to_string();
                     }
                 }
+                if let Some((cell_approach, movement)) = parse_approach_movement(cell) {
+                    // An approach in the cell itself takes precedence over the row of approaches.
+                    let approach = match cell_approach.or(approach) {
+                        Some(v) => v,
+                        None => return Ok(None),
+                    };
+                    columns.push(TmcColumn {
+                        index,
+                        approach,
+                        movement,
+                        vehicle_class: vehicle_class.clone(),
+                    })
+                }
+            }
+            return Ok(Some(TmcHeader { columns, date }));
+        }
+        Ok(None)
+    }
+
+    /// Read the volume of each movement from a row of data.
+    ///
+    /// `date` is the date of the intervals that have only a time, updated by rows that have
+    /// only a date.
+    fn read(
+        &self,
+        row: &FileRow,
+        recordnum: u32,
+        date: &mut Option<NaiveDate>,
+    ) -> Result<Vec<TurningMovementCount>, CountError> {
+        let first = row.value(0)?;
+        let datetime = if let Ok(v) = parse_datetime(first) {
+            v
+        } else if let Ok(v) = parse_time(first) {
+            NaiveDateTime::new(
+                date.ok_or_else(|| row.error(Some(0), CountError::MissingDataColumn))?,
+                v,
+            )
+        } else {
+            if let Ok(v) = parse_date(first) {
+                *date = Some(v);
             }
+            return Ok(vec![]);
+        };
+
+        let mut counts = vec![];
+        for column in &self.columns {
+            if row.value(column.index)?.is_empty() {
+                continue;
+            }
+            counts.push(TurningMovementCount::new(
+                recordnum,
+                datetime,
+                column.approach,
+                column.movement,
+                column.vehicle_class.clone(),
+                row.parse(column.index)?,
+            ));
         }
         Ok(counts)
     }
 }
 
+/// Parse a column header of a TMC file as a movement, optionally preceded by an approach.
+fn parse_approach_movement(s: &str) -> Option<(Option<LaneDirection>, Movement)> {
+    if let Ok(v) = s.parse() {
+        return Some((None, v));
+    }
+    let (approach, movement) = s.trim().split_once(' ')?;
+    Some((Some(parse_approach(approach).ok()?), movement.parse().ok()?))
+}
+
+/// Determine if a file is a TMC, by finding its header.
+pub fn is_turning_movement_count(path: &Path) -> Result<bool, CountError> {
+    let data_file = File::open(path)?;
+    let mut rdr = create_reader(&data_file);
+    Ok(TmcHeader::find(&mut rdr.records())?.is_some_and(|header| !header.columns.is_empty()))
+}
+
+/// How to handle rows of data that can't be extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RowPolicy {
+    /// Abort extraction at the first bad row.
+    #[default]
+    Strict,
+    /// Skip bad rows, collecting them in [`Extraction::rejected`]. Extraction fails
+    /// ([`TooManyRejectedRows`](CountError::TooManyRejectedRows)) if more than `max_rejected_pct`
+    /// percent of data rows are rejected.
+    Lenient { max_rejected_pct: f32 },
+}
+
+/// A row of data that couldn't be extracted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedRow {
+    pub location: RowLocation,
+    /// Why the row was rejected.
+    pub reason: String,
+    /// The raw text of the row.
+    pub row: String,
+}
+
+/// The records extracted from a file, and any rows rejected while doing so.
+#[derive(Debug, Clone)]
+pub struct Extraction<T> {
+    pub records: Vec<T>,
+    pub rejected: Vec<RejectedRow>,
+    /// The number of data rows in the file.
+    pub rows: usize,
+}
+
+/// Applies a [`RowPolicy`] to the rows of a file as they are extracted.
+struct RowChecker {
+    policy: RowPolicy,
+    rows: usize,
+    rejected: Vec<RejectedRow>,
+}
+
+impl RowChecker {
+    fn new(policy: RowPolicy) -> Self {
+        Self {
+            policy,
+            rows: 0,
+            rejected: vec![],
+        }
+    }
+
+    /// Check the result of extracting a row, returning what was extracted, or `None` if the row
+    /// was rejected.
+    fn check<T>(
+        &mut self,
+        row: &FileRow,
+        result: Result<T, CountError>,
+    ) -> Result<Option<T>, CountError> {
+        self.rows += 1;
+        let error = match result {
+            Ok(v) => return Ok(Some(v)),
+            Err(e) if e.location().is_some() => e,
+            Err(e) => row.error(None, e),
+        };
+        match self.policy {
+            RowPolicy::Strict => Err(error),
+            RowPolicy::Lenient { .. } => {
+                self.rejected.push(RejectedRow {
+                    location: error.location().cloned().unwrap_or(row.location(None)),
+                    reason: error.without_location().to_string(),
+                    row: row.text.clone(),
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    /// Get the rows that were rejected from a file, failing if there were too many.
+    fn finish(self, path: &Path) -> Result<Vec<RejectedRow>, CountError> {
+        if let RowPolicy::Lenient { max_rejected_pct } = self.policy {
+            if self.rejected.len() as f32 > self.rows as f32 * max_rejected_pct / 100.0 {
+                return Err(CountError::TooManyRejectedRows {
+                    path: path.to_owned(),
+                    rejected: self.rejected,
+                    rows: self.rows,
+                    max_rejected_pct,
+                });
+            }
+        }
+        Ok(self.rejected)
+    }
+}
+
+// Extracts the records of a row.
+type FromRow<T> = dyn FnMut(&FileRow) -> Result<Vec<T>, CountError>;
+
+/// The records of a file, extracted one row at a time, so that only one row is held in memory.
+///
+/// Iterating yields records until the end of the file, or until a row that can't be extracted
+/// under the stream's [`RowPolicy`]. Afterwards, call [`finish`](RecordStream::finish) to get
+/// that error, if any, and the rows that were rejected.
+pub struct RecordStream<T> {
+    rows: SourceRows,
+    from_row: Box<FromRow<T>>,
+    checker: RowChecker,
+    // Records extracted from the current row that have yet to be yielded.
+    pending: std::vec::IntoIter<T>,
+    error: Option<CountError>,
+}
+
+impl<T> RecordStream<T> {
+    /// Create a stream of the records extracted from `rows`, each by `from_row`.
+    fn new(
+        rows: SourceRows,
+        policy: RowPolicy,
+        from_row: impl FnMut(&FileRow) -> Result<Vec<T>, CountError> + 'static,
+    ) -> Self {
+        Self {
+            rows,
+            from_row: Box::new(from_row),
+            checker: RowChecker::new(policy),
+            pending: vec![].into_iter(),
+            error: None,
+        }
+    }
+
+    /// The number of data rows read so far.
+    pub fn rows(&self) -> usize {
+        self.checker.rows
+    }
+
+    /// Get the error that stopped the stream, if any, or otherwise the rows that were rejected.
+    ///
+    /// Like [`RowPolicy::Lenient`] extraction in general, this errs if too many rows were
+    /// rejected.
+    pub fn finish(self) -> Result<Vec<RejectedRow>, CountError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => self.checker.finish(&self.rows.path),
+        }
+    }
+
+    /// Collect all the records into an [`Extraction`].
+    pub fn extraction(mut self) -> Result<Extraction<T>, CountError> {
+        let records = self.by_ref().collect();
+        let rows = self.rows();
+        Ok(Extraction {
+            records,
+            rejected: self.finish()?,
+            rows,
+        })
+    }
+}
+
+impl<T> Iterator for RecordStream<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(v) = self.pending.next() {
+                return Some(v);
+            }
+            if self.error.is_some() {
+                return None;
+            }
+            let row = match self.rows.next()? {
+                Ok(v) => v,
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            };
+            match self.checker.check(&row, (self.from_row)(&row)) {
+                Ok(Some(v)) => self.pending = v.into_iter(),
+                Ok(None) => (),
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Write rejected rows to a CSV file, with the line, column index, reason, and text of each.
+pub fn write_rejected_rows(path: &Path, rejected: &[RejectedRow]) -> Result<(), CountError> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    wtr.write_record(["line", "column", "reason", "row"])?;
+    for row in rejected {
+        wtr.write_record([
+            row.location.line.to_string(),
+            row.location
+                .column
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            row.reason.clone(),
+            row.row.clone(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// The rows of a file, each along with the line it's on and its raw text, so that errors with
+/// them can be located.
+struct SourceRows {
+    path: Rc<Path>,
+    reader: Reader<LineReader>,
+}
+
+impl SourceRows {
+    fn open(path: &Path) -> Result<Self, CountError> {
+        Ok(Self {
+            path: path.into(),
+            reader: create_reader(LineReader::new(File::open(path)?)),
+        })
+    }
+
+    /// The records of the rows, without their location (e.g. to find the header).
+    fn records(&mut self) -> impl Iterator<Item = Result<StringRecord, CountError>> + '_ {
+        self.map(|row| row.map(|row| row.record))
+    }
+}
+
+impl Iterator for SourceRows {
+    type Item = Result<FileRow, CountError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = StringRecord::new();
+        match self.reader.read_record(&mut record) {
+            Ok(true) => (),
+            Ok(false) => return None,
+            Err(e) => return Some(Err(e.into())),
+        }
+        // The reader is given one line at a time, so the last line it read is the row's.
+        let lines = self.reader.get_ref();
+        Some(Ok(FileRow {
+            path: self.path.clone(),
+            line: lines.number,
+            text: lines.text(),
+            record,
+        }))
+    }
+}
+
+/// Reads a file one line at a time, keeping track of the last line read.
+///
+/// The CSV reader's own line numbers are incorrect for files with CRLF line endings, and it
+/// doesn't keep the raw text of records.
+struct LineReader {
+    inner: BufReader<File>,
+    line: Vec<u8>,
+    // How much of the line has been read.
+    pos: usize,
+    /// The physical line number (starting at 1) of the line.
+    number: u64,
+}
+
+impl LineReader {
+    fn new(file: File) -> Self {
+        Self {
+            inner: BufReader::new(file),
+            line: vec![],
+            pos: 0,
+            number: 0,
+        }
+    }
+
+    /// The text of the line, without its line break.
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.line)
+            .trim_end_matches(['\r', '\n'])
+            .to_string()
+    }
+}
+
+impl Read for LineReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+            self.number += 1;
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A row of data in a file, which can attach its location to errors with the data.
+///
+/// Dereferences to the [`StringRecord`] of the row.
+struct FileRow {
+    path: Rc<Path>,
+    /// The physical line (starting at 1) of the row in the file.
+    line: u64,
+    /// The raw text of the row.
+    text: String,
+    record: StringRecord,
+}
+
+impl FileRow {
+    /// Get the value of a column.
+    fn value(&self, index: usize) -> Result<&str, CountError> {
+        self.record
+            .get(index)
+            .ok_or_else(|| self.error(Some(index), CountError::MissingDataColumn))
+    }
+
+    /// Parse the value of a column.
+    fn parse<T>(&self, index: usize) -> Result<T, CountError>
+    where
+        T: FromStr,
+        CountError: From<T::Err>,
+    {
+        self.parse_with(index, |v| Ok(v.parse::<T>()?))
+    }
+
+    /// Parse the value of a column with a function.
+    fn parse_with<T>(
+        &self,
+        index: usize,
+        f: impl Fn(&str) -> Result<T, CountError>,
+    ) -> Result<T, CountError> {
+        f(self.value(index)?).map_err(|e| self.error(Some(index), e))
+    }
+
+    /// Attach the location of the row - and the column, if the error is with one - to an error.
+    fn error(&self, column: Option<usize>, error: CountError) -> CountError {
+        CountError::InRow {
+            location: self.location(column),
+            source: Box::new(error),
+        }
+    }
+
+    /// The location of the row, and optionally a column in it.
+    fn location(&self, column: Option<usize>) -> RowLocation {
+        RowLocation {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            column,
+            text: match column.and_then(|i| self.record.get(i)) {
+                Some(v) => v.to_string(),
+                None => self.text.clone(),
+            },
+        }
+    }
+}
+
+impl std::ops::Deref for FileRow {
+    type Target = StringRecord;
+
+    fn deref(&self) -> &Self::Target {
+        &self.record
+    }
+}
+
+/// Get the name of the directory immediately above a file.
+pub fn parent_dir(path: &Path) -> Result<&str, CountError> {
+    path.parent()
+        .ok_or(CountError::BadPath(path.to_owned()))?
+        .components()
+        .next_back()
+        .ok_or(CountError::BadPath(path.to_owned()))?
+        .as_os_str()
+        .to_str()
+        .ok_or(CountError::BadPath(path.to_owned()))
+}
+
 /// Create CSV reader from file.
-pub fn create_reader(file: &File) -> Reader<&File> {
+pub fn create_reader<R: Read>(rdr: R) -> Reader<R> {
     ReaderBuilder::new()
         .has_headers(false)
         .trim(csv::Trim::All)
         .flexible(true)
-        .from_reader(file)
+        .from_reader(rdr)
 }
 
 /// Get the number of nondata rows in a file based on header.
@@ -455,24 +1381,51 @@ pub fn create_reader(file: &File) -> Reader<&File> {
 /// is an egregiously large number to ensure that we will never miss the header and prevents the
 /// search going through tens of thousands of lines, which is the typical number in files.
 pub fn num_nondata_rows(path: &Path) -> Result<usize, CountError> {
-    let mut num_rows = 0;
-    let contents = fs::read_to_string(path)?;
-    for line in contents.lines().take(50) {
-        // Ignore empty lines, as they will get ignored during creation of CSV reader.
-        if line.trim().is_empty() {
-            continue;
-        }
-        num_rows += 1;
-        let line = line.replace(['"', ' '], "");
-        if line.starts_with(FIFTEEN_MINUTE_BIKE_OR_PED_HEADER)
+    Ok(find_header(path)?.0)
+}
+
+/// Find the header of a file, returning the number of nondata rows and the header (stripped of
+/// double quotes and spaces).
+fn find_header(path: &Path) -> Result<(usize, String), CountError> {
+    let mut rows = create_reader(File::open(path)?).into_records();
+    read_header(&mut rows)?.ok_or(CountError::BadHeader(path.to_owned()))
+}
+
+/// Open a file and read its rows up to and including the header, returning the remaining rows
+/// and the header (stripped of double quotes and spaces).
+fn read_past_header(path: &Path) -> Result<(SourceRows, String), CountError> {
+    let mut rows = SourceRows::open(path)?;
+    let (_, header) =
+        read_header(&mut rows.records())?.ok_or(CountError::BadHeader(path.to_owned()))?;
+    Ok((rows, header))
+}
+
+/// Read (at most the first 50) rows until the header, consuming them, and return the number of
+/// nondata rows and the header (stripped of double quotes and spaces).
+///
+/// Empty lines are ignored by the CSV reader, and so aren't counted.
+fn read_header<E>(
+    rows: &mut impl Iterator<Item = Result<StringRecord, E>>,
+) -> Result<Option<(usize, String)>, CountError>
+where
+    CountError: From<E>,
+{
+    for (i, row) in rows.take(50).enumerate() {
+        let line = row?
+            .iter()
+            .collect::<Vec<_>>()
+            .join(",")
+            .replace(['"', ' '], "");
+        if TallyHeader::parse(&line).is_some()
+            || line.starts_with(FIFTEEN_MINUTE_BIKE_OR_PED_HEADER)
             || line.contains(FIFTEEN_MINUTE_VEHICLE_HEADER1)
             || line.contains(FIFTEEN_MINUTE_VEHICLE_HEADER2)
             || line.contains(IND_VEH_OR_IND_BIKE)
         {
-            return Ok(num_rows);
+            return Ok(Some((i + 1, line)));
         }
     }
-    Err(CountError::BadHeader(path.to_owned()))
+    Ok(None)
 }
 
 /// Parse time from a str that can be in multiple formats.
@@ -485,6 +1438,7 @@ fn parse_time(s: &str) -> Result<NaiveTime, CountError> {
         "%-I:%M%P",
         "%-I:%M:%S%P",
         "%H:%M:%S",
+        "%H:%M",
     ] {
         match NaiveTime::parse_from_str(s, fmt) {
             Ok(v) => return Ok(v),
@@ -519,6 +1473,7 @@ fn parse_datetime(s: &str) -> Result<NaiveDateTime, CountError> {
         "%Y-%m-%d %H:%M:%S",
         "%Y-%m-%d %H:%M",
         "%-m/%-d/%Y %-H:%M",
+        "%-m/%-d/%Y %-I:%M %p",
     ] {
         match NaiveDateTime::parse_from_str(s, fmt) {
             Ok(v) => return Ok(v),
@@ -531,20 +1486,27 @@ fn parse_datetime(s: &str) -> Result<NaiveDateTime, CountError> {
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
     use crate::non_perm::LaneDirection;
 
+    /// Detect a file's variant with the default mapping of classes, including bicycles.
+    fn detect(path: &Path) -> Detection {
+        InputCount::detect(path, &ClassMapping::modified_scheme_f_with_bikes()).unwrap()
+    }
+
     #[test]
     fn extract_ind_vehicle_gets_correct_number_of_counts() {
         let path = Path::new("test_files/jamar_vehicle/166905.txt");
-        let counted_vehicles = IndividualVehicle::extract(path, Bicycles::Without).unwrap();
+        let counted_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
         assert_eq!(counted_vehicles.len(), 8706);
     }
 
     #[test]
     fn extract_ind_vehicle_gets_correct_number_of_counts_by_lane() {
         let path = Path::new("test_files/jamar_vehicle/101.csv");
-        let counted_vehicles = IndividualVehicle::extract(path, Bicycles::Without).unwrap();
+        let counted_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
         assert_eq!(counted_vehicles.len(), 227);
 
         let lane1 = counted_vehicles
@@ -565,17 +1527,43 @@ mod tests {
         assert_eq!(lane3.len(), 27);
     }
 
+    #[test]
+    fn extract_ind_vehicle_and_bicycle_with_class_mapping() {
+        let path = Path::new("test_files/jamar_vehicle/101.csv");
+        let mapping = |toml: &str| ClassMapping::from_toml(toml).unwrap().remove(0);
+        let classes = "1 = 1\n2 = 2\n3 = 3\n4 = 4\n5 = \"discard\"\n6 = 6\n14 = \"bicycle\"";
+
+        // Class 8 isn't mapped.
+        assert!(matches!(
+            IndividualVehicle::extract(path, &mapping(&format!("[test]\n{classes}"))),
+            Err(CountError::InRow { .. })
+        ));
+
+        let mapping = mapping(&format!("[test]\n{classes}\n8 = \"unclassified\""));
+        // 227 vehicles, less 5 bicycles and 8 discarded.
+        let vehicles = IndividualVehicle::extract(path, &mapping).unwrap();
+        assert_eq!(vehicles.len(), 214);
+        assert_eq!(
+            vehicles
+                .iter()
+                .filter(|v| matches!(v.class, VehicleClass::UnclassifiedVehicle))
+                .count(),
+            1
+        );
+        let bicycles = IndividualBicycle::stream(path, &mapping, RowPolicy::Strict)
+            .unwrap()
+            .extraction()
+            .unwrap();
+        assert_eq!(bicycles.records.len(), 5);
+    }
+
     /// Note that 168193's contents doesn't match with db: this was one that had previously
     /// been separated into two different counts due to limitations of previous import program.
     #[test]
     fn extract_fifteen_min_vehicle_gets_correct_number_of_counts_168193() {
         let path = Path::new("test_files/jamar_15minutevehicle/168193.txt");
-        let directions = Directions {
-            direction1: LaneDirection::East,
-            direction2: Some(LaneDirection::West),
-            direction3: None,
-            one_way_bicycle: false,
-        };
+        let directions =
+            Directions::new(vec![LaneDirection::East, LaneDirection::West], false).unwrap();
         let fifteen_min_volcount =
             FifteenMinuteVehicle::extract(path, 168193, &directions).unwrap();
         assert_eq!(fifteen_min_volcount.len(), 384)
@@ -584,12 +1572,15 @@ mod tests {
     #[test]
     fn extract_fifteen_min_vehicle_gets_correct_number_of_counts_102() {
         let path = Path::new("test_files/jamar_15minutevehicle/102.csv");
-        let directions = Directions {
-            direction1: LaneDirection::West,
-            direction2: Some(LaneDirection::West),
-            direction3: Some(LaneDirection::West),
-            one_way_bicycle: false,
-        };
+        let directions = Directions::new(
+            vec![
+                LaneDirection::West,
+                LaneDirection::West,
+                LaneDirection::West,
+            ],
+            false,
+        )
+        .unwrap();
         let mut fifteen_min_volcount =
             FifteenMinuteVehicle::extract(path, 102, &directions).unwrap();
         fifteen_min_volcount.sort_unstable_by_key(|count| (count.date, count.time, count.lane));
@@ -609,18 +1600,45 @@ mod tests {
         assert_eq!(count2.count, 10);
     }
 
+    #[test]
+    fn extract_fifteen_min_vehicle_more_than_three_lanes_104() {
+        let path = Path::new("test_files/jamar_15minutevehicle/104.csv");
+        let detection = detect(path);
+        assert_eq!(detection.directions, Some(6));
+
+        use LaneDirection::*;
+        let directions = Directions::new(vec![East, East, East, West, West, West], false).unwrap();
+        let mut fifteen_min_volcount =
+            FifteenMinuteVehicle::extract(path, 104, &directions).unwrap();
+        fifteen_min_volcount.sort_unstable_by_key(|count| (count.date, count.time, count.lane));
+        assert_eq!(fifteen_min_volcount.len(), 48);
+
+        let lane5 = &fifteen_min_volcount[4];
+        assert_eq!(lane5.lane, Some(5));
+        assert_eq!(lane5.direction, Some(West));
+        assert_eq!(lane5.count, 31);
+
+        // No channel for a seventh lane.
+        let directions = Directions::new(vec![East; 7], false).unwrap();
+        assert!(FifteenMinuteVehicle::extract(path, 104, &directions).is_err());
+    }
+
     #[test]
     fn extract_fifteen_min_vehicle_errs_when_dirs_mismatch_in_filename_and_data_103() {
         let path = Path::new("test_files/jamar_15minutevehicle/103.csv");
-        let directions = Directions {
-            direction1: LaneDirection::South,
-            direction2: Some(LaneDirection::South),
-            direction3: Some(LaneDirection::South),
-            one_way_bicycle: false,
-        };
+        let directions = Directions::new(
+            vec![
+                LaneDirection::South,
+                LaneDirection::South,
+                LaneDirection::South,
+            ],
+            false,
+        )
+        .unwrap();
 
         assert!(matches!(
-            FifteenMinuteVehicle::extract(path, 103, &directions),
+            FifteenMinuteVehicle::extract(path, 103, &directions)
+                .map_err(CountError::without_location),
             Err(CountError::DirectionLenMisMatch)
         ))
     }
@@ -628,12 +1646,8 @@ mod tests {
     #[test]
     fn extract_fifteen_min_bicycle_gets_correct_number_of_counts_167607() {
         let path = Path::new("test_files/ecocounter_15minutebicycle/167607.csv");
-        let directions = Directions {
-            direction1: LaneDirection::North,
-            direction2: Some(LaneDirection::South),
-            direction3: None,
-            one_way_bicycle: false,
-        };
+        let directions =
+            Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap();
         let fifteen_min_volcount =
             FifteenMinuteBicycle::extract(path, 167607, &directions).unwrap();
         assert_eq!(fifteen_min_volcount.len(), 960);
@@ -655,12 +1669,7 @@ mod tests {
     #[test]
     fn extract_fifteen_min_bicycle_gets_correct_number_of_counts_in_one_way_bicycle_179847() {
         let path = Path::new("test_files/ecocounter_15minutebicycle/179847.csv");
-        let directions = Directions {
-            direction1: LaneDirection::North,
-            direction2: None,
-            direction3: None,
-            one_way_bicycle: true,
-        };
+        let directions = Directions::new(vec![LaneDirection::North], true).unwrap();
 
         let num_skipped_rows = num_nondata_rows(path).unwrap();
         dbg!(&num_skipped_rows);
@@ -682,12 +1691,8 @@ mod tests {
     #[test]
     fn extract_fifteen_min_pedestrian_gets_correct_number_of_counts167297() {
         let path = Path::new("test_files/ecocounter_15minutepedestrian/167297.csv");
-        let directions = Directions {
-            direction1: LaneDirection::North,
-            direction2: Some(LaneDirection::South),
-            direction3: None,
-            one_way_bicycle: false,
-        };
+        let directions =
+            Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap();
         let fifteen_min_volcount =
             FifteenMinutePedestrian::extract(path, 167297, &directions).unwrap();
         assert_eq!(fifteen_min_volcount.len(), 1536);
@@ -706,6 +1711,160 @@ mod tests {
         assert_eq!(south_sum, 1201);
     }
 
+    #[test]
+    fn extract_ind_vehicle_err_has_location_of_bad_value() {
+        let path = Path::new("test_files/jamar_vehicle/bad_speed.txt");
+        match IndividualVehicle::extract(path, &ClassMapping::default()) {
+            Err(CountError::InRow { location, source }) => {
+                assert_eq!(location.path, path);
+                assert_eq!(location.line, 7);
+                assert_eq!(location.column, Some(5));
+                assert_eq!(location.text, "3x.2");
+                assert!(matches!(*source, CountError::ParseFloatError(_)));
+            }
+            other => panic!("expected error with location, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn extract_fifteen_min_bicycle_err_has_location_of_bad_value() {
+        let path = Path::new("test_files/ecocounter_15minutebicycle/bad_volume.csv");
+        let directions =
+            Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap();
+        let err = FifteenMinuteBicycle::extract(path, 1, &directions).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot parse value as number \
+            (\"test_files/ecocounter_15minutebicycle/bad_volume.csv\", line 6, column index 3: 'one')"
+        );
+    }
+
+    #[test]
+    fn source_rows_have_physical_line_and_raw_text_with_crlf() {
+        let path = Path::new("test_files/jamar_15minutevehicle/168193.txt");
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(contents.contains("\r\n"));
+        let lines = contents.lines().collect::<Vec<_>>();
+        let mut rows = 0;
+        for row in SourceRows::open(path).unwrap() {
+            let row = row.unwrap();
+            assert_eq!(row.text, lines[row.line as usize - 1]);
+            rows += 1;
+        }
+        assert_eq!(rows, lines.iter().filter(|l| !l.is_empty()).count());
+    }
+
+    #[test]
+    fn row_location_has_whole_row_if_column_missing() {
+        let path = Path::new("test_files/jamar_vehicle/bad_speed.txt");
+        let row = SourceRows::open(path).unwrap().nth(8).unwrap().unwrap();
+        match row.parse::<f32>(5) {
+            Err(CountError::InRow { location, source }) => {
+                assert_eq!(location.line, 9);
+                assert_eq!(location.text, "5, 11/6/2023, 10:59:55 AM, 1, 2");
+                assert!(matches!(*source, CountError::MissingDataColumn));
+            }
+            other => panic!("expected error with location, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn extract_lenient_rejects_bad_rows() {
+        let path = Path::new("test_files/jamar_vehicle/bad_speed.txt");
+        let extraction = IndividualVehicle::extract_with_policy(
+            path,
+            &ClassMapping::default(),
+            RowPolicy::Lenient {
+                max_rejected_pct: 50.0,
+            },
+        )
+        .unwrap();
+        assert_eq!(extraction.rows, 5);
+        assert_eq!(extraction.records.len(), 3);
+        assert_eq!(extraction.rejected.len(), 2);
+
+        let rejected = &extraction.rejected[0];
+        assert_eq!(rejected.location.line, 7);
+        assert_eq!(rejected.location.column, Some(5));
+        assert_eq!(rejected.reason, "cannot parse value as number");
+        assert_eq!(rejected.row, "3, 11/6/2023, 10:59:50 AM, 2, 2, 3x.2");
+        assert_eq!(extraction.rejected[1].location.line, 9);
+        assert_eq!(
+            extraction.rejected[1].reason,
+            "data does not exist in expected column"
+        );
+    }
+
+    #[test]
+    fn extract_lenient_errs_if_too_many_rows_rejected() {
+        let path = Path::new("test_files/jamar_vehicle/bad_speed.txt");
+        match IndividualVehicle::extract_with_policy(
+            path,
+            &ClassMapping::default(),
+            RowPolicy::Lenient {
+                max_rejected_pct: 25.0,
+            },
+        ) {
+            Err(CountError::TooManyRejectedRows { rejected, rows, .. }) => {
+                assert_eq!(rejected.len(), 2);
+                assert_eq!(rows, 5);
+            }
+            other => panic!("expected too many rejected rows, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn extract_lenient_rejects_whole_row_and_writes_rejected() {
+        let path = Path::new("test_files/ecocounter_15minutebicycle/bad_volume.csv");
+        let directions =
+            Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap();
+        let extraction = FifteenMinuteBicycle::extract_with_policy(
+            path,
+            1,
+            &directions,
+            RowPolicy::Lenient {
+                max_rejected_pct: 50.0,
+            },
+        )
+        .unwrap();
+        // Neither direction of the bad row is included.
+        assert_eq!(extraction.records.len(), 4);
+        assert_eq!(extraction.rejected.len(), 1);
+
+        let rejected_path = std::env::temp_dir().join("bad_volume.csv.rejected.csv");
+        write_rejected_rows(&rejected_path, &extraction.rejected).unwrap();
+        assert_eq!(
+            fs::read_to_string(&rejected_path).unwrap(),
+            "line,column,reason,row\n\
+            6,3,cannot parse value as number,\"2023-09-22 00:30:00,2,1,one,\"\n"
+        );
+        fs::remove_file(rejected_path).unwrap();
+    }
+
+    #[test]
+    fn stream_stops_at_bad_row_unless_lenient() {
+        let path = Path::new("test_files/jamar_vehicle/bad_speed.txt");
+        let mut stream =
+            IndividualVehicle::stream(path, &ClassMapping::default(), RowPolicy::Strict).unwrap();
+        assert_eq!(stream.by_ref().count(), 2);
+        assert_eq!(stream.rows(), 3);
+        assert_eq!(stream.finish().unwrap_err().location().unwrap().line, 7);
+
+        let mut stream = IndividualVehicle::stream(
+            path,
+            &ClassMapping::default(),
+            RowPolicy::Lenient {
+                max_rejected_pct: 50.0,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            stream.by_ref().map(|v| v.lane).collect::<Vec<_>>(),
+            [1, 2, 1]
+        );
+        assert_eq!(stream.finish().unwrap().len(), 2);
+    }
+
     #[test]
     fn count_type_from_location_correct_ind_veh() {
         let count_type =
@@ -743,6 +1902,180 @@ mod tests {
         assert!(matches!(count_type, Err(CountError::BadLocation(_))))
     }
 
+    #[test]
+    fn detect_15min_veh() {
+        let detection = detect(Path::new("test_files/jamar_15minutevehicle/102.csv"));
+        assert_eq!(detection.input_count, InputCount::FifteenMinuteVehicle);
+        assert_eq!(detection.confidence, Confidence::High);
+        assert_eq!(detection.directions, Some(3));
+        assert_eq!(detection.nondata_rows, 3);
+    }
+
+    #[test]
+    fn detect_15min_bicycle_or_ped_directions() {
+        let detection = detect(Path::new(
+            "test_files/ecocounter_15minutebicycle/179847.csv",
+        ));
+        assert_eq!(detection.input_count.layout(), FileLayout::EcoCounter);
+        assert_eq!(detection.confidence, Confidence::Low);
+        assert_eq!(detection.directions, Some(1));
+
+        let detection = detect(Path::new(
+            "test_files/ecocounter_15minutepedestrian/167297.csv",
+        ));
+        assert_eq!(detection.input_count.layout(), FileLayout::EcoCounter);
+        assert_eq!(detection.directions, Some(2));
+    }
+
+    #[test]
+    fn detect_ind_bicycle() {
+        let detection = detect(Path::new(
+            "test_files/jamar_bicycle/181261_include_wrong_way.txt",
+        ));
+        assert_eq!(detection.input_count, InputCount::IndividualBicycle);
+        assert_eq!(detection.confidence, Confidence::Medium);
+    }
+
+    #[test]
+    fn detect_ind_veh_with_and_without_bicycles() {
+        for path in [
+            "test_files/jamar_vehicle/166905.txt",
+            "test_files/jamar_vehicle_and_bicycle/178959.csv",
+        ] {
+            let detection = detect(Path::new(path));
+            assert_eq!(detection.input_count, InputCount::IndividualVehicle);
+            assert_eq!(detection.confidence, Confidence::Low);
+        }
+    }
+
+    #[test]
+    fn detect_ind_veh_without_data_rows_or_bicycles() {
+        // Without any data, the location decides, so a header-only file is an empty count.
+        let path = Path::new("test_files/jamar_vehicle/header_only.txt");
+        let detection = detect(path);
+        assert_eq!(detection.input_count, InputCount::IndividualVehicle);
+        assert_eq!(detection.confidence, Confidence::Low);
+        assert_eq!(
+            InputCount::from_path(path, &ClassMapping::default()).unwrap(),
+            InputCount::IndividualVehicle
+        );
+
+        // Bicycles are only those of the mapping.
+        let path = Path::new("test_files/jamar_bicycle/181261_include_wrong_way.txt");
+        let detection = InputCount::detect(path, &ClassMapping::modified_scheme_f()).unwrap();
+        assert_eq!(detection.input_count, InputCount::IndividualVehicle);
+        assert_eq!(detection.confidence, Confidence::Low);
+        let bicycles_as_1 = ClassMapping::new(
+            "bicycles-as-1",
+            [(1, MappedClass::Bicycle), (14, MappedClass::Discard)],
+        );
+        assert_eq!(
+            InputCount::detect(path, &bicycles_as_1)
+                .unwrap()
+                .input_count,
+            InputCount::IndividualVehicle
+        );
+    }
+
+    #[test]
+    fn detect_manual_class() {
+        let detection = detect(Path::new("test_files/manual_class/170001.csv"));
+        assert_eq!(detection.input_count, InputCount::ManualClass);
+        assert_eq!(detection.confidence, Confidence::High);
+        assert_eq!(detection.directions, None);
+        assert_eq!(detection.nondata_rows, 4);
+
+        let detection = detect(Path::new("test_files/manual_class/170002.csv"));
+        assert_eq!(detection.input_count, InputCount::ManualClass);
+        assert_eq!(detection.directions, Some(1));
+    }
+
+    #[test]
+    fn extract_manual_class_by_direction_170001() {
+        let path = Path::new("test_files/manual_class/170001.csv");
+        let directions =
+            Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap();
+        let counts = TimeBinnedVehicleClassCount::extract(path, 170001, &directions).unwrap();
+
+        // 8 15-minute intervals in 2 directions; the totals row is ignored.
+        assert_eq!(counts.len(), 16);
+        assert_eq!(counts.iter().map(|c| c.total).sum::<u32>(), 1030);
+        assert_eq!(
+            counts
+                .iter()
+                .filter(|c| c.lane == Some(1))
+                .map(|c| c.total)
+                .sum::<u32>(),
+            521
+        );
+        assert!(counts
+            .iter()
+            .filter(|c| c.lane == Some(2))
+            .all(|c| c.direction == Some(LaneDirection::South)));
+        // Unclassified vehicles are also included in class 2.
+        assert_eq!(counts.iter().map(|c| c.c2).sum::<u32>(), 743);
+        assert_eq!(counts.iter().map(|c| c.c15.unwrap()).sum::<u32>(), 8);
+    }
+
+    #[test]
+    fn extract_manual_class_bins_5_min_tallies_170002() {
+        let path = Path::new("test_files/manual_class/170002.csv");
+        let directions = Directions::new(vec![LaneDirection::East], false).unwrap();
+        let counts = TimeBinnedVehicleClassCount::extract(path, 170002, &directions).unwrap();
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts.iter().map(|c| c.total).sum::<u32>(), 278);
+        assert_eq!(counts[0].total, 66);
+        assert_eq!(
+            counts[1].time,
+            NaiveDate::from_ymd_opt(2024, 6, 5)
+                .unwrap()
+                .and_hms_opt(16, 15, 0)
+                .unwrap()
+        );
+
+        // Without a direction column, the count can only have one direction.
+        let directions =
+            Directions::new(vec![LaneDirection::East, LaneDirection::West], false).unwrap();
+        assert!(matches!(
+            TimeBinnedVehicleClassCount::extract(path, 170002, &directions),
+            Err(CountError::DirectionLenMisMatch)
+        ));
+    }
+
+    #[test]
+    fn count_type_from_path_uses_location_when_contents_ambiguous() {
+        let count_type = InputCount::from_path(
+            Path::new("test_files/jamar_vehicle_and_bicycle/178955.csv"),
+            &ClassMapping::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            count_type,
+            InputCount::IndividualVehicleAndIndividualBicycle
+        );
+
+        let count_type = InputCount::from_path(
+            Path::new("test_files/ecocounter_15minutepedestrian/167297.csv"),
+            &ClassMapping::default(),
+        )
+        .unwrap();
+        assert_eq!(count_type, InputCount::FifteenMinutePedestrian);
+    }
+
+    #[test]
+    fn count_type_from_path_errs_if_location_and_contents_mismatch() {
+        for path in [
+            "test_files/jamar_vehicle/15min_veh_count.txt",
+            "test_files/jamar_15minutevehicle/15min_bicycle_count.txt",
+            "test_files/ecocounter_15minutebicycle/15min_veh_count.txt",
+        ] {
+            assert!(matches!(
+                InputCount::from_path(Path::new(path), &ClassMapping::default()),
+                Err(CountError::LocationHeaderMisMatch { .. })
+            ))
+        }
+    }
+
     #[test]
     fn num_nondata_rows_correct_15min_veh_sample() {
         let path = Path::new("test_files/jamar_15minutevehicle/168193.txt");
@@ -783,6 +2116,87 @@ mod tests {
         assert_eq!(num_rows, 4);
     }
 
+    #[test]
+    fn extract_tmc_with_grouped_header_190001() {
+        let path = Path::new("test_files/turningmovement/190001.csv");
+        let counts = TurningMovementCount::extract(path, 190001).unwrap();
+
+        // 8 intervals x 2 classes x 4 approaches x 4 movements; approach totals are ignored.
+        assert_eq!(counts.len(), 256);
+        assert_eq!(counts.iter().map(|c| c.volume as u32).sum::<u32>(), 1693);
+        assert_eq!(
+            counts
+                .iter()
+                .filter(|c| c.vehicle_class == "Heavy")
+                .map(|c| c.volume as u32)
+                .sum::<u32>(),
+            163
+        );
+        assert_eq!(
+            counts
+                .iter()
+                .filter(|c| c.approach == LaneDirection::North && c.movement == Movement::Left)
+                .map(|c| c.volume as u32)
+                .sum::<u32>(),
+            70
+        );
+        // Date from above the header, time from the first column.
+        assert_eq!(
+            counts[0].datetime,
+            NaiveDate::from_ymd_opt(2024, 5, 14)
+                .unwrap()
+                .and_hms_opt(7, 0, 0)
+                .unwrap()
+        );
+        assert!(counts.iter().all(|c| c.recordnum == 190001));
+    }
+
+    #[test]
+    fn extract_tmc_with_single_row_header_190002() {
+        let path = Path::new("test_files/turningmovement/190002.csv");
+        let counts = TurningMovementCount::extract(path, 190002).unwrap();
+        assert_eq!(counts.len(), 48);
+        assert!(counts.iter().all(|c| c.vehicle_class == "All"));
+        assert_eq!(
+            counts
+                .iter()
+                .filter(|c| c.approach == LaneDirection::South)
+                .map(|c| c.volume as u32)
+                .sum::<u32>(),
+            130
+        );
+    }
+
+    #[test]
+    fn is_turning_movement_count_correct() {
+        assert!(
+            is_turning_movement_count(Path::new("test_files/turningmovement/190001.csv")).unwrap()
+        );
+        assert!(
+            is_turning_movement_count(Path::new("test_files/turningmovement/190002.csv")).unwrap()
+        );
+        assert!(
+            !is_turning_movement_count(Path::new("test_files/jamar_vehicle/166905.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_approach_movement_correct() {
+        assert_eq!(
+            parse_approach_movement("NB Thru"),
+            Some((Some(LaneDirection::North), Movement::Through))
+        );
+        assert_eq!(
+            parse_approach_movement("Westbound U-Turn"),
+            Some((Some(LaneDirection::West), Movement::UTurn))
+        );
+        assert_eq!(
+            parse_approach_movement("Right"),
+            Some((None, Movement::Right))
+        );
+        assert_eq!(parse_approach_movement("App. Total"), None);
+    }
+
     #[test]
     fn parse_time_hh_mm_ss_p() {
         assert!(parse_time("03:00:00 PM").is_ok());
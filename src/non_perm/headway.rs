@@ -0,0 +1,378 @@
+//! Headways and gaps between individual vehicles.
+//!
+//! The headway of a vehicle is the time between it and the vehicle ahead of it in the same lane.
+//! Each hour and lane of a count gets an [`HourlyHeadway`]: the distribution of headways (in the
+//! bins of [`HEADWAY_EDGES`]), their mean, and the share of vehicles travelling in platoons -
+//! those with a headway below the [platoon threshold](HeadwayOptions::platoon_threshold).
+//!
+//! A gap is the time between consecutive vehicles in any lane - an opening in traffic across the
+//! whole roadway, as a pedestrian crossing it would need. Each hour gets an [`HourlyGaps`]
+//! summarizing how many gaps were at least the [critical gap](HeadwayOptions::critical_gap) and
+//! so could be accepted. Only the time at which each vehicle was counted is known, not its
+//! length, so gaps are measured from the front of one vehicle to the front of the next, as
+//! headways are.
+//!
+//! Headways and gaps are attributed to the hour of the vehicle that ends them. The first vehicle
+//! of a count (or in a lane) has none.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use log::warn;
+use oracle::RowValue;
+
+use crate::{
+    non_perm::{
+        bin_time, create_time_bins, extend_range, Directions, IndividualVehicle, LaneDirection,
+        TimeInterval,
+    },
+    CountError, GetDate,
+};
+
+/// The lower edges of all but the first headway bin, in seconds.
+///
+/// A headway equal to an edge is in the bin above it, so the bins are 0-1 s, 1-2 s, ... 5-6 s,
+/// 6-8 s, 8-10 s, 10-15 s, 15-30 s, 30-60 s, and 60+ s.
+pub const HEADWAY_EDGES: [f32; 11] = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 15.0, 30.0, 60.0];
+
+/// Thresholds for headway and gap analysis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadwayOptions {
+    /// Vehicles with a headway less than this many seconds are in a platoon.
+    pub platoon_threshold: f32,
+    /// Gaps of at least this many seconds are accepted.
+    pub critical_gap: f32,
+}
+
+impl Default for HeadwayOptions {
+    /// A 3-second platoon threshold and a 10-second critical gap (enough for a pedestrian to
+    /// cross about two lanes).
+    fn default() -> Self {
+        Self {
+            platoon_threshold: 3.0,
+            critical_gap: 10.0,
+        }
+    }
+}
+
+/// Headways of an hour and lane of a count.
+///
+/// The datetime is truncated to the top of the hour - 13:00, 14:00, etc. `h1` through `h12` are
+/// the number of headways in each bin of [`HEADWAY_EDGES`]; the mean and platoon percentage are
+/// `None` if there were no headways.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct HourlyHeadway {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    #[row_value(rename = "countlane")]
+    pub lane: u8,
+    #[row_value(rename = "cntdir")]
+    pub direction: LaneDirection,
+    pub headways: u32,
+    #[row_value(rename = "meanheadway")]
+    pub mean: Option<f32>,
+    #[row_value(rename = "platoonpct")]
+    pub platoon_pct: Option<f32>,
+    pub h1: u32,
+    pub h2: u32,
+    pub h3: u32,
+    pub h4: u32,
+    pub h5: u32,
+    pub h6: u32,
+    pub h7: u32,
+    pub h8: u32,
+    pub h9: u32,
+    pub h10: u32,
+    pub h11: u32,
+    pub h12: u32,
+}
+
+impl GetDate for HourlyHeadway {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+/// Gap acceptance in an hour of a count, across all lanes.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct HourlyGaps {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    /// The critical gap, in seconds, that gaps were accepted at.
+    #[row_value(rename = "criticalgap")]
+    pub critical_gap: f32,
+    pub gaps: u32,
+    /// The number of gaps at least as long as the critical gap.
+    #[row_value(rename = "acceptedgaps")]
+    pub accepted: u32,
+    /// The longest gap, in seconds, if there were any.
+    #[row_value(rename = "maxgap")]
+    pub max_gap: Option<f32>,
+}
+
+impl GetDate for HourlyGaps {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+/// Create [`HourlyHeadway`]s and [`HourlyGaps`] from [`IndividualVehicle`]s, which must be in
+/// the order they were counted.
+pub fn create_headways(
+    recordnum: u32,
+    directions: &Directions,
+    options: HeadwayOptions,
+    counts: impl IntoIterator<Item = IndividualVehicle>,
+) -> Result<(Vec<HourlyHeadway>, Vec<HourlyGaps>), CountError> {
+    let mut binner = HeadwayBinner::new(recordnum, directions, options);
+    for count in counts {
+        binner.add(&count)?;
+    }
+    Ok(binner.finish())
+}
+
+/// Collects the headways and gaps of [`IndividualVehicle`]s by hour as they are added, one at a
+/// time, in the order they were counted.
+pub struct HeadwayBinner<'a> {
+    recordnum: u32,
+    directions: &'a Directions,
+    options: HeadwayOptions,
+    // When the last vehicle was counted, in each lane and in any lane.
+    last_in_lane: HashMap<u8, NaiveDateTime>,
+    last: Option<NaiveDateTime>,
+    headways: HashMap<(NaiveDateTime, u8), HeadwayTally>,
+    gaps: HashMap<NaiveDateTime, GapTally>,
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+}
+
+/// The headways of an hour and lane, tallied as they are added.
+#[derive(Debug, Clone, Default)]
+struct HeadwayTally {
+    bins: [u32; HEADWAY_EDGES.len() + 1],
+    count: u32,
+    sum: f64,
+    platooned: u32,
+}
+
+impl HeadwayTally {
+    fn add(&mut self, headway: f32, platoon_threshold: f32) {
+        self.bins[HEADWAY_EDGES.partition_point(|edge| *edge <= headway)] += 1;
+        self.count += 1;
+        self.sum += headway as f64;
+        if headway < platoon_threshold {
+            self.platooned += 1;
+        }
+    }
+}
+
+/// The gaps of an hour, tallied as they are added.
+#[derive(Debug, Clone, Default)]
+struct GapTally {
+    count: u32,
+    accepted: u32,
+    max: Option<f32>,
+}
+
+impl GapTally {
+    fn add(&mut self, gap: f32, critical_gap: f32) {
+        self.count += 1;
+        if gap >= critical_gap {
+            self.accepted += 1;
+        }
+        self.max = Some(self.max.map_or(gap, |max| max.max(gap)));
+    }
+}
+
+impl<'a> HeadwayBinner<'a> {
+    pub fn new(recordnum: u32, directions: &'a Directions, options: HeadwayOptions) -> Self {
+        Self {
+            recordnum,
+            directions,
+            options,
+            last_in_lane: HashMap::new(),
+            last: None,
+            headways: HashMap::new(),
+            gaps: HashMap::new(),
+            range: None,
+        }
+    }
+
+    /// Add the headway and gap ending with a vehicle to its hour.
+    ///
+    /// If a vehicle was counted before the one ahead of it (as when clocks are set back at the
+    /// end of daylight saving time), it has no headway or gap; the next vehicle's are measured
+    /// from it.
+    pub fn add(&mut self, count: &IndividualVehicle) -> Result<(), CountError> {
+        if self.directions.lane(count.lane).is_none() {
+            return Err(CountError::MissingDirection);
+        }
+        let datetime = NaiveDateTime::new(count.date, count.time.time());
+        let hour = NaiveDateTime::new(count.date, bin_time(count.time.time(), TimeInterval::Hour));
+
+        if let Some(seconds) = self
+            .last_in_lane
+            .insert(count.lane, datetime)
+            .and_then(|last| seconds_since(last, datetime))
+        {
+            self.headways
+                .entry((hour, count.lane))
+                .or_default()
+                .add(seconds, self.options.platoon_threshold);
+        }
+        if let Some(seconds) = self
+            .last
+            .replace(datetime)
+            .and_then(|last| seconds_since(last, datetime))
+        {
+            self.gaps
+                .entry(hour)
+                .or_default()
+                .add(seconds, self.options.critical_gap);
+        }
+        extend_range(&mut self.range, datetime);
+        Ok(())
+    }
+
+    /// Summarize the headways of every hour and lane, and the gaps of every hour, from the first
+    /// vehicle to the last.
+    pub fn finish(mut self) -> (Vec<HourlyHeadway>, Vec<HourlyGaps>) {
+        let Some((first_dt, last_dt)) = self.range else {
+            return (vec![], vec![]);
+        };
+
+        let mut hourly_headways = vec![];
+        let mut hourly_gaps = vec![];
+        for datetime in create_time_bins(first_dt, last_dt, TimeInterval::Hour) {
+            for (lane, direction) in self.directions.lanes() {
+                let headways = self.headways.remove(&(datetime, lane)).unwrap_or_default();
+                hourly_headways.push(self.hourly_headway(datetime, lane, direction, &headways));
+            }
+
+            let gaps = self.gaps.remove(&datetime).unwrap_or_default();
+            hourly_gaps.push(HourlyGaps {
+                recordnum: self.recordnum,
+                datetime,
+                critical_gap: self.options.critical_gap,
+                gaps: gaps.count,
+                accepted: gaps.accepted,
+                max_gap: gaps.max,
+            });
+        }
+        (hourly_headways, hourly_gaps)
+    }
+
+    fn hourly_headway(
+        &self,
+        datetime: NaiveDateTime,
+        lane: u8,
+        direction: LaneDirection,
+        headways: &HeadwayTally,
+    ) -> HourlyHeadway {
+        let num = headways.count;
+        let [h1, h2, h3, h4, h5, h6, h7, h8, h9, h10, h11, h12] = headways.bins;
+
+        HourlyHeadway {
+            recordnum: self.recordnum,
+            datetime,
+            lane,
+            direction,
+            headways: num,
+            mean: (num > 0).then(|| (headways.sum / num as f64) as f32),
+            platoon_pct: (num > 0).then(|| headways.platooned as f32 / num as f32 * 100.0),
+            h1,
+            h2,
+            h3,
+            h4,
+            h5,
+            h6,
+            h7,
+            h8,
+            h9,
+            h10,
+            h11,
+            h12,
+        }
+    }
+}
+
+/// The seconds from one datetime to a later one, or `None` if it is earlier.
+fn seconds_since(earlier: NaiveDateTime, later: NaiveDateTime) -> Option<f32> {
+    let millis = (later - earlier).num_milliseconds();
+    if millis < 0 {
+        warn!("Vehicle counted at {later} before the one ahead of it, at {earlier}.");
+        return None;
+    }
+    Some(millis as f32 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{vehicle, vehicle_on};
+
+    #[test]
+    fn headways_and_gaps_binned_by_hour() {
+        use LaneDirection::*;
+        let directions = Directions::new(vec![East, West], false).unwrap();
+
+        let (headways, gaps) = create_headways(
+            1,
+            &directions,
+            HeadwayOptions::default(),
+            vec![
+                vehicle("10:00:00", 1, 2, 30.0),
+                vehicle("10:00:02", 1, 2, 30.0),
+                vehicle("10:00:04", 2, 2, 30.0),
+                vehicle("10:00:16", 1, 2, 30.0),
+                vehicle("10:00:16", 1, 2, 30.0),
+                vehicle("11:59:00", 2, 2, 30.0),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(headways.len(), 2 * 2);
+        let east = &headways[0];
+        assert_eq!(east.direction, East);
+        // 2, 14, and 0 seconds
+        assert_eq!(east.headways, 3);
+        assert_eq!((east.h1, east.h3, east.h9), (1, 1, 1));
+        assert!((east.mean.unwrap() - 16.0 / 3.0).abs() < 0.001);
+        assert!((east.platoon_pct.unwrap() - 200.0 / 3.0).abs() < 0.001);
+        // No headway for the first vehicle in the lane.
+        assert_eq!(headways[1].headways, 0);
+        assert_eq!(headways[1].mean, None);
+        // Almost two hours
+        assert_eq!(headways[3].headways, 1);
+        assert_eq!(headways[3].h12, 1);
+
+        // 2, 2, 12, 0 seconds
+        assert_eq!(gaps.len(), 2);
+        assert_eq!((gaps[0].gaps, gaps[0].accepted), (4, 1));
+        assert_eq!(gaps[0].max_gap, Some(12.0));
+        assert_eq!((gaps[1].gaps, gaps[1].accepted), (1, 1));
+    }
+
+    #[test]
+    fn vehicle_counted_out_of_order_has_no_headway() {
+        let directions = Directions::new(vec![LaneDirection::North], false).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 11, 3).unwrap();
+        let vehicle = |time| vehicle_on(date, time, 1, 2, 30.0);
+
+        let (headways, gaps) = create_headways(
+            1,
+            &directions,
+            HeadwayOptions::default(),
+            vec![
+                vehicle("01:59:50"),
+                vehicle("01:00:05"),
+                vehicle("01:00:09"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(headways[0].headways, 1);
+        assert_eq!(headways[0].h5, 1);
+        assert_eq!(gaps[0].gaps, 1);
+    }
+}
@@ -11,7 +11,10 @@
 //! [`BinnedCountKey`] + [`SpeedRangeCount`] = [`TimeBinnedSpeedRangeCount`](crate::non_perm::TimeBinnedSpeedRangeCount).
 use chrono::{NaiveDate, NaiveDateTime};
 
-use crate::non_perm::{LaneDirection, VehicleClass};
+use crate::non_perm::{
+    speed_bins::{bin_index, DEFAULT_EDGES},
+    LaneDirection, VehicleClass,
+};
 
 /// The key for records of the TC_SPECOUNT and TC_CLACOUNT tables.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -80,27 +83,31 @@ impl VehicleClassCount {
     }
     /// Insert individual counted vehicles into count.
     pub fn insert(&mut self, class: VehicleClass) {
+        self.add(class, 1)
+    }
+    /// Add a number of vehicles of the same class (e.g. from a manual tally) to count.
+    pub fn add(&mut self, class: VehicleClass, volume: u32) {
         match class {
-            VehicleClass::Motorcycles => self.c1 += 1,
-            VehicleClass::PassengerCars => self.c2 += 1,
-            VehicleClass::OtherFourTireSingleUnitVehicles => self.c3 += 1,
-            VehicleClass::Buses => self.c4 += 1,
-            VehicleClass::TwoAxleSixTireSingleUnitTrucks => self.c5 += 1,
-            VehicleClass::ThreeAxleSingleUnitTrucks => self.c6 += 1,
-            VehicleClass::FourOrMoreAxleSingleUnitTrucks => self.c7 += 1,
-            VehicleClass::FourOrFewerAxleSingleTrailerTrucks => self.c8 += 1,
-            VehicleClass::FiveAxleSingleTrailerTrucks => self.c9 += 1,
-            VehicleClass::SixOrMoreAxleSingleTrailerTrucks => self.c10 += 1,
-            VehicleClass::FiveOrFewerAxleMultiTrailerTrucks => self.c11 += 1,
-            VehicleClass::SixAxleMultiTrailerTrucks => self.c12 += 1,
-            VehicleClass::SevenOrMoreAxleMultiTrailerTrucks => self.c13 += 1,
+            VehicleClass::Motorcycles => self.c1 += volume,
+            VehicleClass::PassengerCars => self.c2 += volume,
+            VehicleClass::OtherFourTireSingleUnitVehicles => self.c3 += volume,
+            VehicleClass::Buses => self.c4 += volume,
+            VehicleClass::TwoAxleSixTireSingleUnitTrucks => self.c5 += volume,
+            VehicleClass::ThreeAxleSingleUnitTrucks => self.c6 += volume,
+            VehicleClass::FourOrMoreAxleSingleUnitTrucks => self.c7 += volume,
+            VehicleClass::FourOrFewerAxleSingleTrailerTrucks => self.c8 += volume,
+            VehicleClass::FiveAxleSingleTrailerTrucks => self.c9 += volume,
+            VehicleClass::SixOrMoreAxleSingleTrailerTrucks => self.c10 += volume,
+            VehicleClass::FiveOrFewerAxleMultiTrailerTrucks => self.c11 += volume,
+            VehicleClass::SixAxleMultiTrailerTrucks => self.c12 += volume,
+            VehicleClass::SevenOrMoreAxleMultiTrailerTrucks => self.c13 += volume,
             VehicleClass::UnclassifiedVehicle => {
                 // Unclassified vehicles get included with class 2 and also counted on their own.
-                self.c2 += 1;
-                self.c15 += 1;
+                self.c2 += volume;
+                self.c15 += volume;
             }
         }
-        self.total += 1;
+        self.total += volume;
     }
 }
 
@@ -159,46 +166,25 @@ impl SpeedRangeCount {
         value
     }
     /// Insert individual speed into count.
+    ///
+    /// The speed ranges are the bins of the default [`SpeedBinScheme`](crate::non_perm::speed_bins::SpeedBinScheme): 0-15 (including 15.0),
+    /// >15-20, and so on, with everything above 75 in `s14`.
     pub fn insert(&mut self, speed: f32) {
-        // The end of the ranges are inclusive to the number's .0 decimal;
-        // that is:
-        // 0-15: 0.0 to 15.0
-        // >15-20: 15.1 to 20.0, etc.
-
-        // Unfortunately, using floats as tests in pattern matching will be an error in a future
-        // Rust release, so need to do if/else rather than match.
-        // <https://github.com/rust-lang/rust/issues/41620>
-        if speed.is_sign_negative() {
-            // This shouldn't be necessary, but I saw a -0.0 in one of the files.
-            self.s1 += 1
-        } else if (0.0..=15.0).contains(&speed) {
-            self.s1 += 1;
-        } else if (15.1..=20.0).contains(&speed) {
-            self.s2 += 1;
-        } else if (20.1..=25.0).contains(&speed) {
-            self.s3 += 1;
-        } else if (25.1..=30.0).contains(&speed) {
-            self.s4 += 1;
-        } else if (30.1..=35.0).contains(&speed) {
-            self.s5 += 1;
-        } else if (35.1..=40.0).contains(&speed) {
-            self.s6 += 1;
-        } else if (40.1..=45.0).contains(&speed) {
-            self.s7 += 1;
-        } else if (45.1..=50.0).contains(&speed) {
-            self.s8 += 1;
-        } else if (50.1..=55.0).contains(&speed) {
-            self.s9 += 1;
-        } else if (55.1..=60.0).contains(&speed) {
-            self.s10 += 1;
-        } else if (60.1..=65.0).contains(&speed) {
-            self.s11 += 1;
-        } else if (65.1..=70.0).contains(&speed) {
-            self.s12 += 1;
-        } else if (70.1..=75.0).contains(&speed) {
-            self.s13 += 1;
-        } else if (75.1..).contains(&speed) {
-            self.s14 += 1;
+        match bin_index(&DEFAULT_EDGES, speed) {
+            0 => self.s1 += 1,
+            1 => self.s2 += 1,
+            2 => self.s3 += 1,
+            3 => self.s4 += 1,
+            4 => self.s5 += 1,
+            5 => self.s6 += 1,
+            6 => self.s7 += 1,
+            7 => self.s8 += 1,
+            8 => self.s9 += 1,
+            9 => self.s10 += 1,
+            10 => self.s11 += 1,
+            11 => self.s12 += 1,
+            12 => self.s13 += 1,
+            _ => self.s14 += 1,
         }
         self.total += 1;
     }
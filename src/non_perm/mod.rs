@@ -8,12 +8,21 @@ use log::{error, Level, Log, Record};
 use oracle::{Connection, RowValue};
 use serde::Deserialize;
 
-use crate::db::{self, ImportLogEntry};
+use crate::db::{store::CountStore, ImportLogEntry};
 use crate::{CountError, GetDate};
 
 pub mod check_data;
+pub mod check_results;
+pub mod class_scheme;
 pub mod extract_from_file;
+pub mod headway;
 pub mod intermediate;
+pub mod parser;
+pub mod resample;
+pub mod speed_bins;
+pub mod speed_class;
+pub mod speed_stats;
+pub mod turning_movement;
 
 use intermediate::{BinnedCountKey, SpeedRangeCount, VehicleClassCount};
 
@@ -101,10 +110,17 @@ impl Display for NonPermCountKind {
 /// [vehicle classification](VehicleClass) and speed,
 /// with no binning applied to it.
 ///
-/// Three kinds of counts can be derived from this type of data:
+/// Six kinds of counts can be derived from this type of data:
 ///   - [TimeBinnedVehicleClassCount] by [create_speed_and_class_count]
 ///   - [TimeBinnedSpeedRangeCount] also by [create_speed_and_class_count]  
 ///   - [HourlyAvgSpeed] by [HourlyAvgSpeed::create]
+///   - [HourlySpeedStats](speed_stats::HourlySpeedStats) and
+///     [CountSpeedStats](speed_stats::CountSpeedStats) by
+///     [create_speed_stats](speed_stats::create_speed_stats)
+///   - [TimeBinnedSpeedClassCount](speed_class::TimeBinnedSpeedClassCount) by
+///     [create_speed_class_count](speed_class::create_speed_class_count)
+///   - [HourlyHeadway](headway::HourlyHeadway) and [HourlyGaps](headway::HourlyGaps) by
+///     [create_headways](headway::create_headways)
 #[derive(Debug, Clone)]
 pub struct IndividualVehicle {
     pub date: NaiveDate,
@@ -168,6 +184,7 @@ impl IndividualBicycle {
 #[derive(Debug, Clone, RowValue, PartialEq)]
 pub struct FifteenMinuteBicycle {
     pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
     pub datetime: NaiveDateTime,
     pub volume: u16,
     pub cntdir: LaneDirection,
@@ -199,6 +216,7 @@ impl FifteenMinuteBicycle {
 #[derive(Debug, Clone, RowValue)]
 pub struct FifteenMinutePedestrian {
     pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
     pub datetime: NaiveDateTime,
     pub volume: u16,
     pub cntdir: LaneDirection,
@@ -271,12 +289,16 @@ impl FifteenMinuteVehicle {
 /// Vehicle counts aggregated by hour.
 ///
 /// The datetime is truncated to the top of the hour - 13:00, 14:00, etc.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, RowValue)]
 pub struct HourlyVehicle {
     pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
     pub datetime: NaiveDateTime,
+    #[row_value(rename = "volume")]
     pub count: u32,
+    #[row_value(rename = "cntdir")]
     pub direction: LaneDirection,
+    #[row_value(rename = "countlane")]
     pub lane: u8,
 }
 
@@ -318,17 +340,80 @@ impl HourlyVehicle {
 
         Ok(hourly_veh_counts)
     }
+
+    /// Create hourly counts from [`FifteenMinuteVehicle`]s, without a trip to the database.
+    pub fn from_fifteen_min(
+        recordnum: u32,
+        counts: &[FifteenMinuteVehicle],
+    ) -> Result<Vec<HourlyVehicle>, CountError> {
+        Self::aggregate(
+            recordnum,
+            counts.iter().map(|c| {
+                let datetime = NaiveDateTime::new(c.date, c.time.time());
+                (datetime, c.direction, c.lane, c.count as u32)
+            }),
+        )
+    }
+
+    /// Create hourly counts from the totals of [`TimeBinnedVehicleClassCount`]s, without a trip
+    /// to the database.
+    pub fn from_class_counts(
+        recordnum: u32,
+        counts: &[TimeBinnedVehicleClassCount],
+    ) -> Result<Vec<HourlyVehicle>, CountError> {
+        Self::aggregate(
+            recordnum,
+            counts.iter().map(|c| {
+                let datetime = NaiveDateTime::new(c.date, c.time.time());
+                (datetime, c.direction, c.lane, c.total)
+            }),
+        )
+    }
+
+    /// Sum volumes by hour, direction, and lane, ordered by hour and then lane.
+    pub(crate) fn aggregate(
+        recordnum: u32,
+        volumes: impl IntoIterator<Item = (NaiveDateTime, Option<LaneDirection>, Option<u8>, u32)>,
+    ) -> Result<Vec<HourlyVehicle>, CountError> {
+        let mut hourly = HashMap::new();
+        for (datetime, direction, lane, volume) in volumes {
+            let hour = NaiveDateTime::new(
+                datetime.date(),
+                bin_time(datetime.time(), TimeInterval::Hour),
+            );
+            let direction = direction.ok_or(CountError::MissingDirection)?;
+            let lane = lane.ok_or(CountError::InconsistentData)?;
+            *hourly.entry((hour, direction, lane)).or_insert(0) += volume;
+        }
+
+        let mut hourly_veh_counts = hourly
+            .into_iter()
+            .map(|((datetime, direction, lane), count)| HourlyVehicle {
+                recordnum,
+                datetime,
+                count,
+                direction,
+                lane,
+            })
+            .collect::<Vec<_>>();
+        hourly_veh_counts.sort_unstable_by_key(|c| (c.datetime, c.lane));
+        Ok(hourly_veh_counts)
+    }
 }
 
 /// Speed counts averaged by hour.
 ///
 /// The datetime is truncated to the top of the hour - 13:00, 14:00, etc.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, RowValue)]
 pub struct HourlyAvgSpeed {
     pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
     pub datetime: NaiveDateTime,
+    #[row_value(rename = "avgspeed")]
     pub speed: Option<f32>,
+    #[row_value(rename = "cntdir")]
     pub direction: LaneDirection,
+    #[row_value(rename = "countlane")]
     pub lane: u8,
 }
 
@@ -337,58 +422,85 @@ impl HourlyAvgSpeed {
     pub fn create(
         recordnum: u32,
         directions: Directions,
-        mut counts: Vec<IndividualVehicle>,
+        counts: impl IntoIterator<Item = IndividualVehicle>,
     ) -> Vec<Self> {
-        if counts.is_empty() {
-            return vec![];
-        }
-        // The key for the hashmap.
-        #[derive(Debug, Eq, PartialEq, Hash)]
-        struct AvgSpeedKey {
-            pub recordnum: u32,
-            pub datetime: NaiveDateTime,
-            pub direction: LaneDirection,
-            pub lane: u8,
+        let mut binner = HourlyAvgSpeedBinner::new(recordnum, directions);
+        for count in counts {
+            binner.add(&count);
         }
+        binner.finish()
+    }
+}
 
-        let mut raw_speed_map: HashMap<AvgSpeedKey, Vec<f32>> = HashMap::new();
-
-        // Get all speeds in each hour.
-        for count in counts.clone() {
-            let direction = match count.lane {
-                1 => directions.direction1,
-                2 => directions.direction2.unwrap(),
-                3 => directions.direction3.unwrap(),
-                _ => {
-                    error!("Unable to determine lane/direction.");
-                    continue;
-                }
-            };
+/// The key for the hashmap of hourly speeds.
+#[derive(Debug, Eq, PartialEq, Hash)]
+struct AvgSpeedKey {
+    pub recordnum: u32,
+    pub datetime: NaiveDateTime,
+    pub direction: LaneDirection,
+    pub lane: u8,
+}
 
-            let key = AvgSpeedKey {
-                recordnum,
-                datetime: NaiveDateTime::new(
-                    count.date,
-                    count
-                        .time
-                        .time()
-                        .with_minute(0)
-                        .unwrap()
-                        .with_second(0)
-                        .unwrap()
-                        .with_nanosecond(0)
-                        .unwrap(),
-                ),
-                direction,
-                lane: count.lane,
-            };
+/// Averages the speeds of [`IndividualVehicle`]s by hour as they are added, one at a time.
+///
+/// Only the sum and number of speeds in each hour are kept, not the vehicles.
+pub struct HourlyAvgSpeedBinner {
+    recordnum: u32,
+    directions: Directions,
+    speeds: HashMap<AvgSpeedKey, (f32, u32)>,
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+}
 
-            // Add new entry if necessary, then insert data.
-            raw_speed_map
-                .entry(key)
-                .and_modify(|c| c.push(count.speed))
-                .or_insert(vec![count.speed]);
+impl HourlyAvgSpeedBinner {
+    pub fn new(recordnum: u32, directions: Directions) -> Self {
+        Self {
+            recordnum,
+            directions,
+            speeds: HashMap::new(),
+            range: None,
         }
+    }
+
+    /// Add a vehicle's speed to its hour.
+    pub fn add(&mut self, count: &IndividualVehicle) {
+        let Some(direction) = self.directions.lane(count.lane) else {
+            error!("Unable to determine lane/direction.");
+            return;
+        };
+
+        let key = AvgSpeedKey {
+            recordnum: self.recordnum,
+            datetime: NaiveDateTime::new(
+                count.date,
+                count
+                    .time
+                    .time()
+                    .with_minute(0)
+                    .unwrap()
+                    .with_second(0)
+                    .unwrap()
+                    .with_nanosecond(0)
+                    .unwrap(),
+            ),
+            direction,
+            lane: count.lane,
+        };
+
+        // Add new entry if necessary, then insert data.
+        let (sum, num) = self.speeds.entry(key).or_insert((0.0, 0));
+        *sum += count.speed;
+        *num += 1;
+        extend_range(
+            &mut self.range,
+            NaiveDateTime::new(count.date, count.time.time()),
+        );
+    }
+
+    /// Calculate the average speed of each hour.
+    pub fn finish(mut self) -> Vec<HourlyAvgSpeed> {
+        let Some((first_dt, last_dt)) = self.range else {
+            return vec![];
+        };
 
         /*
           If there was some time period (whose length is `TimeInterval`) where no vehicle was counted,
@@ -396,85 +508,43 @@ impl HourlyAvgSpeed {
           data we are using - `IndividualVehicle`s, which are vehicles that were counted - not because
           there is missing data for that time period. So create those where necessary.
         */
-
-        // Sort counts by date and time, get range, check if number of records is less than expected
-        // for every period to be included, insert any missing.
-        counts.sort_unstable_by_key(|c| (c.date, c.time.time()));
-
-        let first_dt = NaiveDateTime::new(
-            counts.first().unwrap().date,
-            counts.first().unwrap().time.time(),
-        );
-        let last_dt = NaiveDateTime::new(
-            counts.last().unwrap().date,
-            counts.last().unwrap().time.time(),
-        );
-
         let all_datetimes = create_time_bins(first_dt, last_dt, TimeInterval::Hour);
 
         let mut all_keys = vec![];
 
         // construct all possible keys
-        for datetime in all_datetimes.clone() {
-            // Direction 1
-            all_keys.push(AvgSpeedKey {
-                recordnum,
-                datetime,
-                direction: directions.direction1,
-                lane: 1,
-            });
-            // Direction 2
-            if let Some(v) = directions.direction2 {
+        for datetime in all_datetimes {
+            for (lane, direction) in self.directions.lanes() {
                 all_keys.push(AvgSpeedKey {
-                    recordnum,
+                    recordnum: self.recordnum,
                     datetime,
-                    direction: v,
-                    lane: 2,
-                });
-            }
-            // Direction 3
-            if let Some(v) = directions.direction3 {
-                all_keys.push(AvgSpeedKey {
-                    recordnum,
-                    datetime,
-                    direction: v,
-                    lane: 3,
+                    direction,
+                    lane,
                 });
             }
         }
         // Add missing periods for speed range count
         for key in all_keys {
-            raw_speed_map.entry(key).or_default();
+            self.speeds.entry(key).or_insert((0.0, 0));
         }
 
-        // Calculate the average speed per date/hour from the vecs.
-        let mut hourly_speed_avg = vec![];
-        for (key, value) in raw_speed_map {
-            if value.is_empty() {
-                hourly_speed_avg.push(HourlyAvgSpeed {
-                    recordnum: key.recordnum,
-                    datetime: key.datetime,
-                    speed: None,
-                    direction: key.direction,
-                    lane: key.lane,
-                })
-            } else {
-                hourly_speed_avg.push(HourlyAvgSpeed {
-                    recordnum: key.recordnum,
-                    datetime: key.datetime,
-                    speed: Some(value.iter().sum::<f32>() / value.len() as f32),
-                    direction: key.direction,
-                    lane: key.lane,
-                })
-            }
-        }
-        hourly_speed_avg
+        // Calculate the average speed per date/hour.
+        self.speeds
+            .into_iter()
+            .map(|(key, (sum, num))| HourlyAvgSpeed {
+                recordnum: key.recordnum,
+                datetime: key.datetime,
+                speed: (num > 0).then(|| sum / num as f32),
+                direction: key.direction,
+                lane: key.lane,
+            })
+            .collect()
     }
 }
 
 /// The full metadata of a non-permanent count, which corresponds to the "tc_header" table in the
 /// database.
-#[derive(Debug, Clone, PartialEq, RowValue, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, RowValue, Deserialize)]
 pub struct Metadata {
     pub amending: Option<String>,
     pub ampeak: Option<f32>,
@@ -523,6 +593,56 @@ pub struct Metadata {
     pub y: Option<f32>,
 }
 
+impl Metadata {
+    /// The columns of the "tc_header" table that metadata is selected from (by its
+    /// [`RowValue`]).
+    pub const COLUMNS: &'static [&'static str] = &[
+        "amending",
+        "ampeak",
+        "bikepeddesc",
+        "bikepedfacility",
+        "bikepedgroup",
+        "cntdir",
+        "comments",
+        "type",
+        "counterid",
+        "createheaderdate",
+        "datelastcounted",
+        "description",
+        "fc",
+        "fromlmt",
+        "importdatadate",
+        "indir",
+        "isurban",
+        "latitude",
+        "longitude",
+        "mcd",
+        "mp",
+        "offset",
+        "outdir",
+        "pmending",
+        "pmpeak",
+        "prj",
+        "program",
+        "recordnum",
+        "rdprefix",
+        "rdsuffix",
+        "road",
+        "route",
+        "seg",
+        "sidewalk",
+        "speedlimit",
+        "source",
+        "sr",
+        "sri",
+        "stationid",
+        "tolmt",
+        "trafdir",
+        "x",
+        "y",
+    ];
+}
+
 /// The direction of a road.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize)]
 pub enum RoadDirection {
@@ -595,17 +715,60 @@ impl Display for LaneDirection {
     }
 }
 
-/// The [`LaneDirection`]s that a count could contain and whether or not one-way bicycle.
+/// The [`LaneDirection`] of each lane of a count and whether or not one-way bicycle.
+///
+/// Lanes are numbered from 1, in the order of the channels/columns of count files, and a count
+/// always has at least one.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Directions {
-    pub direction1: LaneDirection,
-    pub direction2: Option<LaneDirection>,
-    pub direction3: Option<LaneDirection>,
+    lanes: Vec<LaneDirection>,
     pub one_way_bicycle: bool,
 }
 
 impl Directions {
+    /// Create from the direction of each lane, in order; errs if there are none.
+    pub fn new(lanes: Vec<LaneDirection>, one_way_bicycle: bool) -> Result<Self, CountError> {
+        if lanes.is_empty() {
+            return Err(CountError::MissingDirection);
+        }
+        Ok(Self {
+            lanes,
+            one_way_bicycle,
+        })
+    }
+
+    /// The direction of the first lane.
+    pub fn first(&self) -> LaneDirection {
+        self.lanes[0]
+    }
+
+    /// The direction of a lane, if the count has that lane.
+    pub fn lane(&self, lane: u8) -> Option<LaneDirection> {
+        self.lanes.get((lane as usize).checked_sub(1)?).copied()
+    }
+
+    /// The number of the first lane in a direction, if any.
+    pub fn lane_of(&self, direction: LaneDirection) -> Option<u8> {
+        self.lanes()
+            .find(|(_, v)| *v == direction)
+            .map(|(lane, _)| lane)
+    }
+
+    /// The number of lanes.
+    pub fn num_lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Each lane's number and direction.
+    pub fn lanes(&self) -> impl Iterator<Item = (u8, LaneDirection)> + '_ {
+        (1..).zip(self.lanes.iter().copied())
+    }
+
     /// Get lane directions for a particular count from the database.
+    ///
+    /// The directions are those in the tc_lanedir table, which is needed for counts with more than
+    /// three lanes. If the count has none there, they are the cldir1, cldir2, and cldir3 fields of
+    /// tc_header.
     pub fn from_db(recordnum: u32, conn: &Connection) -> Result<Directions, CountError> {
         let (dir1, dir2, dir3, one_way_bicycle) = conn.query_row_as::<(
             Option<String>,
@@ -624,28 +787,46 @@ impl Directions {
             false
         };
 
-        let direction1 = if let Some(v) = dir1 {
-            LaneDirection::from_str(&v)?
-        } else {
-            return Err(CountError::MissingDirection);
-        };
-        let direction2 = if let Some(v) = dir2 {
-            Some(LaneDirection::from_str(&v)?)
-        } else {
-            None
-        };
-        let direction3 = if let Some(v) = dir3 {
-            Some(LaneDirection::from_str(&v)?)
-        } else {
-            None
-        };
+        let mut lanes = vec![];
+        for row in conn.query_as::<String>(
+            "select direction from tc_lanedir where recordnum = :1 order by lane",
+            &[&recordnum],
+        )? {
+            lanes.push(LaneDirection::from_str(&row?)?);
+        }
 
-        Ok(Directions {
-            direction1,
-            direction2,
-            direction3,
-            one_way_bicycle,
-        })
+        if lanes.is_empty() {
+            // A lane can't be skipped.
+            let dirs = [dir1, dir2, dir3];
+            if dirs.iter().skip_while(|v| v.is_some()).any(|v| v.is_some()) {
+                return Err(CountError::MissingDirection);
+            }
+            for v in dirs.into_iter().flatten() {
+                lanes.push(LaneDirection::from_str(&v)?);
+            }
+        }
+
+        Directions::new(lanes, one_way_bicycle)
+    }
+
+    /// Set the lane directions of a count in the database, replacing any existing ones.
+    ///
+    /// All lanes are put in the tc_lanedir table, and the first three also in tc_header. This is
+    /// not committed.
+    pub fn update_db(&self, recordnum: u32, conn: &Connection) -> Result<(), CountError> {
+        let cldir = |lane| self.lane(lane).map(|v| v.to_string());
+        conn.execute(
+            "update tc_header set cldir1 = :1, cldir2 = :2, cldir3 = :3 where recordnum = :4",
+            &[&cldir(1), &cldir(2), &cldir(3), &recordnum],
+        )?;
+        conn.execute("delete from tc_lanedir where recordnum = :1", &[&recordnum])?;
+        let mut stmt = conn
+            .statement("insert into tc_lanedir (recordnum, lane, direction) values (:1, :2, :3)")
+            .build()?;
+        for (lane, direction) in self.lanes() {
+            stmt.execute(&[&recordnum, &lane, &direction.to_string()])?;
+        }
+        Ok(())
     }
 }
 
@@ -655,6 +836,9 @@ impl Directions {
 /// undefined, class). However, JAMAR/StarNext uses "14" for unclassfied vehicles, and doesn't use
 /// 15. To cover both cases, 14 and 15 are considered unclassified in `from_num`.
 ///
+/// This is one [classification scheme](class_scheme::ClassScheme); see
+/// [class_scheme] for the length bins each class falls in.
+///
 /// See:
 ///  * <https://www.fhwa.dot.gov/policyinformation/vehclass.cfm>
 ///  * <https://www.fhwa.dot.gov/policyinformation/tmguide/tmg_2013/vehicle-types.cfm>
@@ -746,6 +930,34 @@ pub struct TimeBinnedVehicleClassCount {
     pub total: u32,
 }
 
+impl TimeBinnedVehicleClassCount {
+    /// Create from the key and value used while binning.
+    pub fn from_intermediate(key: BinnedCountKey, value: VehicleClassCount) -> Self {
+        Self {
+            date: key.date,
+            time: key.time,
+            lane: Some(key.lane),
+            recordnum: value.recordnum,
+            direction: Some(value.direction),
+            c1: value.c1,
+            c2: value.c2,
+            c3: value.c3,
+            c4: value.c4,
+            c5: value.c5,
+            c6: value.c6,
+            c7: value.c7,
+            c8: value.c8,
+            c9: value.c9,
+            c10: value.c10,
+            c11: value.c11,
+            c12: value.c12,
+            c13: value.c13,
+            c15: Some(value.c15),
+            total: value.total,
+        }
+    }
+}
+
 /// Count of vehicles by speed range, binned into 15-minute or hourly intervals.
 ///
 /// We almost always want fifteen-minute counts, but hourly is also an option.
@@ -782,7 +994,7 @@ pub fn create_speed_and_class_count(
     interval: TimeInterval,
     recordnum: u32,
     directions: &Directions,
-    mut counts: Vec<IndividualVehicle>,
+    counts: impl IntoIterator<Item = IndividualVehicle>,
 ) -> Result<
     (
         Vec<TimeBinnedSpeedRangeCount>,
@@ -790,34 +1002,56 @@ pub fn create_speed_and_class_count(
     ),
     CountError,
 > {
-    if counts.is_empty() {
-        return Ok((vec![], vec![]));
+    let mut binner = SpeedAndClassBinner::new(interval, recordnum, directions);
+    for count in counts {
+        binner.add(count)?;
     }
+    binner.finish()
+}
 
-    let mut speed_range_map: HashMap<BinnedCountKey, SpeedRangeCount> = HashMap::new();
-    let mut vehicle_class_map: HashMap<BinnedCountKey, VehicleClassCount> = HashMap::new();
+/// Bins [`IndividualVehicle`]s into speed range and class counts as they are added, one at a
+/// time.
+pub struct SpeedAndClassBinner<'a> {
+    interval: TimeInterval,
+    recordnum: u32,
+    directions: &'a Directions,
+    speed_range_map: HashMap<BinnedCountKey, SpeedRangeCount>,
+    vehicle_class_map: HashMap<BinnedCountKey, VehicleClassCount>,
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+}
 
-    for count in counts.clone() {
+impl<'a> SpeedAndClassBinner<'a> {
+    pub fn new(interval: TimeInterval, recordnum: u32, directions: &'a Directions) -> Self {
+        Self {
+            interval,
+            recordnum,
+            directions,
+            speed_range_map: HashMap::new(),
+            vehicle_class_map: HashMap::new(),
+            range: None,
+        }
+    }
+
+    /// Get the direction of a lane.
+    fn direction(&self, lane: u8) -> Result<LaneDirection, CountError> {
+        match lane {
+            0 => Err(CountError::BadDirection(
+                "Unable to determine lane/direction.".to_string(),
+            )),
+            _ => self
+                .directions
+                .lane(lane)
+                .ok_or(CountError::MissingDirection),
+        }
+    }
+
+    /// Add a vehicle to the speed range and class counts of its interval.
+    pub fn add(&mut self, count: IndividualVehicle) -> Result<(), CountError> {
         // Get the direction from the lane.
-        let direction = match count.lane {
-            1 => directions.direction1,
-            2 => match directions.direction2 {
-                Some(v) => v,
-                None => return Err(CountError::MissingDirection),
-            },
-            3 => match directions.direction3 {
-                Some(v) => v,
-                None => return Err(CountError::MissingDirection),
-            },
-            _ => {
-                return Err(CountError::BadDirection(
-                    "Unable to determine lane/direction.".to_string(),
-                ))
-            }
-        };
+        let direction = self.direction(count.lane)?;
 
         // Create a key for the Hashmap for time intervals
-        let time_part = bin_time(count.time.time(), interval);
+        let time_part = bin_time(count.time.time(), self.interval);
         let key = BinnedCountKey {
             date: count.date,
             time: NaiveDateTime::new(count.date, time_part),
@@ -825,140 +1059,127 @@ pub fn create_speed_and_class_count(
         };
 
         // Add new entry to 15-min speed range map or increment existing one.
-        speed_range_map
+        self.speed_range_map
             .entry(key)
             .and_modify(|c| c.insert(count.speed))
-            .or_insert(SpeedRangeCount::first(recordnum, direction, count.speed));
+            .or_insert(SpeedRangeCount::first(
+                self.recordnum,
+                direction,
+                count.speed,
+            ));
 
         // Add new entry to 15-min vehicle class map or increment existing one.
-        vehicle_class_map
+        self.vehicle_class_map
             .entry(key)
             .and_modify(|c| c.insert(count.class.clone()))
-            .or_insert(VehicleClassCount::first(recordnum, direction, count.class));
-    }
+            .or_insert(VehicleClassCount::first(
+                self.recordnum,
+                direction,
+                count.class,
+            ));
 
-    /*
-      If there was some time period (whose length is `TimeInterval`) where no vehicle was counted,
-      there will be no corresponding entry in our HashMap for it. However, that's because of the
-      data we are using - `IndividualVehicle`s, which are vehicles that were counted - not because
-      there is missing data for that time period. So create those where necessary.
-    */
+        extend_range(
+            &mut self.range,
+            NaiveDateTime::new(count.date, count.time.time()),
+        );
+        Ok(())
+    }
 
-    // Sort counts by date and time, get range, check if number of records is less than expected
-    // for every period to be included, insert any missing.
-    counts.sort_unstable_by_key(|c| (c.date, c.time.time()));
+    /// Create the speed range and class counts of every interval from the first vehicle to the
+    /// last.
+    pub fn finish(
+        mut self,
+    ) -> Result<
+        (
+            Vec<TimeBinnedSpeedRangeCount>,
+            Vec<TimeBinnedVehicleClassCount>,
+        ),
+        CountError,
+    > {
+        let Some((first_dt, last_dt)) = self.range else {
+            return Ok((vec![], vec![]));
+        };
 
-    let first_dt = NaiveDateTime::new(
-        counts.first().unwrap().date,
-        counts.first().unwrap().time.time(),
-    );
-    let last_dt = NaiveDateTime::new(
-        counts.last().unwrap().date,
-        counts.last().unwrap().time.time(),
-    );
+        /*
+          If there was some time period (whose length is `TimeInterval`) where no vehicle was counted,
+          there will be no corresponding entry in our HashMap for it. However, that's because of the
+          data we are using - `IndividualVehicle`s, which are vehicles that were counted - not because
+          there is missing data for that time period. So create those where necessary.
+        */
+        let all_datetimes = create_time_bins(first_dt, last_dt, self.interval);
 
-    let all_datetimes = create_time_bins(first_dt, last_dt, interval);
+        let mut all_keys = vec![];
+        let all_lanes = self
+            .directions
+            .lanes()
+            .map(|(lane, _)| lane)
+            .collect::<Vec<_>>();
 
-    let mut all_keys = vec![];
-    let all_lanes = if directions.direction3.is_some() {
-        vec![1, 2, 3]
-    } else if directions.direction3.is_none() && directions.direction2.is_some() {
-        vec![1, 2]
-    } else {
-        vec![1]
-    };
+        // construct all possible keys
+        for datetime in all_datetimes {
+            for lane in all_lanes.iter() {
+                all_keys.push(BinnedCountKey {
+                    date: datetime.date(),
+                    time: datetime,
+                    lane: *lane,
+                })
+            }
+        }
+        // Add missing periods for speed range count
+        for key in all_keys {
+            let direction = self.direction(key.lane)?;
+            self.speed_range_map
+                .entry(key)
+                .or_insert(SpeedRangeCount::new(self.recordnum, direction));
+            self.vehicle_class_map
+                .entry(key)
+                .or_insert(VehicleClassCount::new(self.recordnum, direction));
+        }
 
-    // construct all possible keys
-    for datetime in all_datetimes.clone() {
-        for lane in all_lanes.iter() {
-            all_keys.push(BinnedCountKey {
-                date: datetime.date(),
-                time: datetime,
-                lane: *lane,
-            })
+        // Convert speed range count from HashMap to Vec.
+        let mut speed_range_count = vec![];
+        for (key, value) in self.speed_range_map {
+            speed_range_count.push(TimeBinnedSpeedRangeCount {
+                date: key.date,
+                time: key.time,
+                lane: Some(key.lane),
+                recordnum: value.recordnum,
+                direction: Some(value.direction),
+                s1: value.s1,
+                s2: value.s2,
+                s3: value.s3,
+                s4: value.s4,
+                s5: value.s5,
+                s6: value.s6,
+                s7: value.s7,
+                s8: value.s8,
+                s9: value.s9,
+                s10: value.s10,
+                s11: value.s11,
+                s12: value.s12,
+                s13: value.s13,
+                s14: value.s14,
+                total: value.total,
+            });
         }
-    }
-    // Add missing periods for speed range count
-    for key in all_keys {
-        let direction = match key.lane {
-            1 => directions.direction1,
-            2 => match directions.direction2 {
-                Some(v) => v,
-                None => return Err(CountError::MissingDirection),
-            },
-            3 => match directions.direction3 {
-                Some(v) => v,
-                None => return Err(CountError::MissingDirection),
-            },
-            _ => {
-                return Err(CountError::BadDirection(
-                    "Unable to determine lane/direction.".to_string(),
-                ))
-            }
-        };
-        speed_range_map
-            .entry(key)
-            .or_insert(SpeedRangeCount::new(recordnum, direction));
-        vehicle_class_map
-            .entry(key)
-            .or_insert(VehicleClassCount::new(recordnum, direction));
-    }
 
-    // Convert speed range count from HashMap to Vec.
-    let mut speed_range_count = vec![];
-    for (key, value) in speed_range_map {
-        speed_range_count.push(TimeBinnedSpeedRangeCount {
-            date: key.date,
-            time: key.time,
-            lane: Some(key.lane),
-            recordnum: value.recordnum,
-            direction: Some(value.direction),
-            s1: value.s1,
-            s2: value.s2,
-            s3: value.s3,
-            s4: value.s4,
-            s5: value.s5,
-            s6: value.s6,
-            s7: value.s7,
-            s8: value.s8,
-            s9: value.s9,
-            s10: value.s10,
-            s11: value.s11,
-            s12: value.s12,
-            s13: value.s13,
-            s14: value.s14,
-            total: value.total,
-        });
-    }
+        // Convert vehicle class from HashMap to Vec.
+        let vehicle_class_count = self
+            .vehicle_class_map
+            .into_iter()
+            .map(|(key, value)| TimeBinnedVehicleClassCount::from_intermediate(key, value))
+            .collect();
 
-    // Convert vehicle class from HashMap to Vec.
-    let mut vehicle_class_count = vec![];
-    for (key, value) in vehicle_class_map {
-        vehicle_class_count.push(TimeBinnedVehicleClassCount {
-            date: key.date,
-            time: key.time,
-            lane: Some(key.lane),
-            recordnum: value.recordnum,
-            direction: Some(value.direction),
-            c1: value.c1,
-            c2: value.c2,
-            c3: value.c3,
-            c4: value.c4,
-            c5: value.c5,
-            c6: value.c6,
-            c7: value.c7,
-            c8: value.c8,
-            c9: value.c9,
-            c10: value.c10,
-            c11: value.c11,
-            c12: value.c12,
-            c13: value.c13,
-            c15: Some(value.c15),
-            total: value.total,
-        });
+        Ok((speed_range_count, vehicle_class_count))
     }
+}
 
-    Ok((speed_range_count, vehicle_class_count))
+/// Extend a range of datetimes, if necessary, to include another.
+fn extend_range(range: &mut Option<(NaiveDateTime, NaiveDateTime)>, datetime: NaiveDateTime) {
+    *range = match *range {
+        Some((first, last)) => Some((first.min(datetime), last.max(datetime))),
+        None => Some((datetime, datetime)),
+    };
 }
 
 /// Create time-binned bicycle volume count.
@@ -966,12 +1187,8 @@ pub fn create_binned_bicycle_vol_count(
     interval: TimeInterval,
     recordnum: u32,
     directions: &Directions,
-    mut counts: Vec<IndividualBicycle>,
+    counts: impl IntoIterator<Item = IndividualBicycle>,
 ) -> Vec<FifteenMinuteBicycle> {
-    if counts.is_empty() {
-        return vec![];
-    }
-
     // Create key and value structs to put in the hashmap. Together they combine to include
     // all the structs of `FifteenMinuteBicycle`, but we need them separate for the hashmap
     // in order to keep updating them as we go through all the counts.
@@ -983,17 +1200,18 @@ pub fn create_binned_bicycle_vol_count(
     }
 
     let mut count_map: HashMap<DataKey, u16> = HashMap::new();
+    let mut range = None;
 
-    for count in counts.clone() {
+    for count in counts {
         // Create a key for the Hashmap for time intervals
         let direction = match count.lane {
-            1 => directions.direction1,
+            1 => directions.first(),
             2 => {
                 // If there is no 2nd direction in the metata, this is an instance of someone
                 // going in the wrong direction. Use direction1 so that it's not missed.
-                match directions.direction2 {
+                match directions.lane(2) {
                     Some(v) => v,
-                    None => directions.direction1,
+                    None => directions.first(),
                 }
             }
             _ => {
@@ -1011,6 +1229,10 @@ pub fn create_binned_bicycle_vol_count(
         // Insert or update values.
         // Each item is one bicycle counted; whether it's indir or outdir depends on lane.
         count_map.entry(key).and_modify(|c| *c += 1).or_insert(1);
+        extend_range(
+            &mut range,
+            NaiveDateTime::new(count.date, count.time.time()),
+        );
     }
 
     /*
@@ -1020,19 +1242,11 @@ pub fn create_binned_bicycle_vol_count(
       there is missing data for that time period. So create those where necessary.
     */
 
-    // Sort counts by date and time, get range, check if number of records is less than expected
-    // for every period to be included, insert any missing.
-    counts.sort_unstable_by_key(|c| (c.date, c.time.time()));
-
-    let first_dt = NaiveDateTime::new(
-        counts.first().unwrap().date,
-        counts.first().unwrap().time.time(),
-    );
-    let last_dt = NaiveDateTime::new(
-        counts.last().unwrap().date,
-        counts.last().unwrap().time.time(),
-    );
-
+    // Check if number of records is less than expected for every period from the first bicycle
+    // to the last, insert any missing.
+    let Some((first_dt, last_dt)) = range else {
+        return vec![];
+    };
     let all_datetimes = create_time_bins(first_dt, last_dt, interval);
     let mut all_keys = vec![];
 
@@ -1041,9 +1255,9 @@ pub fn create_binned_bicycle_vol_count(
         all_keys.push(DataKey {
             recordnum,
             datetime,
-            direction: directions.direction1,
+            direction: directions.first(),
         });
-        if let Some(v) = directions.direction2 {
+        if let Some(v) = directions.lane(2) {
             all_keys.push(DataKey {
                 recordnum,
                 datetime,
@@ -1081,27 +1295,52 @@ pub enum Weather {
 }
 
 /// Time interval to bin data by.
-#[derive(Clone, Copy)]
+///
+/// Every interval divides a day evenly, so that intervals always start at the same times of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TimeInterval {
-    Hour,
+    FiveMin,
+    TenMin,
     FifteenMin,
+    ThirtyMin,
+    Hour,
+    Day,
 }
 
-/// Bin time by fifteen-minute or hourly intervals by changing the minute.
-pub fn bin_time(time: NaiveTime, interval: TimeInterval) -> NaiveTime {
-    let time = time.with_second(0).unwrap();
-
-    match interval {
-        TimeInterval::Hour => time.with_minute(0).unwrap(),
-        TimeInterval::FifteenMin => {
-            match time.minute() {
-                0..=14 => time.with_minute(0).unwrap(),
-                15..=29 => time.with_minute(15).unwrap(),
-                30..=44 => time.with_minute(30).unwrap(),
-                _ => time.with_minute(45).unwrap(), // minute is always 0-59, so this is 45-59
-            }
+impl TimeInterval {
+    /// All intervals, from shortest to longest.
+    pub const ALL: [TimeInterval; 6] = [
+        TimeInterval::FiveMin,
+        TimeInterval::TenMin,
+        TimeInterval::FifteenMin,
+        TimeInterval::ThirtyMin,
+        TimeInterval::Hour,
+        TimeInterval::Day,
+    ];
+
+    /// The length of the interval, in minutes.
+    pub fn minutes(&self) -> u32 {
+        match self {
+            TimeInterval::FiveMin => 5,
+            TimeInterval::TenMin => 10,
+            TimeInterval::FifteenMin => 15,
+            TimeInterval::ThirtyMin => 30,
+            TimeInterval::Hour => 60,
+            TimeInterval::Day => 1440,
         }
     }
+
+    /// The interval of a length, in minutes, if there is one.
+    pub fn from_minutes(minutes: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.minutes() == minutes)
+    }
+}
+
+/// Bin time by an interval, truncating it to the start of the interval it's in.
+pub fn bin_time(time: NaiveTime, interval: TimeInterval) -> NaiveTime {
+    let minute_of_day = time.hour() * 60 + time.minute();
+    let start = minute_of_day - minute_of_day % interval.minutes();
+    NaiveTime::from_hms_opt(start / 60, start % 60, 0).unwrap()
 }
 
 /// Create all intervals between (and including) a first and last datetime.
@@ -1118,10 +1357,7 @@ pub fn create_time_bins(
 
     let mut current_bin = first_bin;
 
-    let time_to_add = match interval {
-        TimeInterval::Hour => TimeDelta::hours(1),
-        TimeInterval::FifteenMin => TimeDelta::minutes(15),
-    };
+    let time_to_add = TimeDelta::minutes(interval.minutes() as i64);
 
     while current_bin <= last_bin {
         dts.push(current_bin);
@@ -1134,7 +1370,13 @@ pub fn create_time_bins(
 ///
 /// Since db function is fallible, just log any failure with it to stdout/file.
 /// Mostly just a DRY convenience function.
-pub fn log_msg(recordnum: u32, log: impl Log, level: Level, message: &str, conn: &Connection) {
+pub fn log_msg(
+    recordnum: u32,
+    log: impl Log,
+    level: Level,
+    message: &str,
+    store: &impl CountStore,
+) {
     log.log(
         &Record::builder()
             .args(format_args!("{recordnum}: {message}"))
@@ -1144,7 +1386,7 @@ pub fn log_msg(recordnum: u32, log: impl Log, level: Level, message: &str, conn:
 
     // Try to log to database, log to stdout/file if it fails.
     if let Err(e) =
-        db::insert_import_log_entry(conn, ImportLogEntry::new(recordnum, message.into(), level))
+        store.insert_import_log_entry(ImportLogEntry::new(recordnum, message.into(), level))
     {
         log.log(
             &Record::builder()
@@ -1162,8 +1404,9 @@ mod tests {
     use std::path::Path;
 
     use super::*;
-    use crate::non_perm::extract_from_file::Bicycles;
-    use db::{create_pool, get_non_perm_creds};
+    use crate::db::{self, create_pool, get_non_perm_creds};
+    use crate::non_perm::class_scheme::ClassMapping;
+    use crate::test_support::vehicle;
 
     #[test]
     fn time_binning_fifteen_min_is_correct() {
@@ -1241,6 +1484,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn hourly_vehicles_aggregated_from_fifteen_min_counts() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+        let fifteen_min = |time: &str, lane, count| FifteenMinuteVehicle {
+            recordnum: 1,
+            date,
+            time: NaiveDateTime::new(date, NaiveTime::parse_from_str(time, "%H:%M").unwrap()),
+            count,
+            direction: Some(LaneDirection::North),
+            lane,
+        };
+
+        let hourly = HourlyVehicle::from_fifteen_min(
+            1,
+            &[
+                fifteen_min("11:45", Some(2), 5),
+                fifteen_min("11:30", Some(1), 3),
+                fifteen_min("11:45", Some(1), 4),
+                fifteen_min("10:00", Some(1), 1),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            hourly
+                .iter()
+                .map(|c| (c.datetime.hour(), c.lane, c.count))
+                .collect::<Vec<_>>(),
+            vec![(10, 1, 1), (11, 1, 7), (11, 2, 5)]
+        );
+
+        assert!(HourlyVehicle::from_fifteen_min(1, &[fifteen_min("11:45", None, 5)]).is_err());
+    }
+
+    #[test]
+    fn time_binning_other_intervals_is_correct() {
+        let time = NaiveTime::from_hms_opt(10, 29, 59).unwrap();
+        let binned = |interval| bin_time(time, interval).format("%H:%M").to_string();
+        assert_eq!(binned(TimeInterval::FiveMin), "10:25");
+        assert_eq!(binned(TimeInterval::TenMin), "10:20");
+        assert_eq!(binned(TimeInterval::ThirtyMin), "10:00");
+        assert_eq!(binned(TimeInterval::Day), "00:00");
+        assert_eq!(
+            bin_time(
+                NaiveTime::from_hms_opt(10, 30, 0).unwrap(),
+                TimeInterval::ThirtyMin
+            ),
+            NaiveTime::from_hms_opt(10, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn intervals_from_minutes() {
+        for interval in TimeInterval::ALL {
+            assert_eq!(1440 % interval.minutes(), 0);
+            assert_eq!(
+                TimeInterval::from_minutes(interval.minutes()),
+                Some(interval)
+            );
+        }
+        assert_eq!(TimeInterval::from_minutes(20), None);
+    }
+
     #[test]
     fn create_time_bins_correct() {
         let first_dt = NaiveDateTime::parse_from_str("2024-04-08 7:00", "%Y-%m-%d %-H:%M").unwrap();
@@ -1277,6 +1582,22 @@ mod tests {
         let keys_hour = create_time_bins(first_dt, last_dt, TimeInterval::Hour);
         assert_eq!(keys_15.len(), 96);
         assert_eq!(keys_hour.len(), 24);
+        assert_eq!(
+            create_time_bins(first_dt, last_dt, TimeInterval::FiveMin).len(),
+            288
+        );
+        assert_eq!(
+            create_time_bins(first_dt, last_dt, TimeInterval::TenMin).len(),
+            144
+        );
+        assert_eq!(
+            create_time_bins(first_dt, last_dt, TimeInterval::ThirtyMin).len(),
+            48
+        );
+        assert_eq!(
+            create_time_bins(first_dt, last_dt, TimeInterval::Day).len(),
+            1
+        );
 
         let first_dt = NaiveDateTime::parse_from_str("2024-04-08 0:00", "%Y-%m-%d %-H:%M").unwrap();
         let last_dt = NaiveDateTime::parse_from_str("2024-04-09 0:00", "%Y-%m-%d %-H:%M").unwrap();
@@ -1302,10 +1623,88 @@ mod tests {
         assert_eq!(keys_hour.len(), 2);
     }
 
+    #[test]
+    fn directions_map_lanes_from_1() {
+        use LaneDirection::*;
+        assert!(Directions::new(vec![], false).is_err());
+
+        let directions = Directions::new(vec![North, North, South, South, South], false).unwrap();
+        assert_eq!(directions.num_lanes(), 5);
+        assert_eq!(directions.first(), North);
+        assert_eq!(directions.lane(0), None);
+        assert_eq!(directions.lane(5), Some(South));
+        assert_eq!(directions.lane(6), None);
+        assert_eq!(directions.lane_of(South), Some(3));
+        assert_eq!(directions.lane_of(East), None);
+    }
+
+    #[test]
+    fn vehicles_binned_in_lanes_beyond_3() {
+        use LaneDirection::*;
+        let directions = Directions::new(vec![East, East, West, West, West], false).unwrap();
+        let vehicles = vec![
+            vehicle("10:05", 1, 2, 40.0),
+            vehicle("10:20", 5, 2, 40.0),
+            vehicle("10:50", 5, 2, 40.0),
+        ];
+
+        let (speed_range, class) = create_speed_and_class_count(
+            TimeInterval::FifteenMin,
+            1,
+            &directions,
+            vehicles.clone(),
+        )
+        .unwrap();
+        // Every lane in every interval.
+        assert_eq!(speed_range.len(), 4 * 5);
+        assert_eq!(class.len(), 4 * 5);
+        let lane5 = class
+            .iter()
+            .filter(|c| c.lane == Some(5))
+            .map(|c| c.total)
+            .sum::<u32>();
+        assert_eq!(lane5, 2);
+        assert!(class
+            .iter()
+            .all(|c| c.direction == c.lane.and_then(|lane| directions.lane(lane))));
+
+        let avg_speed = HourlyAvgSpeed::create(1, directions.clone(), vehicles);
+        assert_eq!(avg_speed.len(), 5);
+    }
+
+    #[test]
+    fn vehicles_in_lane_without_direction_err() {
+        use LaneDirection::*;
+        let directions = Directions::new(vec![East, West], false).unwrap();
+        let vehicles = || vec![vehicle("10:05", 3, 2, 32.0)];
+        let interval = TimeInterval::FifteenMin;
+
+        assert!(create_speed_and_class_count(interval, 1, &directions, vehicles()).is_err());
+        assert!(speed_bins::create_speed_distribution(
+            interval,
+            1,
+            &directions,
+            &[speed_bins::SpeedBinScheme::ten_mph()],
+            vehicles(),
+        )
+        .is_err());
+        assert!(speed_stats::create_speed_stats(1, &directions, None, vehicles()).is_err());
+        assert!(
+            speed_class::create_speed_class_count(interval, 1, &directions, vehicles()).is_err()
+        );
+        assert!(headway::create_headways(
+            1,
+            &directions,
+            headway::HeadwayOptions::default(),
+            vehicles(),
+        )
+        .is_err());
+    }
+
     #[test]
     fn hourly_vol_count_correct_num_records_and_total_count_166905() {
-        let (username, password) = get_non_perm_creds();
-        let pool = create_pool(username, password, 1).unwrap();
+        let db_config = get_non_perm_creds();
+        let pool = create_pool(&db_config, 1).unwrap();
         let conn = pool.get().unwrap();
 
         // two directions, two lanes
@@ -1360,8 +1759,8 @@ mod tests {
 
     #[test]
     fn hourly_vol_count_correct_num_records_and_total_count_165367() {
-        let (username, password) = get_non_perm_creds();
-        let pool = create_pool(username, password, 1).unwrap();
+        let db_config = get_non_perm_creds();
+        let pool = create_pool(&db_config, 1).unwrap();
         let conn = pool.get().unwrap();
 
         // one direction, two lanes
@@ -1414,12 +1813,12 @@ mod tests {
     fn create_hourly_avg_speed_count_166905_is_correct() {
         // two directions, two lanes
         let path = Path::new("test_files/jamar_vehicle/166905.txt");
-        let (username, password) = db::get_non_perm_creds();
-        let pool = db::create_pool(username, password, 1).unwrap();
+        let db_config = db::get_non_perm_creds();
+        let pool = db::create_pool(&db_config, 1).unwrap();
         let conn = pool.get().unwrap();
         let directions = Directions::from_db(166905, &conn).unwrap();
 
-        let counted_vehicles = IndividualVehicle::extract(path, Bicycles::Without).unwrap();
+        let counted_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
         let mut hourly_avg_speed = HourlyAvgSpeed::create(166905, directions, counted_vehicles);
         assert_eq!(hourly_avg_speed.len(), 98);
 
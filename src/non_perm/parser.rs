@@ -0,0 +1,1190 @@
+//! Parse count files into records, via a registry of parsers.
+//!
+//! Each format of file that can be imported has a [`CountFileParser`], which detects whether a
+//! file is of its format, extracts the data from it, and produces the [`Records`] to be stored for
+//! each count in it. The parsers are kept in a [`ParserRegistry`], which determines which parser
+//! to use for a file from the subdirectory it was uploaded to, verified against the file's
+//! contents. Supporting a new format is a matter of implementing `CountFileParser` and
+//! [registering](ParserRegistry::register) it.
+//!
+//! The built-in formats are the variants of [`InputCount`] and [`TurningMovementParser`]; see
+//! [`ParserRegistry::default`].
+
+use std::cell::OnceCell;
+use std::path::Path;
+
+use crate::{
+    db::{
+        crud::{CountSpan, NonPermCrud, DEFAULT_BATCH_SIZE},
+        store::{CountRecord, CountStore},
+    },
+    non_perm::{
+        class_scheme::{ClassMapping, TimeBinnedLengthClassCount},
+        create_binned_bicycle_vol_count,
+        extract_from_file::is_turning_movement_count,
+        extract_from_file::{
+            parent_dir, Confidence, Detection, InputCount, RejectedRow, RowPolicy,
+        },
+        headway::{HeadwayBinner, HeadwayOptions, HourlyGaps, HourlyHeadway},
+        resample::to_fifteen_min,
+        speed_bins::{SpeedBinScheme, SpeedDistributionBinner, TimeBinnedSpeedDistribution},
+        speed_class::{SpeedClassBinner, TimeBinnedSpeedClassCount},
+        speed_stats::{CountSpeedStats, HourlySpeedStats, SpeedStatsBinner},
+        turning_movement::{PeakHour, TurningMovementCount},
+        Directions, FifteenMinuteBicycle, FifteenMinutePedestrian, FifteenMinuteVehicle,
+        HourlyAvgSpeed, HourlyAvgSpeedBinner, HourlyVehicle, IndividualBicycle, IndividualVehicle,
+        Metadata, NonPermCountKind, SpeedAndClassBinner, TimeBinnedSpeedRangeCount,
+        TimeBinnedVehicleClassCount, TimeInterval,
+    },
+    CountError,
+};
+
+/// A format of count file that can be imported.
+pub trait CountFileParser {
+    /// A short name for the format, unique within a [`ParserRegistry`], used in logs.
+    fn name(&self) -> &'static str;
+
+    /// The subdirectory (of the directory watched for files) that files of this format are
+    /// uploaded to.
+    fn directory(&self) -> &'static str;
+
+    /// The number of counts (recordnums) that a file of this format contains data for.
+    fn num_counts(&self) -> usize {
+        1
+    }
+
+    /// Determine whether a file is of this format, and with what [`Confidence`].
+    ///
+    /// `None` means that this parser cannot extract data from the file.
+    fn detect(&self, file: &FileToDetect) -> Result<Option<Confidence>, CountError>;
+
+    /// Extract data from a file and produce the records of each count it contains data for.
+    ///
+    /// `counts` are in the order of the recordnums in the filename; there will be
+    /// [`num_counts`](CountFileParser::num_counts) of them.
+    fn extract(
+        &self,
+        path: &Path,
+        counts: &[CountInfo],
+        options: &ExtractOptions,
+    ) -> Result<Vec<ParsedCount>, CountError>;
+}
+
+/// Options for [extracting](CountFileParser::extract) data from a file.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// How rows that can't be extracted are handled.
+    pub policy: RowPolicy,
+    /// Alternative schemes to create [`TimeBinnedSpeedDistribution`]s for, in addition to the
+    /// speed range counts, from counts of individual vehicles.
+    pub speed_bins: Vec<SpeedBinScheme>,
+    /// Thresholds for the [`HourlyHeadway`]s and [`HourlyGaps`] created from counts of individual
+    /// vehicles.
+    pub headway: HeadwayOptions,
+    /// How class numbers in files of individual vehicles and bicycles are interpreted; if
+    /// `None`, by the built-in "Modified Scheme F" mapping, with bikes if the file includes
+    /// them.
+    pub classes: Option<ClassMapping>,
+}
+
+impl ExtractOptions {
+    /// The [`ClassMapping`] to use for a file, depending on whether it includes bicycles.
+    fn classes(&self, bicycles: bool) -> ClassMapping {
+        match (&self.classes, bicycles) {
+            (Some(v), _) => v.clone(),
+            (None, false) => ClassMapping::modified_scheme_f(),
+            (None, true) => ClassMapping::modified_scheme_f_with_bikes(),
+        }
+    }
+}
+
+/// A file whose format is being [detected](CountFileParser::detect), given the options it would
+/// be extracted with (e.g. the classes that are bicycles).
+///
+/// What is detected from its contents is cached, so that the file is only read once however many
+/// parsers check it.
+pub struct FileToDetect<'a> {
+    pub path: &'a Path,
+    pub options: &'a ExtractOptions,
+    input_count: OnceCell<Option<Detection>>,
+}
+
+impl<'a> FileToDetect<'a> {
+    pub fn new(path: &'a Path, options: &'a ExtractOptions) -> Self {
+        Self {
+            path,
+            options,
+            input_count: OnceCell::new(),
+        }
+    }
+
+    /// The [`InputCount`] detected from the file's contents, or `None` if its header isn't that
+    /// of any of them.
+    pub fn input_count(&self) -> Result<Option<Detection>, CountError> {
+        if let Some(detection) = self.input_count.get() {
+            return Ok(*detection);
+        }
+        let detection = match InputCount::detect(self.path, &self.options.classes(true)) {
+            Ok(v) => Some(v),
+            Err(CountError::BadHeader(_)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(*self.input_count.get_or_init(|| detection))
+    }
+}
+
+/// What is known about a count prior to extracting its data.
+#[derive(Debug, Clone)]
+pub struct CountInfo {
+    pub recordnum: u32,
+    pub metadata: Metadata,
+    /// The lane directions, if they have been set; not all kinds of counts need them.
+    pub directions: Option<Directions>,
+}
+
+impl CountInfo {
+    /// Get the info of a count from a [`CountStore`].
+    pub fn from_store(recordnum: u32, store: &impl CountStore) -> Result<Self, CountError> {
+        Ok(Self {
+            recordnum,
+            metadata: store.get_metadata(recordnum)?,
+            directions: match store.get_directions(recordnum) {
+                Ok(v) => Some(v),
+                Err(CountError::MissingDirection) => None,
+                Err(e) => return Err(e),
+            },
+        })
+    }
+
+    /// Get the lane directions, or err if they haven't been set.
+    pub fn directions(&self) -> Result<&Directions, CountError> {
+        self.directions.as_ref().ok_or(CountError::MissingDirection)
+    }
+
+    /// Err if the kind of count in the metadata isn't `expected`.
+    pub fn expect_count_kind(&self, expected: NonPermCountKind) -> Result<(), CountError> {
+        if self.metadata.count_kind.as_ref() != Some(&expected) {
+            return Err(CountError::IncorrectCountKind {
+                recordnum: self.recordnum,
+                expected,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The records produced from a file for one count.
+#[derive(Debug, Clone)]
+pub struct ParsedCount {
+    pub recordnum: u32,
+    pub records: Vec<Records>,
+    /// Whether AADV should be calculated for the count once its records are stored.
+    pub calc_aadv: bool,
+    /// Rows of the file rejected while extracting the count's data.
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl ParsedCount {
+    /// Aggregate the count's motor vehicle volumes by hour, if it has any at a finer interval.
+    pub fn hourly_vehicles(&self) -> Result<Option<Vec<HourlyVehicle>>, CountError> {
+        for records in &self.records {
+            match records {
+                Records::FifteenMinuteVehicle(v) => {
+                    return HourlyVehicle::from_fifteen_min(self.recordnum, v).map(Some)
+                }
+                Records::VehicleClass(v) => {
+                    return HourlyVehicle::from_class_counts(self.recordnum, v).map(Some)
+                }
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Add the count's [hourly volumes](Self::hourly_vehicles) to its records, if it has any,
+    /// and [verify](Self::verify_totals) that all of its motor vehicle volumes agree.
+    ///
+    /// This derives [`HourlyVehicle`]s from the same records that will be stored, rather than
+    /// from what was stored, so that it can be done before anything is.
+    pub fn add_hourly(&mut self) -> Result<(), CountError> {
+        if !self
+            .records
+            .iter()
+            .any(|records| matches!(records, Records::HourlyVehicle(_)))
+        {
+            if let Some(hourly) = self.hourly_vehicles()? {
+                self.records.push(Records::HourlyVehicle(hourly));
+            }
+        }
+        self.verify_totals()
+    }
+
+    /// Verify that every table of motor vehicle volumes has the same total volume.
+    pub fn verify_totals(&self) -> Result<(), CountError> {
+        let mut totals = self
+            .records
+            .iter()
+            .filter_map(|records| Some((records.table(), records.vehicle_total()?)));
+        let Some((expected_table, expected)) = totals.next() else {
+            return Ok(());
+        };
+        for (table, total) in totals {
+            if total != expected {
+                return Err(CountError::VolumeTotalMisMatch {
+                    table,
+                    total,
+                    expected_table,
+                    expected,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records of one of the count tables.
+#[derive(Debug, Clone)]
+pub enum Records {
+    CountSpeedStats(Vec<CountSpeedStats>),
+    FifteenMinuteBicycle(Vec<FifteenMinuteBicycle>),
+    FifteenMinutePedestrian(Vec<FifteenMinutePedestrian>),
+    FifteenMinuteVehicle(Vec<FifteenMinuteVehicle>),
+    Gaps(Vec<HourlyGaps>),
+    Headway(Vec<HourlyHeadway>),
+    HourlyAvgSpeed(Vec<HourlyAvgSpeed>),
+    HourlyVehicle(Vec<HourlyVehicle>),
+    LengthClass(Vec<TimeBinnedLengthClassCount>),
+    PeakHour(Vec<PeakHour>),
+    SpeedClass(Vec<TimeBinnedSpeedClassCount>),
+    SpeedDistribution(Vec<TimeBinnedSpeedDistribution>),
+    SpeedRange(Vec<TimeBinnedSpeedRangeCount>),
+    SpeedStats(Vec<HourlySpeedStats>),
+    TurningMovement(Vec<TurningMovementCount>),
+    VehicleClass(Vec<TimeBinnedVehicleClassCount>),
+}
+
+impl Records {
+    /// The table the records belong in.
+    pub fn table(&self) -> &'static str {
+        match self {
+            Records::CountSpeedStats(_) => CountSpeedStats::COUNT_TABLE,
+            Records::FifteenMinuteBicycle(_) => FifteenMinuteBicycle::COUNT_TABLE,
+            Records::FifteenMinutePedestrian(_) => FifteenMinutePedestrian::COUNT_TABLE,
+            Records::FifteenMinuteVehicle(_) => FifteenMinuteVehicle::COUNT_TABLE,
+            Records::Gaps(_) => HourlyGaps::COUNT_TABLE,
+            Records::Headway(_) => HourlyHeadway::COUNT_TABLE,
+            Records::HourlyAvgSpeed(_) => HourlyAvgSpeed::COUNT_TABLE,
+            Records::HourlyVehicle(_) => HourlyVehicle::COUNT_TABLE,
+            Records::LengthClass(_) => TimeBinnedLengthClassCount::COUNT_TABLE,
+            Records::PeakHour(_) => PeakHour::COUNT_TABLE,
+            Records::SpeedClass(_) => TimeBinnedSpeedClassCount::COUNT_TABLE,
+            Records::SpeedDistribution(_) => TimeBinnedSpeedDistribution::COUNT_TABLE,
+            Records::SpeedRange(_) => TimeBinnedSpeedRangeCount::COUNT_TABLE,
+            Records::SpeedStats(_) => HourlySpeedStats::COUNT_TABLE,
+            Records::TurningMovement(_) => TurningMovementCount::COUNT_TABLE,
+            Records::VehicleClass(_) => TimeBinnedVehicleClassCount::COUNT_TABLE,
+        }
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        match self {
+            Records::CountSpeedStats(v) => v.len(),
+            Records::FifteenMinuteBicycle(v) => v.len(),
+            Records::FifteenMinutePedestrian(v) => v.len(),
+            Records::FifteenMinuteVehicle(v) => v.len(),
+            Records::Gaps(v) => v.len(),
+            Records::Headway(v) => v.len(),
+            Records::HourlyAvgSpeed(v) => v.len(),
+            Records::HourlyVehicle(v) => v.len(),
+            Records::LengthClass(v) => v.len(),
+            Records::PeakHour(v) => v.len(),
+            Records::SpeedClass(v) => v.len(),
+            Records::SpeedDistribution(v) => v.len(),
+            Records::SpeedRange(v) => v.len(),
+            Records::SpeedStats(v) => v.len(),
+            Records::TurningMovement(v) => v.len(),
+            Records::VehicleClass(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The total volume of motor vehicles, if these are records of every motor vehicle counted.
+    pub fn vehicle_total(&self) -> Option<u64> {
+        fn sum<T>(counts: &[T], volume: impl Fn(&T) -> u32) -> Option<u64> {
+            Some(counts.iter().map(|c| volume(c) as u64).sum())
+        }
+        match self {
+            Records::FifteenMinuteVehicle(v) => sum(v, |c| c.count as u32),
+            Records::HourlyVehicle(v) => sum(v, |c| c.count),
+            Records::LengthClass(v) => sum(v, |c| c.total),
+            Records::SpeedClass(v) => sum(v, |c| c.total),
+            Records::SpeedRange(v) => sum(v, |c| c.total),
+            Records::VehicleClass(v) => sum(v, |c| c.total),
+            _ => None,
+        }
+    }
+
+    /// Replace all of a count's records in the table with these.
+    ///
+    /// This is not committed; see [`import_count`](crate::db::unit_of_work::import_count) to
+    /// replace all of a count's records at once.
+    pub fn replace(&self, recordnum: u32, store: &impl CountStore) -> Result<(), CountError> {
+        self.replace_batched(recordnum, store, DEFAULT_BATCH_SIZE)
+    }
+
+    /// Replace all of a count's records in the table with these, sending `batch_size` of them to
+    /// the database at a time.
+    ///
+    /// This is not committed.
+    pub fn replace_batched(
+        &self,
+        recordnum: u32,
+        store: &impl CountStore,
+        batch_size: usize,
+    ) -> Result<(), CountError> {
+        match self {
+            Records::CountSpeedStats(v) => replace(recordnum, v, store, batch_size),
+            Records::FifteenMinuteBicycle(v) => replace(recordnum, v, store, batch_size),
+            Records::FifteenMinutePedestrian(v) => replace(recordnum, v, store, batch_size),
+            Records::FifteenMinuteVehicle(v) => replace(recordnum, v, store, batch_size),
+            Records::Gaps(v) => replace(recordnum, v, store, batch_size),
+            Records::Headway(v) => replace(recordnum, v, store, batch_size),
+            Records::HourlyAvgSpeed(v) => replace(recordnum, v, store, batch_size),
+            Records::HourlyVehicle(v) => replace(recordnum, v, store, batch_size),
+            Records::LengthClass(v) => replace(recordnum, v, store, batch_size),
+            Records::PeakHour(v) => replace(recordnum, v, store, batch_size),
+            Records::SpeedClass(v) => replace(recordnum, v, store, batch_size),
+            Records::SpeedDistribution(v) => replace(recordnum, v, store, batch_size),
+            Records::SpeedRange(v) => replace(recordnum, v, store, batch_size),
+            Records::SpeedStats(v) => replace(recordnum, v, store, batch_size),
+            Records::TurningMovement(v) => replace(recordnum, v, store, batch_size),
+            Records::VehicleClass(v) => replace(recordnum, v, store, batch_size),
+        }
+    }
+
+    /// The datetimes and lanes covered by the records, if there are any.
+    pub fn span(&self) -> Option<CountSpan> {
+        match self {
+            Records::CountSpeedStats(v) => CountSpan::of(v),
+            Records::FifteenMinuteBicycle(v) => CountSpan::of(v),
+            Records::FifteenMinutePedestrian(v) => CountSpan::of(v),
+            Records::FifteenMinuteVehicle(v) => CountSpan::of(v),
+            Records::Gaps(v) => CountSpan::of(v),
+            Records::Headway(v) => CountSpan::of(v),
+            Records::HourlyAvgSpeed(v) => CountSpan::of(v),
+            Records::HourlyVehicle(v) => CountSpan::of(v),
+            Records::LengthClass(v) => CountSpan::of(v),
+            Records::PeakHour(v) => CountSpan::of(v),
+            Records::SpeedClass(v) => CountSpan::of(v),
+            Records::SpeedDistribution(v) => CountSpan::of(v),
+            Records::SpeedRange(v) => CountSpan::of(v),
+            Records::SpeedStats(v) => CountSpan::of(v),
+            Records::TurningMovement(v) => CountSpan::of(v),
+            Records::VehicleClass(v) => CountSpan::of(v),
+        }
+    }
+
+    /// Merge these into a count's records in the table, replacing only those within their
+    /// [span](Self::span), and sending `batch_size` of them to the database at a time.
+    ///
+    /// Returns the number of existing records that were replaced. This is not committed; see
+    /// [`merge_count`](crate::db::unit_of_work::merge_count), which also handles the tables whose
+    /// records aren't of particular times and lanes (e.g. peak hours), and so can't be merged by
+    /// span.
+    pub fn merge_batched(
+        &self,
+        recordnum: u32,
+        store: &impl CountStore,
+        batch_size: usize,
+    ) -> Result<usize, CountError> {
+        match self {
+            Records::CountSpeedStats(v) => merge(recordnum, v, store, batch_size),
+            Records::FifteenMinuteBicycle(v) => merge(recordnum, v, store, batch_size),
+            Records::FifteenMinutePedestrian(v) => merge(recordnum, v, store, batch_size),
+            Records::FifteenMinuteVehicle(v) => merge(recordnum, v, store, batch_size),
+            Records::Gaps(v) => merge(recordnum, v, store, batch_size),
+            Records::Headway(v) => merge(recordnum, v, store, batch_size),
+            Records::HourlyAvgSpeed(v) => merge(recordnum, v, store, batch_size),
+            Records::HourlyVehicle(v) => merge(recordnum, v, store, batch_size),
+            Records::LengthClass(v) => merge(recordnum, v, store, batch_size),
+            Records::PeakHour(v) => merge(recordnum, v, store, batch_size),
+            Records::SpeedClass(v) => merge(recordnum, v, store, batch_size),
+            Records::SpeedDistribution(v) => merge(recordnum, v, store, batch_size),
+            Records::SpeedRange(v) => merge(recordnum, v, store, batch_size),
+            Records::SpeedStats(v) => merge(recordnum, v, store, batch_size),
+            Records::TurningMovement(v) => merge(recordnum, v, store, batch_size),
+            Records::VehicleClass(v) => merge(recordnum, v, store, batch_size),
+        }
+    }
+}
+
+fn replace<T: CountRecord>(
+    recordnum: u32,
+    counts: &[T],
+    store: &impl CountStore,
+    batch_size: usize,
+) -> Result<(), CountError> {
+    store.delete_counts::<T>(recordnum)?;
+    store.insert_counts_batched(counts, batch_size)
+}
+
+fn merge<T: CountRecord>(
+    recordnum: u32,
+    counts: &[T],
+    store: &impl CountStore,
+    batch_size: usize,
+) -> Result<usize, CountError> {
+    let Some(span) = CountSpan::of(counts) else {
+        return Ok(0);
+    };
+    let replaced = store
+        .select_counts::<T>(recordnum)?
+        .iter()
+        .filter(|count| span.contains(*count))
+        .count();
+    store.delete_counts_in::<T>(recordnum, &span)?;
+    store.insert_counts_batched(counts, batch_size)?;
+    Ok(replaced)
+}
+
+/// A collection of [`CountFileParser`]s.
+pub struct ParserRegistry {
+    parsers: Vec<Box<dyn CountFileParser>>,
+}
+
+impl ParserRegistry {
+    /// Create a registry with no parsers.
+    pub fn empty() -> Self {
+        Self { parsers: vec![] }
+    }
+
+    /// Add a parser to the registry, replacing any existing parser with the same name.
+    pub fn register(&mut self, parser: impl CountFileParser + 'static) {
+        self.parsers.retain(|p| p.name() != parser.name());
+        self.parsers.push(Box::new(parser));
+    }
+
+    /// The parsers in the registry.
+    pub fn parsers(&self) -> impl Iterator<Item = &dyn CountFileParser> {
+        self.parsers.iter().map(|p| p.as_ref())
+    }
+
+    /// Get the parser for files uploaded to a subdirectory.
+    pub fn for_directory(&self, directory: &str) -> Option<&dyn CountFileParser> {
+        self.parsers().find(|p| p.directory() == directory)
+    }
+
+    /// Get the parser that detects a file with the highest [`Confidence`].
+    pub fn detect(
+        &self,
+        path: &Path,
+        options: &ExtractOptions,
+    ) -> Result<Option<(&dyn CountFileParser, Confidence)>, CountError> {
+        Ok(best_detection(
+            &self.detect_all(&FileToDetect::new(path, options))?,
+        ))
+    }
+
+    /// Get every parser that detects a file, with its [`Confidence`].
+    fn detect_all(
+        &self,
+        file: &FileToDetect,
+    ) -> Result<Vec<(&dyn CountFileParser, Confidence)>, CountError> {
+        let mut detected = vec![];
+        for parser in self.parsers() {
+            if let Some(confidence) = parser.detect(file)? {
+                detected.push((parser, confidence));
+            }
+        }
+        Ok(detected)
+    }
+
+    /// Get the parser for a file from its location, verified against its contents.
+    ///
+    /// The parser is the one for the subdirectory the file is in. It is an error
+    /// ([`LocationHeaderMisMatch`](CountError::LocationHeaderMisMatch)) if that parser can't
+    /// detect the file, or if another parser detects it with more [`Confidence`], so long as
+    /// that is at least [`Confidence::Medium`].
+    pub fn parser_for(
+        &self,
+        path: &Path,
+        options: &ExtractOptions,
+    ) -> Result<&dyn CountFileParser, CountError> {
+        let directory = parent_dir(path)?;
+        let parser = self
+            .for_directory(directory)
+            .ok_or(CountError::BadLocation(directory.to_string()))?;
+        let detected = self.detect_all(&FileToDetect::new(path, options))?;
+        let confidence = detected
+            .iter()
+            .find(|(p, _)| p.name() == parser.name())
+            .map(|(_, c)| *c);
+        let best = best_detection(&detected);
+
+        match (confidence, best) {
+            (_, None) => Err(CountError::BadHeader(path.to_owned())),
+            (None, Some((detected, _))) => Err(CountError::LocationHeaderMisMatch {
+                path: path.to_owned(),
+                location: parser.name().to_string(),
+                detected: detected.name().to_string(),
+            }),
+            (Some(confidence), Some((detected, best_confidence)))
+                if best_confidence > confidence && best_confidence > Confidence::Low =>
+            {
+                Err(CountError::LocationHeaderMisMatch {
+                    path: path.to_owned(),
+                    location: parser.name().to_string(),
+                    detected: detected.name().to_string(),
+                })
+            }
+            _ => Ok(parser),
+        }
+    }
+}
+
+/// The first of the detections with the highest [`Confidence`].
+fn best_detection<'a>(
+    detected: &[(&'a dyn CountFileParser, Confidence)],
+) -> Option<(&'a dyn CountFileParser, Confidence)> {
+    let mut best: Option<(&dyn CountFileParser, Confidence)> = None;
+    for &(parser, confidence) in detected {
+        if best.is_none_or(|(_, c)| confidence > c) {
+            best = Some((parser, confidence));
+        }
+    }
+    best
+}
+
+impl Default for ParserRegistry {
+    /// A registry of the built-in parsers: one for each [`InputCount`] and the
+    /// [`TurningMovementParser`].
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for input_count in InputCount::ALL {
+            registry.register(input_count);
+        }
+        registry.register(TurningMovementParser);
+        registry
+    }
+}
+
+impl CountFileParser for InputCount {
+    fn name(&self) -> &'static str {
+        match self {
+            InputCount::FifteenMinuteBicycle => "FifteenMinuteBicycle",
+            InputCount::FifteenMinutePedestrian => "FifteenMinutePedestrian",
+            InputCount::FifteenMinuteVehicle => "FifteenMinuteVehicle",
+            InputCount::IndividualVehicle => "IndividualVehicle",
+            InputCount::IndividualBicycle => "IndividualBicycle",
+            InputCount::IndividualVehicleAndIndividualBicycle => {
+                "IndividualVehicleAndIndividualBicycle"
+            }
+            InputCount::ManualClass => "ManualClass",
+        }
+    }
+
+    fn directory(&self) -> &'static str {
+        InputCount::directory(self)
+    }
+
+    fn num_counts(&self) -> usize {
+        match self {
+            InputCount::IndividualVehicleAndIndividualBicycle => 2,
+            _ => 1,
+        }
+    }
+
+    fn detect(&self, file: &FileToDetect) -> Result<Option<Confidence>, CountError> {
+        let Some(detection) = file.input_count()? else {
+            return Ok(None);
+        };
+        if detection.input_count.layout() != self.layout() {
+            Ok(None)
+        } else if detection.input_count == *self {
+            Ok(Some(detection.confidence))
+        } else {
+            // Same layout, but the contents indicate another variant.
+            Ok(Some(Confidence::Low))
+        }
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        counts: &[CountInfo],
+        options: &ExtractOptions,
+    ) -> Result<Vec<ParsedCount>, CountError> {
+        if counts.len() != CountFileParser::num_counts(self) {
+            return Err(CountError::BadPath(path.to_owned()));
+        }
+        let count = &counts[0];
+        let directions = count.directions()?;
+        let policy = options.policy;
+
+        match self {
+            InputCount::IndividualVehicle | InputCount::IndividualVehicleAndIndividualBicycle => {
+                let bicycles = *self == InputCount::IndividualVehicleAndIndividualBicycle;
+                if bicycles {
+                    // Ensure that the count type is correct in the database before doing
+                    // anything. (It's previously been mostly incorrect.)
+                    counts[1].expect_count_kind(NonPermCountKind::Bicycle5)?;
+                }
+                let classes = options.classes(bicycles);
+
+                // Create these counts in a single pass through the file: 15-minute speed count,
+                // 15-minute class count, 15-minute speed by class count, speed averaged by hour,
+                // hourly speed statistics, and hourly headways and gaps - plus speed
+                // distributions, if there are any alternative speed bins.
+                let mut speed_and_class =
+                    SpeedAndClassBinner::new(TimeInterval::FifteenMin, count.recordnum, directions);
+                let mut avg_speed = HourlyAvgSpeedBinner::new(count.recordnum, directions.clone());
+                let mut speed_class =
+                    SpeedClassBinner::new(TimeInterval::FifteenMin, count.recordnum, directions);
+                let mut speed_stats =
+                    SpeedStatsBinner::new(count.recordnum, directions, count.metadata.speedlimit);
+                let mut headway = HeadwayBinner::new(count.recordnum, directions, options.headway);
+                let mut speed_distribution = SpeedDistributionBinner::new(
+                    TimeInterval::FifteenMin,
+                    count.recordnum,
+                    directions,
+                    &options.speed_bins,
+                );
+                let mut individual_vehicles = IndividualVehicle::stream(path, &classes, policy)?;
+                for vehicle in &mut individual_vehicles {
+                    avg_speed.add(&vehicle);
+                    speed_stats.add(&vehicle)?;
+                    speed_class.add(&vehicle)?;
+                    headway.add(&vehicle)?;
+                    if !options.speed_bins.is_empty() {
+                        speed_distribution.add(&vehicle)?;
+                    }
+                    speed_and_class.add(vehicle)?;
+                }
+                let rejected = individual_vehicles.finish()?;
+                let (speed_range_count, vehicle_class_count) = speed_and_class.finish()?;
+                let avg_speed = avg_speed.finish();
+                let (speed_stats, count_speed_stats) = speed_stats.finish();
+                let (speed_class_count, class_speed_stats) = speed_class.finish();
+                let (headways, gaps) = headway.finish();
+
+                let length_class_count =
+                    TimeBinnedLengthClassCount::from_class_counts(&vehicle_class_count)?;
+                let mut records = vec![
+                    Records::VehicleClass(vehicle_class_count),
+                    Records::SpeedRange(speed_range_count),
+                    Records::HourlyAvgSpeed(avg_speed),
+                    Records::SpeedStats(speed_stats),
+                    Records::SpeedClass(speed_class_count),
+                    Records::Headway(headways),
+                    Records::Gaps(gaps),
+                ];
+                if !options.speed_bins.is_empty() {
+                    records.push(Records::SpeedDistribution(speed_distribution.finish()));
+                }
+                records.push(Records::LengthClass(length_class_count));
+                records.push(Records::CountSpeedStats(
+                    count_speed_stats
+                        .into_iter()
+                        .chain(class_speed_stats)
+                        .collect(),
+                ));
+                let mut parsed = vec![ParsedCount {
+                    recordnum: count.recordnum,
+                    records,
+                    calc_aadv: true,
+                    rejected,
+                }];
+
+                if bicycles {
+                    let bicycle_count = &counts[1];
+                    let mut individual_bicycles =
+                        IndividualBicycle::stream(path, &classes, policy)?;
+                    let fifteen_min_volcount = create_binned_bicycle_vol_count(
+                        TimeInterval::FifteenMin,
+                        bicycle_count.recordnum,
+                        bicycle_count.directions()?,
+                        &mut individual_bicycles,
+                    );
+                    parsed.push(ParsedCount {
+                        recordnum: bicycle_count.recordnum,
+                        records: vec![Records::FifteenMinuteBicycle(fifteen_min_volcount)],
+                        // Bicycle counts first require an additional field in the database to
+                        // be set after the import.
+                        calc_aadv: false,
+                        rejected: individual_bicycles.finish()?,
+                    });
+                }
+                Ok(parsed)
+            }
+            InputCount::IndividualBicycle => {
+                count.expect_count_kind(NonPermCountKind::Bicycle5)?;
+                let mut individual_bicycles =
+                    IndividualBicycle::stream(path, &options.classes(true), policy)?;
+                let fifteen_min_volcount = create_binned_bicycle_vol_count(
+                    TimeInterval::FifteenMin,
+                    count.recordnum,
+                    directions,
+                    &mut individual_bicycles,
+                );
+                Ok(vec![ParsedCount {
+                    recordnum: count.recordnum,
+                    records: vec![Records::FifteenMinuteBicycle(fifteen_min_volcount)],
+                    calc_aadv: true,
+                    rejected: individual_bicycles.finish()?,
+                }])
+            }
+            // As these are already binned, they only need to be resampled to 15-minute periods
+            // if binned by another interval.
+            InputCount::FifteenMinuteVehicle => {
+                let extraction = FifteenMinuteVehicle::extract_with_policy(
+                    path,
+                    count.recordnum,
+                    directions,
+                    policy,
+                )?;
+                Ok(vec![ParsedCount {
+                    recordnum: count.recordnum,
+                    records: vec![Records::FifteenMinuteVehicle(to_fifteen_min(
+                        extraction.records,
+                    )?)],
+                    calc_aadv: true,
+                    rejected: extraction.rejected,
+                }])
+            }
+            InputCount::FifteenMinuteBicycle => {
+                let extraction = FifteenMinuteBicycle::extract_with_policy(
+                    path,
+                    count.recordnum,
+                    directions,
+                    policy,
+                )?;
+                Ok(vec![ParsedCount {
+                    recordnum: count.recordnum,
+                    records: vec![Records::FifteenMinuteBicycle(to_fifteen_min(
+                        extraction.records,
+                    )?)],
+                    // Bicycle counts first require an additional field in the database to be
+                    // set after the import.
+                    calc_aadv: false,
+                    rejected: extraction.rejected,
+                }])
+            }
+            InputCount::FifteenMinutePedestrian => {
+                let extraction = FifteenMinutePedestrian::extract_with_policy(
+                    path,
+                    count.recordnum,
+                    directions,
+                    policy,
+                )?;
+                Ok(vec![ParsedCount {
+                    recordnum: count.recordnum,
+                    records: vec![Records::FifteenMinutePedestrian(to_fifteen_min(
+                        extraction.records,
+                    )?)],
+                    calc_aadv: true,
+                    rejected: extraction.rejected,
+                }])
+            }
+            // Tallies are binned into 15-minute periods while extracting.
+            InputCount::ManualClass => {
+                let extraction = TimeBinnedVehicleClassCount::extract_with_policy(
+                    path,
+                    count.recordnum,
+                    directions,
+                    policy,
+                )?;
+                let length_class_count =
+                    TimeBinnedLengthClassCount::from_class_counts(&extraction.records)?;
+                Ok(vec![ParsedCount {
+                    recordnum: count.recordnum,
+                    records: vec![
+                        Records::VehicleClass(extraction.records),
+                        Records::LengthClass(length_class_count),
+                    ],
+                    calc_aadv: true,
+                    rejected: extraction.rejected,
+                }])
+            }
+        }
+    }
+}
+
+/// Parser for turning movement counts, from JAMAR or Miovision.
+///
+/// See [`TurningMovementCount::extract`] for the format of the file. No hourly volumes or AADV
+/// are derived from these counts, but their [`PeakHour`]s are.
+#[derive(Debug, Clone, Copy)]
+pub struct TurningMovementParser;
+
+impl CountFileParser for TurningMovementParser {
+    fn name(&self) -> &'static str {
+        "TurningMovement"
+    }
+
+    fn directory(&self) -> &'static str {
+        "turningmovement"
+    }
+
+    fn detect(&self, file: &FileToDetect) -> Result<Option<Confidence>, CountError> {
+        if is_turning_movement_count(file.path)? {
+            Ok(Some(Confidence::High))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        counts: &[CountInfo],
+        options: &ExtractOptions,
+    ) -> Result<Vec<ParsedCount>, CountError> {
+        if counts.len() != self.num_counts() {
+            return Err(CountError::BadPath(path.to_owned()));
+        }
+        let count = &counts[0];
+        count.expect_count_kind(NonPermCountKind::TurningMovement)?;
+        let extraction =
+            TurningMovementCount::extract_with_policy(path, count.recordnum, options.policy)?;
+        let peak_hours = PeakHour::all(&extraction.records, None);
+        Ok(vec![ParsedCount {
+            recordnum: count.recordnum,
+            records: vec![
+                Records::TurningMovement(extraction.records),
+                Records::PeakHour(peak_hours),
+            ],
+            calc_aadv: false,
+            rejected: extraction.rejected,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::non_perm::LaneDirection;
+
+    fn count_info(recordnum: u32, count_kind: NonPermCountKind) -> CountInfo {
+        CountInfo {
+            recordnum,
+            metadata: Metadata {
+                count_kind: Some(count_kind),
+                ..Default::default()
+            },
+            directions: Some(
+                Directions::new(vec![LaneDirection::North, LaneDirection::South], false).unwrap(),
+            ),
+        }
+    }
+
+    #[test]
+    fn default_registry_has_parser_for_each_input_count() {
+        let registry = ParserRegistry::default();
+        for input_count in InputCount::ALL {
+            let parser = registry.for_directory(input_count.directory()).unwrap();
+            assert_eq!(parser.name(), CountFileParser::name(&input_count));
+        }
+    }
+
+    #[test]
+    fn parser_for_uses_location_when_contents_ambiguous() {
+        let registry = ParserRegistry::default();
+        let parser = registry
+            .parser_for(
+                Path::new("test_files/jamar_vehicle_and_bicycle/178955.csv"),
+                &ExtractOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(parser.name(), "IndividualVehicleAndIndividualBicycle");
+
+        let parser = registry
+            .parser_for(
+                Path::new("test_files/ecocounter_15minutepedestrian/167297.csv"),
+                &ExtractOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(parser.name(), "FifteenMinutePedestrian");
+    }
+
+    #[test]
+    fn header_only_vehicle_file_is_empty_vehicle_count() {
+        let path = Path::new("test_files/jamar_vehicle/header_only.txt");
+        let registry = ParserRegistry::default();
+        let parser = registry
+            .parser_for(path, &ExtractOptions::default())
+            .unwrap();
+        assert_eq!(parser.name(), "IndividualVehicle");
+        let parsed = parser
+            .extract(
+                path,
+                &[count_info(1, NonPermCountKind::Class)],
+                &ExtractOptions::default(),
+            )
+            .unwrap();
+        assert!(parsed[0].records.iter().all(|records| records.is_empty()));
+    }
+
+    #[test]
+    fn parser_for_errs_if_location_and_contents_mismatch() {
+        let registry = ParserRegistry::default();
+        for path in [
+            "test_files/jamar_vehicle/15min_veh_count.txt",
+            "test_files/jamar_15minutevehicle/15min_bicycle_count.txt",
+            "test_files/ecocounter_15minutebicycle/15min_veh_count.txt",
+        ] {
+            assert!(matches!(
+                registry.parser_for(Path::new(path), &ExtractOptions::default()),
+                Err(CountError::LocationHeaderMisMatch { .. })
+            ))
+        }
+    }
+
+    #[test]
+    fn file_to_detect_reads_file_once() {
+        let path = std::env::temp_dir().join("file_to_detect_168193.txt");
+        fs::copy("test_files/jamar_15minutevehicle/168193.txt", &path).unwrap();
+        let options = ExtractOptions::default();
+        let file = FileToDetect::new(&path, &options);
+        let detection = file.input_count().unwrap();
+        assert_eq!(
+            detection.map(|d| d.input_count),
+            Some(InputCount::FifteenMinuteVehicle)
+        );
+        // Every other parser uses what was detected, without reading the file again.
+        fs::remove_file(&path).unwrap();
+        for parser in InputCount::ALL {
+            assert!(parser.detect(&file).is_ok());
+        }
+        assert_eq!(file.input_count().unwrap(), detection);
+    }
+
+    #[test]
+    fn extract_ind_veh_and_bicycle_produces_both_counts() {
+        let parsed = InputCount::IndividualVehicleAndIndividualBicycle
+            .extract(
+                Path::new("test_files/jamar_vehicle_and_bicycle/178959.csv"),
+                &[
+                    count_info(1, NonPermCountKind::Class),
+                    count_info(2, NonPermCountKind::Bicycle5),
+                ],
+                &ExtractOptions::default(),
+            )
+            .unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].recordnum, 1);
+        assert!(parsed[0].verify_totals().is_ok());
+        assert!(parsed[0].hourly_vehicles().unwrap().is_some());
+        assert_eq!(parsed[1].recordnum, 2);
+        assert!(!parsed[1].calc_aadv);
+        assert!(parsed[1].hourly_vehicles().unwrap().is_none());
+        match &parsed[1].records[..] {
+            [Records::FifteenMinuteBicycle(v)] => {
+                assert_eq!(v.iter().map(|c| c.volume as u32).sum::<u32>(), 965)
+            }
+            _ => panic!("expected only bicycle records"),
+        }
+    }
+
+    #[test]
+    fn hourly_volumes_added_and_totals_verified() {
+        let mut parsed = InputCount::IndividualVehicle
+            .extract(
+                Path::new("test_files/jamar_vehicle/166905.txt"),
+                &[count_info(1, NonPermCountKind::Class)],
+                &ExtractOptions::default(),
+            )
+            .unwrap()
+            .remove(0);
+        let num_records = parsed.records.len();
+        parsed.add_hourly().unwrap();
+        // Only added once.
+        parsed.add_hourly().unwrap();
+        assert_eq!(parsed.records.len(), num_records + 1);
+        let Some(Records::HourlyVehicle(hourly)) = parsed.records.last() else {
+            panic!("expected hourly records last")
+        };
+        let total = hourly.iter().map(|c| c.count as u64).sum::<u64>();
+        assert_eq!(parsed.records[0].vehicle_total(), Some(total));
+        let length_class = parsed
+            .records
+            .iter()
+            .find(|r| matches!(r, Records::LengthClass(_)))
+            .unwrap();
+        assert_eq!(length_class.vehicle_total(), Some(total));
+
+        // Lose a vehicle from the speed range counts.
+        let Records::SpeedRange(speed_range) = &mut parsed.records[1] else {
+            panic!("expected speed range records")
+        };
+        let count = speed_range.iter_mut().find(|c| c.total > 0).unwrap();
+        count.total -= 1;
+        assert!(matches!(
+            parsed.verify_totals(),
+            Err(CountError::VolumeTotalMisMatch { expected, total: t, .. })
+                if expected == total && t == total - 1
+        ));
+    }
+
+    #[test]
+    fn extract_ind_veh_keeps_speed_stats_of_whole_count_and_each_class() {
+        let parsed = InputCount::IndividualVehicle
+            .extract(
+                Path::new("test_files/jamar_vehicle/166905.txt"),
+                &[count_info(1, NonPermCountKind::Class)],
+                &ExtractOptions::default(),
+            )
+            .unwrap()
+            .remove(0);
+        let hourly = parsed
+            .records
+            .iter()
+            .find_map(|r| match r {
+                Records::SpeedStats(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        let Some(Records::CountSpeedStats(count_stats)) = parsed.records.last() else {
+            panic!("expected speed stats of the whole count last")
+        };
+        // Of every vehicle, and then of each class.
+        let (count_stats, class_stats) = count_stats.split_first().unwrap();
+        assert_eq!(count_stats.recordnum, 1);
+        assert_eq!(count_stats.class, None);
+        assert_eq!(count_stats.datetime, hourly[0].datetime);
+        assert_eq!(
+            count_stats.volume,
+            hourly.iter().map(|c| c.volume).sum::<u32>()
+        );
+        assert!(count_stats.p50 <= count_stats.p85);
+        assert!(!class_stats.is_empty());
+        assert!(class_stats
+            .iter()
+            .all(|s| s.class.is_some() && s.datetime == count_stats.datetime));
+        assert_eq!(
+            class_stats.iter().map(|s| s.volume).sum::<u32>(),
+            count_stats.volume
+        );
+    }
+
+    #[test]
+    fn extract_ind_veh_with_speed_bins_produces_speed_distribution() {
+        let options = ExtractOptions {
+            speed_bins: vec![SpeedBinScheme::ten_mph()],
+            ..Default::default()
+        };
+        let parsed = InputCount::IndividualVehicle
+            .extract(
+                Path::new("test_files/jamar_vehicle/166905.txt"),
+                &[count_info(1, NonPermCountKind::Class)],
+                &options,
+            )
+            .unwrap();
+
+        let (Records::SpeedRange(speed_range), Some(Records::SpeedDistribution(distribution))) =
+            (&parsed[0].records[1], parsed[0].records.get(7))
+        else {
+            panic!("expected speed range and speed distribution records")
+        };
+        // Every vehicle is in exactly one bin of each.
+        assert_eq!(
+            distribution.iter().map(|d| d.volume).sum::<u32>(),
+            speed_range.iter().map(|c| c.total).sum::<u32>()
+        );
+        assert!(speed_range.iter().all(|c| {
+            [
+                c.s1, c.s2, c.s3, c.s4, c.s5, c.s6, c.s7, c.s8, c.s9, c.s10, c.s11, c.s12, c.s13,
+                c.s14,
+            ]
+            .iter()
+            .sum::<u32>()
+                == c.total
+        }));
+    }
+
+    #[test]
+    fn extract_errs_if_bicycle_count_kind_incorrect() {
+        let parsed = InputCount::IndividualBicycle.extract(
+            Path::new("test_files/jamar_bicycle/181261_include_wrong_way.txt"),
+            &[count_info(1, NonPermCountKind::Bicycle1)],
+            &ExtractOptions::default(),
+        );
+        assert!(matches!(
+            parsed,
+            Err(CountError::IncorrectCountKind {
+                recordnum: 1,
+                expected: NonPermCountKind::Bicycle5
+            })
+        ))
+    }
+
+    #[test]
+    fn registered_parser_replaces_one_with_same_name() {
+        struct Other;
+        impl CountFileParser for Other {
+            fn name(&self) -> &'static str {
+                "FifteenMinuteVehicle"
+            }
+            fn directory(&self) -> &'static str {
+                "other_15minutevehicle"
+            }
+            fn detect(&self, _file: &FileToDetect) -> Result<Option<Confidence>, CountError> {
+                Ok(None)
+            }
+            fn extract(
+                &self,
+                _path: &Path,
+                _counts: &[CountInfo],
+                _options: &ExtractOptions,
+            ) -> Result<Vec<ParsedCount>, CountError> {
+                Ok(vec![])
+            }
+        }
+
+        let mut registry = ParserRegistry::default();
+        registry.register(Other);
+        assert_eq!(registry.parsers().count(), InputCount::ALL.len() + 1);
+        assert!(registry.for_directory("jamar_15minutevehicle").is_none());
+        assert!(registry.for_directory("other_15minutevehicle").is_some());
+    }
+
+    #[test]
+    fn turning_movement_count_detected_and_extracted() {
+        let path = Path::new("test_files/turningmovement/190001.csv");
+        let registry = ParserRegistry::default();
+        let parser = registry
+            .parser_for(path, &ExtractOptions::default())
+            .unwrap();
+        assert_eq!(parser.name(), "TurningMovement");
+
+        let mut count = count_info(1, NonPermCountKind::TurningMovement);
+        count.directions = None;
+        let parsed = parser
+            .extract(path, &[count], &ExtractOptions::default())
+            .unwrap();
+        assert!(!parsed[0].calc_aadv);
+        assert!(parsed[0].hourly_vehicles().unwrap().is_none());
+        match &parsed[0].records[..] {
+            [Records::TurningMovement(counts), Records::PeakHour(peak_hours)] => {
+                assert_eq!(peak_hours, &PeakHour::all(counts, None));
+                assert_eq!(peak_hours[0].approach, None);
+            }
+            _ => panic!("expected turning movement counts and their peak hours"),
+        }
+
+        // Not a TMC.
+        assert!(matches!(
+            registry.parser_for(
+                Path::new("test_files/turningmovement/15min_veh_count.txt"),
+                &ExtractOptions::default()
+            ),
+            Err(CountError::LocationHeaderMisMatch { .. })
+        ));
+    }
+}
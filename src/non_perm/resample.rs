@@ -0,0 +1,330 @@
+//! Resampling pre-binned counts from one interval to another.
+//!
+//! Counters can bin volumes by any [`TimeInterval`], but we store them in 15-minute intervals.
+//! The interval of a pre-binned count is detected from the times of its records
+//! ([`detect_interval`]), and then its records are [resampled](resample) to 15 minutes.
+//!
+//! Shorter intervals are summed into the 15-minute interval they fall in. The volume of an
+//! interval that overlaps more than one 15-minute interval (10-minute, 30-minute, hourly, and
+//! daily intervals) is split among them in proportion to the overlap, assuming that traffic was
+//! uniform throughout it. Volumes are kept whole: each share is rounded down, and the vehicles
+//! left over go to the shares with the largest remainders (the earliest, in case of ties), so the
+//! total volume of a count is unchanged by resampling.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::{
+    non_perm::{
+        bin_time, FifteenMinuteBicycle, FifteenMinutePedestrian, FifteenMinuteVehicle,
+        LaneDirection, TimeInterval,
+    },
+    CountError,
+};
+
+/// A pre-binned count record that can be resampled.
+pub trait Resample: Sized {
+    /// What distinguishes records of the same interval from each other, such as their lane.
+    type Series: Ord + Copy;
+
+    /// The start of the record's interval.
+    fn datetime(&self) -> NaiveDateTime;
+    fn series(&self) -> Self::Series;
+    fn volume(&self) -> u32;
+    /// A copy of the record for another interval and volume.
+    fn rebinned(&self, datetime: NaiveDateTime, volume: u32) -> Result<Self, CountError>;
+}
+
+impl Resample for FifteenMinuteVehicle {
+    type Series = (Option<u8>, Option<LaneDirection>);
+
+    fn datetime(&self) -> NaiveDateTime {
+        NaiveDateTime::new(self.date, self.time.time())
+    }
+
+    fn series(&self) -> Self::Series {
+        (self.lane, self.direction)
+    }
+
+    fn volume(&self) -> u32 {
+        self.count as u32
+    }
+
+    fn rebinned(&self, datetime: NaiveDateTime, volume: u32) -> Result<Self, CountError> {
+        Ok(Self {
+            date: datetime.date(),
+            time: datetime,
+            count: to_u16(volume, datetime)?,
+            ..self.clone()
+        })
+    }
+}
+
+impl Resample for FifteenMinuteBicycle {
+    type Series = LaneDirection;
+
+    fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn series(&self) -> Self::Series {
+        self.cntdir
+    }
+
+    fn volume(&self) -> u32 {
+        self.volume as u32
+    }
+
+    fn rebinned(&self, datetime: NaiveDateTime, volume: u32) -> Result<Self, CountError> {
+        Ok(Self {
+            datetime,
+            volume: to_u16(volume, datetime)?,
+            ..self.clone()
+        })
+    }
+}
+
+impl Resample for FifteenMinutePedestrian {
+    type Series = LaneDirection;
+
+    fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn series(&self) -> Self::Series {
+        self.cntdir
+    }
+
+    fn volume(&self) -> u32 {
+        self.volume as u32
+    }
+
+    fn rebinned(&self, datetime: NaiveDateTime, volume: u32) -> Result<Self, CountError> {
+        Ok(Self {
+            datetime,
+            volume: to_u16(volume, datetime)?,
+            ..self.clone()
+        })
+    }
+}
+
+fn to_u16(volume: u32, datetime: NaiveDateTime) -> Result<u16, CountError> {
+    u16::try_from(volume).map_err(|_| CountError::VolumeTooLarge(volume, datetime))
+}
+
+/// Detect the interval of pre-binned records from the shortest time between any two of them.
+///
+/// Returns `None` if there are fewer than two distinct times, and an error if the shortest time
+/// between them isn't a [`TimeInterval`].
+pub fn detect_interval(
+    datetimes: impl IntoIterator<Item = NaiveDateTime>,
+) -> Result<Option<TimeInterval>, CountError> {
+    let mut datetimes = datetimes.into_iter().collect::<Vec<_>>();
+    datetimes.sort_unstable();
+    datetimes.dedup();
+
+    let Some(shortest) = datetimes.windows(2).map(|w| w[1] - w[0]).min() else {
+        return Ok(None);
+    };
+    if shortest.subsec_nanos() != 0 || shortest.num_seconds() % 60 != 0 {
+        return Err(CountError::BadIntervalCount);
+    }
+    u32::try_from(shortest.num_minutes())
+        .ok()
+        .and_then(TimeInterval::from_minutes)
+        .map(Some)
+        .ok_or(CountError::BadIntervalCount)
+}
+
+/// Resample records binned by one interval to another.
+///
+/// Records are returned ordered by interval and then series, with one record for each interval
+/// and series that any of the original records overlapped.
+pub fn resample<T: Resample>(
+    counts: Vec<T>,
+    from: TimeInterval,
+    to: TimeInterval,
+) -> Result<Vec<T>, CountError> {
+    let from_minutes = from.minutes() as i64;
+    let to_length = TimeDelta::minutes(to.minutes() as i64);
+
+    // The first record of each interval and series, to copy, and its volume.
+    let mut resampled: BTreeMap<(NaiveDateTime, T::Series), (T, u32)> = BTreeMap::new();
+    for count in counts {
+        let start = count.datetime();
+        let end = start + TimeDelta::minutes(from_minutes);
+
+        // The minutes of the record's interval that fall in each interval it overlaps.
+        let mut overlaps = vec![];
+        let mut bin = NaiveDateTime::new(start.date(), bin_time(start.time(), to));
+        while bin < end {
+            let overlap = (end.min(bin + to_length) - start.max(bin)).num_minutes();
+            overlaps.push((bin, overlap));
+            bin += to_length;
+        }
+
+        let shares = split(count.volume(), from_minutes, &overlaps);
+        for ((bin, _), share) in overlaps.into_iter().zip(shares) {
+            match resampled.get_mut(&(bin, count.series())) {
+                Some((_, volume)) => *volume += share,
+                None => {
+                    resampled.insert((bin, count.series()), (count.rebinned(bin, 0)?, share));
+                }
+            }
+        }
+    }
+
+    resampled
+        .into_iter()
+        .map(|((datetime, _), (count, volume))| count.rebinned(datetime, volume))
+        .collect()
+}
+
+/// Resample records to 15-minute intervals, if they aren't already.
+pub fn to_fifteen_min<T: Resample>(counts: Vec<T>) -> Result<Vec<T>, CountError> {
+    match detect_interval(counts.iter().map(|c| c.datetime()))? {
+        None | Some(TimeInterval::FifteenMin) => Ok(counts),
+        Some(interval) => resample(counts, interval, TimeInterval::FifteenMin),
+    }
+}
+
+/// Split a volume into whole shares, in proportion to the minutes of each overlap out of the
+/// total.
+fn split(volume: u32, total: i64, overlaps: &[(NaiveDateTime, i64)]) -> Vec<u32> {
+    let exact = overlaps
+        .iter()
+        .map(|(_, minutes)| volume as i64 * minutes)
+        .collect::<Vec<_>>();
+    let mut shares = exact.iter().map(|v| (v / total) as u32).collect::<Vec<_>>();
+
+    // Give what's left over to the largest remainders, earliest first.
+    let left_over = volume - shares.iter().sum::<u32>();
+    let mut by_remainder = (0..shares.len()).collect::<Vec<_>>();
+    by_remainder.sort_by_key(|i| std::cmp::Reverse(exact[*i] % total));
+    for i in by_remainder.into_iter().take(left_over as usize) {
+        shares[i] += 1;
+    }
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn dt(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2024-04-08 {time}"), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn pedestrians(counts: &[(&str, u16)]) -> Vec<FifteenMinutePedestrian> {
+        counts
+            .iter()
+            .map(|(time, volume)| {
+                FifteenMinutePedestrian::new(1, dt(time), *volume, LaneDirection::North).unwrap()
+            })
+            .collect()
+    }
+
+    fn volumes(counts: &[FifteenMinutePedestrian]) -> Vec<(NaiveDateTime, u16)> {
+        counts.iter().map(|c| (c.datetime, c.volume)).collect()
+    }
+
+    #[test]
+    fn interval_detected_from_shortest_time_between_records() {
+        let times = ["10:00", "10:30", "10:35", "10:35", "11:00"].map(dt);
+        assert_eq!(detect_interval(times).unwrap(), Some(TimeInterval::FiveMin));
+        let times = ["10:00", "11:00", "13:00"].map(dt);
+        assert_eq!(detect_interval(times).unwrap(), Some(TimeInterval::Hour));
+        let times = [dt("10:00"), dt("10:00") + TimeDelta::days(1)];
+        assert_eq!(detect_interval(times).unwrap(), Some(TimeInterval::Day));
+
+        assert_eq!(detect_interval([dt("10:00"), dt("10:00")]).unwrap(), None);
+        assert!(detect_interval(["10:00", "10:07"].map(dt)).is_err());
+        assert!(detect_interval([dt("10:00"), dt("10:15") + TimeDelta::seconds(1)]).is_err());
+    }
+
+    #[test]
+    fn shorter_intervals_summed() {
+        let counts = pedestrians(&[("10:00", 1), ("10:05", 2), ("10:10", 3), ("10:15", 4)]);
+        let resampled = to_fifteen_min(counts).unwrap();
+        assert_eq!(
+            volumes(&resampled),
+            vec![(dt("10:00"), 6), (dt("10:15"), 4)]
+        );
+    }
+
+    #[test]
+    fn overlapping_intervals_split_and_total_kept() {
+        // 10:00-10:10 is all in 10:00, 10:10-10:20 is split evenly between 10:00 and 10:15 (with
+        // the odd vehicle going to the earlier), and 10:20-10:30 is all in 10:15.
+        let counts = pedestrians(&[("10:00", 4), ("10:10", 5), ("10:20", 6)]);
+        let resampled = resample(counts, TimeInterval::TenMin, TimeInterval::FifteenMin).unwrap();
+        assert_eq!(
+            volumes(&resampled),
+            vec![(dt("10:00"), 4 + 3), (dt("10:15"), 2 + 6)]
+        );
+
+        // 10 over four intervals: 2.5 each, with the extra vehicles going to the earliest.
+        let counts = pedestrians(&[("10:00", 10), ("11:00", 3)]);
+        let resampled = to_fifteen_min(counts).unwrap();
+        assert_eq!(
+            volumes(&resampled),
+            vec![
+                (dt("10:00"), 3),
+                (dt("10:15"), 3),
+                (dt("10:30"), 2),
+                (dt("10:45"), 2),
+                (dt("11:00"), 1),
+                (dt("11:15"), 1),
+                (dt("11:30"), 1),
+                (dt("11:45"), 0),
+            ]
+        );
+
+        let counts = pedestrians(&[("00:00", 1000)]);
+        let resampled = resample(counts, TimeInterval::Day, TimeInterval::FifteenMin).unwrap();
+        assert_eq!(resampled.len(), 96);
+        assert_eq!(resampled.iter().map(|c| c.volume as u32).sum::<u32>(), 1000);
+        assert_eq!(resampled[95].datetime, dt("23:45"));
+    }
+
+    #[test]
+    fn vehicles_resampled_by_lane() {
+        let date = NaiveDate::from_ymd_opt(2024, 4, 8).unwrap();
+        let vehicle = |time: &str, lane, count| FifteenMinuteVehicle {
+            recordnum: 1,
+            date,
+            time: dt(time),
+            count,
+            direction: Some(LaneDirection::East),
+            lane: Some(lane),
+        };
+        let counts = vec![
+            vehicle("10:00", 1, 10),
+            vehicle("10:00", 2, 20),
+            vehicle("10:30", 1, 30),
+            vehicle("10:30", 2, 40),
+        ];
+        let resampled = to_fifteen_min(counts).unwrap();
+        assert_eq!(resampled.len(), 8);
+        assert_eq!(
+            resampled
+                .iter()
+                .map(|c| (c.time, c.lane, c.count))
+                .take(4)
+                .collect::<Vec<_>>(),
+            vec![
+                (dt("10:00"), Some(1), 5),
+                (dt("10:00"), Some(2), 10),
+                (dt("10:15"), Some(1), 5),
+                (dt("10:15"), Some(2), 10),
+            ]
+        );
+        assert_eq!(resampled[7].date, date);
+
+        let counts = vec![vehicle("10:00", 1, u16::MAX), vehicle("10:05", 1, 1)];
+        assert!(to_fifteen_min(counts).is_err());
+    }
+}
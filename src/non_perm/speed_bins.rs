@@ -0,0 +1,417 @@
+//! Schemes for binning vehicles by speed, and speed distributions binned by them.
+//!
+//! A [`SpeedBinScheme`] is defined as data: the upper edge of each bin but the last, and a label
+//! for every bin. Bins are half-open intervals, including their upper edge but not their lower
+//! one - e.g. (15, 20] - so that every speed falls in exactly one bin. The default scheme is that
+//! of the speed range counts ([`TimeBinnedSpeedRangeCount`](crate::non_perm::TimeBinnedSpeedRangeCount)):
+//! 0-15 mph, then 5-mph bins up to 75, then 75+.
+//!
+//! Alternative schemes produce a [`TimeBinnedSpeedDistribution`] - one record per interval,
+//! lane, and bin - for when a different breakdown of speeds is needed.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use oracle::RowValue;
+use serde::Deserialize;
+
+use crate::{
+    non_perm::{
+        bin_time, create_time_bins, extend_range, Directions, IndividualVehicle, LaneDirection,
+        TimeInterval,
+    },
+    CountError, GetDate,
+};
+
+/// The upper edges of all but the last bin of the default scheme, in mph.
+pub const DEFAULT_EDGES: [f32; 13] = [
+    15.0, 20.0, 25.0, 30.0, 35.0, 40.0, 45.0, 50.0, 55.0, 60.0, 65.0, 70.0, 75.0,
+];
+
+/// Kilometers per mile.
+const KM_PER_MILE: f32 = 1.609344;
+
+/// The unit of the edges of a [`SpeedBinScheme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedUnit {
+    #[default]
+    Mph,
+    Kph,
+}
+
+impl SpeedUnit {
+    /// Convert a speed in mph (the unit of [`IndividualVehicle::speed`]) to this unit.
+    pub fn from_mph(&self, speed: f32) -> f32 {
+        match self {
+            SpeedUnit::Mph => speed,
+            SpeedUnit::Kph => speed * KM_PER_MILE,
+        }
+    }
+}
+
+impl Display for SpeedUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpeedUnit::Mph => write!(f, "mph"),
+            SpeedUnit::Kph => write!(f, "kph"),
+        }
+    }
+}
+
+/// The index of the bin that a speed falls in, given the upper edges of all but the last bin.
+///
+/// A speed equal to an edge is in the bin below it. Negative speeds (-0.0 has been seen in files)
+/// are in the first bin.
+pub fn bin_index(edges: &[f32], speed: f32) -> usize {
+    edges.partition_point(|edge| *edge < speed)
+}
+
+/// How to bin vehicles by speed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawSpeedBinScheme")]
+pub struct SpeedBinScheme {
+    name: String,
+    unit: SpeedUnit,
+    edges: Vec<f32>,
+    labels: Vec<String>,
+}
+
+/// A [`SpeedBinScheme`] before it has been validated.
+#[derive(Deserialize)]
+struct RawSpeedBinScheme {
+    name: String,
+    #[serde(default)]
+    unit: SpeedUnit,
+    edges: Vec<f32>,
+    labels: Vec<String>,
+}
+
+impl TryFrom<RawSpeedBinScheme> for SpeedBinScheme {
+    type Error = CountError;
+
+    fn try_from(raw: RawSpeedBinScheme) -> Result<Self, Self::Error> {
+        SpeedBinScheme::new(raw.name, raw.unit, raw.edges, raw.labels)
+    }
+}
+
+impl SpeedBinScheme {
+    /// Create a scheme from the upper edges of all but its last bin, and a label for each bin.
+    ///
+    /// Edges must be finite and increasing, and there must be one more label than edges.
+    pub fn new(
+        name: impl Into<String>,
+        unit: SpeedUnit,
+        edges: Vec<f32>,
+        labels: Vec<String>,
+    ) -> Result<Self, CountError> {
+        let name = name.into();
+        if edges.iter().any(|edge| !edge.is_finite()) || !edges.is_sorted_by(|a, b| a < b) {
+            return Err(CountError::BadSpeedBins(format!(
+                "edges of '{name}' must be finite and increasing"
+            )));
+        }
+        if labels.len() != edges.len() + 1 {
+            return Err(CountError::BadSpeedBins(format!(
+                "'{name}' has {} edges, so needs {} labels, not {}",
+                edges.len(),
+                edges.len() + 1,
+                labels.len()
+            )));
+        }
+        Ok(Self {
+            name,
+            unit,
+            edges,
+            labels,
+        })
+    }
+
+    /// Create a scheme of bins of equal width from 0 up to `last`, with one more bin for speeds
+    /// above it, labelled like "20-30" and "80+".
+    pub fn uniform(name: impl Into<String>, unit: SpeedUnit, width: u16, last: u16) -> Self {
+        let edges = (1..=last / width)
+            .map(|i| (i * width) as f32)
+            .collect::<Vec<_>>();
+        let mut labels = (0..last / width)
+            .map(|i| format!("{}-{}", i * width, (i + 1) * width))
+            .collect::<Vec<_>>();
+        labels.push(format!("{}+", last / width * width));
+        Self::new(name, unit, edges, labels).expect("uniform edges are increasing")
+    }
+
+    /// 10-mph bins, from 0 to 80+.
+    pub fn ten_mph() -> Self {
+        Self::uniform("10mph", SpeedUnit::Mph, 10, 80)
+    }
+
+    /// 10-km/h bins, from 0 to 130+.
+    pub fn ten_kph() -> Self {
+        Self::uniform("10kph", SpeedUnit::Kph, 10, 130)
+    }
+
+    /// Get one of the built-in schemes by name: "5mph" (the default), "10mph", or "10kph".
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "5mph" => Some(Self::default()),
+            "10mph" => Some(Self::ten_mph()),
+            "10kph" => Some(Self::ten_kph()),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn unit(&self) -> SpeedUnit {
+        self.unit
+    }
+
+    /// The number of bins.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Always false: there is at least one bin.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The labels of the bins, in order.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// The index of the bin that a speed in mph falls in.
+    pub fn bin(&self, speed: f32) -> usize {
+        bin_index(&self.edges, self.unit.from_mph(speed))
+    }
+}
+
+impl Default for SpeedBinScheme {
+    /// The bins of [`TimeBinnedSpeedRangeCount`](crate::non_perm::TimeBinnedSpeedRangeCount).
+    fn default() -> Self {
+        let mut labels = vec!["0-15".to_string()];
+        for edges in DEFAULT_EDGES.windows(2) {
+            labels.push(format!("{}-{}", edges[0], edges[1]));
+        }
+        labels.push("75+".to_string());
+        Self::new("5mph", SpeedUnit::Mph, DEFAULT_EDGES.to_vec(), labels)
+            .expect("default edges are increasing")
+    }
+}
+
+impl FromStr for SpeedBinScheme {
+    type Err = CountError;
+
+    /// Get a built-in scheme by name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::builtin(s.trim())
+            .ok_or_else(|| CountError::BadSpeedBins(format!("no built-in scheme named '{s}'")))
+    }
+}
+
+/// Volume of vehicles in one speed bin of a [`SpeedBinScheme`], for a time interval and lane.
+///
+/// This is a long-format speed distribution: there is one record for every bin of each interval
+/// and lane, including those with no vehicles.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct TimeBinnedSpeedDistribution {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    #[row_value(rename = "countlane")]
+    pub lane: u8,
+    #[row_value(rename = "cntdir")]
+    pub direction: LaneDirection,
+    /// The name of the [`SpeedBinScheme`].
+    pub scheme: String,
+    /// The position of the bin in the scheme, starting at 1.
+    #[row_value(rename = "binnum")]
+    pub bin_num: u8,
+    /// The label of the bin.
+    #[row_value(rename = "speedbin")]
+    pub bin: String,
+    pub volume: u32,
+}
+
+impl GetDate for TimeBinnedSpeedDistribution {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+/// Create time-binned speed distributions for each scheme from [`IndividualVehicle`]s.
+pub fn create_speed_distribution(
+    interval: TimeInterval,
+    recordnum: u32,
+    directions: &Directions,
+    schemes: &[SpeedBinScheme],
+    counts: impl IntoIterator<Item = IndividualVehicle>,
+) -> Result<Vec<TimeBinnedSpeedDistribution>, CountError> {
+    let mut binner = SpeedDistributionBinner::new(interval, recordnum, directions, schemes);
+    for count in counts {
+        binner.add(&count)?;
+    }
+    Ok(binner.finish())
+}
+
+/// Bins [`IndividualVehicle`]s into the speed distribution of each of a number of schemes as
+/// they are added, one at a time.
+pub struct SpeedDistributionBinner<'a> {
+    interval: TimeInterval,
+    recordnum: u32,
+    directions: &'a Directions,
+    schemes: &'a [SpeedBinScheme],
+    // Volume of each bin of each scheme, by interval and lane.
+    volumes: HashMap<(NaiveDateTime, u8), Vec<Vec<u32>>>,
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+}
+
+impl<'a> SpeedDistributionBinner<'a> {
+    pub fn new(
+        interval: TimeInterval,
+        recordnum: u32,
+        directions: &'a Directions,
+        schemes: &'a [SpeedBinScheme],
+    ) -> Self {
+        Self {
+            interval,
+            recordnum,
+            directions,
+            schemes,
+            volumes: HashMap::new(),
+            range: None,
+        }
+    }
+
+    /// Add a vehicle to the bin of its speed, for each scheme.
+    pub fn add(&mut self, count: &IndividualVehicle) -> Result<(), CountError> {
+        if self.directions.lane(count.lane).is_none() {
+            return Err(CountError::MissingDirection);
+        }
+        let datetime = NaiveDateTime::new(count.date, bin_time(count.time.time(), self.interval));
+        let volumes = self
+            .volumes
+            .entry((datetime, count.lane))
+            .or_insert_with(|| self.schemes.iter().map(|s| vec![0; s.len()]).collect());
+        for (scheme, volumes) in self.schemes.iter().zip(volumes) {
+            volumes[scheme.bin(count.speed)] += 1;
+        }
+        extend_range(
+            &mut self.range,
+            NaiveDateTime::new(count.date, count.time.time()),
+        );
+        Ok(())
+    }
+
+    /// Create the distributions of every interval from the first vehicle to the last.
+    pub fn finish(self) -> Vec<TimeBinnedSpeedDistribution> {
+        let Some((first_dt, last_dt)) = self.range else {
+            return vec![];
+        };
+
+        let mut distributions = vec![];
+        for datetime in create_time_bins(first_dt, last_dt, self.interval) {
+            for (lane, direction) in self.directions.lanes() {
+                let volumes = self.volumes.get(&(datetime, lane));
+                for (i, scheme) in self.schemes.iter().enumerate() {
+                    for (j, label) in scheme.labels().iter().enumerate() {
+                        distributions.push(TimeBinnedSpeedDistribution {
+                            recordnum: self.recordnum,
+                            datetime,
+                            lane,
+                            direction,
+                            scheme: scheme.name().to_string(),
+                            bin_num: j as u8 + 1,
+                            bin: label.clone(),
+                            volume: volumes.map_or(0, |v| v[i][j]),
+                        });
+                    }
+                }
+            }
+        }
+        distributions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::vehicle;
+
+    #[test]
+    fn default_bins_are_half_open() {
+        let scheme = SpeedBinScheme::default();
+        assert_eq!(scheme.len(), 14);
+        assert_eq!(scheme.labels()[1], "15-20");
+        assert_eq!(scheme.bin(-0.0), 0);
+        assert_eq!(scheme.bin(15.0), 0);
+        // Previously fell between bins.
+        assert_eq!(scheme.bin(15.05), 1);
+        assert_eq!(scheme.bin(20.0), 1);
+        assert_eq!(scheme.bin(75.0), 12);
+        assert_eq!(scheme.bin(75.01), 13);
+        assert_eq!(scheme.bin(120.0), 13);
+    }
+
+    #[test]
+    fn kph_bins_convert_speed() {
+        let scheme = SpeedBinScheme::ten_kph();
+        assert_eq!(scheme.labels()[0], "0-10");
+        assert_eq!(scheme.labels().last().unwrap(), "130+");
+        // 30 mph is 48.3 km/h.
+        assert_eq!(scheme.labels()[scheme.bin(30.0)], "40-50");
+    }
+
+    #[test]
+    fn invalid_schemes_err() {
+        assert!(SpeedBinScheme::new("a", SpeedUnit::Mph, vec![20.0, 10.0], vec![]).is_err());
+        assert!(SpeedBinScheme::new(
+            "b",
+            SpeedUnit::Mph,
+            vec![10.0],
+            vec!["slow".to_string(), "fast".to_string(), "faster".to_string()]
+        )
+        .is_err());
+        assert!(SpeedBinScheme::from_str("15mph").is_err());
+        assert_eq!(
+            SpeedBinScheme::from_str("10mph").unwrap(),
+            SpeedBinScheme::ten_mph()
+        );
+    }
+
+    #[test]
+    fn speed_distribution_has_every_interval_lane_and_bin() {
+        use LaneDirection::*;
+        let directions = Directions::new(vec![East, West], false).unwrap();
+        let schemes = [SpeedBinScheme::ten_mph(), SpeedBinScheme::ten_kph()];
+
+        let distributions = create_speed_distribution(
+            TimeInterval::FifteenMin,
+            1,
+            &directions,
+            &schemes,
+            vec![
+                vehicle("10:05", 1, 2, 32.0),
+                vehicle("10:10", 1, 2, 38.5),
+                vehicle("10:35", 2, 2, 55.0),
+            ],
+        )
+        .unwrap();
+        // 3 intervals x 2 lanes x (9 + 14) bins
+        assert_eq!(distributions.len(), 3 * 2 * 23);
+        assert_eq!(distributions.iter().map(|d| d.volume).sum::<u32>(), 6);
+
+        let in_30_40 = distributions
+            .iter()
+            .find(|d| d.scheme == "10mph" && d.bin == "30-40" && d.lane == 1 && d.volume > 0)
+            .unwrap();
+        assert_eq!(in_30_40.volume, 2);
+        assert_eq!(in_30_40.bin_num, 4);
+        assert_eq!(in_30_40.direction, East);
+    }
+}
@@ -0,0 +1,226 @@
+//! Speed by vehicle class: speed range counts of each
+//! [`VehicleClass`](crate::non_perm::VehicleClass), so that how fast trucks
+//! (or any other class) are going can be answered from stored data.
+//!
+//! A [`TimeBinnedSpeedClassCount`] is the cross-tabulation of speed and class for one interval
+//! and lane: the number of vehicles of one class in each of the speed ranges of
+//! [`TimeBinnedSpeedRangeCount`](crate::non_perm::TimeBinnedSpeedRangeCount), along with their
+//! average and 85th percentile speeds. The [`CountSpeedStats`] of each class over the whole count
+//! are also created.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use oracle::RowValue;
+
+use crate::{
+    non_perm::{
+        bin_time,
+        intermediate::SpeedRangeCount,
+        speed_stats::{CountSpeedStats, SpeedStats},
+        Directions, IndividualVehicle, LaneDirection, TimeInterval,
+    },
+    CountError, GetDate,
+};
+
+/// Speed range counts and speeds of one class of vehicle, binned into 15-minute or hourly
+/// intervals.
+///
+/// There is only a record for a class in an interval and lane if a vehicle of that class was
+/// counted in it; see [`TimeBinnedVehicleClassCount`](crate::non_perm::TimeBinnedVehicleClassCount)
+/// for the volume of every class in every interval.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct TimeBinnedSpeedClassCount {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    #[row_value(rename = "countlane")]
+    pub lane: u8,
+    #[row_value(rename = "cntdir")]
+    pub direction: LaneDirection,
+    /// The number of the FHWA [`VehicleClass`](crate::non_perm::VehicleClass).
+    #[row_value(rename = "vehclass")]
+    pub class: u8,
+    pub s1: u32,
+    pub s2: u32,
+    pub s3: u32,
+    pub s4: u32,
+    pub s5: u32,
+    pub s6: u32,
+    pub s7: u32,
+    pub s8: u32,
+    pub s9: u32,
+    pub s10: u32,
+    pub s11: u32,
+    pub s12: u32,
+    pub s13: u32,
+    pub s14: u32,
+    pub total: u32,
+    #[row_value(rename = "avgspeed")]
+    pub avg_speed: f32,
+    #[row_value(rename = "speed85")]
+    pub p85: f32,
+}
+
+impl GetDate for TimeBinnedSpeedClassCount {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+/// Create time-binned speed by class counts, and the [`CountSpeedStats`] of each class over the
+/// whole count, from [`IndividualVehicle`]s.
+pub fn create_speed_class_count(
+    interval: TimeInterval,
+    recordnum: u32,
+    directions: &Directions,
+    counts: impl IntoIterator<Item = IndividualVehicle>,
+) -> Result<(Vec<TimeBinnedSpeedClassCount>, Vec<CountSpeedStats>), CountError> {
+    let mut binner = SpeedClassBinner::new(interval, recordnum, directions);
+    for count in counts {
+        binner.add(&count)?;
+    }
+    Ok(binner.finish())
+}
+
+/// Bins [`IndividualVehicle`]s by interval, lane, class, and speed as they are added, one at a
+/// time.
+pub struct SpeedClassBinner<'a> {
+    interval: TimeInterval,
+    recordnum: u32,
+    directions: &'a Directions,
+    // Speed range count and speeds by interval, lane, and class.
+    counts: HashMap<(NaiveDateTime, u8, u8), (SpeedRangeCount, Vec<f32>)>,
+}
+
+impl<'a> SpeedClassBinner<'a> {
+    pub fn new(interval: TimeInterval, recordnum: u32, directions: &'a Directions) -> Self {
+        Self {
+            interval,
+            recordnum,
+            directions,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Add a vehicle to the speed range count of its class, interval, and lane.
+    pub fn add(&mut self, count: &IndividualVehicle) -> Result<(), CountError> {
+        let direction = self
+            .directions
+            .lane(count.lane)
+            .ok_or(CountError::MissingDirection)?;
+        let datetime = NaiveDateTime::new(count.date, bin_time(count.time.time(), self.interval));
+        let class = count.class.clone() as u8;
+
+        let (speed_range, speeds) = self
+            .counts
+            .entry((datetime, count.lane, class))
+            .or_insert_with(|| (SpeedRangeCount::new(self.recordnum, direction), vec![]));
+        speed_range.insert(count.speed);
+        speeds.push(count.speed);
+        Ok(())
+    }
+
+    /// Create the speed by class counts, ordered by interval, lane, and class, and the
+    /// [`CountSpeedStats`] of each class over the whole count, ordered by class.
+    pub fn finish(self) -> (Vec<TimeBinnedSpeedClassCount>, Vec<CountSpeedStats>) {
+        let Some(first) = self.counts.keys().map(|(datetime, _, _)| *datetime).min() else {
+            return (vec![], vec![]);
+        };
+        let start = NaiveDateTime::new(first.date(), bin_time(first.time(), TimeInterval::Hour));
+
+        let mut class_speeds: BTreeMap<u8, Vec<f32>> = BTreeMap::new();
+        let mut counts = self
+            .counts
+            .into_iter()
+            .map(|((datetime, lane, class), (c, mut speeds))| {
+                let stats = SpeedStats::new(&mut speeds, None).expect("at least one vehicle");
+                class_speeds.entry(class).or_default().append(&mut speeds);
+                TimeBinnedSpeedClassCount {
+                    recordnum: self.recordnum,
+                    datetime,
+                    lane,
+                    direction: c.direction,
+                    class,
+                    s1: c.s1,
+                    s2: c.s2,
+                    s3: c.s3,
+                    s4: c.s4,
+                    s5: c.s5,
+                    s6: c.s6,
+                    s7: c.s7,
+                    s8: c.s8,
+                    s9: c.s9,
+                    s10: c.s10,
+                    s11: c.s11,
+                    s12: c.s12,
+                    s13: c.s13,
+                    s14: c.s14,
+                    total: c.total,
+                    avg_speed: stats.mean,
+                    p85: stats.p85,
+                }
+            })
+            .collect::<Vec<_>>();
+        counts.sort_unstable_by_key(|c| (c.datetime, c.lane, c.class));
+
+        let class_stats = class_speeds
+            .into_iter()
+            .filter_map(|(class, mut speeds)| {
+                SpeedStats::new(&mut speeds, None)
+                    .map(|stats| CountSpeedStats::new(self.recordnum, start, Some(class), stats))
+            })
+            .collect();
+        (counts, class_stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::vehicle;
+
+    #[test]
+    fn speeds_cross_tabulated_by_class() {
+        use LaneDirection::*;
+        let directions = Directions::new(vec![East, West], false).unwrap();
+
+        let (counts, class_stats) = create_speed_class_count(
+            TimeInterval::FifteenMin,
+            1,
+            &directions,
+            vec![
+                vehicle("10:01", 1, 2, 42.0),
+                vehicle("10:02", 1, 9, 52.0),
+                vehicle("10:03", 1, 9, 58.0),
+                vehicle("10:04", 1, 9, 61.0),
+                vehicle("10:20", 2, 9, 48.0),
+                vehicle("10:21", 2, 0, 30.0),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(counts.len(), 4);
+        let trucks = &counts[1];
+        assert_eq!((trucks.lane, trucks.class), (1, 9));
+        assert_eq!(trucks.direction, East);
+        // 50-55, 55-60, 60-65
+        assert_eq!((trucks.s9, trucks.s10, trucks.s11), (1, 1, 1));
+        assert_eq!(trucks.total, 3);
+        assert_eq!(trucks.avg_speed, 57.0);
+        assert_eq!(trucks.p85, 61.0);
+        // Class 0 is unclassified.
+        assert_eq!(counts[3].class, 15);
+
+        assert_eq!(
+            class_stats.iter().map(|s| s.class).collect::<Vec<_>>(),
+            vec![Some(2), Some(9), Some(15)]
+        );
+        let trucks = &class_stats[1];
+        assert_eq!(trucks.datetime, counts[0].datetime);
+        assert_eq!(trucks.volume, 4);
+        assert_eq!(trucks.mean, 54.75);
+        assert_eq!(trucks.p50, 52.0);
+        assert_eq!(trucks.p85, 61.0);
+    }
+}
@@ -0,0 +1,348 @@
+//! Speed statistics: percentile speeds, the 10-mph pace, and the share of vehicles exceeding the
+//! speed limit.
+//!
+//! [`SpeedStats`] are calculated from the speeds of [`IndividualVehicle`]s, both for each hour
+//! and lane of a count ([`HourlySpeedStats`]) and for the count as a whole ([`CountSpeedStats`]),
+//! both of which are stored.
+//!
+//! Percentile speeds use the nearest-rank method: the 85th percentile speed is the lowest speed
+//! at or below which at least 85% of vehicles were travelling. The 10-mph pace is the 10-mph
+//! range, starting on a whole mph, that contains the most vehicles; the earliest is used in case
+//! of ties.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use oracle::RowValue;
+
+use crate::{
+    non_perm::{
+        create_time_bins, extend_range, Directions, IndividualVehicle, LaneDirection, TimeInterval,
+    },
+    CountError, GetDate,
+};
+
+/// The width of the pace, in mph.
+const PACE_WIDTH: f32 = 10.0;
+
+/// Statistics of a set of vehicle speeds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeedStats {
+    pub volume: u32,
+    pub mean: f32,
+    /// 50th percentile (median) speed.
+    pub p50: f32,
+    /// 85th percentile speed.
+    pub p85: f32,
+    /// The lower end of the 10-mph pace.
+    pub pace_start: f32,
+    /// The percentage of vehicles travelling within the pace.
+    pub pace_pct: f32,
+    /// The percentage of vehicles travelling faster than the speed limit, if it is known.
+    pub over_limit_pct: Option<f32>,
+}
+
+impl SpeedStats {
+    /// Calculate statistics of speeds, if there are any.
+    ///
+    /// `speeds` are sorted in place.
+    pub fn new(speeds: &mut [f32], speed_limit: Option<u8>) -> Option<Self> {
+        if speeds.is_empty() {
+            return None;
+        }
+        speeds.sort_unstable_by(f32::total_cmp);
+        let volume = speeds.len();
+        let (pace_start, pace_volume) = pace(speeds);
+
+        Some(Self {
+            volume: volume as u32,
+            mean: speeds.iter().sum::<f32>() / volume as f32,
+            p50: percentile(speeds, 50.0),
+            p85: percentile(speeds, 85.0),
+            pace_start,
+            pace_pct: pct(pace_volume, volume),
+            over_limit_pct: speed_limit.map(|limit| {
+                let over = speeds.len() - speeds.partition_point(|v| *v <= limit as f32);
+                pct(over, volume)
+            }),
+        })
+    }
+}
+
+/// The nearest-rank percentile of sorted, non-empty speeds.
+fn percentile(speeds: &[f32], p: f32) -> f32 {
+    let rank = (p / 100.0 * speeds.len() as f32).ceil() as usize;
+    speeds[rank.clamp(1, speeds.len()) - 1]
+}
+
+/// The start and volume of the 10-mph pace of sorted, non-empty speeds.
+fn pace(speeds: &[f32]) -> (f32, usize) {
+    let first = speeds[0].max(0.0).floor();
+    let last = speeds[speeds.len() - 1].max(0.0).floor();
+
+    let mut best = (first, 0);
+    let mut start = first;
+    while start <= last {
+        let volume = speeds.partition_point(|v| *v < start + PACE_WIDTH)
+            - speeds.partition_point(|v| *v < start);
+        if volume > best.1 {
+            best = (start, volume);
+        }
+        start += 1.0;
+    }
+    best
+}
+
+fn pct(part: usize, whole: usize) -> f32 {
+    part as f32 / whole as f32 * 100.0
+}
+
+/// [`SpeedStats`] of an hour and lane of a count.
+///
+/// The datetime is truncated to the top of the hour - 13:00, 14:00, etc. Hours in which no
+/// vehicles were counted have no statistics.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct HourlySpeedStats {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    #[row_value(rename = "countlane")]
+    pub lane: u8,
+    #[row_value(rename = "cntdir")]
+    pub direction: LaneDirection,
+    pub volume: u32,
+    #[row_value(rename = "speed50")]
+    pub p50: Option<f32>,
+    #[row_value(rename = "speed85")]
+    pub p85: Option<f32>,
+    #[row_value(rename = "pacestart")]
+    pub pace_start: Option<f32>,
+    #[row_value(rename = "pacepct")]
+    pub pace_pct: Option<f32>,
+    #[row_value(rename = "overlimitpct")]
+    pub over_limit_pct: Option<f32>,
+}
+
+impl GetDate for HourlySpeedStats {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+/// [`SpeedStats`] of a whole count, of every vehicle or of one class.
+///
+/// The datetime is the start of the first hour of the count.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct CountSpeedStats {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    /// The number of the FHWA [`VehicleClass`](crate::non_perm::VehicleClass), or `None` for
+    /// every vehicle.
+    #[row_value(rename = "vehclass")]
+    pub class: Option<u8>,
+    pub volume: u32,
+    #[row_value(rename = "avgspeed")]
+    pub mean: f32,
+    #[row_value(rename = "speed50")]
+    pub p50: f32,
+    #[row_value(rename = "speed85")]
+    pub p85: f32,
+    #[row_value(rename = "pacestart")]
+    pub pace_start: f32,
+    #[row_value(rename = "pacepct")]
+    pub pace_pct: f32,
+    #[row_value(rename = "overlimitpct")]
+    pub over_limit_pct: Option<f32>,
+}
+
+impl CountSpeedStats {
+    pub fn new(
+        recordnum: u32,
+        datetime: NaiveDateTime,
+        class: Option<u8>,
+        stats: SpeedStats,
+    ) -> Self {
+        Self {
+            recordnum,
+            datetime,
+            class,
+            volume: stats.volume,
+            mean: stats.mean,
+            p50: stats.p50,
+            p85: stats.p85,
+            pace_start: stats.pace_start,
+            pace_pct: stats.pace_pct,
+            over_limit_pct: stats.over_limit_pct,
+        }
+    }
+}
+
+impl GetDate for CountSpeedStats {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+/// Calculate [`HourlySpeedStats`] and the [`CountSpeedStats`] of every vehicle from
+/// [`IndividualVehicle`]s.
+pub fn create_speed_stats(
+    recordnum: u32,
+    directions: &Directions,
+    speed_limit: Option<u8>,
+    counts: impl IntoIterator<Item = IndividualVehicle>,
+) -> Result<(Vec<HourlySpeedStats>, Option<CountSpeedStats>), CountError> {
+    let mut binner = SpeedStatsBinner::new(recordnum, directions, speed_limit);
+    for count in counts {
+        binner.add(&count)?;
+    }
+    Ok(binner.finish())
+}
+
+/// Collects the speeds of [`IndividualVehicle`]s by hour and lane as they are added, one at a
+/// time, to calculate their statistics.
+pub struct SpeedStatsBinner<'a> {
+    recordnum: u32,
+    directions: &'a Directions,
+    speed_limit: Option<u8>,
+    speeds: HashMap<(NaiveDateTime, u8), Vec<f32>>,
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+}
+
+impl<'a> SpeedStatsBinner<'a> {
+    pub fn new(recordnum: u32, directions: &'a Directions, speed_limit: Option<u8>) -> Self {
+        Self {
+            recordnum,
+            directions,
+            speed_limit,
+            speeds: HashMap::new(),
+            range: None,
+        }
+    }
+
+    /// Add a vehicle's speed to its hour and lane.
+    pub fn add(&mut self, count: &IndividualVehicle) -> Result<(), CountError> {
+        if self.directions.lane(count.lane).is_none() {
+            return Err(CountError::MissingDirection);
+        }
+        let datetime = NaiveDateTime::new(count.date, count.time.time())
+            .with_minute(0)
+            .unwrap()
+            .with_second(0)
+            .unwrap()
+            .with_nanosecond(0)
+            .unwrap();
+        self.speeds
+            .entry((datetime, count.lane))
+            .or_default()
+            .push(count.speed);
+        extend_range(
+            &mut self.range,
+            NaiveDateTime::new(count.date, count.time.time()),
+        );
+        Ok(())
+    }
+
+    /// Calculate the statistics of every hour and lane, from the first vehicle to the last, and
+    /// of the whole count.
+    pub fn finish(mut self) -> (Vec<HourlySpeedStats>, Option<CountSpeedStats>) {
+        let Some((first_dt, last_dt)) = self.range else {
+            return (vec![], None);
+        };
+
+        let hours = create_time_bins(first_dt, last_dt, TimeInterval::Hour);
+        let mut hourly = vec![];
+        let mut all_speeds = vec![];
+        for &datetime in &hours {
+            for (lane, direction) in self.directions.lanes() {
+                let mut speeds = self.speeds.remove(&(datetime, lane)).unwrap_or_default();
+                let stats = SpeedStats::new(&mut speeds, self.speed_limit);
+                hourly.push(HourlySpeedStats {
+                    recordnum: self.recordnum,
+                    datetime,
+                    lane,
+                    direction,
+                    volume: stats.map_or(0, |v| v.volume),
+                    p50: stats.map(|v| v.p50),
+                    p85: stats.map(|v| v.p85),
+                    pace_start: stats.map(|v| v.pace_start),
+                    pace_pct: stats.map(|v| v.pace_pct),
+                    over_limit_pct: stats.and_then(|v| v.over_limit_pct),
+                });
+                all_speeds.append(&mut speeds);
+            }
+        }
+        let count_stats = SpeedStats::new(&mut all_speeds, self.speed_limit)
+            .map(|stats| CountSpeedStats::new(self.recordnum, hours[0], None, stats));
+        (hourly, count_stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::vehicle;
+
+    #[test]
+    fn percentiles_pace_and_over_limit_correct() {
+        let mut speeds = (1..=20).map(|v| v as f32 * 3.0).collect::<Vec<_>>();
+        speeds.reverse();
+        let stats = SpeedStats::new(&mut speeds, Some(45)).unwrap();
+        assert_eq!(stats.volume, 20);
+        assert_eq!(stats.mean, 31.5);
+        // 10th and 17th of 3, 6, ... 60
+        assert_eq!(stats.p50, 30.0);
+        assert_eq!(stats.p85, 51.0);
+        // 3, 6, 9, 12 within 3-13 mph; earliest of several ranges of 4.
+        assert_eq!(stats.pace_start, 3.0);
+        assert_eq!(stats.pace_pct, 20.0);
+        // 48 through 60
+        assert_eq!(stats.over_limit_pct, Some(25.0));
+
+        assert!(SpeedStats::new(&mut [], Some(45)).is_none());
+        let stats = SpeedStats::new(&mut [40.0], None).unwrap();
+        assert_eq!((stats.p50, stats.p85, stats.pace_pct), (40.0, 40.0, 100.0));
+        assert_eq!(stats.over_limit_pct, None);
+    }
+
+    #[test]
+    fn pace_is_range_with_most_vehicles() {
+        let mut speeds = [12.0, 31.5, 33.0, 38.9, 39.0, 40.5, 55.0];
+        let stats = SpeedStats::new(&mut speeds, None).unwrap();
+        // 31.5 through 40.5 are within 31-41 mph, but not 30-40.
+        assert_eq!(stats.pace_start, 31.0);
+        assert!((stats.pace_pct - 500.0 / 7.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn hourly_and_count_stats_created_for_every_hour_and_lane() {
+        use LaneDirection::*;
+        let directions = Directions::new(vec![North, South], false).unwrap();
+
+        let (hourly, count) = create_speed_stats(
+            1,
+            &directions,
+            Some(35),
+            vec![
+                vehicle("10:05", 1, 2, 32.0),
+                vehicle("10:50", 1, 2, 38.0),
+                vehicle("12:15", 2, 2, 44.0),
+            ],
+        )
+        .unwrap();
+        assert_eq!(hourly.len(), 3 * 2);
+        assert_eq!(hourly[0].volume, 2);
+        assert_eq!(hourly[0].p85, Some(38.0));
+        assert_eq!(hourly[0].over_limit_pct, Some(50.0));
+        assert_eq!(hourly[1].direction, South);
+        assert_eq!(hourly[1].volume, 0);
+        assert_eq!(hourly[1].p50, None);
+
+        let count = count.unwrap();
+        assert_eq!(count.datetime, hourly[0].datetime);
+        assert_eq!(count.class, None);
+        assert_eq!(count.volume, 3);
+        assert_eq!(count.p50, 38.0);
+        assert!((count.over_limit_pct.unwrap() - 200.0 / 3.0).abs() < 0.001);
+    }
+}
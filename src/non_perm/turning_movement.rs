@@ -0,0 +1,403 @@
+//! Turning movement counts (TMCs) at intersections, and their peak hour analysis.
+//!
+//! A [`TurningMovementCount`] is the volume of one movement (left, through, right, U-turn) from one
+//! approach of one class of vehicle in a 15-minute interval. See
+//! [`TurningMovementCount::extract`](crate::non_perm::extract_from_file) for the file format.
+//!
+//! [`PeakHour`]s - the hour with the highest volume, and its peak hour factor (PHF) - can be found
+//! for the intersection as a whole or for each approach. Those of the whole count are stored along
+//! with it when it is imported.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use oracle::RowValue;
+
+use crate::{non_perm::LaneDirection, CountError, GetDate};
+
+/// A movement through an intersection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Movement {
+    Left,
+    Through,
+    Right,
+    UTurn,
+}
+
+impl FromStr for Movement {
+    type Err = CountError;
+
+    /// Parse a movement from its name in the database or a column header of a TMC file.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "left" | "l" => Ok(Movement::Left),
+            "through" | "thru" | "t" => Ok(Movement::Through),
+            "right" | "r" => Ok(Movement::Right),
+            "uturn" | "u-turn" | "u turn" | "u" => Ok(Movement::UTurn),
+            _ => Err(CountError::BadMovement(s.to_string())),
+        }
+    }
+}
+
+impl Display for Movement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let movement = match self {
+            Movement::Left => "left",
+            Movement::Through => "through",
+            Movement::Right => "right",
+            Movement::UTurn => "uturn",
+        };
+        write!(f, "{movement}")
+    }
+}
+
+/// Parse an approach - the direction of travel into the intersection - from a column header of a
+/// TMC file, e.g. "Northbound" or "NB".
+pub fn parse_approach(s: &str) -> Result<LaneDirection, CountError> {
+    match s.trim().to_lowercase().as_str() {
+        "northbound" | "nb" => Ok(LaneDirection::North),
+        "eastbound" | "eb" => Ok(LaneDirection::East),
+        "southbound" | "sb" => Ok(LaneDirection::South),
+        "westbound" | "wb" => Ok(LaneDirection::West),
+        _ => Err(CountError::BadDirection(s.to_string())),
+    }
+}
+
+/// Pre-binned, 15-minute volume of one movement from one approach, of one class of vehicle.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct TurningMovementCount {
+    pub recordnum: u32,
+    #[row_value(rename = "countdatetime")]
+    pub datetime: NaiveDateTime,
+    /// The direction of travel when entering the intersection.
+    pub approach: LaneDirection,
+    pub movement: Movement,
+    /// The class of vehicle, as named by the vendor (e.g. "Lights", "Heavy"), or "All" if the
+    /// file did not break down the count by class.
+    #[row_value(rename = "vehclass")]
+    pub vehicle_class: String,
+    pub volume: u16,
+}
+
+impl GetDate for TurningMovementCount {
+    fn get_date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
+}
+
+impl TurningMovementCount {
+    pub fn new(
+        recordnum: u32,
+        datetime: NaiveDateTime,
+        approach: LaneDirection,
+        movement: Movement,
+        vehicle_class: String,
+        volume: u16,
+    ) -> Self {
+        Self {
+            recordnum,
+            datetime,
+            approach,
+            movement,
+            vehicle_class,
+            volume,
+        }
+    }
+}
+
+/// The hour with the highest volume, starting on any 15-minute interval.
+#[derive(Debug, Clone, RowValue, PartialEq)]
+pub struct PeakHour {
+    pub recordnum: u32,
+    /// The approach, or `None` for the intersection as a whole.
+    pub approach: Option<LaneDirection>,
+    /// The start of the first 15-minute interval of the hour.
+    #[row_value(rename = "countdatetime")]
+    pub start: NaiveDateTime,
+    pub volume: u32,
+    /// The highest volume of the four 15-minute intervals in the hour.
+    #[row_value(rename = "peak15min")]
+    pub peak_15_min_volume: u32,
+    /// Peak hour factor: the hour's volume divided by four times its highest 15-minute volume.
+    pub phf: f32,
+    /// The volume of each movement during the hour.
+    pub left: u32,
+    pub through: u32,
+    pub right: u32,
+    pub uturn: u32,
+}
+
+impl GetDate for PeakHour {
+    fn get_date(&self) -> NaiveDate {
+        self.start.date()
+    }
+}
+
+impl PeakHour {
+    /// Find the peak hour of the intersection as a whole, followed by that of each approach.
+    ///
+    /// See [`PeakHour::intersection`] for `period`.
+    pub fn all(
+        counts: &[TurningMovementCount],
+        period: Option<(NaiveDateTime, NaiveDateTime)>,
+    ) -> Vec<Self> {
+        Self::intersection(counts, period)
+            .into_iter()
+            .chain(Self::by_approach(counts, period))
+            .collect()
+    }
+
+    /// Find the peak hour of the intersection as a whole.
+    ///
+    /// If `period` is provided, only intervals starting at or after its first datetime and ending
+    /// at or before its second are considered (e.g. 6:00-10:00 on a day for an AM peak hour).
+    pub fn intersection(
+        counts: &[TurningMovementCount],
+        period: Option<(NaiveDateTime, NaiveDateTime)>,
+    ) -> Option<Self> {
+        let counts = in_period(counts, period);
+        let intervals = intervals(&counts);
+        find_peak_hour(None, &counts, &intervals)
+    }
+
+    /// Find the peak hour of each approach.
+    ///
+    /// See [`PeakHour::intersection`] for `period`.
+    pub fn by_approach(
+        counts: &[TurningMovementCount],
+        period: Option<(NaiveDateTime, NaiveDateTime)>,
+    ) -> Vec<Self> {
+        let counts = in_period(counts, period);
+        let intervals = intervals(&counts);
+        let approaches = counts.iter().map(|c| c.approach).collect::<BTreeSet<_>>();
+
+        approaches
+            .into_iter()
+            .filter_map(|approach| {
+                let counts = counts
+                    .iter()
+                    .filter(|c| c.approach == approach)
+                    .copied()
+                    .collect::<Vec<_>>();
+                find_peak_hour(Some(approach), &counts, &intervals)
+            })
+            .collect()
+    }
+
+    /// The volume of a movement during the hour.
+    pub fn movement_volume(&self, movement: Movement) -> u32 {
+        match movement {
+            Movement::Left => self.left,
+            Movement::Through => self.through,
+            Movement::Right => self.right,
+            Movement::UTurn => self.uturn,
+        }
+    }
+}
+
+/// Filter counts to those in a period.
+fn in_period(
+    counts: &[TurningMovementCount],
+    period: Option<(NaiveDateTime, NaiveDateTime)>,
+) -> Vec<&TurningMovementCount> {
+    counts
+        .iter()
+        .filter(|c| match period {
+            Some((start, end)) => c.datetime >= start && c.datetime + TimeDelta::minutes(15) <= end,
+            None => true,
+        })
+        .collect()
+}
+
+/// All 15-minute intervals in the counts.
+fn intervals(counts: &[&TurningMovementCount]) -> BTreeSet<NaiveDateTime> {
+    counts.iter().map(|c| c.datetime).collect()
+}
+
+/// Find the peak hour of counts, considering only hours made up of four intervals in
+/// `intervals`. The earliest hour is used in case of ties.
+fn find_peak_hour(
+    approach: Option<LaneDirection>,
+    counts: &[&TurningMovementCount],
+    intervals: &BTreeSet<NaiveDateTime>,
+) -> Option<PeakHour> {
+    let mut volume_by_interval: BTreeMap<NaiveDateTime, u32> = BTreeMap::new();
+    for count in counts {
+        *volume_by_interval.entry(count.datetime).or_insert(0) += count.volume as u32;
+    }
+
+    let mut peak: Option<(NaiveDateTime, u32, u32)> = None;
+    for &start in intervals {
+        let hour = (0..4)
+            .map(|i| start + TimeDelta::minutes(15 * i))
+            .collect::<Vec<_>>();
+        if !hour.iter().all(|dt| intervals.contains(dt)) {
+            continue;
+        }
+        let volumes = hour
+            .iter()
+            .map(|dt| volume_by_interval.get(dt).copied().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let volume = volumes.iter().sum::<u32>();
+        if peak.is_none_or(|(_, peak_volume, _)| volume > peak_volume) {
+            peak = Some((start, volume, *volumes.iter().max().unwrap()));
+        }
+    }
+
+    let (start, volume, peak_15_min_volume) = peak?;
+    if volume == 0 {
+        return None;
+    }
+
+    let end = start + TimeDelta::hours(1);
+    let mut movement_volumes: BTreeMap<Movement, u32> = BTreeMap::new();
+    for count in counts
+        .iter()
+        .filter(|c| c.datetime >= start && c.datetime < end)
+    {
+        *movement_volumes.entry(count.movement).or_insert(0) += count.volume as u32;
+    }
+    let movement_volume = |movement| movement_volumes.get(&movement).copied().unwrap_or(0);
+
+    Some(PeakHour {
+        recordnum: counts[0].recordnum,
+        approach,
+        start,
+        volume,
+        peak_15_min_volume,
+        phf: volume as f32 / (4 * peak_15_min_volume) as f32,
+        left: movement_volume(Movement::Left),
+        through: movement_volume(Movement::Through),
+        right: movement_volume(Movement::Right),
+        uturn: movement_volume(Movement::UTurn),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    fn tmc(
+        time: &str,
+        approach: LaneDirection,
+        movement: Movement,
+        volume: u16,
+    ) -> TurningMovementCount {
+        TurningMovementCount::new(
+            1,
+            datetime(&format!("2024-05-14 {time}")),
+            approach,
+            movement,
+            "All".to_string(),
+            volume,
+        )
+    }
+
+    fn datetime(datetime: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn sample() -> Vec<TurningMovementCount> {
+        let mut counts = vec![];
+        for (time, nb, sb) in [
+            ("07:00", 10, 5),
+            ("07:15", 20, 5),
+            ("07:30", 30, 5),
+            ("07:45", 40, 5),
+            ("08:00", 10, 50),
+            ("08:15", 5, 5),
+        ] {
+            counts.push(tmc(time, LaneDirection::North, Movement::Through, nb));
+            counts.push(tmc(time, LaneDirection::North, Movement::Left, 1));
+            counts.push(tmc(time, LaneDirection::South, Movement::Through, sb));
+        }
+        counts
+    }
+
+    #[test]
+    fn movement_from_str_and_display_round_trip() {
+        for movement in [
+            Movement::Left,
+            Movement::Through,
+            Movement::Right,
+            Movement::UTurn,
+        ] {
+            assert_eq!(Movement::from_str(&movement.to_string()).unwrap(), movement);
+        }
+        assert_eq!(Movement::from_str("Thru").unwrap(), Movement::Through);
+        assert_eq!(Movement::from_str("U-Turn").unwrap(), Movement::UTurn);
+        assert!(Movement::from_str("App. Total").is_err());
+    }
+
+    #[test]
+    fn intersection_peak_hour_correct() {
+        let peak = PeakHour::intersection(&sample(), None).unwrap();
+        // 7:15-8:15: nb 20+30+40+10 + 4 lefts, sb 5+5+5+50
+        assert_eq!(
+            peak.start.time(),
+            NaiveTime::from_hms_opt(7, 15, 0).unwrap()
+        );
+        assert_eq!(peak.volume, 169);
+        assert_eq!(peak.peak_15_min_volume, 61);
+        assert!((peak.phf - 169.0 / 244.0).abs() < 0.0001);
+        assert_eq!(peak.movement_volume(Movement::Left), 4);
+        assert_eq!(peak.movement_volume(Movement::UTurn), 0);
+    }
+
+    #[test]
+    fn approach_peak_hours_correct() {
+        let peaks = PeakHour::by_approach(&sample(), None);
+        assert_eq!(peaks.len(), 2);
+
+        let nb = &peaks[0];
+        assert_eq!(nb.approach, Some(LaneDirection::North));
+        assert_eq!(nb.start.time(), NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+        assert_eq!(nb.volume, 104);
+        assert_eq!(nb.peak_15_min_volume, 41);
+
+        let sb = &peaks[1];
+        assert_eq!(sb.approach, Some(LaneDirection::South));
+        assert_eq!(sb.start.time(), NaiveTime::from_hms_opt(7, 15, 0).unwrap());
+        assert_eq!(sb.volume, 65);
+        assert_eq!(sb.phf, 65.0 / 200.0);
+    }
+
+    #[test]
+    fn all_peak_hours_are_intersection_then_approaches() {
+        let peaks = PeakHour::all(&sample(), None);
+        assert_eq!(
+            peaks.iter().map(|p| p.approach).collect::<Vec<_>>(),
+            vec![None, Some(LaneDirection::North), Some(LaneDirection::South)]
+        );
+        assert!(peaks.iter().all(|p| p.recordnum == 1));
+    }
+
+    #[test]
+    fn peak_hour_limited_to_period() {
+        let period = Some((datetime("2024-05-14 07:00"), datetime("2024-05-14 08:00")));
+        let peak = PeakHour::intersection(&sample(), period).unwrap();
+        assert_eq!(peak.start.time(), NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+        assert_eq!(peak.volume, 124);
+
+        // Not a full hour in period.
+        let period = Some((datetime("2024-05-14 07:00"), datetime("2024-05-14 07:45")));
+        assert!(PeakHour::intersection(&sample(), period).is_none());
+    }
+
+    #[test]
+    fn peak_hour_period_can_end_at_midnight() {
+        let counts = ["23:00", "23:15", "23:30", "23:45"]
+            .into_iter()
+            .map(|time| tmc(time, LaneDirection::East, Movement::Through, 10))
+            .collect::<Vec<_>>();
+        let period = Some((datetime("2024-05-14 23:00"), datetime("2024-05-15 00:00")));
+        let peak = PeakHour::intersection(&counts, period).unwrap();
+        assert_eq!(peak.start, datetime("2024-05-14 23:00"));
+        assert_eq!(peak.volume, 40);
+        assert_eq!(peak.phf, 1.0);
+    }
+}
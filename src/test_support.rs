@@ -0,0 +1,59 @@
+//! Fixtures shared by the tests of several modules.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::non_perm::{
+    parser::{ParsedCount, Records},
+    FifteenMinuteVehicle, IndividualVehicle, LaneDirection,
+};
+
+/// The date that [`vehicle`]s are counted on.
+pub fn test_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 1, 3).unwrap()
+}
+
+/// An individual vehicle counted on [`test_date`] at `time` ("%H:%M" or "%H:%M:%S").
+pub fn vehicle(time: &str, lane: u8, class: u8, speed: f32) -> IndividualVehicle {
+    vehicle_on(test_date(), time, lane, class, speed)
+}
+
+/// An individual vehicle counted on `date` at `time` ("%H:%M" or "%H:%M:%S").
+pub fn vehicle_on(
+    date: NaiveDate,
+    time: &str,
+    lane: u8,
+    class: u8,
+    speed: f32,
+) -> IndividualVehicle {
+    let time = NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .unwrap();
+    IndividualVehicle::new(date, NaiveDateTime::new(date, time), lane, class, speed).unwrap()
+}
+
+/// A 15-minute volume count starting at `time` ("%Y-%m-%d %H:%M"), in lane 1 going east or
+/// another lane going west.
+pub fn fifteen_min_vehicle(
+    recordnum: u32,
+    time: &str,
+    count: u16,
+    lane: u8,
+) -> FifteenMinuteVehicle {
+    let dt = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+    let direction = if lane == 1 {
+        LaneDirection::East
+    } else {
+        LaneDirection::West
+    };
+    FifteenMinuteVehicle::new(recordnum, dt.date(), dt, count, Some(direction), Some(lane)).unwrap()
+}
+
+/// A count parsed from a file of 15-minute volume counts, with no rows rejected.
+pub fn parsed_count(recordnum: u32, counts: Vec<FifteenMinuteVehicle>) -> ParsedCount {
+    ParsedCount {
+        recordnum,
+        records: vec![Records::FifteenMinuteVehicle(counts)],
+        calc_aadv: true,
+        rejected: vec![],
+    }
+}
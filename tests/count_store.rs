@@ -0,0 +1,354 @@
+//! Run counts through extraction, storage, and data checks with a [`MemoryStore`], so no database
+//! is needed.
+
+use std::path::Path;
+
+use log::Level;
+use simplelog::{Config, LevelFilter, SimpleLogger};
+
+use traffic_counts::{
+    db::{crud::NonPermCrud, memory_store::MemoryStore, store::CountStore},
+    non_perm::{
+        check_data::{run_checks, run_parsed_checks, CheckConfig, CheckRegistry},
+        check_results::{CheckReport, CheckResultFilter, CheckStage},
+        class_scheme::ClassMapping,
+        parser::{CountInfo, ExtractOptions, ParserRegistry},
+        *,
+    },
+};
+
+fn bidirectional_count(
+    store: &MemoryStore,
+    recordnum: u32,
+    count_kind: NonPermCountKind,
+    dir1: LaneDirection,
+    dir2: LaneDirection,
+) -> Directions {
+    let directions = Directions::new(vec![dir1, dir2], false).unwrap();
+    store.add_count(
+        recordnum,
+        Metadata {
+            count_kind: Some(count_kind),
+            cntdir: Some(RoadDirection::Both),
+            indir: Some(dir1),
+            outdir: Some(dir2),
+            ..Default::default()
+        },
+        directions.clone(),
+    );
+    directions
+}
+
+#[test]
+fn class_count_stored_aggregated_and_checked_166905() {
+    let path = Path::new("test_files/jamar_vehicle/166905.txt");
+    let store = MemoryStore::new();
+    let directions = bidirectional_count(
+        &store,
+        166905,
+        NonPermCountKind::Class,
+        LaneDirection::East,
+        LaneDirection::West,
+    );
+
+    let individual_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
+    let (speed_range_count, vehicle_class_count) = create_speed_and_class_count(
+        TimeInterval::FifteenMin,
+        166905,
+        &directions,
+        individual_vehicles,
+    )
+    .unwrap();
+    store.insert_counts(&speed_range_count).unwrap();
+    store.insert_counts(&vehicle_class_count).unwrap();
+
+    let hourly = store
+        .hourly_volumes(166905, TimeBinnedVehicleClassCount::COUNT_TABLE, "total")
+        .unwrap();
+    store.insert_counts(&hourly).unwrap();
+    store.mark_imported(166905).unwrap();
+
+    // Every vehicle is accounted for in hourly volumes.
+    assert_eq!(hourly.iter().map(|c| c.count).sum::<u32>(), 3943 + 4763);
+    assert_eq!(
+        store
+            .select_counts::<TimeBinnedSpeedRangeCount>(166905)
+            .unwrap()
+            .len(),
+        386
+    );
+    assert!(store.get_metadata(166905).unwrap().importdatadate.is_some());
+
+    let logger = SimpleLogger::new(LevelFilter::Off, Config::default());
+    assert!(run_checks(
+        166905,
+        &store,
+        &logger,
+        &CheckRegistry::default(),
+        &CheckConfig::default()
+    )
+    .is_ok());
+
+    // Deleting a table's records leaves other tables alone.
+    store
+        .delete_counts::<TimeBinnedSpeedRangeCount>(166905)
+        .unwrap();
+    assert!(store
+        .select_counts::<TimeBinnedSpeedRangeCount>(166905)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        store
+            .select_counts::<TimeBinnedVehicleClassCount>(166905)
+            .unwrap()
+            .len(),
+        386
+    );
+}
+
+#[test]
+fn bicycle_consecutive_0_hours_logged_167607() {
+    let path = Path::new("test_files/ecocounter_15minutebicycle/167607.csv");
+    let store = MemoryStore::new();
+    let directions = bidirectional_count(
+        &store,
+        167607,
+        NonPermCountKind::Bicycle2,
+        LaneDirection::North,
+        LaneDirection::South,
+    );
+
+    let counts = FifteenMinuteBicycle::extract(path, 167607, &directions).unwrap();
+    store.insert_counts(&counts).unwrap();
+
+    let logger = SimpleLogger::new(LevelFilter::Off, Config::default());
+    assert_eq!(
+        run_checks(
+            167607,
+            &store,
+            &logger,
+            &CheckRegistry::default(),
+            &CheckConfig::default()
+        )
+        .unwrap(),
+        Level::Warn
+    );
+
+    let log = store.get_import_log(Some(167607)).unwrap();
+    assert!(log
+        .iter()
+        .any(|entry| entry.level == "WARN" && entry.msg.contains("zero volumes")));
+    assert!(store.get_import_log(Some(1)).unwrap().is_empty());
+}
+
+#[test]
+fn bicycle_checked_before_stored_167607() {
+    let path = Path::new("test_files/ecocounter_15minutebicycle/167607.csv");
+    let store = MemoryStore::new();
+    bidirectional_count(
+        &store,
+        167607,
+        NonPermCountKind::Bicycle2,
+        LaneDirection::North,
+        LaneDirection::South,
+    );
+
+    let registry = ParserRegistry::default();
+    let parsed = registry
+        .parser_for(path, &ExtractOptions::default())
+        .unwrap()
+        .extract(
+            path,
+            &[CountInfo::from_store(167607, &store).unwrap()],
+            &ExtractOptions::default(),
+        )
+        .unwrap();
+
+    let logger = SimpleLogger::new(LevelFilter::Off, Config::default());
+    let warnings = run_parsed_checks(
+        &parsed[0],
+        &store,
+        &logger,
+        &CheckRegistry::default(),
+        &CheckConfig::default(),
+    )
+    .unwrap();
+
+    // The same issues are found as once the data is stored, without anything being stored.
+    assert!(warnings.iter().any(|w| w.contains("zero volumes")));
+    assert!(store
+        .select_counts::<FifteenMinuteBicycle>(167607)
+        .unwrap()
+        .is_empty());
+    let log = store.get_import_log(Some(167607)).unwrap();
+    assert_eq!(log.len(), warnings.len());
+    assert!(log
+        .iter()
+        .all(|entry| entry.level == "WARN" && entry.msg.starts_with("Before import")));
+    // The results are stored as those of the parsed data.
+    let results = store
+        .select_check_results(&CheckResultFilter {
+            recordnum: Some(167607),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r.stage == CheckStage::Parsed));
+}
+
+#[test]
+fn check_results_stored_and_reported_167607() {
+    let path = Path::new("test_files/ecocounter_15minutebicycle/167607.csv");
+    let store = MemoryStore::new();
+    let directions = bidirectional_count(
+        &store,
+        167607,
+        NonPermCountKind::Bicycle2,
+        LaneDirection::North,
+        LaneDirection::South,
+    );
+    let counts = FifteenMinuteBicycle::extract(path, 167607, &directions).unwrap();
+    store.insert_counts(&counts).unwrap();
+
+    let logger = SimpleLogger::new(LevelFilter::Off, Config::default());
+    for _ in 0..2 {
+        run_checks(
+            167607,
+            &store,
+            &logger,
+            &CheckRegistry::default(),
+            &CheckConfig::default(),
+        )
+        .unwrap();
+    }
+
+    // Every check's result is stored, not just the warnings, most recent run first.
+    let results = store
+        .select_check_results(&CheckResultFilter::default())
+        .unwrap();
+    assert_eq!(results.len(), 6);
+    assert_eq!(
+        results.iter().map(|r| r.run_id).collect::<Vec<_>>(),
+        vec![2, 2, 2, 1, 1, 1]
+    );
+    assert!(results
+        .iter()
+        .all(|r| r.stage == CheckStage::Stored && r.checked.is_some()));
+
+    let zero_hours = store
+        .select_check_results(&CheckResultFilter {
+            run_id: Some(2),
+            check_id: Some("consecutive zero hours".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(zero_hours.len(), 1);
+    let zero_hours = &zero_hours[0];
+    assert_eq!(zero_hours.severity, "WARN");
+    assert_eq!(zero_hours.threshold, Some(1.0));
+    assert!(zero_hours.measured.unwrap() > 1.0);
+    assert!(!zero_hours.periods.is_empty());
+    assert!(zero_hours.periods.iter().all(|p| p.start < p.end));
+
+    let excessive = store
+        .select_check_results(&CheckResultFilter {
+            check_id: Some("excessive bicycles".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(excessive[0].threshold, Some(20.0));
+    assert_eq!(
+        excessive[0].measured,
+        counts.iter().map(|c| c.volume as f64).reduce(f64::max)
+    );
+    // Results can be filtered by what they measured.
+    assert!(store
+        .select_check_results(&CheckResultFilter {
+            check_id: Some("excessive bicycles".to_string()),
+            min_measured: excessive[0].measured.map(|v| v + 1.0),
+            ..Default::default()
+        })
+        .unwrap()
+        .is_empty());
+
+    let report = CheckReport::new(results);
+    let zero_hours_summary = report
+        .summary
+        .iter()
+        .find(|s| s.check_id == "consecutive zero hours")
+        .unwrap();
+    assert_eq!(zero_hours_summary.results, 2);
+    assert_eq!(zero_hours_summary.warnings, 2);
+    assert_eq!(zero_hours_summary.counts, 1);
+    let json = report.to_json().unwrap();
+    assert!(json.contains("\"check_id\": \"consecutive zero hours\""));
+    assert!(json.contains("\"periods\": ["));
+}
+
+#[test]
+fn aadv_procedures_recorded() {
+    let store = MemoryStore::new();
+    bidirectional_count(
+        &store,
+        1,
+        NonPermCountKind::Volume,
+        LaneDirection::East,
+        LaneDirection::West,
+    );
+
+    store.update_intermediate_aadv(1).unwrap();
+    store.update_setdate(1).unwrap();
+    store.calc_aadv(1).unwrap();
+
+    assert_eq!(
+        store.procedure_calls(),
+        vec![
+            (1, "update_tc_countdate"),
+            (1, "update_setdate"),
+            (1, "calc_aadv")
+        ]
+    );
+}
+
+#[test]
+fn manual_class_count_parsed_stored_and_aggregated_170001() {
+    let path = Path::new("test_files/manual_class/170001.csv");
+    let store = MemoryStore::new();
+    bidirectional_count(
+        &store,
+        170001,
+        NonPermCountKind::ManualClass,
+        LaneDirection::North,
+        LaneDirection::South,
+    );
+
+    let registry = ParserRegistry::default();
+    let parser = registry
+        .parser_for(path, &ExtractOptions::default())
+        .unwrap();
+    let parsed = parser
+        .extract(
+            path,
+            &[CountInfo::from_store(170001, &store).unwrap()],
+            &ExtractOptions::default(),
+        )
+        .unwrap();
+    assert!(parsed[0].calc_aadv);
+    let mut parsed = parsed.into_iter().next().unwrap();
+    parsed.add_hourly().unwrap();
+    for records in &parsed.records {
+        records.replace(170001, &store).unwrap();
+    }
+
+    // Hourly volumes are aggregated from the class counts, like those from tubes, and agree with
+    // those aggregated from what was stored.
+    let hourly = store
+        .hourly_volumes(170001, TimeBinnedVehicleClassCount::COUNT_TABLE, "total")
+        .unwrap();
+    assert_eq!(hourly.len(), 4);
+    assert_eq!(hourly.iter().map(|c| c.count).sum::<u32>(), 1030);
+    assert_eq!(
+        store.select_counts::<HourlyVehicle>(170001).unwrap().len(),
+        4
+    );
+}
@@ -10,12 +10,7 @@ use traffic_counts::non_perm::{
 #[test]
 fn empty_periods_created_correctly_178955() {
     let path = Path::new("test_files/jamar_vehicle_and_bicycle/178955.csv");
-    let directions = Directions {
-        direction1: LaneDirection::South,
-        direction2: None,
-        direction3: None,
-        one_way_bicycle: false,
-    };
+    let directions = Directions::new(vec![LaneDirection::South], false).unwrap();
 
     let individual_bicycles = IndividualBicycle::extract(path).unwrap();
 
@@ -52,12 +47,7 @@ fn empty_periods_created_correctly_178955() {
 #[test]
 fn counts_correct_178955() {
     let path = Path::new("test_files/jamar_vehicle_and_bicycle/178955.csv");
-    let directions = Directions {
-        direction1: LaneDirection::South,
-        direction2: None,
-        direction3: None,
-        one_way_bicycle: false,
-    };
+    let directions = Directions::new(vec![LaneDirection::South], false).unwrap();
     let individual_bicycles = IndividualBicycle::extract(path).unwrap();
 
     let mut bikes15min = create_binned_bicycle_vol_count(
@@ -101,12 +91,8 @@ fn counts_correct_178955() {
 #[test]
 fn counts_correct_178959() {
     let path = Path::new("test_files/jamar_vehicle_and_bicycle/178959.csv");
-    let directions = Directions {
-        direction1: LaneDirection::East,
-        direction2: Some(LaneDirection::West),
-        direction3: None,
-        one_way_bicycle: false,
-    };
+    let directions =
+        Directions::new(vec![LaneDirection::East, LaneDirection::West], false).unwrap();
 
     let individual_bicycles = IndividualBicycle::extract(path).unwrap();
 
@@ -187,12 +173,7 @@ fn counts_correct_178959() {
 #[test]
 fn wrong_way_gets_added_to_direction1_181261() {
     let path = Path::new("test_files/jamar_bicycle/181261_include_wrong_way.txt");
-    let directions = Directions {
-        direction1: LaneDirection::West,
-        direction2: None,
-        direction3: None,
-        one_way_bicycle: true,
-    };
+    let directions = Directions::new(vec![LaneDirection::West], true).unwrap();
 
     let individual_bicycles = IndividualBicycle::extract(path).unwrap();
 
@@ -6,7 +6,7 @@ use std::path::Path;
 use chrono::NaiveDateTime;
 
 use traffic_counts::{
-    non_perm::{extract_from_file::Bicycles, intermediate::*, *},
+    non_perm::{class_scheme::ClassMapping, intermediate::*, *},
     *,
 };
 
@@ -93,13 +93,13 @@ fn speed_binning_is_correct() {
 #[test]
 fn empty_periods_created_correctly_166905() {
     let path = Path::new("test_files/jamar_vehicle/166905.txt");
-    let (username, password) = db::get_non_perm_creds();
-    let pool = db::create_pool(username, password, 1).unwrap();
+    let db_config = db::get_non_perm_creds();
+    let pool = db::create_pool(&db_config, 1).unwrap();
     let conn = pool.get().unwrap();
 
     let directions = Directions::from_db(166905, &conn).unwrap();
 
-    let individual_vehicles = IndividualVehicle::extract(path, Bicycles::Without).unwrap();
+    let individual_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
 
     let (mut speed_range_count, mut vehicle_class_count) = create_speed_and_class_count(
         TimeInterval::FifteenMin,
@@ -167,12 +167,12 @@ fn empty_periods_created_correctly_166905() {
 
 #[test]
 fn counts_created_correctly_165367() {
-    let (username, password) = db::get_non_perm_creds();
-    let pool = db::create_pool(username, password, 1).unwrap();
+    let db_config = db::get_non_perm_creds();
+    let pool = db::create_pool(&db_config, 1).unwrap();
     let conn = pool.get().unwrap();
     let path = Path::new("test_files/jamar_vehicle/165367.txt");
     let directions = Directions::from_db(165367, &conn).unwrap();
-    let individual_vehicles = IndividualVehicle::extract(path, Bicycles::Without).unwrap();
+    let individual_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
 
     let (mut speed_range_count, mut vehicle_class_count) = create_speed_and_class_count(
         TimeInterval::FifteenMin,
@@ -252,13 +252,16 @@ fn counts_created_correctly_101() {
     // This file was made up, based on another, but with just over an hour of counts.
     let path = Path::new("test_files/jamar_vehicle/101.csv");
 
-    let directions = Directions {
-        direction1: LaneDirection::East,
-        direction2: Some(LaneDirection::East),
-        direction3: Some(LaneDirection::East),
-        one_way_bicycle: false,
-    };
-    let individual_vehicles = IndividualVehicle::extract(path, Bicycles::Without).unwrap();
+    let directions = Directions::new(
+        vec![
+            LaneDirection::East,
+            LaneDirection::East,
+            LaneDirection::East,
+        ],
+        false,
+    )
+    .unwrap();
+    let individual_vehicles = IndividualVehicle::extract(path, &ClassMapping::default()).unwrap();
 
     let (mut speed_range_count, mut vehicle_class_count) = create_speed_and_class_count(
         TimeInterval::FifteenMin,